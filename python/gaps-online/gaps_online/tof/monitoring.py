@@ -19,11 +19,13 @@ try:
     MtbMoniSeries     = tdc.moni.MtbMoniSeries
     CPUMoniSeries     = tdc.moni.CPUMoniSeries
     LTBMoniSeries     = tdc.moni.LTBMoniSeries
+    RBChannelNoiseMoniSeries = tdc.moni.RBChannelNoiseMoniSeries
     RBMoniData        = tdc.moni.RBMoniData
     MtbMoniData       = tdc.moni.MtbMoniData
     PAMoniData        = tdc.moni.PAMoniData
     PBMoniData        = tdc.moni.PBMoniData
     LTBMoniData       = tdc.moni.LTBMoniData
+    RBChannelNoiseMoniData = tdc.moni.RBChannelNoiseMoniData
     EVTBLDRHeartbeat  = tdc.commands.EVTBLDRHeartbeat
     MTBHeartbeat      = tdc.commands.MTBHeartbeat
     HeartbeatDataSink = tdc.commands.HeartbeatDataSink
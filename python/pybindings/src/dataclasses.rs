@@ -1,3 +1,6 @@
+use std::env;
+use std::collections::HashMap;
+
 use numpy::{
   PyArray,
   PyArray1,
@@ -18,6 +21,11 @@ use tof_dataclasses::packets::{
   PacketType
 };
 use tof_dataclasses::database::DsiJChPidMapping;
+use tof_dataclasses::database::{
+  connect_to_db,
+  Paddle,
+  DBPanel,
+};
 
 use tof_dataclasses::heartbeats::HeartBeatDataSink;
 use tof_dataclasses::heartbeats::MTBHeartbeat;
@@ -32,6 +40,7 @@ use tof_dataclasses::monitoring::{
   MtbMoniData, 
   CPUMoniData,
   LTBMoniData,
+  RBChannelNoiseMoniData,
 };
 
 use tof_dataclasses::status::TofDetectorStatus;
@@ -43,6 +52,7 @@ use tof_dataclasses::series::{
   MtbMoniDataSeries,
   CPUMoniDataSeries,
   LTBMoniDataSeries,
+  RBChannelNoiseMoniDataSeries,
 };
 
 use tof_dataclasses::events::{
@@ -72,6 +82,10 @@ use tof_dataclasses::calibrations::{
   //clean_spikes,
   //spike_cleaning
 };
+use tof_dataclasses::calibration_quality::{
+  RBCalibrationQuality,
+  RBCalibrationQualityCuts,
+};
 
 use tof_dataclasses::commands::config::{
   AnalysisEngineConfig,
@@ -91,7 +105,23 @@ use pyo3::exceptions::{
   PyIOError,
 };
 
+use tof_dataclasses::reconstruction::{
+  TofTrack,
+  TofTrackFitter,
+  ChargeEstimate,
+  ChargeEstimator,
+};
+use tof_dataclasses::event_display::{
+  DetectorGeometry,
+  DisplayTrackerHit,
+  EventDisplayExporter,
+};
 use tof_dataclasses::events::TriggerType;
+use tof_dataclasses::events::EventClassifier;
+use tof_dataclasses::events::{
+  TriggerEmulator,
+  TriggerEfficiency,
+};
 use tof_dataclasses::events::master_trigger::LTBThreshold;
 use tof_dataclasses::events::rb_event::RBPaddleID;
 
@@ -1367,6 +1397,15 @@ impl PyRBCalibration {
     Ok(pyarray)
   }
 
+  /// Evaluate the quality of the calibration. Metrics
+  /// which need the calibration data are NaN if the 
+  /// data has been discarded
+  fn get_quality(&self) -> PyRBCalibrationQuality {
+    PyRBCalibrationQuality {
+      quality : RBCalibrationQuality::from_calibration(&self.cali)
+    }
+  }
+
   /// Load the calibration from a file with a 
   /// TofPacket of type RBCalibration in it
   ///
@@ -1389,6 +1428,82 @@ impl PyRBCalibration {
   }
 }
   
+#[pyclass]
+#[pyo3(name="RBCalibrationQuality")]
+pub struct PyRBCalibrationQuality {
+  quality : RBCalibrationQuality,
+}
+
+#[pymethods]
+impl PyRBCalibrationQuality {
+  #[new]
+  fn new() -> Self {
+    Self {
+      quality : RBCalibrationQuality::new(),
+    }
+  }
+
+  fn from_tofpacket(&mut self, packet : &PyTofPacket) -> PyResult<()> {
+    let tp = packet.get_tp();
+    match tp.unpack::<RBCalibrationQuality>() {
+      Ok(quality) => {
+        self.quality = quality;
+        return Ok(());
+      }
+      Err(err) => {
+        let err_msg = format!("Unable to unpack TofPacket! {err}");
+        return Err(PyIOError::new_err(err_msg));
+      }
+    }
+  }
+
+  fn __repr__(&self) -> PyResult<String> {
+    Ok(format!("<PyO3Wrapper: {}>", self.quality)) 
+  }
+
+  /// Check the report against the default cuts 
+  /// of liftof-cc. Returns the failed cuts, empty
+  /// if the calibration is good
+  fn get_failures(&self) -> Vec<String> {
+    RBCalibrationQualityCuts::new().get_failures(&self.quality)
+  }
+
+  #[getter]
+  fn rb_id          (&self) -> u8 {
+    self.quality.rb_id
+  }
+  
+  #[getter]
+  fn timestamp      (&self) -> u32 {
+    self.quality.timestamp
+  }
+  
+  #[getter]
+  fn vcal_residual  (&self) -> [f32;9] {
+    self.quality.vcal_residual
+  }
+  
+  #[getter]
+  fn n_outlier_cells(&self) -> [u16;9] {
+    self.quality.n_outlier_cells
+  }
+  
+  #[getter]
+  fn tbin_sum       (&self) -> [f32;9] {
+    self.quality.tbin_sum
+  }
+  
+  #[getter]
+  fn sine_chi2      (&self) -> [f32;9] {
+    self.quality.sine_chi2
+  }
+  
+  #[getter]
+  fn noise          (&self) -> [f32;9] {
+    self.quality.noise
+  }
+}
+
 //#[getter]
 //fn spike_cleaning_all_channel<'_py>(&self, py: Python<'_py>) -> PyResult<Bound<'_py, PyArray2<f32>>> {  
 //  let mut data = Vec::<Vec<f32>>::with_capacity(9);
@@ -1723,6 +1838,99 @@ impl PyLTBMoniData {
   }
 }
 
+#[pyclass]
+#[pyo3(name="RBChannelNoiseMoniData")]
+pub struct PyRBChannelNoiseMoniData {
+  moni : RBChannelNoiseMoniData,
+}
+
+#[pymethods]
+impl PyRBChannelNoiseMoniData {
+  #[new]
+  fn new() -> Self {
+    let moni = RBChannelNoiseMoniData::new();
+    Self {
+      moni,
+    }
+  }
+ 
+  fn from_tofpacket(&mut self, packet : &PyTofPacket) -> PyResult<()> {
+    let tp = packet.get_tp();
+    match tp.unpack::<RBChannelNoiseMoniData>() {
+      Ok(moni) => {
+        self.moni = moni;
+        return Ok(());
+      }
+      Err(err) => {
+        let err_msg = format!("Unable to unpack TofPacket! {err}");
+        return Err(PyIOError::new_err(err_msg));
+      }
+    }
+  }
+
+  fn __repr__(&self) -> PyResult<String> {
+    Ok(format!("<PyO3Wrapper: {}>", self.moni)) 
+  }
+
+  fn keys(&self) -> Vec<&'static str> {
+    RBChannelNoiseMoniData::keys()
+  }
+
+  /// Access the (data) members by name
+  fn get(&self, varname : &str) -> PyResult<f32> {
+    match self.moni.get(varname) {
+      None => {
+        let err_msg = format!("RBChannelNoiseMoniData does not have a key with name {}! See RBChannelNoiseMoniData.keys() for a list of available keys!", varname);
+        return Err(PyKeyError::new_err(err_msg));
+      }
+      Some(val) => {
+        return Ok(val)
+      }
+    }
+  }
+
+  /// RB channels (1-8) with a noise above max_noise [mV]
+  fn get_noisy_channels(&self, max_noise : f32) -> Vec<u8> {
+    self.moni.get_noisy_channels(max_noise)
+  }
+  
+  /// RB channels (1-8) which are flat (noise below 
+  /// min_noise [mV]) or did not see any pedestal
+  fn get_dead_channels(&self, min_noise : f32) -> Vec<u8> {
+    self.moni.get_dead_channels(min_noise)
+  }
+
+  #[getter]
+  fn board_id      (&self)  -> u8  {
+    self.moni.board_id
+  }
+  
+  #[getter]
+  fn period        (&self)  -> u16 {
+    self.moni.period
+  }
+  
+  #[getter]
+  fn n_entries     (&self)  -> [u32;8] {
+    self.moni.n_entries
+  }
+  
+  #[getter]
+  fn baseline      (&self)  -> [f32;8] {
+    self.moni.baseline
+  }
+  
+  #[getter]
+  fn baseline_spread(&self) -> [f32;8] {
+    self.moni.baseline_spread
+  }
+  
+  #[getter]
+  fn noise         (&self)  -> [f32;8] {
+    self.moni.noise
+  }
+}
+
 #[pyclass]
 #[pyo3(name="RBMoniData")]
 pub struct PyRBMoniData {
@@ -2002,11 +2210,6 @@ impl PyHeartBeatDataSink{
   fn get_n_bytes_written(&self) -> PyResult<u64> {
     Ok(self.config.n_bytes_written)
   }
-  // num. missing their event id
-  #[getter]
-  fn get_n_evid_chunksize(&self) -> PyResult<u64> {
-    Ok(self.config.n_evid_chunksize)
-  }
   // num. missing event id
   #[getter]
   fn get_evid_missing(&self) -> PyResult<u64> {
@@ -2196,6 +2399,22 @@ impl PyEVTBLDRHeartbeat {
   fn get_data_mangled_ev(&self) -> PyResult<usize> {
     Ok(self.config.data_mangled_ev)
   }
+  /// The protocol version. The interesting event 
+  /// counters are only available for V2 and larger
+  #[getter]
+  fn version(&self) -> ProtocolVersion {
+    self.config.version
+  }
+  #[getter]
+  fn get_n_interesting(&self) -> PyResult<usize> {
+    Ok(self.config.n_interesting)
+  }
+  /// Number of events passing each of the 
+  /// interesting event selection rules
+  #[getter]
+  fn get_n_rule_pass(&self) -> PyResult<Vec<usize>> {
+    Ok(self.config.n_rule_pass.to_vec())
+  }
   
   fn from_tofpacket(&mut self, packet : &PyTofPacket) -> PyResult<()> {
     let tp = packet.get_tp();
@@ -2443,6 +2662,42 @@ impl PyLTBMoniSeries {
   }
 }
 
+#[pyclass]
+#[pyo3(name="RBChannelNoiseMoniSeries")]
+pub struct PyRBChannelNoiseMoniSeries {
+  noisemoniseries : RBChannelNoiseMoniDataSeries,
+}
+
+#[pymethods]
+impl PyRBChannelNoiseMoniSeries {
+  #[new]
+  fn new() -> Self {
+    let noisemoniseries = RBChannelNoiseMoniDataSeries::new();
+    Self {
+      noisemoniseries,
+    }
+  }
+  
+  fn from_file(&mut self, filename : String) -> PyResult<PyDataFrame> {
+    let mut reader = TofPacketReader::new(filename);
+    reader.filter = PacketType::RBChannelNoiseMoniData;
+    for tp in reader {
+      if let Ok(moni) =  tp.unpack::<RBChannelNoiseMoniData>() {
+        self.noisemoniseries.add(moni);
+      }
+    }
+    match self.noisemoniseries.get_dataframe() {
+      Ok(df) => {
+        let pydf = PyDataFrame(df);
+        return Ok(pydf);
+      },
+      Err(err) => {
+        return Err(PyValueError::new_err(err.to_string()));
+      }
+    }
+  }
+}
+
 
 #[pyclass]
 #[pyo3(name="TofPacket")]
@@ -2750,11 +3005,40 @@ impl PyTofEventSummary {
   fn event_status(&self) -> EventStatus {
     self.event.status
   }
+
+  /// The EventQuality (as assigned by the 
+  /// EventClassifier) as u8
+  #[getter]
+  fn quality(&self) -> u8 {
+    self.event.quality
+  }
+
+  /// Result of the interesting event selection, 
+  /// bit k is set if the event passed rule k
+  #[getter]
+  fn interesting(&self) -> u8 {
+    self.event.interesting
+  }
+
+  #[getter]
+  fn is_interesting(&self) -> bool {
+    self.event.is_interesting()
+  }
+
+  /// The reconstructed track (only for 
+  /// ProtocolVersion V3)
+  #[getter]
+  fn track(&self) -> Option<PyTofTrack> {
+    self.event.track.map(|track| PyTofTrack { track })
+  }
   
   /// Compare the hg hits of the event with the triggered paddles and 
   /// return the paddles which have at least a missing HG hit
-  fn get_missing_paddles_hg(&self, mapping : DsiJChPidMapping) -> Vec<u8> {
-    self.event.get_missing_paddles_hg(&mapping)
+  fn get_missing_paddles_hg(&self, mapping : DsiJChPidMapping) -> PyResult<Vec<u8>> {
+    match self.event.get_missing_paddles_hg(&mapping) {
+      Err(err) => Err(PyValueError::new_err(err.to_string())),
+      Ok(missing) => Ok(missing)
+    }
   }
 
   /// Get all the paddle ids which have been triggered
@@ -2881,6 +3165,552 @@ impl PyTofEventSummary {
   }
 }
 
+/// Result of the straight line fit of the TOF hits
+#[pyclass]
+#[pyo3(name="TofTrack")]
+#[derive(Debug, Clone)]
+pub struct PyTofTrack {
+  pub track : TofTrack,
+}
+
+#[pymethods]
+impl PyTofTrack {
+  #[new]
+  fn new() -> Self {
+    Self {
+      track : TofTrack::new(),
+    }
+  }
+
+  /// A point on the track (mm)
+  #[getter]
+  fn origin(&self) -> [f32;3] {
+    self.track.origin
+  }
+
+  /// Unit vector in the direction of flight
+  #[getter]
+  fn direction(&self) -> [f32;3] {
+    self.track.direction
+  }
+
+  #[getter]
+  fn beta(&self) -> f32 {
+    self.track.beta
+  }
+
+  #[getter]
+  fn beta_err(&self) -> f32 {
+    self.track.beta_err
+  }
+
+  #[getter]
+  fn chi2_pos(&self) -> f32 {
+    self.track.chi2_pos
+  }
+
+  #[getter]
+  fn chi2_time(&self) -> f32 {
+    self.track.chi2_time
+  }
+
+  #[getter]
+  fn ndof(&self) -> u16 {
+    self.track.ndof
+  }
+
+  #[getter]
+  fn reduced_chi2(&self) -> f32 {
+    self.track.get_reduced_chi2()
+  }
+
+  #[getter]
+  fn n_hits(&self) -> u8 {
+    self.track.n_hits
+  }
+
+  #[getter]
+  fn n_outer(&self) -> u8 {
+    self.track.n_outer
+  }
+
+  #[getter]
+  fn n_inner(&self) -> u8 {
+    self.track.n_inner
+  }
+
+  /// Zenith angle (deg), 0 for vertically downgoing
+  #[getter]
+  fn zenith(&self) -> f32 {
+    self.track.get_zenith()
+  }
+
+  #[getter]
+  fn azimuth(&self) -> f32 {
+    self.track.get_azimuth()
+  }
+
+  #[getter]
+  fn is_downgoing(&self) -> bool {
+    self.track.is_downgoing()
+  }
+
+  /// Position of the track at a given z (mm)
+  fn get_pos_at_z(&self, z : f32) -> Option<[f32;3]> {
+    self.track.get_pos_at_z(z)
+  }
+
+  fn __repr__(&self) -> PyResult<String> {
+    Ok(format!("<PyO3Wrapper: {}>", self.track))
+  }
+}
+
+/// Straight line fit and beta reconstruction
+/// for TofEventSummary. 
+///
+/// The paddle information needs to be added to 
+/// the events first (e.g. TofPacketReader.add_paddleinfo)
+#[pyclass]
+#[pyo3(name="TofTrackFitter")]
+#[derive(Debug, Clone)]
+pub struct PyTofTrackFitter {
+  pub fitter : TofTrackFitter,
+}
+
+#[pymethods]
+impl PyTofTrackFitter {
+  #[new]
+  fn new() -> Self {
+    Self {
+      fitter : TofTrackFitter::new(),
+    }
+  }
+
+  /// Position resolution of a hit (mm)
+  #[getter]
+  fn get_sigma_pos(&self) -> f32 {
+    self.fitter.sigma_pos
+  }
+
+  #[setter]
+  fn set_sigma_pos(&mut self, value : f32) {
+    self.fitter.sigma_pos = value;
+  }
+
+  /// Time resolution of a hit (ns)
+  #[getter]
+  fn get_sigma_time(&self) -> f32 {
+    self.fitter.sigma_time
+  }
+
+  #[setter]
+  fn set_sigma_time(&mut self, value : f32) {
+    self.fitter.sigma_time = value;
+  }
+
+  /// Ignore hits which don't obey causality
+  #[getter]
+  fn get_only_causal(&self) -> bool {
+    self.fitter.only_causal
+  }
+
+  #[setter]
+  fn set_only_causal(&mut self, value : bool) {
+    self.fitter.only_causal = value;
+  }
+
+  /// Fit a straight track to the hits of the 
+  /// event and reconstruct beta
+  fn fit(&self, event : &PyTofEventSummary) -> PyResult<PyTofTrack> {
+    match self.fitter.fit(&event.event) {
+      Ok(track) => {
+        Ok(PyTofTrack { track })
+      }
+      Err(err) => {
+        Err(PyValueError::new_err(err.to_string()))
+      }
+    }
+  }
+
+  fn __repr__(&self) -> PyResult<String> {
+    Ok(format!("<PyO3Wrapper: {}>", self.fitter))
+  }
+}
+
+/// Grade events by their quality. The result is 
+/// the EventQuality as u8
+/// (0 : Unknown, 10 : Silver, 20 : Gold, 
+///  30 : Diamond, 40 : FourLeafClover)
+///
+/// The rules from the [event_quality_settings] 
+/// section of a liftof settings file can be 
+/// obtained with LiftofSettings.get_event_classifier
+#[pyclass]
+#[pyo3(name="EventClassifier")]
+#[derive(Debug, Clone)]
+pub struct PyEventClassifier {
+  pub classifier : EventClassifier,
+}
+
+#[pymethods]
+impl PyEventClassifier {
+  #[new]
+  fn new() -> Self {
+    Self {
+      classifier : EventClassifier::new(),
+    }
+  }
+ 
+  /// Get the quality of the event and set it 
+  /// for the event. The paddle information needs 
+  /// to be set for the event for the causality 
+  /// check.
+  fn classify(&self, event : &mut PyTofEventSummary) -> u8 {
+    let quality = self.classifier.classify(&event.event) as u8;
+    event.event.quality = quality;
+    quality
+  }
+
+  fn __repr__(&self) -> PyResult<String> {
+    Ok(format!("<PyO3Wrapper: {}>", self.classifier))
+  }
+}
+
+/// Emulate the MTB trigger logic from the LTB hit 
+/// pattern of MasterTriggerEvents. 
+///
+/// The mapping DSI/J/CH -> paddle id can be obtained 
+/// with create_mtb_connection_to_pid_map. Events 
+/// added with add_event are used to compare the 
+/// emulated triggers with the ones reported by the 
+/// MTB.
+#[pyclass]
+#[pyo3(name="TriggerEmulator")]
+pub struct PyTriggerEmulator {
+  pub emulator   : TriggerEmulator,
+  pub efficiency : TriggerEfficiency,
+}
+
+#[pymethods]
+impl PyTriggerEmulator {
+  /// Emulate all trigger types which depend on 
+  /// the hits
+  #[new]
+  #[pyo3(signature = (mapping, use_beta = true))]
+  fn new(mapping : DsiJChPidMapping, use_beta : bool) -> Self {
+    let emulator   = TriggerEmulator::new(mapping, use_beta);
+    let efficiency = TriggerEfficiency::new(&emulator);
+    Self {
+      emulator,
+      efficiency,
+    }
+  }
+
+  /// Emulate only the trigger (and combo trigger)
+  /// of a TriggerConfig
+  #[staticmethod]
+  fn from_trigger_config(config : &PyTriggerConfig, mapping : DsiJChPidMapping) -> Self {
+    let emulator   = TriggerEmulator::from_trigger_config(&config.config, mapping);
+    let efficiency = TriggerEfficiency::new(&emulator);
+    Self {
+      emulator,
+      efficiency,
+    }
+  }
+
+  /// The emulated triggers which fired for 
+  /// this event
+  fn evaluate(&self, event : &PyMasterTriggerEvent) -> Vec<TriggerType> {
+    self.emulator.evaluate(&event.event)
+  }
+
+  /// Emulate the triggers and update the 
+  /// efficiency counters
+  fn add_event(&mut self, event : &PyMasterTriggerEvent) {
+    self.efficiency.add_event(&self.emulator, &event.event);
+  }
+
+  /// Fraction of the events reported by the MTB 
+  /// for which the emulated trigger fired
+  fn get_efficiency(&self, trigger_type : TriggerType) -> Option<f32> {
+    self.efficiency.get_efficiency(trigger_type)
+  }
+
+  /// Fraction of all added events for which the 
+  /// emulated trigger fired
+  fn get_fire_fraction(&self, trigger_type : TriggerType) -> Option<f32> {
+    self.efficiency.get_fire_fraction(trigger_type)
+  }
+
+  fn reset_efficiency(&mut self) {
+    self.efficiency = TriggerEfficiency::new(&self.emulator);
+  }
+
+  #[getter]
+  fn n_events(&self) -> usize {
+    self.efficiency.n_events
+  }
+
+  fn __repr__(&self) -> PyResult<String> {
+    Ok(format!("<PyO3Wrapper: {}\n{}>", self.emulator, self.efficiency))
+  }
+}
+
+/// Result of the charge estimation
+#[pyclass]
+#[pyo3(name="ChargeEstimate")]
+#[derive(Debug, Clone)]
+pub struct PyChargeEstimate {
+  pub estimate : ChargeEstimate,
+}
+
+#[pymethods]
+impl PyChargeEstimate {
+  #[getter]
+  fn z(&self) -> f32 {
+    self.estimate.z
+  }
+
+  #[getter]
+  fn z_err(&self) -> f32 {
+    self.estimate.z_err
+  }
+
+  /// Truncated mean of dE/dx (MeV/mm)
+  #[getter]
+  fn dedx_mean(&self) -> f32 {
+    self.estimate.dedx_mean
+  }
+
+  #[getter]
+  fn n_used(&self) -> u8 {
+    self.estimate.n_used
+  }
+
+  #[getter]
+  fn n_hits(&self) -> u8 {
+    self.estimate.n_hits
+  }
+
+  fn __repr__(&self) -> PyResult<String> {
+    Ok(format!("<PyO3Wrapper: {}>", self.estimate))
+  }
+}
+
+/// Charge (Z) estimation from the path length
+/// corrected energy deposition of the TOF hits
+#[pyclass]
+#[pyo3(name="ChargeEstimator")]
+#[derive(Debug, Clone)]
+pub struct PyChargeEstimator {
+  pub estimator : ChargeEstimator,
+}
+
+#[pymethods]
+impl PyChargeEstimator {
+  #[new]
+  fn new() -> Self {
+    Self {
+      estimator : ChargeEstimator::new(),
+    }
+  }
+
+  /// Get the paddle geometries from the database 
+  /// given by the DATABASE_URL environment variable
+  fn load_paddles(&mut self) -> PyResult<()> {
+    let db_path = env::var("DATABASE_URL").unwrap_or_else(|_| "".to_string());
+    match connect_to_db(db_path) {
+      Err(err) => {
+        return Err(PyIOError::new_err(err.to_string()));
+      }
+      Ok(mut conn) => {
+        match Paddle::all(&mut conn) {
+          None => {
+            return Err(PyIOError::new_err("Unable to retrieve paddle information from DB!"));
+          }
+          Some(pdls) => {
+            let mut paddles = HashMap::<u8, Paddle>::new();
+            for p in pdls {
+              paddles.insert(p.paddle_id as u8, p);
+            }
+            self.estimator.set_paddles(&paddles);
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Set the relative gain for a paddle
+  fn set_gain(&mut self, paddle_id : u8, gain : f32) {
+    self.estimator.set_gain(paddle_id, gain);
+  }
+
+  fn get_gain(&self, paddle_id : u8) -> f32 {
+    self.estimator.get_gain(paddle_id)
+  }
+
+  /// dE/dx of a minimum ionizing particle (MeV/mm)
+  #[getter]
+  fn get_mip_dedx(&self) -> f32 {
+    self.estimator.mip_dedx
+  }
+
+  #[setter]
+  fn set_mip_dedx(&mut self, value : f32) {
+    self.estimator.mip_dedx = value;
+  }
+
+  /// Fraction of the largest dE/dx values which 
+  /// are discarded for the truncated mean
+  #[getter]
+  fn get_truncation(&self) -> f32 {
+    self.estimator.truncation
+  }
+
+  #[setter]
+  fn set_truncation(&mut self, value : f32) {
+    self.estimator.truncation = value;
+  }
+
+  /// Estimate the charge for an event with a 
+  /// reconstructed track
+  fn estimate(&self, event : &PyTofEventSummary, track : &PyTofTrack) -> PyResult<PyChargeEstimate> {
+    match self.estimator.estimate(&event.event, &track.track) {
+      Ok(estimate) => {
+        Ok(PyChargeEstimate { estimate })
+      }
+      Err(err) => {
+        Err(PyValueError::new_err(err.to_string()))
+      }
+    }
+  }
+
+  fn __repr__(&self) -> PyResult<String> {
+    Ok(format!("<PyO3Wrapper: {}>", self.estimator))
+  }
+}
+
+/// Export TofEventSummaries together with the 
+/// detector geometry as a JSON scene for event 
+/// displays. Tracks are fit for the events, unless
+/// given explicitly or fit_tracks is disabled.
+#[pyclass]
+#[pyo3(name="EventDisplayExporter")]
+#[derive(Debug, Clone)]
+pub struct PyEventDisplayExporter {
+  pub exporter : EventDisplayExporter,
+}
+
+impl PyEventDisplayExporter {
+  fn get_tracker_hits(hits : Option<Vec<(u8, u8, u8, u8, u16, f32, f32, f32)>>) -> Vec<DisplayTrackerHit> {
+    hits.unwrap_or_default().iter().map(|h| DisplayTrackerHit {
+      layer   : h.0,
+      row     : h.1,
+      module  : h.2,
+      channel : h.3,
+      adc     : h.4,
+      x       : h.5,
+      y       : h.6,
+      z       : h.7,
+    }).collect()
+  }
+}
+
+#[pymethods]
+impl PyEventDisplayExporter {
+  #[new]
+  fn new() -> Self {
+    Self {
+      exporter : EventDisplayExporter::new(DetectorGeometry::new()),
+    }
+  }
+
+  /// Get paddles and panels from the database 
+  /// given by the DATABASE_URL environment variable
+  fn load_geometry(&mut self) -> PyResult<()> {
+    let db_path = env::var("DATABASE_URL").unwrap_or_else(|_| "".to_string());
+    let mut conn = match connect_to_db(db_path) {
+      Err(err) => {
+        return Err(PyIOError::new_err(err.to_string()));
+      }
+      Ok(conn) => conn
+    };
+    let paddles = match Paddle::all(&mut conn) {
+      None => {
+        return Err(PyIOError::new_err("Unable to retrieve paddle information from DB!"));
+      }
+      Some(pdls) => pdls
+    };
+    let panels = match DBPanel::all(&mut conn) {
+      None => {
+        return Err(PyIOError::new_err("Unable to retrieve panel information from DB!"));
+      }
+      Some(pnls) => pnls
+    };
+    let fitter    = self.exporter.fitter;
+    self.exporter = EventDisplayExporter::from_db(&paddles, &panels);
+    self.exporter.fitter = fitter;
+    Ok(())
+  }
+
+  /// Fit a track for events which don't come 
+  /// with one
+  #[getter]
+  fn get_fit_tracks(&self) -> bool {
+    self.exporter.fitter.is_some()
+  }
+
+  #[setter]
+  fn set_fit_tracks(&mut self, value : bool) {
+    if value {
+      self.exporter.fitter = Some(TofTrackFitter::new());
+    } else {
+      self.exporter.fitter = None;
+    }
+  }
+
+  /// Number of paddles in the geometry
+  #[getter]
+  fn get_npaddles(&self) -> usize {
+    self.exporter.geometry.paddles.len()
+  }
+
+  /// Export a single event as JSON scene
+  ///
+  /// # Arguments:
+  ///   * event        : TofEventSummary
+  ///   * track        : already reconstructed track (optional)
+  ///   * tracker_hits : list of (layer, row, module, channel, adc, x, y, z)
+  #[pyo3(signature = (event, track=None, tracker_hits=None))]
+  fn export_event(&self,
+                  event        : &PyTofEventSummary,
+                  track        : Option<PyTofTrack>,
+                  tracker_hits : Option<Vec<(u8, u8, u8, u8, u16, f32, f32, f32)>>) -> PyResult<String> {
+    let scene = self.exporter.export_event(&event.event,
+                                           track.as_ref().map(|t| &t.track),
+                                           Self::get_tracker_hits(tracker_hits));
+    scene.to_json().map_err(|err| PyValueError::new_err(err.to_string()))
+  }
+
+  /// Export a list of events as JSON scene
+  fn export_events(&self, events : Vec<PyTofEventSummary>) -> PyResult<String> {
+    let events : Vec<TofEventSummary> = events.into_iter().map(|ev| ev.event).collect();
+    let scene = self.exporter.export_events(&events);
+    scene.to_json().map_err(|err| PyValueError::new_err(err.to_string()))
+  }
+
+  /// Write a list of events as JSON scene to a file
+  fn write_events(&self, events : Vec<PyTofEventSummary>, filename : &str) -> PyResult<()> {
+    let events : Vec<TofEventSummary> = events.into_iter().map(|ev| ev.event).collect();
+    self.exporter.write_events(&events, filename).map_err(|err| PyIOError::new_err(err.to_string()))
+  }
+
+  fn __repr__(&self) -> PyResult<String> {
+    Ok(format!("<PyO3Wrapper: {}>", self.exporter))
+  }
+}
+
 #[pyclass]
 #[pyo3(name="TofEventHeader")]
 #[derive(Debug, Clone)]
@@ -2917,8 +3747,11 @@ impl PyTofEvent {
     }
   }
 
-  fn get_missing_paddles_hg(&self, mapping : DsiJChPidMapping) -> Vec<u8> {
-    self.event.get_missing_paddles_hg(&mapping)
+  fn get_missing_paddles_hg(&self, mapping : DsiJChPidMapping) -> PyResult<Vec<u8>> {
+    match self.event.get_missing_paddles_hg(&mapping) {
+      Err(err) => Err(PyValueError::new_err(err.to_string())),
+      Ok(missing) => Ok(missing)
+    }
   }
 
   #[getter]
@@ -3276,6 +4109,66 @@ impl PyTofHit {
     self.hit.get_edep()
   }
 
+  /// All pulses at paddle end A as (time, peak, charge),
+  /// including the first one
+  #[getter]
+  fn pulses_a(&self) -> Vec<(f32, f32, f32)> {
+    self.hit.get_pulses_a().iter().map(|p| (p.get_time(), p.get_peak(), p.get_charge())).collect()
+  }
+  
+  /// All pulses at paddle end B as (time, peak, charge),
+  /// including the first one
+  #[getter]
+  fn pulses_b(&self) -> Vec<(f32, f32, f32)> {
+    self.hit.get_pulses_b().iter().map(|p| (p.get_time(), p.get_peak(), p.get_charge())).collect()
+  }
+
+  /// Pile-up flags (see TOFHIT_PILEUP_A etc.)
+  #[getter]
+  fn pileup(&self) -> u8 {
+    self.hit.pileup
+  }
+
+  #[getter]
+  fn has_pileup(&self) -> bool {
+    self.hit.has_pileup()
+  }
+
+  /// Delay of the latest secondary pulse with 
+  /// respect to the first pulse [ns]
+  #[getter]
+  fn max_pulse_delay(&self) -> f32 {
+    self.hit.get_max_pulse_delay()
+  }
+
+  /// The pulse at paddle end A reached the 
+  /// saturation level
+  #[getter]
+  fn saturated_a(&self) -> bool {
+    self.hit.saturated_a
+  }
+  
+  /// The pulse at paddle end B reached the 
+  /// saturation level
+  #[getter]
+  fn saturated_b(&self) -> bool {
+    self.hit.saturated_b
+  }
+
+  /// χ²/ndof of the template fit at paddle end A
+  /// (0 if the CFD was used)
+  #[getter]
+  fn fit_chi2_a(&self) -> f32 {
+    self.hit.fit_chi2_a
+  }
+  
+  /// χ²/ndof of the template fit at paddle end B
+  /// (0 if the CFD was used)
+  #[getter]
+  fn fit_chi2_b(&self) -> f32 {
+    self.hit.fit_chi2_b
+  }
+
   fn __repr__(&self) -> PyResult<String> {
     Ok(format!("<PyO3Wrapper: {}>", self.hit)) 
   }
@@ -26,6 +26,8 @@ cfg_if::cfg_if! {
       PyTelemetryPacket,
       PyTelemetryPacketReader,
       PyMergedEvent,
+      PyGapsEvent,
+      PyGapsEventReader,
       PyTrackerHit,
       PyTrackerHitV2,
       PyTrackerEvent,
@@ -35,6 +37,9 @@ cfg_if::cfg_if! {
       PyTrackerDAQTempPacket,
       PyTrackerDAQHSKPacket,
       PyTrackerEventIDEchoPacket,
+      PyTrackerCalibration,
+      PyCalibratedTrackerHit,
+      PyTrackerGeometry,
     };
   }
 }
@@ -247,10 +252,12 @@ fn tof_moni<'_py>(m: &Bound<'_py, PyModule>) -> PyResult<()> {
   m.add_class::<PyMtbMoniSeries>()?;
   m.add_class::<PyCPUMoniSeries>()?;
   m.add_class::<PyLTBMoniSeries>()?;
+  m.add_class::<PyRBChannelNoiseMoniSeries>()?;
   m.add_class::<PyRBMoniData>()?;
   m.add_class::<PyPAMoniData>()?;
   m.add_class::<PyPBMoniData>()?;
   m.add_class::<PyLTBMoniData>()?;
+  m.add_class::<PyRBChannelNoiseMoniData>()?;
   m.add_class::<PyMtbMoniData>()?;
   m.add_class::<PyTofDetectorStatus>()?;
   Ok(())
@@ -284,7 +291,15 @@ fn tof_events<'_py>(m: &Bound<'_py, PyModule>) -> PyResult<()> {
   m.add_class::<PyTofHit>()?;
   m.add_class::<PyRBWaveform>()?;
   m.add_class::<PyRBCalibration>()?;
+  m.add_class::<PyRBCalibrationQuality>()?;
   m.add_class::<PyTofEventSummary>()?;
+  m.add_class::<PyEventClassifier>()?;
+  m.add_class::<PyTriggerEmulator>()?;
+  m.add_class::<PyTofTrack>()?;
+  m.add_class::<PyTofTrackFitter>()?;
+  m.add_class::<PyChargeEstimate>()?;
+  m.add_class::<PyChargeEstimator>()?;
+  m.add_class::<PyEventDisplayExporter>()?;
   m.add_class::<LTBThreshold>()?;
   m.add_class::<EventStatus>()?;
   m.add_class::<TriggerType>()?;
@@ -296,12 +311,12 @@ cfg_if::cfg_if! {
     #[pymodule]
     #[pyo3(name = "telemetry")]
     fn py_telemetry<'_py> (m: &Bound<'_py, PyModule>) -> PyResult<()> {
-      //m.add_function(wrap_pyfunction!(get_gapsevents,m)?)?;
       m.add_class::<tel_api::TelemetryPacketType>()?;
       m.add_class::<PyTelemetryPacket>()?;
       m.add_class::<PyTelemetryPacketReader>()?;
       m.add_class::<PyMergedEvent>()?;
-      //m.add_class::<PyGapsEvent>()?;
+      m.add_class::<PyGapsEvent>()?;
+      m.add_class::<PyGapsEventReader>()?;
       //m.add_class::<PyTofHit>()?;
       //m.add_class::<PyTofEventSummary>()?;
       m.add_class::<PyTrackerHit>()?;
@@ -313,6 +328,9 @@ cfg_if::cfg_if! {
       m.add_class::<PyTrackerDAQTempPacket>()?;
       m.add_class::<PyTrackerDAQHSKPacket>()?;
       m.add_class::<PyTrackerEventIDEchoPacket>()?;
+      m.add_class::<PyTrackerCalibration>()?;
+      m.add_class::<PyCalibratedTrackerHit>()?;
+      m.add_class::<PyTrackerGeometry>()?;
       Ok(())
     }
   }
@@ -6,7 +6,8 @@ use pyo3::exceptions::PyValueError;
 pub use crate::dataclasses::{
   PyMasterTriggerEvent,
   PyRBEvent,
-  PyTofEvent
+  PyTofEvent,
+  PyEventClassifier,
 };
 
 pub use crate::liftof_dataclasses::PyIPBus;
@@ -118,6 +119,14 @@ impl PyLiftofSettings {
     }
     Ok(pysettings)
   }
+
+  /// The EventClassifier with the rules from 
+  /// the event_quality_settings
+  fn get_event_classifier(&self) -> PyEventClassifier {
+    PyEventClassifier {
+      classifier : self.settings.event_quality_settings.get_classifier()
+    }
+  }
  
   fn __repr__(&self) -> PyResult<String> {
     Ok(format!("<PyO3Wrapper: {}>", self.settings))
@@ -146,7 +155,7 @@ pub fn py_waveform_analysis(event : &PyTofEvent,
 //  &rb_map[&rb_id],
 //  settings.clone()
 //) {
-  let ana_settings = settings.settings.analysis_engine_settings;
+  let ana_settings = settings.settings.analysis_engine_settings.clone();
   let pth          = settings.settings.db_path.clone();
   let mut conn     = connect_to_db(pth).expect("Check the DB path in the liftof settings!");
   let rbs          = ReadoutBoard::all(&mut conn).expect("Check DB");
@@ -156,7 +165,7 @@ pub fn py_waveform_analysis(event : &PyTofEvent,
   for rb_ev in new_rb_evs.iter_mut() {
     for rb_ in &rbs {
       if rb_.rb_id == rb_ev.header.rb_id {
-        match waveform_analysis(rb_ev, rb_, ana_settings) {
+        match waveform_analysis(rb_ev, rb_, &ana_settings) {
           Err(err) => {
             println!("Unable to perform waveform_analysis! {err}");
           }
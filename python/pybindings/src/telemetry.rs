@@ -11,6 +11,7 @@ use std::fmt;
 
 use telemetry_dataclasses::packets as tel_api;
 use telemetry_dataclasses::io as tel_io_api;
+use telemetry_dataclasses::tracker as trk_api;
 use crate::dataclasses::{
   //PyTofHit,
   PyTofEventSummary,
@@ -107,6 +108,47 @@ impl PyMergedEvent {
     self.event.version
   }
 
+  #[getter]
+  fn event_id(&self) -> u32 {
+    self.event.event_id
+  }
+
+  #[getter]
+  fn flags0(&self) -> u8 {
+    self.event.flags0
+  }
+
+  /// Only available for version 0
+  #[getter]
+  fn flags1(&self) -> u8 {
+    self.event.flags1
+  }
+
+  /// The time the event was assembled by the 
+  /// flight computer. Only available for 
+  /// version 1+
+  #[getter]
+  fn creation_time(&self) -> u64 {
+    self.event.creation_time
+  }
+
+  /// Bitmask indicating which tracker oscillators
+  /// are present in this event (version 1+)
+  #[getter]
+  fn tracker_osc_flags(&self) -> u8 {
+    self.event.tracker_osc_flags
+  }
+
+  #[getter]
+  fn tracker_oscillators(&self) -> Vec<u64> {
+    self.event.tracker_oscillators.clone()
+  }
+
+  #[getter]
+  fn tof_delimiter(&self) -> u8 {
+    self.event.tof_delimiter
+  }
+
   #[getter]
   fn tracker_v2(&self) -> PyResult<Vec<PyTrackerHitV2>> {
     let mut hits = Vec::<PyTrackerHitV2>::new();
@@ -129,6 +171,25 @@ impl PyMergedEvent {
     Ok(events)
   }
 
+  /// Pedestal subtracted tracker hits, independent
+  /// of the version of the MergedEvent
+  ///
+  /// # Arguments:
+  ///
+  /// * calibration : pedestals and channel mask
+  /// * only_good   : return only hits which are not
+  ///                 masked, noisy or below threshold
+  #[pyo3(signature = (calibration, only_good = true))]
+  fn get_calibrated_tracker_hits(&self, calibration : &PyTrackerCalibration, only_good : bool) -> Vec<PyCalibratedTrackerHit> {
+    let hits : Vec<trk_api::CalibratedTrackerHit>;
+    if only_good {
+      hits = calibration.cali.get_good_hits(&self.event);
+    } else {
+      hits = calibration.cali.calibrate_mergedevent(&self.event);
+    }
+    hits.into_iter().map(|hit| PyCalibratedTrackerHit { hit }).collect()
+  }
+
 
 
   /// Check if TOF/tracker data can be unpacked an no errors are thrown
@@ -486,21 +547,70 @@ impl PyTelemetryPacketReader {
 }
 
 
-// FIXME - with the new style tracker hit, we might not 
-// need the gaps event anymore
-//#[pyfunction]
-//fn get_gapsevents(fname : String) -> Vec<PyGapsEvent> {
-//  let mut pyevents = Vec::<PyGapsEvent>::new();
-//  let events = tel_io_api::get_gaps_events(fname);
-//  for ev in events {
-//    let mut pyev = PyGapsEvent::new();
-//    pyev.set_tof(ev.tof.clone());
-//    pyev.set_tracker(ev.tracker.clone());
-//    pyevents.push(pyev);
-//  }
-//  pyevents
-//}
+/// Stream combined TOF + tracker events from 
+/// telemetry files
+///
+/// Events which can not be decoded are counted in
+/// the statistics instead of being reported 
+/// individually.
+///
+/// # Arguments
+///
+/// * filename - a single binary file or a directory 
+///              with RAWYYMMDD_HHMMSS.bin files
+#[pyclass]
+#[pyo3(name="GapsEventReader")]
+pub struct PyGapsEventReader {
+  reader : tel_io_api::GapsEventReader,
+}
+
+#[pymethods]
+impl PyGapsEventReader {
+  #[new]
+  fn new(filename : String) -> Self {
+    Self {
+      reader : tel_io_api::GapsEventReader::new(filename),
+    }
+  }
+
+  /// Decoding statistics, e.g. number of events 
+  /// and errors
+  #[getter]
+  fn statistics(&self) -> HashMap<String, usize> {
+    let stats = &self.reader.stats;
+    let mut result = HashMap::<String, usize>::new();
+    result.insert(String::from("n_merged_events")  , stats.n_merged_events);
+    result.insert(String::from("n_gaps_events")    , stats.n_gaps_events);
+    result.insert(String::from("n_err_merged")     , stats.n_err_merged);
+    result.insert(String::from("n_no_tof_data")    , stats.n_no_tof_data);
+    result.insert(String::from("n_err_tofpacket")  , stats.n_err_tofpacket);
+    result.insert(String::from("n_err_packet_type"), stats.n_err_packet_type);
+    result.insert(String::from("n_err_tofsummary") , stats.n_err_tofsummary);
+    result
+  }
+
+  /// "Rewind" the files, meaning start again 
+  /// from the beginning. This resets the 
+  /// statistics
+  fn rewind(&mut self) -> PyResult<()> {
+    Ok(self.reader.rewind()?)
+  }
+
+  fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<PyGapsEvent> {
+    let ev = slf.reader.next()?;
+    Some(PyGapsEvent {
+      event : ev
+    })
+  }
+
+  fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+    slf 
+  }
 
+  fn __repr__(&self) -> PyResult<String> {
+    Ok(format!("<PyO3Wrapper: {}>", self.reader))
+  }
+}
 
 #[pyclass]
 #[pyo3(name="GapsTelemetryEvent")]
@@ -534,6 +644,11 @@ impl PyGapsEvent {
     tof
   }
 
+  #[getter]
+  fn event_id(&self) -> u32 {
+    self.event.event_id
+  }
+
   #[getter]
   fn tracker(&self) -> Vec<PyTrackerEvent> {
     let mut trk_ev = Vec::<PyTrackerEvent>::new();
@@ -545,6 +660,24 @@ impl PyGapsEvent {
     trk_ev
   }
   
+  #[getter]
+  fn tracker_hitsv2(&self) -> Vec<PyTrackerHitV2> {
+    let mut hits = Vec::<PyTrackerHitV2>::new();
+    for h in &self.event.tracker_hitsv2 {
+      let mut py_hit = PyTrackerHitV2::new();
+      py_hit.set_hit(*h);
+      hits.push(py_hit);
+    }
+    hits
+  }
+
+  /// Combined TOF and tracker pointcloud as 
+  /// (x, y, z, t, edep). Requires the paddle
+  /// information to be set for the TOF event
+  fn get_pointcloud(&self, geometry : &PyTrackerGeometry) -> Option<Vec<(f32,f32,f32,f32,f32)>> {
+    self.event.get_pointcloud(&geometry.geo)
+  }
+  
   fn __repr__(&self) -> PyResult<String> {
     Ok(format!("<PyO3Wrapper: {}>", self.event))
   }
@@ -814,6 +947,13 @@ impl PyTrackerEvent {
   //  Ok(()) 
   //}
 
+  /// The positions of the hit strips as 
+  /// (x, y, z, t, edep), with t = NaN and 
+  /// edep the raw adc value
+  fn get_pointcloud(&self, geometry : &PyTrackerGeometry) -> Vec<(f32,f32,f32,f32,f32)> {
+    self.te.get_pointcloud(&geometry.geo)
+  }
+
   #[getter]
   fn hits(&self) -> Vec<PyTrackerHit> {
     let mut hits = Vec::<PyTrackerHit>::new();
@@ -1204,4 +1344,238 @@ impl PyTrackerEventIDEchoPacket {
   }
 }
 
+#[pyclass]
+#[pyo3(name="CalibratedTrackerHit")]
+#[derive(Clone)]
+pub struct PyCalibratedTrackerHit {
+  hit : trk_api::CalibratedTrackerHit,
+}
+
+#[pymethods]
+impl PyCalibratedTrackerHit {
+  #[new]
+  fn new() -> Self {
+    Self {
+      hit : trk_api::CalibratedTrackerHit::new(),
+    }
+  }
+
+  #[getter]
+  fn layer(&self) -> u8 {
+    self.hit.layer
+  }
+
+  #[getter]
+  fn row(&self) -> u8 {
+    self.hit.row
+  }
+
+  #[getter]
+  fn module(&self) -> u8 {
+    self.hit.module
+  }
+
+  #[getter]
+  fn channel(&self) -> u8 {
+    self.hit.channel
+  }
+
+  /// The raw adc value
+  #[getter]
+  fn adc(&self) -> u16 {
+    self.hit.adc
+  }
+
+  #[getter]
+  fn pedestal(&self) -> f32 {
+    self.hit.pedestal
+  }
+
+  #[getter]
+  fn sigma(&self) -> f32 {
+    self.hit.sigma
+  }
+
+  /// Pedestal subtracted adc value
+  #[getter]
+  fn energy(&self) -> f32 {
+    self.hit.energy
+  }
+
+  /// Pedestal subtracted adc value in units
+  /// of the pedestal width
+  #[getter]
+  fn significance(&self) -> f32 {
+    self.hit.get_significance()
+  }
+
+  #[getter]
+  fn flags(&self) -> u8 {
+    self.hit.flags
+  }
+
+  #[getter]
+  fn is_good(&self) -> bool {
+    self.hit.is_good()
+  }
+
+  #[getter]
+  fn is_masked(&self) -> bool {
+    self.hit.is_masked()
+  }
+
+  #[getter]
+  fn is_noisy(&self) -> bool {
+    self.hit.is_noisy()
+  }
+  
+  #[getter]
+  fn is_below_threshold(&self) -> bool {
+    self.hit.is_below_threshold()
+  }
+
+  fn __repr__(&self) -> PyResult<String> {
+    Ok(format!("<PyO3Wrapper: {}>", self.hit))
+  }
+}
+
+/// Pedestals and channel mask for the tracker 
+/// strips.
+///
+/// # Arguments:
+///
+/// * pedestal_file : text file with columns 
+///                   Layer Row Module Channel Pedestal Sigma
+/// * mask_file     : json file with the channel mask per 
+///                   module
+#[pyclass]
+#[pyo3(name="TrackerCalibration")]
+pub struct PyTrackerCalibration {
+  cali : trk_api::TrackerCalibration,
+}
+
+#[pymethods]
+impl PyTrackerCalibration {
+  #[new]
+  #[pyo3(signature = (pedestal_file = None, mask_file = None))]
+  fn new(pedestal_file : Option<String>, mask_file : Option<String>) -> PyResult<Self> {
+    let mut cali = trk_api::TrackerCalibration::new();
+    if let Some(fname) = pedestal_file {
+      if let Err(err) = cali.load_pedestals(&fname) {
+        return Err(PyValueError::new_err(err.to_string()));
+      }
+    }
+    if let Some(fname) = mask_file {
+      if let Err(err) = cali.load_mask(&fname) {
+        return Err(PyValueError::new_err(err.to_string()));
+      }
+    }
+    Ok(Self {
+      cali
+    })
+  }
+
+  /// Hits need to exceed the pedestal by this many 
+  /// sigma
+  #[getter]
+  fn get_threshold_sigma(&self) -> f32 {
+    self.cali.threshold_sigma
+  }
+  
+  #[setter]
+  fn set_threshold_sigma(&mut self, value : f32) {
+    self.cali.threshold_sigma = value;
+  }
+
+  /// Strips with a pedestal width larger than 
+  /// this factor times the median width are 
+  /// considered noisy
+  #[getter]
+  fn get_noisy_factor(&self) -> f32 {
+    self.cali.noisy_factor
+  }
+  
+  #[setter]
+  fn set_noisy_factor(&mut self, value : f32) {
+    self.cali.noisy_factor = value;
+  }
+
+  #[getter]
+  fn median_sigma(&self) -> f32 {
+    self.cali.get_median_sigma()
+  }
+
+  #[getter]
+  fn n_bad_strips(&self) -> usize {
+    self.cali.get_n_bad_strips()
+  }
+
+  fn get_pedestal(&self, layer : u8, row : u8, module : u8, channel : u8) -> Option<(f32, f32)> {
+    self.cali.get_pedestal(layer, row, module, channel)
+  }
+
+  fn is_masked(&self, layer : u8, row : u8, module : u8, channel : u8) -> bool {
+    self.cali.is_masked(layer, row, module, channel)
+  }
+  
+  fn is_noisy(&self, layer : u8, row : u8, module : u8, channel : u8) -> bool {
+    self.cali.is_noisy(layer, row, module, channel)
+  }
+
+  fn calibrate(&self, layer : u8, row : u8, module : u8, channel : u8, adc : u16) -> PyCalibratedTrackerHit {
+    PyCalibratedTrackerHit {
+      hit : self.cali.calibrate(layer, row, module, channel, adc)
+    }
+  }
+
+  fn __repr__(&self) -> PyResult<String> {
+    Ok(format!("<PyO3Wrapper: {}>", self.cali))
+  }
+}
+
+/// Map tracker strips to positions in the 
+/// detector (mm), in the same frame as the 
+/// TOF paddles
+#[pyclass]
+#[pyo3(name="TrackerGeometry")]
+#[derive(Clone)]
+pub struct PyTrackerGeometry {
+  geo : trk_api::TrackerGeometry,
+}
+
+#[pymethods]
+impl PyTrackerGeometry {
+  #[new]
+  fn new() -> Self {
+    Self {
+      geo : trk_api::TrackerGeometry::new(),
+    }
+  }
+ 
+  /// Shift all positions by this amount
+  #[getter]
+  fn get_offset(&self) -> (f32, f32, f32) {
+    self.geo.offset
+  }
+  
+  #[setter]
+  fn set_offset(&mut self, value : (f32, f32, f32)) {
+    self.geo.offset = value;
+  }
+
+  fn get_module_position(&self, layer : u8, row : u8, module : u8) -> (f32, f32, f32) {
+    self.geo.get_module_position(layer, row, module)
+  }
+  
+  fn get_detector_position(&self, layer : u8, row : u8, module : u8, channel : u8) -> (f32, f32, f32) {
+    self.geo.get_detector_position(layer, row, module, channel)
+  }
+  
+  fn get_strip_position(&self, layer : u8, row : u8, module : u8, channel : u8) -> (f32, f32, f32) {
+    self.geo.get_strip_position(layer, row, module, channel)
+  }
 
+  fn __repr__(&self) -> PyResult<String> {
+    Ok(format!("<PyO3Wrapper: {}>", self.geo))
+  }
+}
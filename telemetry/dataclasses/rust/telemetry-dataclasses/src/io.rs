@@ -11,17 +11,22 @@ use std::path::Path;
 use std::collections::HashMap;
 use std::fs::OpenOptions;
 use std::io::Read;
+use std::io::Write;
 use std::io::ErrorKind;
 use regex::Regex;
+use chrono::{
+  DateTime,
+  Duration,
+  Utc,
+};
 
 use log::{
   debug,
+  info,
   error
 };
 
-use tof_dataclasses::io::read_file;
 use tof_dataclasses::serialization::{
-  search_for_u16,
   Serialization,
   //parse_u16,
   parse_u32,
@@ -31,7 +36,6 @@ use crate::packets::{
   TelemetryHeader,
   TelemetryPacket,
   MergedEvent,
-  TrackerPacket,
   GapsEvent,
 };
 use tof_dataclasses::packets::{
@@ -41,100 +45,15 @@ use tof_dataclasses::packets::{
 use tof_dataclasses::events::TofEventSummary;
 use crate::packets::TelemetryPacketType;
 
-/// Extract all merged events from a file and ignore all others
+/// Extract all merged events from a file or directory and 
+/// ignore all others
+///
+/// This reads all events into memory. For large runs, 
+/// use the GapsEventReader directly.
 pub fn get_gaps_events(filename : String) -> Vec<GapsEvent> {
-  let mut events = Vec::<GapsEvent>::new();
-  let stream = read_file(Path::new(&filename)).expect("Unable to open input file!");
-  let mut pos : usize = 0;
-  //let mut npackets : usize = 0;
-  let mut packet_types = Vec::<u8>::new();
-  loop {
-    match TelemetryHeader::from_bytestream(&stream, &mut pos) {
-      Err(err) => {
-        println!("Can not decode telemtry header! {err}");
-        //for k in pos - 5 .. pos + 5 {
-        //  println!("{}",stream[k]);
-        //}
-        match search_for_u16(0x90eb, &stream, pos) {
-          Err(err) => {
-            println!("Unable to find next header! {err}");
-            break;
-          }
-          Ok(head_pos) => {
-            pos = head_pos;
-          }
-        }
-      }
-      Ok(header) => {
-        println!("HEADER {}", header);
-        //for k in pos - 10 .. pos + 10 {
-        //  println!("{}",stream[k]);
-        //}
-        if header.ptype == 80 {
-          match TrackerPacket::from_bytestream(&stream, &mut pos) {
-            Err(err) => {
-              //for k in pos - 5 .. pos + 5 {
-              //  println!("{}",stream[k]);
-              //}
-              println!("Unable to decode TrackerPacket! {err}");
-            }
-            Ok(mut tp) => {
-              tp.telemetry_header = header;
-              println!("{}", tp);
-            }
-          }
-        }
-        if header.ptype == 90 {
-          match MergedEvent::from_bytestream(&stream, &mut pos) {
-            Err(err) => {
-              println!("Unable to decode MergedEvent! {err}");
-            }
-            Ok(mut me) => {
-              me.header  = header;
-              let mut g_event = GapsEvent::new();
-              //println!("Event ID  : {}", me.event_id);
-              //println!("Tof bytes : {:?}", me.tof_data);
-              //println!("len tof bytes : {}", me.tof_data.len());
-              match TofPacket::from_bytestream(&me.tof_data, &mut 0) {
-                Err(err) => {
-                  println!("Can't unpack TofPacket! {err}");
-                }
-                Ok(tp) => {
-                  println!("{}", tp);
-                  if tp.packet_type == PacketType::TofEventSummary {
-                    match TofEventSummary::from_tofpacket(&tp) {
-                      Err(err) => println!("Can't unpack TofEventSummary! {err}"),
-                      Ok(ts) => {
-                        println!("{}", ts);
-                        g_event.tof = ts;
-                      }
-                    }
-                  }
-                }
-              }
-              g_event.tracker = me.tracker_events;
-              events.push(g_event)
-            }
-          }
-        }
-        //npackets += 1;
-        packet_types.push(header.ptype);
-        match search_for_u16(0x90eb, &stream, pos) {
-          Err(err) => {
-            println!("Unable to find next header! {err}");
-            break;
-          }
-          Ok(head_pos) => {
-            pos = head_pos;
-          }
-        }
-      }
-    }
-  }
-  events
+  GapsEventReader::new(filename).collect()
 }
 
-
 /// Read serialized TelemetryPackets from an existing file
 ///
 /// Read GAPS binary files ("Berkeley binaries)
@@ -584,4 +503,283 @@ impl Iterator for TelemetryPacketReader {
   }
 }
 
+/// Write TelemetryPackets to disk.
+///
+/// The files follow the naming convention of the 
+/// flight computer ("RAWYYMMDD_HHMMSS.bin"), so that 
+/// a directory written by this writer can be read 
+/// back with the TelemetryPacketReader.
+pub struct TelemetryPacketWriter {
+  pub file            : File,
+  /// location to store the files
+  pub file_path       : String,
+  /// The maximum number of packets 
+  /// for a single file. After this 
+  /// number is reached, a new 
+  /// file is started.
+  pub pkts_per_file   : usize,
+  /// The maximum number of (Mega)bytes
+  /// per file. After this a new file 
+  /// is started
+  pub mbytes_per_file : usize,
+  pub file_name       : String,
+  /// The time encoded in the filename of 
+  /// the first file. Subsequent files will 
+  /// be 1 second apart, to keep the names 
+  /// unique and ordered.
+  start_time          : DateTime<Utc>,
+  file_id             : usize,
+  /// internal packet counter, number of 
+  /// packets which went through the writer
+  n_packets           : usize,
+  /// internal counter for bytes written in 
+  /// this file
+  file_nbytes_wr      : usize,
+}
+
+impl TelemetryPacketWriter {
+
+  /// Instantiate a new TelemetryPacketWriter
+  ///
+  /// # Arguments
+  ///
+  /// * file_path : Directory where the files will be 
+  ///               written to
+  pub fn new(mut file_path : String) -> Self {
+    if !file_path.ends_with("/") {
+      file_path += "/";
+    }
+    let start_time = Utc::now();
+    let file_name  = Self::get_filename(&file_path, &start_time, 0);
+    let file       = Self::open_file(&file_name);
+    Self {
+      file,
+      file_path,
+      pkts_per_file    : 0,
+      mbytes_per_file  : 420,
+      file_name,
+      start_time,
+      file_id          : 0,
+      n_packets        : 0,
+      file_nbytes_wr   : 0,
+    }
+  }
+  
+  fn get_filename(file_path : &str, start_time : &DateTime<Utc>, file_id : usize) -> String {
+    let ftime = *start_time + Duration::seconds(file_id as i64);
+    format!("{}RAW{}.bin", file_path, ftime.format("%y%m%d_%H%M%S"))
+  }
+
+  fn open_file(filename : &str) -> File {
+    let path = Path::new(filename);
+    info!("Writing to file {filename}");
+    OpenOptions::new().create(true).append(true).open(path).expect("Unable to open file {filename}")
+  }
+
+  /// Induce serialization to disk for a TelemetryPacket
+  pub fn add_telemetry_packet(&mut self, packet : &TelemetryPacket) {
+    let buffer = packet.to_bytestream();
+    self.file_nbytes_wr += buffer.len();
+    match self.file.write_all(buffer.as_slice()) {
+      Err(err) => error!("Writing to file {} failed! {}", self.file_name, err),
+      Ok(_)    => ()
+    }
+    self.n_packets += 1;
+    let mut newfile = false;
+    if self.pkts_per_file != 0 {
+      if self.n_packets == self.pkts_per_file {
+        newfile = true;
+        self.n_packets = 0;
+      }
+    } else if self.mbytes_per_file != 0 {
+      // multiply by mebibyte
+      if self.file_nbytes_wr >= self.mbytes_per_file * 1_048_576 {
+        newfile = true;
+        self.file_nbytes_wr = 0;
+      }
+    }
+    if newfile {
+      match self.file.sync_all() {
+        Err(err) => {
+          error!("Unable to sync file to disc! {err}");
+        },
+        Ok(_) => ()
+      }
+      self.file_id  += 1;
+      self.file_name = Self::get_filename(&self.file_path, &self.start_time, self.file_id);
+      self.file      = Self::open_file(&self.file_name);
+    }
+    debug!("TelemetryPacket written!");
+  }
+}
+
+/// Counters for the GapsEventReader
+///
+/// Packets which can not be decoded are not 
+/// reported individually, but counted here.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct GapsEventReaderStatistics {
+  /// Number of merged event packets seen 
+  /// (BoringEvent, InterestingEvent, 
+  /// NoGapsTriggerEvent, NoTofDataEvent)
+  pub n_merged_events   : usize,
+  /// Number of emitted GapsEvents
+  pub n_gaps_events     : usize,
+  /// MergedEvents which could not be decoded.
+  /// These are skipped.
+  pub n_err_merged      : usize,
+  /// MergedEvents without any TOF data
+  pub n_no_tof_data     : usize,
+  /// The TOF data could not be decoded as 
+  /// a TofPacket
+  pub n_err_tofpacket   : usize,
+  /// The TofPacket is not a TofEventSummary
+  pub n_err_packet_type : usize,
+  /// The TofEventSummary could not be decoded
+  pub n_err_tofsummary  : usize,
+}
 
+impl GapsEventReaderStatistics {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Total number of decoding errors
+  pub fn get_n_errors(&self) -> usize {
+    self.n_err_merged + self.n_err_tofpacket + self.n_err_packet_type + self.n_err_tofsummary
+  }
+}
+
+impl fmt::Display for GapsEventReaderStatistics {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<GapsEventReaderStatistics:");
+    repr += &(format!("\n  merged events seen            : {}", self.n_merged_events));
+    repr += &(format!("\n  gaps events emitted           : {}", self.n_gaps_events));
+    repr += &(format!("\n  no TOF data                   : {}", self.n_no_tof_data));
+    repr += "\n  -- decoding errors --";
+    repr += &(format!("\n  MergedEvent                   : {}", self.n_err_merged));
+    repr += &(format!("\n  TofPacket                     : {}", self.n_err_tofpacket));
+    repr += &(format!("\n  TofPacket not TofEventSummary : {}", self.n_err_packet_type));
+    repr += &(format!("\n  TofEventSummary               : {}>", self.n_err_tofsummary));
+    write!(f, "{}", repr)
+  }
+}
+
+/// Stream combined TOF + tracker events (GapsEvent)
+/// from telemetry files.
+///
+/// This reads the merged event packets with a 
+/// TelemetryPacketReader, so it works for a single
+/// file as well as for a directory of 
+/// "RAWYYMMDD_HHMMSS.bin" files.
+/// 
+/// MergedEvents which can not be decoded are 
+/// skipped. If only the TOF part can not be decoded,
+/// the event is still emitted with the tracker 
+/// information and an empty TofEventSummary. In both
+/// cases, the error is counted in the statistics.
+pub struct GapsEventReader {
+  /// The underlying packet reader. Use it to 
+  /// e.g. skip ahead
+  pub packet_reader : TelemetryPacketReader,
+  /// Decoding statistics
+  pub stats         : GapsEventReaderStatistics,
+}
+
+impl GapsEventReader {
+
+  pub fn new(filename_or_directory : String) -> Self {
+    Self {
+      packet_reader : TelemetryPacketReader::new(filename_or_directory),
+      stats         : GapsEventReaderStatistics::new(),
+    }
+  }
+
+  /// Start reading from the beginning of the first file
+  /// again. This resets the statistics.
+  pub fn rewind(&mut self) -> io::Result<()> {
+    self.stats = GapsEventReaderStatistics::new();
+    self.packet_reader.rewind()
+  }
+  
+  /// Check if a packet contains a MergedEvent
+  fn is_merged_event(ptype : TelemetryPacketType) -> bool {
+    matches!(ptype,
+             TelemetryPacketType::BoringEvent
+           | TelemetryPacketType::InterestingEvent
+           | TelemetryPacketType::NoGapsTriggerEvent
+           | TelemetryPacketType::NoTofDataEvent)
+  }
+
+  /// Assemble the GapsEvent from a decoded MergedEvent
+  fn unpack(&mut self, me : &MergedEvent) -> GapsEvent {
+    let mut g_event = GapsEvent::from_mergedevent(me);
+    if me.tof_data.is_empty() {
+      self.stats.n_no_tof_data += 1;
+      return g_event;
+    }
+    match TofPacket::from_bytestream(&me.tof_data, &mut 0) {
+      Err(err) => {
+        debug!("Can't unpack TofPacket for event {}! {err}", me.event_id);
+        self.stats.n_err_tofpacket += 1;
+      }
+      Ok(tp) => {
+        if tp.packet_type != PacketType::TofEventSummary {
+          debug!("Expected TofEventSummary, but got {}!", tp.packet_type);
+          self.stats.n_err_packet_type += 1;
+          return g_event;
+        }
+        match TofEventSummary::from_tofpacket(&tp) {
+          Err(err) => {
+            debug!("Can't unpack TofEventSummary for event {}! {err}", me.event_id);
+            self.stats.n_err_tofsummary += 1;
+          }
+          Ok(ts) => {
+            g_event.tof = ts;
+          }
+        }
+      }
+    }
+    g_event
+  }
+
+  /// Get the next GapsEvent
+  ///
+  /// Returns None if all files are exhausted
+  pub fn get_next_event(&mut self) -> Option<GapsEvent> {
+    loop {
+      let packet = self.packet_reader.get_next_packet()?;
+      if !Self::is_merged_event(TelemetryPacketType::from(packet.header.ptype)) {
+        continue;
+      }
+      self.stats.n_merged_events += 1;
+      match MergedEvent::from_bytestream(&packet.payload, &mut 0) {
+        Err(err) => {
+          debug!("Unable to decode MergedEvent! {err}");
+          self.stats.n_err_merged += 1;
+        }
+        Ok(mut me) => {
+          me.header   = packet.header;
+          let g_event = self.unpack(&me);
+          self.stats.n_gaps_events += 1;
+          return Some(g_event);
+        }
+      }
+    }
+  }
+}
+
+impl fmt::Display for GapsEventReader {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let repr = format!("<GapsEventReader : {} events, {} errors,\n  {}>", self.stats.n_gaps_events, self.stats.get_n_errors(), self.packet_reader);
+    write!(f, "{}", repr)
+  }
+}
+
+impl Iterator for GapsEventReader {
+  type Item = GapsEvent;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.get_next_event()
+  }
+}
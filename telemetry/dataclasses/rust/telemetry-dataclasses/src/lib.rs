@@ -4,6 +4,9 @@
 
 pub mod packets;
 pub mod io;
+pub mod tracker;
+pub mod timing;
+pub mod magfield;
 #[cfg(feature="caraspace-serial")]
 pub mod caraspace;
 
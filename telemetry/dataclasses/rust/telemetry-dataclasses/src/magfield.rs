@@ -0,0 +1,496 @@
+//! Magnetic field time series
+//!
+//! There are two sources of magnetic field readings:
+//!
+//! * The gondola magnetometer (MagnetoMeter packets),
+//!   which also provides the acceleration (gravity)
+//!   vector
+//! * The LIS3MDL sensors on the individual
+//!   ReadoutBoards (RBMoniData)
+//!
+//! Both are converted to MagFieldSamples in the
+//! payload frame, which can be collected in a
+//! MagFieldSeries. The conversion factors of the
+//! gondola magnetometer are not part of the 
+//! telemetry, they have to be loaded from a json
+//! file (see MagCalibration::for_gondola). Until
+//! then, its field (and orientation angles) stay 
+//! in raw counts. This is marked by 
+//! MagFieldSample::calibrated. The RB readings are
+//! always in µT. The series implements MoniSeries,
+//! so it can be exported the same way as the other
+//! monitoring series (e.g. as a polars dataframe).
+//!
+//! From the field and gravity vectors, the
+//! orientation of the payload with respect to the
+//! geomagnetic field is derived (field zenith, dip
+//! and heading), which is needed e.g. for cut-off
+//! rigidity studies. These only depend on the 
+//! directions of the vectors, so they are meaningful
+//! for raw readings as well, as long as the offsets
+//! are small compared to the field.
+//!
+//! The gondola calibration file has the keys
+//! "scale", "offset", "gain", "acc_scale" and
+//! "angle_scale" (see MagCalibration). Missing
+//! keys take the identity value, e.g.
+//!
+//! {"scale" : 0.1, "offset" : [1.5, -0.3, 2.0]}
+
+use std::fmt;
+use std::fs::File;
+use std::io::{
+  self,
+  BufReader,
+  ErrorKind,
+};
+use std::collections::{
+  HashMap,
+  VecDeque,
+};
+
+use log::info;
+
+use tof_dataclasses::monitoring::{
+  MoniData,
+  RBMoniData,
+};
+use tof_dataclasses::series::MoniSeries;
+
+use crate::packets::MagnetoMeter;
+
+/// The board id used for samples from the
+/// gondola magnetometer. The RB samples use
+/// the RB id.
+pub const GONDOLA_MAG_ID : u8 = 0;
+
+/// Conversion of magnetometer readings to µT
+///
+/// field = gain * (scale * raw - offset)
+///
+/// Fields missing in a calibration file take 
+/// their identity value (see new())
+#[derive(Debug, Copy, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct MagCalibration {
+  /// Conversion factor raw -> µT
+  pub scale       : f32,
+  /// Hard iron offset (µT)
+  pub offset      : [f32;3],
+  /// Soft iron correction (diagonal only)
+  pub gain        : [f32;3],
+  /// Conversion factor raw acceleration -> g
+  pub acc_scale   : f32,
+  /// Conversion factor raw roll/pitch/yaw -> deg
+  pub angle_scale : f32,
+  /// The factors are known, so that the field is
+  /// in µT. If not, the readings are raw counts.
+  /// This is not read from a calibration file
+  #[serde(skip)]
+  pub calibrated  : bool,
+}
+
+impl MagCalibration {
+
+  /// Identity calibration.
+  ///
+  /// With this calibration, the readings of the
+  /// gondola magnetometer stay in (signed) counts
+  /// and are not marked as calibrated
+  pub fn new() -> Self {
+    Self {
+      scale       : 1.0,
+      offset      : [0.0;3],
+      gain        : [1.0;3],
+      acc_scale   : 1.0,
+      angle_scale : 1.0,
+      calibrated  : false,
+    }
+  }
+
+  /// Calibration for the RB magnetometers,
+  /// which report Gauss
+  pub fn for_rb() -> Self {
+    let mut cali     = Self::new();
+    cali.scale       = 100.0;
+    cali.calibrated  = true;
+    cali
+  }
+
+  /// Calibration for the gondola magnetometer,
+  /// loaded from a json file (see module docs)
+  ///
+  /// # Arguments:
+  ///
+  /// * filename : json file with the conversion 
+  ///              factors
+  pub fn for_gondola(filename : &str) -> io::Result<Self> {
+    info!("Loading gondola magnetometer calibration from {filename}");
+    let file = BufReader::new(File::open(filename)?);
+    let mut cali : Self = match serde_json::from_reader(file) {
+      Err(err) => {
+        return Err(io::Error::new(ErrorKind::InvalidData, format!("Can not decode {}! {err}", filename)));
+      }
+      Ok(cali) => cali
+    };
+    cali.calibrated = true;
+    Ok(cali)
+  }
+
+  /// Apply the calibration to a raw field vector
+  pub fn apply(&self, raw : [f32;3]) -> [f32;3] {
+    let mut field = [0.0f32;3];
+    for k in 0..3 {
+      field[k] = self.gain[k]*(self.scale*raw[k] - self.offset[k]);
+    }
+    field
+  }
+}
+
+impl Default for MagCalibration {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for MagCalibration {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<MagCalibration:");
+    repr += &(format!("\n  scale       : {}", self.scale));
+    repr += &(format!("\n  offset      : {:?}", self.offset));
+    repr += &(format!("\n  gain        : {:?}", self.gain));
+    repr += &(format!("\n  acc scale   : {}", self.acc_scale));
+    repr += &(format!("\n  angle scale : {}", self.angle_scale));
+    repr += &(format!("\n  calibrated  : {}>", self.calibrated));
+    write!(f, "{}", repr)
+  }
+}
+
+/// A single magnetic field reading in the 
+/// payload frame
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MagFieldSample {
+  /// GONDOLA_MAG_ID or the RB id
+  pub board_id   : u8,
+  /// Time of the reading (s). This is the
+  /// GCU time for the gondola magnetometer
+  pub time       : f64,
+  /// Field components (µT if calibrated, else
+  /// raw counts)
+  pub b_x        : f32,
+  pub b_y        : f32,
+  pub b_z        : f32,
+  /// Gravity vector (g). NaN if there is no
+  /// accelerometer for this source
+  pub acc_x      : f32,
+  pub acc_y      : f32,
+  pub acc_z      : f32,
+  /// Orientation as reported by the gondola
+  /// magnetometer (deg if calibrated, else raw
+  /// counts). NaN for the RBs
+  pub roll       : f32,
+  pub pitch      : f32,
+  pub yaw        : f32,
+  /// The readings were converted with a known
+  /// calibration (see MagCalibration)
+  pub calibrated : bool,
+}
+
+impl MagFieldSample {
+
+  pub fn new() -> Self {
+    Self {
+      board_id   : 0,
+      time       : 0.0,
+      b_x        : 0.0,
+      b_y        : 0.0,
+      b_z        : 0.0,
+      acc_x      : f32::NAN,
+      acc_y      : f32::NAN,
+      acc_z      : f32::NAN,
+      roll       : f32::NAN,
+      pitch      : f32::NAN,
+      yaw        : f32::NAN,
+      calibrated : false,
+    }
+  }
+
+  /// Reading of the gondola magnetometer
+  pub fn from_magnetometer(mag : &MagnetoMeter, cali : &MagCalibration) -> Self {
+    let raw   = [mag.mag_x as i16 as f32, mag.mag_y as i16 as f32, mag.mag_z as i16 as f32];
+    let field = cali.apply(raw);
+    Self {
+      board_id   : GONDOLA_MAG_ID,
+      time       : mag.header.get_gcutime(),
+      b_x        : field[0],
+      b_y        : field[1],
+      b_z        : field[2],
+      acc_x      : cali.acc_scale*(mag.acc_x as i16 as f32),
+      acc_y      : cali.acc_scale*(mag.acc_y as i16 as f32),
+      acc_z      : cali.acc_scale*(mag.acc_z as i16 as f32),
+      roll       : cali.angle_scale*(mag.roll as i16 as f32),
+      pitch      : cali.angle_scale*(mag.pitch as i16 as f32),
+      yaw        : cali.angle_scale*(mag.yaw as i16 as f32),
+      calibrated : cali.calibrated,
+    }
+  }
+
+  /// Reading of a RB magnetometer.
+  ///
+  /// RBMoniData does not carry a timestamp, so
+  /// the time (e.g. of the enclosing packet)
+  /// has to be given
+  pub fn from_rbmonidata(moni : &RBMoniData, time : f64, cali : &MagCalibration) -> Self {
+    let field = cali.apply([moni.mag_x, moni.mag_y, moni.mag_z]);
+    let mut sample = Self::new();
+    sample.board_id = moni.board_id;
+    sample.time     = time;
+    sample.b_x      = field[0];
+    sample.b_y      = field[1];
+    sample.b_z      = field[2];
+    sample.calibrated = cali.calibrated;
+    sample
+  }
+
+  pub fn get_b_tot(&self) -> f32 {
+    (self.b_x.powi(2) + self.b_y.powi(2) + self.b_z.powi(2)).sqrt()
+  }
+
+  /// The direction of "down" in the payload frame
+  ///
+  /// If there is no accelerometer reading, the
+  /// payload is assumed to be level
+  fn get_down(&self) -> [f32;3] {
+    let acc_tot = (self.acc_x.powi(2) + self.acc_y.powi(2) + self.acc_z.powi(2)).sqrt();
+    if acc_tot.is_nan() || acc_tot == 0.0 {
+      return [0.0, 0.0, -1.0];
+    }
+    [self.acc_x/acc_tot, self.acc_y/acc_tot, self.acc_z/acc_tot]
+  }
+
+  /// Angle between the field and the payload z-axis
+  /// (deg)
+  pub fn get_field_zenith(&self) -> f32 {
+    let b_tot = self.get_b_tot();
+    if b_tot == 0.0 {
+      return f32::NAN;
+    }
+    (self.b_z/b_tot).clamp(-1.0, 1.0).acos().to_degrees()
+  }
+
+  /// Inclination of the field with respect to the
+  /// local horizontal (deg), positive if the field
+  /// points downwards
+  pub fn get_dip(&self) -> f32 {
+    let b_tot = self.get_b_tot();
+    if b_tot == 0.0 {
+      return f32::NAN;
+    }
+    let down = self.get_down();
+    let b_down = self.b_x*down[0] + self.b_y*down[1] + self.b_z*down[2];
+    (b_down/b_tot).clamp(-1.0, 1.0).asin().to_degrees()
+  }
+
+  /// Azimuth (deg) of the horizontal component of the
+  /// field (magnetic north) in the payload frame,
+  /// measured from the payload x-axis towards the
+  /// y-axis. Range [0, 360)
+  pub fn get_heading(&self) -> f32 {
+    let down   = self.get_down();
+    let b      = [self.b_x, self.b_y, self.b_z];
+    let b_down = b[0]*down[0] + b[1]*down[1] + b[2]*down[2];
+    let b_hor  = [b[0] - b_down*down[0], b[1] - b_down*down[1], b[2] - b_down*down[2]];
+    // the payload x-axis, projected to the horizontal
+    let x_down = down[0];
+    let x_hor  = [1.0 - x_down*down[0], -x_down*down[1], -x_down*down[2]];
+    // and the corresponding y-axis in the horizontal plane
+    let y_hor  = [x_hor[1]*down[2] - x_hor[2]*down[1],
+                  x_hor[2]*down[0] - x_hor[0]*down[2],
+                  x_hor[0]*down[1] - x_hor[1]*down[0]];
+    let bx = b_hor[0]*x_hor[0] + b_hor[1]*x_hor[1] + b_hor[2]*x_hor[2];
+    let by = b_hor[0]*y_hor[0] + b_hor[1]*y_hor[1] + b_hor[2]*y_hor[2];
+    if bx == 0.0 && by == 0.0 {
+      return f32::NAN;
+    }
+    by.atan2(bx).to_degrees().rem_euclid(360.0)
+  }
+}
+
+impl Default for MagFieldSample {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for MagFieldSample {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let (b_unit, angle_unit) = if self.calibrated {
+      ("µT", "deg")
+    } else {
+      ("raw", "raw")
+    };
+    let mut repr = String::from("<MagFieldSample:");
+    repr += &(format!("\n  board id     : {}", self.board_id));
+    repr += &(format!("\n  time         : {:.3}", self.time));
+    repr += &(format!("\n  B (x,y,z)    : {:.3} {:.3} {:.3} [{}]", self.b_x, self.b_y, self.b_z, b_unit));
+    repr += &(format!("\n  |B|          : {:.3} [{}]", self.get_b_tot(), b_unit));
+    repr += &(format!("\n  acc (x,y,z)  : {:.3} {:.3} {:.3} [g]", self.acc_x, self.acc_y, self.acc_z));
+    repr += &(format!("\n  r/p/yaw      : {:.2} {:.2} {:.2} [{}]", self.roll, self.pitch, self.yaw, angle_unit));
+    repr += &(format!("\n  field zenith : {:.2} [deg]", self.get_field_zenith()));
+    repr += &(format!("\n  dip          : {:.2} [deg]", self.get_dip()));
+    repr += &(format!("\n  heading      : {:.2} [deg]>", self.get_heading()));
+    write!(f, "{}", repr)
+  }
+}
+
+impl MoniData for MagFieldSample {
+
+  fn get_board_id(&self) -> u8 {
+    self.board_id
+  }
+
+  /// Access the (data) members by name
+  ///
+  /// The time is not available here, since it
+  /// would lose its precision as f32. Use
+  /// MagFieldSeries::get_times instead
+  fn get(&self, varname : &str) -> Option<f32> {
+    match varname {
+      "board_id"     => Some(self.board_id as f32),
+      "b_x"          => Some(self.b_x),
+      "b_y"          => Some(self.b_y),
+      "b_z"          => Some(self.b_z),
+      "b_tot"        => Some(self.get_b_tot()),
+      "acc_x"        => Some(self.acc_x),
+      "acc_y"        => Some(self.acc_y),
+      "acc_z"        => Some(self.acc_z),
+      "roll"         => Some(self.roll),
+      "pitch"        => Some(self.pitch),
+      "yaw"          => Some(self.yaw),
+      "field_zenith" => Some(self.get_field_zenith()),
+      "dip"          => Some(self.get_dip()),
+      "heading"      => Some(self.get_heading()),
+      _              => None
+    }
+  }
+
+  /// A list of the variables in this MoniData
+  fn keys() -> Vec<&'static str> {
+    vec![
+      "board_id",
+      "b_x",
+      "b_y",
+      "b_z",
+      "b_tot",
+      "acc_x",
+      "acc_y",
+      "acc_z",
+      "roll",
+      "pitch",
+      "yaw",
+      "field_zenith",
+      "dip",
+      "heading",
+    ]
+  }
+}
+
+////////////////////////////////////////////////////////////////////
+
+/// Time series of magnetic field readings of
+/// all sources
+#[derive(Debug, Clone, PartialEq)]
+pub struct MagFieldSeries {
+  data             : HashMap<u8, VecDeque<MagFieldSample>>,
+  pub max_size     : usize,
+  /// Calibration for the gondola magnetometer.
+  /// This is the identity unless loaded with 
+  /// load_gondola_calibration, so the gondola 
+  /// readings are raw counts
+  pub gondola_cali : MagCalibration,
+  /// Calibration for the RB magnetometers
+  pub rb_cali      : MagCalibration,
+}
+
+impl MagFieldSeries {
+  pub fn new() -> Self {
+    Self {
+      data         : HashMap::<u8, VecDeque<MagFieldSample>>::new(),
+      max_size     : 10000,
+      gondola_cali : MagCalibration::new(),
+      rb_cali      : MagCalibration::for_rb(),
+    }
+  }
+
+  /// Load the conversion factors for the gondola
+  /// magnetometer (see MagCalibration::for_gondola). 
+  /// This applies to samples added afterwards.
+  pub fn load_gondola_calibration(&mut self, filename : &str) -> io::Result<()> {
+    self.gondola_cali = MagCalibration::for_gondola(filename)?;
+    Ok(())
+  }
+
+  pub fn add_magnetometer(&mut self, mag : &MagnetoMeter) {
+    let sample = MagFieldSample::from_magnetometer(mag, &self.gondola_cali);
+    self.add(sample);
+  }
+
+  pub fn add_rbmonidata(&mut self, moni : &RBMoniData, time : f64) {
+    let sample = MagFieldSample::from_rbmonidata(moni, time, &self.rb_cali);
+    self.add(sample);
+  }
+
+  /// The times of the samples for a single board
+  pub fn get_times(&self, board_id : u8) -> Option<Vec<f64>> {
+    Some(self.data.get(&board_id)?.iter().map(|s| s.time).collect())
+  }
+
+  /// A variable for a single board, smoothed with
+  /// a centered moving average
+  ///
+  /// # Arguments:
+  ///
+  /// * varname  : see MagFieldSample::keys
+  /// * board_id : GONDOLA_MAG_ID or RB id
+  /// * window   : number of samples to average.
+  ///              At the edges of the series, the
+  ///              window is truncated.
+  pub fn get_smoothed(&self, varname : &str, board_id : u8, window : usize) -> Option<Vec<f32>> {
+    let values = self.get_var_for_board(varname, &board_id)?;
+    let half   = window/2;
+    let mut smoothed = Vec::<f32>::with_capacity(values.len());
+    for k in 0..values.len() {
+      let first = k.saturating_sub(half);
+      let last  = usize::min(k + half + 1, values.len());
+      let slice = &values[first..last];
+      smoothed.push(slice.iter().sum::<f32>()/(slice.len() as f32));
+    }
+    Some(smoothed)
+  }
+}
+
+impl Default for MagFieldSeries {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for MagFieldSeries {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "<MagFieldSeries : {} sources>", self.data.len())
+  }
+}
+
+impl MoniSeries<MagFieldSample> for MagFieldSeries {
+  fn get_data(&self) -> &HashMap<u8,VecDeque<MagFieldSample>> {
+    &self.data
+  }
+
+  fn get_data_mut(&mut self) -> &mut HashMap<u8,VecDeque<MagFieldSample>> {
+    &mut self.data
+  }
+
+  fn get_max_size(&self) -> usize {
+    self.max_size
+  }
+}
@@ -23,6 +23,7 @@ use tof_dataclasses::serialization::{
 };
 
 use tof_dataclasses::events::TofEventSummary;
+use crate::tracker::TrackerGeometry;
 use tof_dataclasses::packets::{
   TofPacket,
   PacketType
@@ -31,6 +32,11 @@ use tof_dataclasses::packets::{
 #[cfg(feature = "pybindings")]
 use pyo3::pyclass;
 
+#[cfg(feature = "random")]
+use tof_dataclasses::FromRandom;
+#[cfg(feature = "random")]
+use rand::Rng;
+
 /// Recreate 48bit timestamp from u32 and u16
 pub fn make_systime(lower : u32, upper : u16) -> u64 {
   (upper as u64) << 32 | lower as u64
@@ -134,6 +140,25 @@ impl TelemetryPacket {
     Ok(tpacket)
   }
 
+  /// Wrap a payload into a TelemetryPacket
+  ///
+  /// The length field of the header will be 
+  /// adjusted so that it matches the payload
+  ///
+  /// # Arguments:
+  ///
+  /// * header  : the header will be copied, sync 
+  ///             and length will be set
+  /// * payload : the serialized packet body
+  pub fn from_payload(header : &TelemetryHeader, payload : Vec<u8>) -> Self {
+    let mut tpacket   = TelemetryPacket::new();
+    tpacket.header    = *header;
+    tpacket.header.sync   = TelemetryHeader::HEAD;
+    tpacket.header.length = (payload.len() + TelemetryHeader::SIZE) as u16;
+    tpacket.payload   = payload;
+    tpacket
+  }
+
   // FIXME - this needs to be a trait
   pub fn to_bytestream(&self) -> Vec<u8> {
     let mut stream = Vec::<u8>::new();
@@ -145,6 +170,20 @@ impl TelemetryPacket {
   }
 }
 
+#[cfg(feature = "random")]
+impl FromRandom for TelemetryPacket {
+  fn from_random() -> Self {
+    let mut rng     = rand::thread_rng();
+    let header      = TelemetryHeader::from_random();
+    let n_payload   = rng.gen_range(0..1000usize);
+    let mut payload = Vec::<u8>::with_capacity(n_payload);
+    for _ in 0..n_payload {
+      payload.push(rng.gen::<u8>());
+    }
+    TelemetryPacket::from_payload(&header, payload)
+  }
+}
+
 impl fmt::Display for TelemetryPacket {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     let mut repr = String::from("<TelemetryPacket:");
@@ -208,6 +247,19 @@ impl TelemetryHeader {
   }
 }
 
+#[cfg(feature = "random")]
+impl FromRandom for TelemetryHeader {
+  fn from_random() -> Self {
+    let mut rng    = rand::thread_rng();
+    let mut header = TelemetryHeader::forge(rng.gen::<u8>());
+    header.timestamp = rng.gen::<u32>();
+    header.counter   = rng.gen::<u16>();
+    header.length    = rng.gen::<u16>();
+    header.checksum  = rng.gen::<u16>();
+    header
+  }
+}
+
 impl Serialization for TelemetryHeader {
   
   const HEAD : u16 = 0x90eb;
@@ -341,9 +393,28 @@ impl fmt::Display for AckBfsw {
   }
 }
 
+/// The combined TOF + tracker event as it is 
+/// assembled by the flight computer and sent 
+/// down via telemetry.
+///
+/// The layout of the serialized event depends on 
+/// the version (first byte):
+///
+/// * version 0 : version, flags0, flags1, event id, 
+///               TOF section, tracker section with 
+///               TrackerEvents 
+/// * version 1 : version, flags0, creation time (u64), 
+///               event id, TOF section, tracker section
+///               with TrackerHitV2 followed by the 
+///               tracker oscillators
+///
+/// Other versions are rejected by the decoder.
+#[derive(Debug, Clone, PartialEq)]
 pub struct MergedEvent {
   
   pub header              : TelemetryHeader,
+  /// Time when the event was assembled by the 
+  /// flight computer (version 1+ only)
   pub creation_time       : u64,
   pub event_id            : u32,
   pub tracker_events      : Vec<TrackerEvent>,
@@ -352,14 +423,33 @@ pub struct MergedEvent {
   /// (TrackerHitV2)
   pub tracker_hitsv2      : Vec<TrackerHitV2>,
   pub tracker_oscillators : Vec<u64>,
+  /// Bitmask indicating which of the (first 8) 
+  /// tracker_oscillators are present in the 
+  /// stream (version 1+ only)
+  pub tracker_osc_flags   : u8,
+  /// The delimiter byte in front of the TOF
+  /// section
+  pub tof_delimiter       : u8,
   pub tof_data            : Vec<u8>,
   pub raw_data            : Vec<u8>,
   pub flags0              : u8,
+  /// Only present in version 0
   pub flags1              : u8,
   pub version             : u8
 }
 
 impl MergedEvent {
+  
+  /// Delimiter for the tracker section
+  pub const TRK_DELIMITER : u8 = 0xbb;
+  /// Delimiter in front of the tracker oscillators
+  pub const OSC_DELIMITER : u8 = 0xcc;
+  /// Delimiter in front of the TOF section as 
+  /// written by the encoder. It is not checked 
+  /// by the decoder.
+  pub const TOF_DELIMITER : u8 = 0xaa;
+  /// The latest version the decoder understands
+  pub const MAX_VERSION   : u8 = 1;
 
   pub fn new() -> Self {
     let mut tracker_oscillators = Vec::<u64>::new();
@@ -373,6 +463,8 @@ impl MergedEvent {
       tracker_events      : Vec::<TrackerEvent>::new(),
       tracker_hitsv2      : Vec::<TrackerHitV2>::new(),
       tracker_oscillators : tracker_oscillators,
+      tracker_osc_flags   : 0,
+      tof_delimiter       : 0,
       tof_data            : Vec::<u8>::new(),
       raw_data            : Vec::<u8>::new(),
       flags0              : 0,
@@ -402,121 +494,252 @@ impl MergedEvent {
     }
   }
 
-
   pub fn from_bytestream(stream : &Vec<u8>,
                          pos    : &mut usize)
     -> Result<Self, SerializationError> {
-    let mut me        = MergedEvent::new();
-    let version      = parse_u8(stream, pos);
-    me.version       = version;
-    //println!("_version {}", _version);
-    me.flags0         = parse_u8(stream, pos);
-    // skip a bunch of Alex newly implemented things
-    // FIXME
-    if version == 0 {
-      me.flags1      = parse_u8(stream, pos);
-    } else {
-      *pos += 8;
+    if stream.len() < *pos + 1 {
+      return Err(SerializationError::StreamTooShort);
+    }
+    let mut me    = MergedEvent::new();
+    me.version    = parse_u8(stream, pos);
+    match me.version {
+      0 => {
+        me.decode_header_v0(stream, pos)?;
+        me.decode_tof(stream, pos)?;
+        me.decode_tracker_v0(stream, pos)?;
+      }
+      1 => {
+        me.decode_header_v1(stream, pos)?;
+        me.decode_tof(stream, pos)?;
+        me.decode_tracker_v1(stream, pos)?;
+      }
+      _ => {
+        error!("Unsupported MergedEvent version {}! We understand versions up to {}", me.version, Self::MAX_VERSION);
+        return Err(SerializationError::UnsupportedVersion);
+      }
+    }
+    Ok(me)
+  }
+
+  /// flags0, flags1, event id
+  fn decode_header_v0(&mut self, stream : &Vec<u8>, pos : &mut usize)
+    -> Result<(), SerializationError> {
+    if stream.len() < *pos + 6 {
+      return Err(SerializationError::StreamTooShort);
+    }
+    self.flags0   = parse_u8 (stream, pos);
+    self.flags1   = parse_u8 (stream, pos);
+    self.event_id = parse_u32(stream, pos);
+    Ok(())
+  }
+  
+  /// flags0, creation time, event id
+  fn decode_header_v1(&mut self, stream : &Vec<u8>, pos : &mut usize)
+    -> Result<(), SerializationError> {
+    if stream.len() < *pos + 13 {
+      return Err(SerializationError::StreamTooShort);
     }
+    self.flags0        = parse_u8 (stream, pos);
+    self.creation_time = parse_u64(stream, pos);
+    self.event_id      = parse_u32(stream, pos);
+    Ok(())
+  }
 
-    me.event_id       = parse_u32(stream, pos);
-    //println!("EVENT ID {}", me.event_id);
-    let _tof_delim    = parse_u8(stream, pos);
-    //println!("TOF delim : {}", _tof_delim);
-    if stream.len() <= *pos + 2 {
+  /// The TOF section is the same for all versions
+  ///
+  /// delimiter, number of bytes (u16), TofPacket
+  fn decode_tof(&mut self, stream : &Vec<u8>, pos : &mut usize) 
+    -> Result<(), SerializationError> {
+    if stream.len() < *pos + 3 {
       error!("Not able to parse merged event!");
       return Err(SerializationError::StreamTooShort);
     }
-    let num_tof_bytes = parse_u16(stream, pos);
-    //println!("Num TOF bytes : {}", num_tof_bytes);
-    if stream.len() < *pos+num_tof_bytes as usize {
-      error!("Not enough bytes for TOF packet! Expected {}, seen {}", *pos+num_tof_bytes as usize, stream.len());
+    self.tof_delimiter = parse_u8(stream, pos);
+    let num_tof_bytes  = parse_u16(stream, pos) as usize;
+    if stream.len() < *pos + num_tof_bytes {
+      error!("Not enough bytes for TOF packet! Expected {}, seen {}", *pos + num_tof_bytes, stream.len());
       return Err(SerializationError::StreamTooShort); 
     }
-    for _ in *pos..*pos+num_tof_bytes as usize {
-      me.tof_data.push(parse_u8(stream, pos));
-    }
-    let trk_delim    = parse_u8(stream, pos);
+    self.tof_data = stream[*pos..*pos + num_tof_bytes].to_vec();
+    *pos += num_tof_bytes;
+    Ok(())
+  }
 
-    //println!("TRK delim {}", trk_delim);
-    if trk_delim != 0xbb {
-      return Err(SerializationError::HeadInvalid);
+  /// Tracker section for version 0
+  ///
+  /// delimiter, number of bytes (u16), TrackerEvents
+  fn decode_tracker_v0(&mut self, stream : &Vec<u8>, pos : &mut usize) 
+    -> Result<(), SerializationError> {
+    if stream.len() < *pos + 3 {
+      return Err(SerializationError::StreamTooShort);
     }
-    if version == 1 {
-      let num_trk_hits = parse_u16(stream, pos);
-      if (*pos + (num_trk_hits as usize)*4 ) > stream.len() {
-        return Err(SerializationError::StreamTooShort);
+    let trk_delim = parse_u8(stream, pos);
+    if trk_delim != Self::TRK_DELIMITER {
+      error!("Tracker delimiter is {}, but should be {}!", trk_delim, Self::TRK_DELIMITER);
+      return Err(SerializationError::TrackerDelimiterInvalid);
+    }
+    let num_trk_bytes = parse_u16(stream, pos) as usize;
+    if *pos + num_trk_bytes > stream.len() {
+      return Err(SerializationError::StreamTooShort);
+    }
+    let max_pos = *pos + num_trk_bytes;
+    while *pos < max_pos {
+      let mut te = TrackerEvent::from_bytestream(stream, pos)?;
+      te.event_id = self.event_id;
+      self.tracker_events.push(te);
+    }
+    if *pos > max_pos {
+      error!("Tracker events exceed the tracker section by {} bytes!", *pos - max_pos);
+      return Err(SerializationError::StreamTooLong);
+    }
+    Ok(())
+  }
+  
+  /// Tracker section for version 1
+  ///
+  /// delimiter, number of hits (u16), TrackerHitV2, 
+  /// oscillator delimiter, oscillator flags, oscillators
+  fn decode_tracker_v1(&mut self, stream : &Vec<u8>, pos : &mut usize) 
+    -> Result<(), SerializationError> {
+    if stream.len() < *pos + 3 {
+      return Err(SerializationError::StreamTooShort);
+    }
+    let trk_delim = parse_u8(stream, pos);
+    if trk_delim != Self::TRK_DELIMITER {
+      error!("Tracker delimiter is {}, but should be {}!", trk_delim, Self::TRK_DELIMITER);
+      return Err(SerializationError::TrackerDelimiterInvalid);
+    }
+    let num_trk_hits = parse_u16(stream, pos) as usize;
+    // 4 bytes per hit and 2 bytes for the 
+    // oscillator delimiter and flags
+    if *pos + num_trk_hits*4 + 2 > stream.len() {
+      return Err(SerializationError::StreamTooShort);
+    }
+    for _ in 0..num_trk_hits { 
+      let strip_id = parse_u16(stream, pos);
+      let adc      = parse_u16(stream, pos);
+      self.tracker_hitsv2.push(TrackerHitV2::from_strip_id(strip_id, adc));
+    }
+    let osc_delim = parse_u8(stream, pos);
+    if osc_delim != Self::OSC_DELIMITER {
+      error!("Oscillator delimiter is {}, but should be {}!", osc_delim, Self::OSC_DELIMITER);
+      return Err(SerializationError::TrackerDelimiterInvalid);
+    }
+    self.tracker_osc_flags = parse_u8(stream, pos);
+    let n_osc = self.tracker_osc_flags.count_ones() as usize;
+    if *pos + n_osc*6 > stream.len() {
+      return Err(SerializationError::StreamTooShort);
+    }
+    for j in 0..8 {
+      if (self.tracker_osc_flags >> j) & 0b1 > 0 {
+        let lower = parse_u32(stream, pos);
+        let upper = parse_u16(stream, pos);
+        self.tracker_oscillators[j] = make_systime(lower, upper);
       }
-      for _ in 0..num_trk_hits { 
-        let mut hit = TrackerHitV2::new();
-        let strip_id = parse_u16(stream, pos);
-        let adc      = parse_u16(stream, pos);
-        hit.channel  = strip_id & 0b11111;
-        hit.module   = (strip_id >> 5) & 0b111;
-        hit.row      = (strip_id >> 8) & 0b111;
-        hit.layer    = (strip_id >> 11) & 0b1111;
-        hit.adc      = adc;
-        me.tracker_hitsv2.push(hit);
+    }
+    Ok(())
+  }
+  
+  /// Serialize to the payload of a telemetry packet
+  ///
+  /// This is the inverse of ::from_bytestream. The 
+  /// layout depends on self.version. For unsupported
+  /// versions, an empty vector is returned.
+  pub fn to_bytestream(&self) -> Vec<u8> {
+    let mut stream = Vec::<u8>::new();
+    stream.push(self.version);
+    match self.version {
+      0 => {
+        stream.push(self.flags0);
+        stream.push(self.flags1);
+        stream.extend_from_slice(&self.event_id.to_le_bytes());
       }
-      // oscillators
-      let oscillators_delimiter = parse_u8(stream, pos);
-      if oscillators_delimiter != 0xcc {
-        return Err(SerializationError::HeadInvalid);
+      1 => {
+        stream.push(self.flags0);
+        stream.extend_from_slice(&self.creation_time.to_le_bytes());
+        stream.extend_from_slice(&self.event_id.to_le_bytes());
       }
-      let osc_flags = parse_u8(stream, pos);
-      let mut oscillator_idx = Vec::<u8>::new();
-      for j in 0..8 {
-        if (osc_flags >> j & 0b1) > 0 {
-          oscillator_idx.push(j)
-        }
+      _ => {
+        error!("Unsupported MergedEvent version {}!", self.version);
+        return Vec::<u8>::new();
       }
-      if (*pos + oscillator_idx.len()*6) > stream.len() {
-        return Err(SerializationError::StreamTooShort);
+    }
+    stream.push(Self::TOF_DELIMITER);
+    stream.extend_from_slice(&(self.tof_data.len() as u16).to_le_bytes());
+    stream.extend_from_slice(self.tof_data.as_slice());
+    stream.push(Self::TRK_DELIMITER);
+    if self.version == 0 {
+      let mut trk_stream = Vec::<u8>::new();
+      for te in &self.tracker_events {
+        trk_stream.extend_from_slice(te.to_bytestream().as_slice());
       }
-      for idx in oscillator_idx.iter() {
-        let lower = parse_u32(stream, pos);
-        let upper = parse_u16(stream, pos);
-        let osc : u64 = (upper as u64) << 32 | (lower as u64);
-        me.tracker_oscillators[*idx as usize] = osc;
+      stream.extend_from_slice(&(trk_stream.len() as u16).to_le_bytes());
+      stream.extend_from_slice(trk_stream.as_slice());
+    } else {
+      stream.extend_from_slice(&(self.tracker_hitsv2.len() as u16).to_le_bytes());
+      for hit in &self.tracker_hitsv2 {
+        stream.extend_from_slice(&hit.get_strip_id().to_le_bytes());
+        stream.extend_from_slice(&hit.adc.to_le_bytes());
       }
-    } else if version == 0 {
-      let num_trk_bytes = parse_u16(stream, pos);
-      if (num_trk_bytes as usize + *pos - 2) > stream.len() {
-        return Err(SerializationError::StreamTooShort);
+      stream.push(Self::OSC_DELIMITER);
+      stream.push(self.tracker_osc_flags);
+      for j in 0..8 {
+        if (self.tracker_osc_flags >> j) & 0b1 > 0 {
+          let osc = self.tracker_oscillators[j];
+          stream.extend_from_slice(&((osc & 0xffffffff) as u32).to_le_bytes());
+          stream.extend_from_slice(&(((osc >> 32) & 0xffff) as u16).to_le_bytes());
+        }
       }
-      //println!("Num TRK bytes : {}", num_trk_bytes);
-      // for now, don't unpack tracker data
-      //*pos += num_trk_bytes as usize;
-      let max_pos = *pos + num_trk_bytes as usize;
-      loop {
-         //if *pos > max_pos {
-         //  //return Err(SerializationError::StreamTooLong);
-         //} 
-         if *pos >= max_pos {
-           break;
-         }
-         if *pos >= stream.len() {
-           break;
-         }
-         let mut te = TrackerEvent::from_bytestream(stream, pos)?;
-         //println!("{}",te);
-         te.event_id = me.event_id;
-         me.tracker_events.push(te);
-         //if(rc < 0)
-         //{
-         //   spdlog::info("DEBUG event.unpack rc = {}", rc);
-         //   return -9;
-         //}
-         //else
-         //{
-         //    tracker_events.push_back(std::move(event));
-         //    i += rc;
-         //}
+    }
+    stream
+  }
+
+  /// Wrap the serialized event in a TelemetryPacket, 
+  /// using self.header
+  pub fn to_telemetrypacket(&self) -> TelemetryPacket {
+    TelemetryPacket::from_payload(&self.header, self.to_bytestream())
+  }
+}
+
+#[cfg(feature = "random")]
+impl FromRandom for MergedEvent {
+  fn from_random() -> Self {
+    let mut rng    = rand::thread_rng();
+    let mut me     = MergedEvent::new();
+    me.header      = TelemetryHeader::forge(TelemetryPacketType::BoringEvent as u8);
+    me.header.timestamp = rng.gen::<u32>();
+    me.header.counter   = rng.gen::<u16>();
+    me.version     = rng.gen_range(0..MergedEvent::MAX_VERSION + 1);
+    me.flags0      = rng.gen::<u8>();
+    me.event_id    = rng.gen::<u32>();
+    me.tof_delimiter = MergedEvent::TOF_DELIMITER;
+    let mut summary = TofEventSummary::from_random();
+    summary.event_id = me.event_id;
+    me.tof_data    = summary.pack().to_bytestream();
+    if me.version == 0 {
+      me.flags1    = rng.gen::<u8>();
+      let n_events = rng.gen_range(0..10);
+      for _ in 0..n_events {
+        let mut te   = TrackerEvent::from_random();
+        te.event_id  = me.event_id;
+        // not serialized
+        te.flags1    = 0;
+        me.tracker_events.push(te);
       }
     } else {
-      error!("Unrecognized version {version}!");
+      me.creation_time = rng.gen::<u64>();
+      let n_hits   = rng.gen_range(0..200);
+      for _ in 0..n_hits {
+        me.tracker_hitsv2.push(TrackerHitV2::from_random());
+      }
+      me.tracker_osc_flags = rng.gen::<u8>();
+      for j in 0..8 {
+        if (me.tracker_osc_flags >> j) & 0b1 > 0 {
+          me.tracker_oscillators[j] = rng.gen_range(0..(1u64 << 48));
+        }
+      }
     }
-    Ok(me)
+    me
   }
 }
 
@@ -559,14 +782,19 @@ impl fmt::Display for MergedEvent {
     repr += "\n  ** ** ** MERGED  ** ** **";
     repr += &(format!("\n  version         {}", self.version));
     repr += &(format!("\n  event ID        {}", self.event_id));  
+    repr += &(format!("\n  flags0          {}", self.flags0));  
     if self.version == 0 {
+      repr += &(format!("\n  flags1          {}", self.flags1));  
       repr += &(format!("\n  -- TOF          {}", tof_evid));
       repr += &(format!("\n  -- TRK          {:?}", evids));
+    } else if self.version == 1 {
+      repr += &(format!("\n  creation time   {}", self.creation_time));  
     }
     repr += "\n  ** ** ** TRACKER ** ** **";
     if self.version == 0 {
       repr += &(format!("\n  N Trk events    {}", self.tracker_events.len()));
     } else if self.version == 1 {
+      repr += &(format!("\n  Trk osc. flags  {:#010b}", self.tracker_osc_flags)); 
       repr += &(format!("\n  Trk oscillators {:?}", self.tracker_oscillators)); 
     }
     repr += &(format!("\n  N Good Trk Hits {}", good_hits));
@@ -575,7 +803,7 @@ impl fmt::Display for MergedEvent {
   }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TrackerEvent {
 
   pub layer      : u8,
@@ -613,6 +841,26 @@ impl TrackerEvent {
     filtered_hits
   }
 
+  /// Get the positions of all strips which have a hit
+  ///
+  /// Analogous to TofEventSummary::get_pointcloud, the
+  /// points are (x, y, z, t, edep). The tracker does not
+  /// provide a time per hit, so t is NaN. Edep is the 
+  /// raw adc value.
+  ///
+  /// # Arguments:
+  ///
+  /// * geometry : tracker geometry to map the strips to
+  ///              positions (mm)
+  pub fn get_pointcloud(&self, geometry : &TrackerGeometry) -> Vec<(f32,f32,f32,f32,f32)> {
+    let mut pc = Vec::<(f32,f32,f32,f32,f32)>::with_capacity(self.hits.len());
+    for h in &self.hits {
+      let pos = geometry.get_strip_position(self.layer, h.row, h.module, h.channel);
+      pc.push((pos.0, pos.1, pos.2, f32::NAN, h.adc as f32));
+    }
+    pc
+  }
+
   pub fn from_bytestream(stream : &Vec<u8>,
                          pos    : &mut usize)
     -> Result<Self, SerializationError> {
@@ -623,7 +871,7 @@ impl TrackerEvent {
   // first timestamp
   let ts32   = parse_u32(stream, pos);
   let ts16   = parse_u16(stream, pos);
-  let ts64   = ((ts16 as u64) << 16) | ts32 as u64;
+  let ts64   = ((ts16 as u64) << 32) | ts32 as u64;
   te.event_time = ts64;
   
   te.layer   = parse_u8(stream, pos);
@@ -635,6 +883,39 @@ impl TrackerEvent {
   }
   Ok(te)
   }
+
+  /// Serialize in the format used within MergedEvents
+  /// (version 0)
+  ///
+  /// Event id and flags are not part of this format
+  pub fn to_bytestream(&self) -> Vec<u8> {
+    let mut stream = Vec::<u8>::with_capacity(8 + self.hits.len()*TrackerHit::SIZE);
+    stream.extend_from_slice(&((self.event_time & 0xffffffff) as u32).to_le_bytes());
+    stream.extend_from_slice(&(((self.event_time >> 32) & 0xffff) as u16).to_le_bytes());
+    stream.push(self.layer);
+    stream.push(self.hits.len() as u8);
+    for h in &self.hits {
+      stream.extend_from_slice(h.to_bytestream().as_slice());
+    }
+    stream
+  }
+}
+
+#[cfg(feature = "random")]
+impl FromRandom for TrackerEvent {
+  fn from_random() -> Self {
+    let mut rng   = rand::thread_rng();
+    let mut te    = TrackerEvent::new();
+    te.layer      = rng.gen_range(0..10);
+    te.flags1     = rng.gen::<u8>();
+    te.event_id   = rng.gen::<u32>();
+    te.event_time = rng.gen_range(0..(1u64 << 48));
+    let n_hits    = rng.gen_range(0..50);
+    for _ in 0..n_hits {
+      te.hits.push(TrackerHit::from_random());
+    }
+    te
+  }
 }
 
 impl fmt::Display for TrackerEvent {
@@ -652,7 +933,7 @@ impl fmt::Display for TrackerEvent {
   }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct TrackerHitV2 {
   pub layer           : u16,
   pub row             : u16,
@@ -675,6 +956,47 @@ impl TrackerHitV2 {
       oscillator      : 0,
     }
   }
+
+  /// Decode the 16bit strip id as it is used 
+  /// in the MergedEvent (version 1)
+  ///
+  /// channel : bits 0-4, module : bits 5-7,
+  /// row : bits 8-10, layer : bits 11-14
+  pub fn from_strip_id(strip_id : u16, adc : u16) -> Self {
+    let mut hit  = TrackerHitV2::new();
+    hit.channel  = strip_id & 0b11111;
+    hit.module   = (strip_id >> 5) & 0b111;
+    hit.row      = (strip_id >> 8) & 0b111;
+    hit.layer    = (strip_id >> 11) & 0b1111;
+    hit.adc      = adc;
+    hit
+  }
+
+  /// The 16bit strip id as it is used in the 
+  /// MergedEvent (version 1)
+  pub fn get_strip_id(&self) -> u16 {
+      (self.channel & 0b11111)
+    | (self.module  & 0b111 ) << 5
+    | (self.row     & 0b111 ) << 8
+    | (self.layer   & 0b1111) << 11
+  }
+}
+
+#[cfg(feature = "random")]
+impl FromRandom for TrackerHitV2 {
+  /// Respects the bit widths of the strip id 
+  /// in the MergedEvent. The oscillator is 
+  /// not part of the hit serialization.
+  fn from_random() -> Self {
+    let mut rng  = rand::thread_rng();
+    let mut hit  = TrackerHitV2::new();
+    hit.layer    = rng.gen_range(0..16);
+    hit.row      = rng.gen_range(0..8);
+    hit.module   = rng.gen_range(0..8);
+    hit.channel  = rng.gen_range(0..32);
+    hit.adc      = rng.gen::<u16>();
+    hit
+  }
 }
 
 impl fmt::Display for TrackerHitV2 {
@@ -690,7 +1012,7 @@ impl fmt::Display for TrackerHitV2 {
   }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct TrackerHit {
   pub row             : u8,
   pub module          : u8,
@@ -727,6 +1049,41 @@ impl TrackerHit {
     th.asic_event_code = parse_u8(stream, pos);
     Ok(th)
   } 
+  
+  pub fn to_bytestream(&self) -> Vec<u8> {
+    let mut stream = Vec::<u8>::with_capacity(Self::SIZE);
+    stream.push(self.row);
+    stream.push(self.module);
+    stream.push(self.channel);
+    stream.extend_from_slice(&self.adc.to_le_bytes());
+    stream.push(self.asic_event_code);
+    stream
+  }
+
+  /// The compressed 3 byte representation 
+  /// used in the TrackerPacket
+  pub fn to_bytestream_compressed(&self) -> [u8;3] {
+    let h0 = (self.channel & 0x1f) | (self.module << 5);
+    let h1 = (self.row & 0x7) | (((self.adc & 0x1f) as u8) << 3);
+    let h2 = (((self.adc >> 5) & 0x3f) as u8) | (self.asic_event_code << 6);
+    [h0, h1, h2]
+  }
+}
+
+#[cfg(feature = "random")]
+impl FromRandom for TrackerHit {
+  /// Respects the bit widths of the compressed
+  /// representation in the TrackerPacket
+  fn from_random() -> Self {
+    let mut rng = rand::thread_rng();
+    Self {
+      row             : rng.gen_range(0..8),
+      module          : rng.gen_range(0..8),
+      channel         : rng.gen_range(0..32),
+      adc             : rng.gen_range(0..2048),
+      asic_event_code : rng.gen_range(0..4),
+    }
+  }
 }
 
 impl fmt::Display for TrackerHit {
@@ -741,7 +1098,7 @@ impl fmt::Display for TrackerHit {
   }
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TrackerHeader {
   pub sync        : u16,
   pub crc         : u16,
@@ -790,6 +1147,37 @@ impl TrackerHeader {
     h.version     = parse_u8 (stream, pos);
     Ok(h)
   }
+
+  pub fn to_bytestream(&self) -> Vec<u8> {
+    let mut stream = Vec::<u8>::with_capacity(Self::SIZE);
+    stream.extend_from_slice(&self.sync.to_le_bytes());
+    stream.extend_from_slice(&self.crc.to_le_bytes());
+    stream.push(self.sys_id);
+    stream.push(self.packet_id);
+    stream.extend_from_slice(&self.length.to_le_bytes());
+    stream.extend_from_slice(&self.daq_count.to_le_bytes());
+    stream.extend_from_slice(&((self.sys_time & 0xffffffff) as u32).to_le_bytes());
+    stream.extend_from_slice(&(((self.sys_time >> 32) & 0xffff) as u16).to_le_bytes());
+    stream.push(self.version);
+    stream
+  }
+}
+
+#[cfg(feature = "random")]
+impl FromRandom for TrackerHeader {
+  fn from_random() -> Self {
+    let mut rng = rand::thread_rng();
+    Self {
+      sync        : rng.gen::<u16>(),
+      crc         : rng.gen::<u16>(),
+      sys_id      : rng.gen::<u8>(),
+      packet_id   : rng.gen::<u8>(),
+      length      : rng.gen::<u16>(),
+      daq_count   : rng.gen::<u16>(),
+      sys_time    : rng.gen_range(0..(1u64 << 48)),
+      version     : rng.gen::<u8>(),
+    }
+  }
 }
 
 impl fmt::Display for TrackerHeader {
@@ -807,6 +1195,7 @@ impl fmt::Display for TrackerHeader {
 }
 
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct GPSPacket {
   pub telemetry_header : TelemetryHeader,
   pub tracker_header   : TrackerHeader,
@@ -837,6 +1226,34 @@ impl GPSPacket {
     gps_p.gps_info = parse_u8(stream, pos);
     Ok(gps_p)
   }
+
+  pub fn to_bytestream(&self) -> Vec<u8> {
+    let mut stream = self.tracker_header.to_bytestream();
+    stream.extend_from_slice(&self.utc_time.to_le_bytes());
+    stream.push(self.gps_info);
+    stream
+  }
+  
+  /// Wrap the serialized packet in a TelemetryPacket, 
+  /// using self.telemetry_header
+  pub fn to_telemetrypacket(&self) -> TelemetryPacket {
+    TelemetryPacket::from_payload(&self.telemetry_header, self.to_bytestream())
+  }
+}
+
+#[cfg(feature = "random")]
+impl FromRandom for GPSPacket {
+  fn from_random() -> Self {
+    let mut rng = rand::thread_rng();
+    let mut gps_p = GPSPacket::new();
+    gps_p.telemetry_header           = TelemetryHeader::forge(TelemetryPacketType::GPS as u8);
+    gps_p.telemetry_header.timestamp = rng.gen::<u32>();
+    gps_p.telemetry_header.counter   = rng.gen::<u16>();
+    gps_p.tracker_header             = TrackerHeader::from_random();
+    gps_p.utc_time                   = rng.gen::<u32>();
+    gps_p.gps_info                   = rng.gen::<u8>();
+    gps_p
+  }
 }
 
 impl fmt::Display for GPSPacket {
@@ -853,6 +1270,7 @@ impl fmt::Display for GPSPacket {
 }
 
 /// Re-implementation of Alex' tracker packet
+#[derive(Debug, Clone, PartialEq)]
 pub struct TrackerPacket {
   pub telemetry_header : TelemetryHeader,
   pub tracker_header   : TrackerHeader,
@@ -935,8 +1353,63 @@ impl TrackerPacket {
     }
     Ok(tp)
   }
+
+  /// Serialize to the payload of a telemetry packet
+  ///
+  /// The event list gets terminated by a filler 
+  /// event header, as the decoder expects it.
+  /// The layer of the events is given by the 
+  /// sys_id of the tracker header.
+  pub fn to_bytestream(&self) -> Vec<u8> {
+    let mut stream = self.tracker_header.to_bytestream();
+    // settings
+    stream.push(0);
+    for ev in &self.events {
+      if ev.hits.len() > 192 {
+        error!("Tracker event with {} hits can not be serialized, truncating to 192!", ev.hits.len());
+      }
+      let n_hits = ev.hits.len().min(192);
+      stream.push(n_hits as u8);
+      stream.push(ev.flags1);
+      stream.extend_from_slice(&ev.event_id.to_le_bytes());
+      stream.extend_from_slice(&((ev.event_time & 0xffffffff) as u32).to_le_bytes());
+      stream.extend_from_slice(&(((ev.event_time >> 32) & 0xffff) as u16).to_le_bytes());
+      for h in &ev.hits[0..n_hits] {
+        stream.extend_from_slice(&h.to_bytestream_compressed());
+      }
+    }
+    // filler
+    stream.extend_from_slice(&[0xff;12]);
+    stream
+  }
+  
+  /// Wrap the serialized packet in a TelemetryPacket, 
+  /// using self.telemetry_header
+  pub fn to_telemetrypacket(&self) -> TelemetryPacket {
+    TelemetryPacket::from_payload(&self.telemetry_header, self.to_bytestream())
+  }
+}
+
+#[cfg(feature = "random")]
+impl FromRandom for TrackerPacket {
+  fn from_random() -> Self {
+    let mut rng = rand::thread_rng();
+    let mut tp  = TrackerPacket::new();
+    tp.telemetry_header           = TelemetryHeader::forge(TelemetryPacketType::Tracker as u8);
+    tp.telemetry_header.timestamp = rng.gen::<u32>();
+    tp.telemetry_header.counter   = rng.gen::<u16>();
+    tp.tracker_header             = TrackerHeader::from_random();
+    let n_events = rng.gen_range(0..20);
+    for _ in 0..n_events {
+      let mut ev = TrackerEvent::from_random();
+      ev.layer   = tp.tracker_header.sys_id;
+      tp.events.push(ev);
+    }
+    tp
+  }
 }
       
+#[derive(Debug, Clone, PartialEq)]
 pub struct TrackerTempLeakPacket {
   pub telemetry_header : TelemetryHeader,
   pub tracker_header   : TrackerHeader,
@@ -1003,8 +1476,54 @@ impl TrackerTempLeakPacket {
     }
     Ok(tp)
   }
+
+  /// Serialize to the payload of a telemetry packet
+  ///
+  /// Templeak is an 11bit and seu a 7bit 
+  /// number, higher bits will be lost.
+  pub fn to_bytestream(&self) -> Vec<u8> {
+    let mut stream = self.tracker_header.to_bytestream();
+    stream.push(self.row_offset & 0x7);
+    for row in 0..6 {
+      for module in 0..6 {
+        let templeak_ = self.templeak[row][module] & 0x7ff;
+        let seu_      = self.seu[row][module] & 0x7f;
+        stream.push(((templeak_ & 0x3) << 6) as u8);
+        stream.push(((templeak_ >> 2) & 0xff) as u8);
+        stream.push((((templeak_ >> 10) & 0x1) | (seu_ << 1)) as u8);
+      }
+    }
+    stream
+  }
+
+  /// Wrap the serialized packet in a TelemetryPacket, 
+  /// using self.telemetry_header
+  pub fn to_telemetrypacket(&self) -> TelemetryPacket {
+    TelemetryPacket::from_payload(&self.telemetry_header, self.to_bytestream())
+  }
 }
 
+#[cfg(feature = "random")]
+impl FromRandom for TrackerTempLeakPacket {
+  fn from_random() -> Self {
+    let mut rng = rand::thread_rng();
+    let mut tp  = TrackerTempLeakPacket::new();
+    tp.telemetry_header           = TelemetryHeader::forge(TelemetryPacketType::TrkTempLeak as u8);
+    tp.telemetry_header.timestamp = rng.gen::<u32>();
+    tp.telemetry_header.counter   = rng.gen::<u16>();
+    tp.tracker_header             = TrackerHeader::from_random();
+    tp.row_offset                 = rng.gen_range(0..8);
+    for row in 0..6 {
+      for module in 0..6 {
+        tp.templeak[row][module] = rng.gen_range(0..0x800);
+        tp.seu[row][module]      = rng.gen_range(0..0x80);
+      }
+    }
+    tp
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct TrackerDAQTempPacket {
   pub telemetry_header : TelemetryHeader,
   pub tracker_header   : TrackerHeader,
@@ -1070,8 +1589,55 @@ impl TrackerDAQTempPacket {
     }
     Ok(tp)
   }
+
+  /// Serialize to the payload of a telemetry packet
+  ///
+  /// Mirrors the missing version byte of the 
+  /// tracker header in this packet (see
+  /// ::from_bytestream). The number of entries 
+  /// is given by tracker_header.length/2
+  pub fn to_bytestream(&self) -> Vec<u8> {
+    let mut stream = self.tracker_header.to_bytestream();
+    stream.pop();
+    let n_entries  = ((self.tracker_header.length as usize)/2).min(256);
+    for k in 0..n_entries {
+      stream.extend_from_slice(&self.rom_id[k].to_le_bytes());
+      stream.extend_from_slice(&self.temp[k].to_le_bytes());
+    }
+    stream
+  }
+
+  /// Wrap the serialized packet in a TelemetryPacket, 
+  /// using self.telemetry_header
+  pub fn to_telemetrypacket(&self) -> TelemetryPacket {
+    TelemetryPacket::from_payload(&self.telemetry_header, self.to_bytestream())
+  }
 }
 
+#[cfg(feature = "random")]
+impl FromRandom for TrackerDAQTempPacket {
+  fn from_random() -> Self {
+    let mut rng = rand::thread_rng();
+    let mut tp  = TrackerDAQTempPacket::new();
+    tp.telemetry_header           = TelemetryHeader::forge(TelemetryPacketType::AnyTrackerHK as u8);
+    tp.telemetry_header.timestamp = rng.gen::<u32>();
+    tp.telemetry_header.counter   = rng.gen::<u16>();
+    tp.tracker_header             = TrackerHeader::from_random();
+    tp.tracker_header.packet_id   = 0x09;
+    let n_entries                 = rng.gen_range(1..257usize);
+    tp.tracker_header.length      = 2*n_entries as u16;
+    for k in 0..n_entries {
+      tp.rom_id[k] = rng.gen::<u64>();
+      tp.temp[k]   = rng.gen::<u16>();
+    }
+    // the decoder will see the first byte 
+    // of the first rom id as version
+    tp.tracker_header.version     = (tp.rom_id[0] & 0xff) as u8;
+    tp
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct TrackerDAQHSKPacket {
   pub telemetry_header : TelemetryHeader,
   pub tracker_header   : TrackerHeader,
@@ -1122,8 +1688,45 @@ impl TrackerDAQHSKPacket {
     }
     Ok(tp)
   }
+
+  /// Serialize to the payload of a telemetry packet
+  ///
+  /// The fields which are skipped by the 
+  /// decoder will be filled with 0
+  pub fn to_bytestream(&self) -> Vec<u8> {
+    let mut stream = self.tracker_header.to_bytestream();
+    stream.extend_from_slice(&[0u8;193]);
+    for k in 0..12usize {
+      stream.extend_from_slice(&self.temp[k].to_le_bytes());
+    }
+    stream
+  }
+
+  /// Wrap the serialized packet in a TelemetryPacket, 
+  /// using self.telemetry_header
+  pub fn to_telemetrypacket(&self) -> TelemetryPacket {
+    TelemetryPacket::from_payload(&self.telemetry_header, self.to_bytestream())
+  }
 }
 
+#[cfg(feature = "random")]
+impl FromRandom for TrackerDAQHSKPacket {
+  fn from_random() -> Self {
+    let mut rng = rand::thread_rng();
+    let mut tp  = TrackerDAQHSKPacket::new();
+    tp.telemetry_header           = TelemetryHeader::forge(TelemetryPacketType::AnyTrackerHK as u8);
+    tp.telemetry_header.timestamp = rng.gen::<u32>();
+    tp.telemetry_header.counter   = rng.gen::<u16>();
+    tp.tracker_header             = TrackerHeader::from_random();
+    tp.tracker_header.packet_id   = 0xff;
+    for k in 0..12usize {
+      tp.temp[k] = rng.gen::<u16>();
+    }
+    tp
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct TrackerEventIDEchoPacket {
   pub telemetry_header : TelemetryHeader,
   pub tracker_header   : TrackerHeader,
@@ -1174,6 +1777,35 @@ impl TrackerEventIDEchoPacket {
     tp.event_id_errors = parse_u16(stream, pos);
     Ok(tp)
   }
+  
+  pub fn to_bytestream(&self) -> Vec<u8> {
+    let mut stream = self.tracker_header.to_bytestream();
+    stream.extend_from_slice(&self.event_id.to_le_bytes());
+    stream.extend_from_slice(&self.event_id_errors.to_le_bytes());
+    stream
+  }
+
+  /// Wrap the serialized packet in a TelemetryPacket, 
+  /// using self.telemetry_header
+  pub fn to_telemetrypacket(&self) -> TelemetryPacket {
+    TelemetryPacket::from_payload(&self.telemetry_header, self.to_bytestream())
+  }
+}
+
+#[cfg(feature = "random")]
+impl FromRandom for TrackerEventIDEchoPacket {
+  fn from_random() -> Self {
+    let mut rng = rand::thread_rng();
+    let mut tp  = TrackerEventIDEchoPacket::new();
+    tp.telemetry_header           = TelemetryHeader::forge(TelemetryPacketType::AnyTrackerHK as u8);
+    tp.telemetry_header.timestamp = rng.gen::<u32>();
+    tp.telemetry_header.counter   = rng.gen::<u16>();
+    tp.tracker_header             = TrackerHeader::from_random();
+    tp.tracker_header.packet_id   = 0x03;
+    tp.event_id                   = rng.gen::<u32>();
+    tp.event_id_errors            = rng.gen::<u16>();
+    tp
+  }
 }
 
 
@@ -1182,29 +1814,74 @@ pub struct GapsTracker {
 
 }
 
+/// Combined TOF and tracker event, as assembled
+/// from a MergedEvent
+#[derive(Debug, Clone)]
 pub struct GapsEvent {
-  pub tof     : TofEventSummary,
-  pub tracker : Vec<TrackerEvent>
+  /// The event id of the MergedEvent
+  pub event_id       : u32,
+  pub tof            : TofEventSummary,
+  pub tracker        : Vec<TrackerEvent>,
+  /// New-style tracker hits (MergedEvent 
+  /// version 1+)
+  pub tracker_hitsv2 : Vec<TrackerHitV2>,
 }
 
 impl GapsEvent {
   pub fn new() -> Self {
     Self {
-      tof     : TofEventSummary::new(),
-      tracker : Vec::<TrackerEvent>::new(),
+      event_id       : 0,
+      tof            : TofEventSummary::new(),
+      tracker        : Vec::<TrackerEvent>::new(),
+      tracker_hitsv2 : Vec::<TrackerHitV2>::new(),
     }
   }
+
+  /// Take the tracker information from a MergedEvent. 
+  ///
+  /// The TOF part needs to be unpacked separately, 
+  /// since MergedEvent::tof_data holds a serialized
+  /// TofPacket
+  pub fn from_mergedevent(event : &MergedEvent) -> Self {
+    let mut g_event        = GapsEvent::new();
+    g_event.event_id       = event.event_id;
+    g_event.tracker        = event.tracker_events.clone();
+    g_event.tracker_hitsv2 = event.tracker_hitsv2.clone();
+    g_event
+  }
+
+  /// The combined pointcloud of TOF and tracker hits
+  /// as (x, y, z, t, edep). See TofEventSummary::get_pointcloud
+  /// and TrackerEvent::get_pointcloud.
+  ///
+  /// Returns None in case the paddle information has
+  /// not been set for the TOF event.
+  pub fn get_pointcloud(&self, geometry : &TrackerGeometry) -> Option<Vec<(f32,f32,f32,f32,f32)>> {
+    let mut pc = self.tof.get_pointcloud()?;
+    for ev in &self.tracker {
+      pc.extend(ev.get_pointcloud(geometry));
+    }
+    for h in &self.tracker_hitsv2 {
+      let pos = geometry.get_strip_position(h.layer as u8, h.row as u8, h.module as u8, h.channel as u8);
+      pc.push((pos.0, pos.1, pos.2, f32::NAN, h.adc as f32));
+    }
+    Some(pc)
+  }
 }
 
 impl fmt::Display for GapsEvent {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     let mut repr = String::from("<GapsEvent");
+    repr    += &(format!("\n  event id : {}", self.event_id));
     repr    += "\n  *** TOF ***";
     repr    += &(format!("\n  {}", self.tof));
     repr    += "*** TRACKER ***";
     for ev in &self.tracker {
       repr    += &(format!("\n  -- {}", ev));
     }
+    for h in &self.tracker_hitsv2 {
+      repr    += &(format!("\n  -- {}", h));
+    }
     repr    += ">";
     write!(f, "{}", repr)
   }
@@ -14,8 +14,19 @@ use tof_dataclasses::serialization::{
   //Packable
 };
 
-use crate::packets::TelemetryHeader;
+use crate::packets::{
+  TelemetryHeader,
+  TelemetryPacket,
+};
+
+#[cfg(feature = "random")]
+use crate::packets::TelemetryPacketType;
+#[cfg(feature = "random")]
+use tof_dataclasses::FromRandom;
+#[cfg(feature = "random")]
+use rand::Rng;
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct MagnetoMeter {
  pub header        : TelemetryHeader,
  pub temp          : u16, 
@@ -60,6 +71,16 @@ impl MagnetoMeter {
      ndata             : 0, 
     }
   }
+  
+  /// Wrap the serialized packet in a TelemetryPacket
+  ///
+  /// Other than for the tracker packets, the 
+  /// serialized MagnetoMeter contains the 
+  /// TelemetryHeader already
+  pub fn to_telemetrypacket(&self) -> TelemetryPacket {
+    let stream = self.to_bytestream();
+    TelemetryPacket::from_payload(&self.header, stream[TelemetryHeader::SIZE..].to_vec())
+  }
 }
 
 impl Default for MagnetoMeter {
@@ -166,4 +187,73 @@ impl Serialization for MagnetoMeter {
     }
     Ok(mag)
   }
+
+  /// Serialize including the TelemetryHeader
+  ///
+  /// All the bytes which are skipped by the decoder
+  /// (temperatures and checksums) will be 0
+  fn to_bytestream(&self) -> Vec<u8> {
+    let mut stream = Vec::<u8>::with_capacity(Self::SIZE);
+    let mut header = self.header;
+    header.sync    = TelemetryHeader::HEAD;
+    header.length  = Self::SIZE as u16;
+    stream.extend_from_slice(&header.to_bytestream());
+    // first magnetometer packet
+    stream.push(0);
+    stream.push(16);
+    stream.extend_from_slice(&self.mag_x.to_be_bytes());
+    stream.extend_from_slice(&self.acc_x.to_be_bytes());
+    stream.extend_from_slice(&self.mag_y.to_be_bytes());
+    stream.extend_from_slice(&self.acc_y.to_be_bytes());
+    stream.extend_from_slice(&self.mag_z.to_be_bytes());
+    stream.extend_from_slice(&self.acc_z.to_be_bytes());
+    stream.extend_from_slice(&self.temp.to_le_bytes());
+    stream.extend_from_slice(&[0,0]);
+    stream.push(0); // zero
+    stream.push(0); // checksum
+    stream.extend_from_slice(&32767u16.to_be_bytes());
+    // second magnetometer packet
+    stream.push(0);
+    stream.push(16);
+    stream.extend_from_slice(&self.roll.to_be_bytes());
+    stream.extend_from_slice(&self.mag_roll.to_be_bytes());
+    stream.extend_from_slice(&self.pitch.to_be_bytes());
+    stream.extend_from_slice(&self.mag_field.to_be_bytes());
+    stream.extend_from_slice(&self.yaw.to_be_bytes());
+    stream.extend_from_slice(&self.grav_field.to_be_bytes());
+    stream.extend_from_slice(&[0,0,0,0]);
+    stream.push(0); // zero
+    stream.push(0); // checksum
+    stream.extend_from_slice(&32767u16.to_be_bytes());
+    stream
+  }
+}
+
+#[cfg(feature = "random")]
+impl FromRandom for MagnetoMeter {
+  /// Only the fields which survive a round trip 
+  /// through the serialization are randomized
+  fn from_random() -> Self {
+    let mut rng        = rand::thread_rng();
+    let mut mag        = MagnetoMeter::new();
+    mag.header         = TelemetryHeader::forge(TelemetryPacketType::MagHK as u8);
+    mag.header.timestamp = rng.gen::<u32>();
+    mag.header.counter   = rng.gen::<u16>();
+    mag.header.length    = Self::SIZE as u16;
+    mag.temp           = rng.gen::<u16>();
+    mag.mag_x          = rng.gen::<u16>();
+    mag.mag_y          = rng.gen::<u16>();
+    mag.mag_z          = rng.gen::<u16>();
+    mag.acc_x          = rng.gen::<u16>();
+    mag.acc_y          = rng.gen::<u16>();
+    mag.acc_z          = rng.gen::<u16>();
+    mag.roll           = rng.gen::<u16>();
+    mag.pitch          = rng.gen::<u16>();
+    mag.yaw            = rng.gen::<u16>();
+    mag.mag_roll       = rng.gen::<u16>();
+    mag.mag_field      = rng.gen::<u16>();
+    mag.grav_field     = rng.gen::<u16>();
+    mag.end_byte       = 32767;
+    mag
+  }
 }
@@ -0,0 +1,320 @@
+//! Reconstruction of the absolute (UTC) event time
+//!
+//! There are several clocks in the data stream:
+//!
+//! * The GCU time in the TelemetryHeader (64 ms
+//!   resolution, free running)
+//! * The GPS packets, which carry the UTC second
+//!   together with a GCU timestamp
+//! * The MTB timestamp of each TofEventSummary. This
+//!   is the TIU GPS second latched at the last PPS
+//!   plus the 100 MHz MTB clock since that PPS,
+//!   truncated to 10 µs (see
+//!   MasterTriggerEvent::get_timestamp_abs48)
+//!
+//! The ClockModel maps the GCU time to UTC with the
+//! help of the GPS packets and then uses this to
+//! find the (integer) offset between the TIU GPS
+//! seconds and UTC. Events with a valid MTB timestamp
+//! get the precise MTB time, all others fall back
+//! to the GCU time.
+
+use std::fmt;
+use std::collections::HashMap;
+
+use tof_dataclasses::events::TofEventSummary;
+
+use crate::packets::GPSPacket;
+
+/// Resolution of the GCU time in the TelemetryHeader (s)
+pub const GCU_RESOLUTION     : f64 = 0.064;
+/// Resolution of the TofEventSummary timestamp (s)
+pub const MTB_RESOLUTION     : f64 = 1e-5;
+/// Period after which the 32bit MTB clock
+/// (100 MHz) rolls over (s)
+pub const MTB_ROLLOVER       : f64 = 4294967296.0 * 1e-8;
+
+/// How the time of an event was obtained
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[repr(u8)]
+pub enum TimeQuality {
+  /// MTB timestamp, referenced to the GPS PPS
+  GpsPps          = 10,
+  /// MTB timestamp, but the PPS was missing for
+  /// a while and the MTB clock rolled over. The
+  /// number of rollovers is inferred from the
+  /// GCU time.
+  MtbExtrapolated = 20,
+  /// GCU time, mapped to UTC with the GPS packets
+  Gcu             = 30,
+  /// No GPS information available for this time,
+  /// the raw GCU time is used
+  NoGpsLock       = 40,
+}
+
+impl fmt::Display for TimeQuality {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let r = serde_json::to_string(self).unwrap_or(
+      String::from("Error - Don't understand time quality!"));
+    write!(f, "<TimeQuality: {}>", r)
+  }
+}
+
+/// The absolute time of an event
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EventTime {
+  pub event_id    : u32,
+  /// UTC as seconds since the epoch
+  pub utc         : f64,
+  /// Estimated uncertainty of utc (s)
+  pub uncertainty : f64,
+  pub quality     : TimeQuality,
+}
+
+impl EventTime {
+
+  /// The event time has been derived from GPS
+  /// information
+  pub fn has_gps_lock(&self) -> bool {
+    self.quality != TimeQuality::NoGpsLock
+  }
+}
+
+impl fmt::Display for EventTime {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<EventTime:");
+    repr += &(format!("\n  event id    : {}", self.event_id));
+    repr += &(format!("\n  UTC         : {:.5}", self.utc));
+    repr += &(format!("\n  uncertainty : {:.5}", self.uncertainty));
+    repr += &(format!("\n  quality     : {}>", self.quality));
+    write!(f, "{}", repr)
+  }
+}
+
+/// Relation between GCU time and UTC, as well
+/// as between the TIU GPS seconds and UTC
+#[derive(Debug, Clone)]
+pub struct ClockModel {
+  /// (GCU time, UTC) pairs from GPS packets,
+  /// sorted by GCU time
+  anchors            : Vec<(f64, f64)>,
+  /// Observed offsets (UTC - MTB seconds)
+  /// with their number of occurrences
+  mtb_offsets        : HashMap<i64, usize>,
+  /// Bits in GPSPacket::gps_info which need to be
+  /// set for the GPS to be considered locked
+  pub lock_mask      : u8,
+  /// Use GPS packets within this window (s)
+  /// around a given time for the fit of the
+  /// GCU clock. This allows for the GCU clock
+  /// to drift.
+  pub fit_window     : f64,
+  /// If no GPS packet is seen for longer than
+  /// this (s), the period is considered to be
+  /// without GPS lock
+  pub max_gap        : f64,
+  /// Maximum allowed deviation (s) between the
+  /// MTB and GCU derived times
+  pub max_deviation  : f64,
+  /// Relative drift of the MTB clock, used
+  /// to estimate the uncertainty when the PPS
+  /// was missing
+  pub mtb_drift      : f64,
+}
+
+impl ClockModel {
+
+  pub fn new() -> Self {
+    Self {
+      anchors       : Vec::<(f64, f64)>::new(),
+      mtb_offsets   : HashMap::<i64, usize>::new(),
+      lock_mask     : 0,
+      fit_window    : 60.0,
+      max_gap       : 10.0,
+      max_deviation : 2.0,
+      mtb_drift     : 50e-6,
+    }
+  }
+
+  /// Number of GPS packets used for the model
+  pub fn get_n_anchors(&self) -> usize {
+    self.anchors.len()
+  }
+
+  /// Add a (GCU time, UTC) pair
+  pub fn add_anchor(&mut self, gcu_time : f64, utc : f64) {
+    let idx = self.anchors.partition_point(|a| a.0 <= gcu_time);
+    self.anchors.insert(idx, (gcu_time, utc));
+  }
+
+  /// Add a GPS packet to the model
+  ///
+  /// The GPS packet only carries the full UTC second,
+  /// so the packet is assumed to be sent in the middle
+  /// of that second.
+  ///
+  /// Returns false in case the packet was rejected
+  /// because the GPS was not locked
+  pub fn add_gps_packet(&mut self, packet : &GPSPacket) -> bool {
+    if packet.utc_time == 0
+    || packet.gps_info & self.lock_mask != self.lock_mask {
+      return false;
+    }
+    self.add_anchor(packet.telemetry_header.get_gcutime(), packet.utc_time as f64 + 0.5);
+    true
+  }
+
+  /// Periods (in GCU time) in which there were no
+  /// GPS packets for longer than max_gap
+  pub fn get_unlocked_periods(&self) -> Vec<(f64, f64)> {
+    let mut periods = Vec::<(f64, f64)>::new();
+    for pair in self.anchors.windows(2) {
+      if pair[1].0 - pair[0].0 > self.max_gap {
+        periods.push((pair[0].0, pair[1].0));
+      }
+    }
+    periods
+  }
+
+  /// Map GCU time to UTC
+  ///
+  /// A straight line is fitted to the GPS packets
+  /// within the fit_window. Returns (UTC, uncertainty)
+  /// or None if there is no GPS packet within
+  /// max_gap.
+  pub fn gcu_to_utc(&self, gcu_time : f64) -> Option<(f64, f64)> {
+    let first = self.anchors.partition_point(|a| a.0 < gcu_time - self.fit_window);
+    let last  = self.anchors.partition_point(|a| a.0 <= gcu_time + self.fit_window);
+    let local = &self.anchors[first..last];
+    // the closest GPS packet needs to be within max_gap
+    let closest = local.iter().map(|a| f64::abs(a.0 - gcu_time)).fold(f64::MAX, f64::min);
+    if closest > self.max_gap {
+      return None;
+    }
+    // the UTC is only given in full seconds
+    let quant = 1.0/f64::sqrt(12.0);
+    let n     = local.len() as f64;
+    let g_mean = local.iter().map(|a| a.0).sum::<f64>()/n;
+    let d_mean = local.iter().map(|a| a.1 - a.0).sum::<f64>()/n;
+    let mut s_gg = 0.0;
+    let mut s_gd = 0.0;
+    for a in local {
+      s_gg += (a.0 - g_mean)*(a.0 - g_mean);
+      s_gd += (a.0 - g_mean)*(a.1 - a.0 - d_mean);
+    }
+    let slope = if s_gg > 0.0 { s_gd/s_gg } else { 0.0 };
+    let mut rms = 0.0;
+    for a in local {
+      let res = a.1 - a.0 - d_mean - slope*(a.0 - g_mean);
+      rms    += res*res;
+    }
+    rms = f64::sqrt(rms/n).max(quant);
+    let utc = gcu_time + d_mean + slope*(gcu_time - g_mean);
+    let unc = f64::sqrt(rms*rms/n + GCU_RESOLUTION*GCU_RESOLUTION/12.0);
+    Some((utc, unc))
+  }
+
+  /// The MTB time in seconds. The reference of the
+  /// full seconds is the TIU GPS time.
+  ///
+  /// Returns None in case the TIU did not provide
+  /// a GPS time
+  pub fn get_mtb_time(event : &TofEventSummary) -> Option<f64> {
+    let ts48 = event.get_timestamp48();
+    // without GPS second, the timestamp is only the
+    // MTB clock since the last PPS
+    if (ts48 as f64)*MTB_RESOLUTION < MTB_ROLLOVER {
+      return None;
+    }
+    Some((ts48 / 100_000) as f64 + ((ts48 % 100_000) as f64)*MTB_RESOLUTION)
+  }
+
+  /// Use an event to determine the offset between
+  /// the TIU GPS seconds and UTC
+  ///
+  /// Returns false if the event could not be used
+  pub fn add_mtb_reference(&mut self, gcu_time : f64, event : &TofEventSummary) -> bool {
+    let Some(mtb) = Self::get_mtb_time(event) else {
+      return false;
+    };
+    let Some((utc, _)) = self.gcu_to_utc(gcu_time) else {
+      return false;
+    };
+    *self.mtb_offsets.entry(f64::round(utc - mtb) as i64).or_insert(0) += 1;
+    true
+  }
+
+  /// The most frequent offset between TIU GPS
+  /// seconds and UTC
+  pub fn get_mtb_offset(&self) -> Option<i64> {
+    self.mtb_offsets.iter()
+      .max_by_key(|(offset, count)| (**count, -**offset))
+      .map(|(offset, _)| *offset)
+  }
+
+  /// Assign an absolute time to an event
+  ///
+  /// # Arguments:
+  ///
+  /// * gcu_time : GCU time of the telemetry packet
+  ///              which contained the event
+  /// * event    : the TOF event
+  pub fn get_event_time(&self, gcu_time : f64, event : &TofEventSummary) -> EventTime {
+    let mut etime = EventTime {
+      event_id    : event.event_id,
+      utc         : gcu_time,
+      uncertainty : f64::INFINITY,
+      quality     : TimeQuality::NoGpsLock,
+    };
+    let Some((utc, unc)) = self.gcu_to_utc(gcu_time) else {
+      return etime;
+    };
+    etime.utc         = utc;
+    etime.uncertainty = unc;
+    etime.quality     = TimeQuality::Gcu;
+    let (Some(mtb), Some(offset)) = (Self::get_mtb_time(event), self.get_mtb_offset()) else {
+      return etime;
+    };
+    let mtb_utc = mtb + offset as f64;
+    let diff    = utc - mtb_utc;
+    if f64::abs(diff) < self.max_deviation {
+      etime.utc         = mtb_utc;
+      etime.uncertainty = MTB_RESOLUTION;
+      etime.quality     = TimeQuality::GpsPps;
+      return etime;
+    }
+    // the PPS was missing for longer than the
+    // MTB clock rollover
+    let n_rollovers = f64::round(diff/MTB_ROLLOVER);
+    if n_rollovers >= 1.0 && f64::abs(diff - n_rollovers*MTB_ROLLOVER) < self.max_deviation {
+      let elapsed       = n_rollovers*MTB_ROLLOVER;
+      etime.utc         = mtb_utc + elapsed;
+      etime.uncertainty = MTB_RESOLUTION + self.mtb_drift*elapsed;
+      etime.quality     = TimeQuality::MtbExtrapolated;
+    }
+    etime
+  }
+}
+
+impl Default for ClockModel {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for ClockModel {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<ClockModel:");
+    repr += &(format!("\n  GPS anchors      : {}", self.anchors.len()));
+    if let (Some(first), Some(last)) = (self.anchors.first(), self.anchors.last()) {
+      repr += &(format!("\n  GCU time range   : {:.3} - {:.3}", first.0, last.0));
+    }
+    repr += &(format!("\n  unlocked periods : {}", self.get_unlocked_periods().len()));
+    match self.get_mtb_offset() {
+      None         => repr += "\n  MTB offset       : -",
+      Some(offset) => repr += &(format!("\n  MTB offset       : {} s", offset)),
+    }
+    repr += ">";
+    write!(f, "{}", repr)
+  }
+}
@@ -0,0 +1,42 @@
+//! Tracker specific (offline) tools which go beyond 
+//! the decoding of the telemetry stream
+//!
+//! A tracker strip is identified by layer, row, module
+//! and channel.
+
+pub mod calibration;
+pub mod geometry;
+
+pub use calibration::{
+  TrackerCalibration,
+  CalibratedTrackerHit,
+};
+pub use geometry::TrackerGeometry;
+
+/// Maximum number of tracker layers
+pub const N_LAYERS   : usize = 10;
+/// Number of rows per layer
+pub const N_ROWS     : usize = 6;
+/// Number of modules per row
+pub const N_MODULES  : usize = 6;
+/// Number of strips (channels) per module
+pub const N_CHANNELS : usize = 32;
+/// Total number of addressable strips
+pub const N_STRIPS   : usize = N_LAYERS*N_ROWS*N_MODULES*N_CHANNELS;
+
+/// Flat index of a tracker strip, e.g. for lookup
+/// tables
+///
+/// Returns None in case the strip is out of bounds
+pub fn get_strip_index(layer : u8, row : u8, module : u8, channel : u8) -> Option<usize> {
+  if layer   as usize >= N_LAYERS
+  || row     as usize >= N_ROWS
+  || module  as usize >= N_MODULES
+  || channel as usize >= N_CHANNELS {
+    return None;
+  }
+  Some(  (layer  as usize)*N_ROWS*N_MODULES*N_CHANNELS
+       + (row    as usize)*N_MODULES*N_CHANNELS
+       + (module as usize)*N_CHANNELS
+       +  channel as usize)
+}
@@ -0,0 +1,439 @@
+//! Pedestal subtraction and channel masking for the
+//! tracker strips
+//!
+//! The input files are the ones shipped in
+//! tracker/resources:
+//!
+//! * pedestals : whitespace separated text file with
+//!               columns Layer Row Module Channel Pedestal Sigma
+//!               (e.g. SiLi-pedestals.txt)
+//! * mask      : json file with "LRM" (layer, row, module) keys
+//!               and a 32bit hex mask as value. A set bit
+//!               indicates an active channel
+//!               (e.g. mapping/channel-mask-Mar24.json)
+
+use std::fmt;
+use std::fs::File;
+use std::io::{
+  self,
+  BufRead,
+  BufReader,
+  ErrorKind,
+};
+use std::collections::HashMap;
+
+use log::{
+  info,
+  warn,
+};
+
+use crate::packets::{
+  TrackerEvent,
+  TrackerHit,
+  TrackerHitV2,
+  MergedEvent,
+};
+use crate::tracker::{
+  N_STRIPS,
+  N_CHANNELS,
+  get_strip_index,
+};
+
+/// A tracker hit after pedestal subtraction
+///
+/// The flags indicate if the hit should be
+/// used for analysis, see ::is_good
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CalibratedTrackerHit {
+  pub layer     : u8,
+  pub row       : u8,
+  pub module    : u8,
+  pub channel   : u8,
+  /// The raw adc value
+  pub adc       : u16,
+  pub pedestal  : f32,
+  /// Width of the pedestal (noise)
+  pub sigma     : f32,
+  /// Pedestal subtracted adc value
+  pub energy    : f32,
+  /// Bitmask, see CalibratedTrackerHit::MASKED etc.
+  pub flags     : u8,
+}
+
+impl CalibratedTrackerHit {
+  /// The strip is disabled in the channel mask
+  pub const MASKED          : u8 = 1;
+  /// The pedestal width of the strip exceeds
+  /// the noise limit
+  pub const NOISY           : u8 = 2;
+  /// The pedestal subtracted adc is below
+  /// threshold
+  pub const BELOW_THRESHOLD : u8 = 4;
+  /// There is no pedestal for this strip
+  pub const NO_PEDESTAL     : u8 = 8;
+
+  pub fn new() -> Self {
+    Self {
+      layer    : 0,
+      row      : 0,
+      module   : 0,
+      channel  : 0,
+      adc      : 0,
+      pedestal : 0.0,
+      sigma    : 0.0,
+      energy   : 0.0,
+      flags    : 0,
+    }
+  }
+
+  /// A good hit has none of the flags set
+  pub fn is_good(&self) -> bool {
+    self.flags == 0
+  }
+
+  pub fn is_masked(&self) -> bool {
+    self.flags & Self::MASKED > 0
+  }
+
+  pub fn is_noisy(&self) -> bool {
+    self.flags & Self::NOISY > 0
+  }
+
+  pub fn is_below_threshold(&self) -> bool {
+    self.flags & Self::BELOW_THRESHOLD > 0
+  }
+
+  pub fn has_pedestal(&self) -> bool {
+    self.flags & Self::NO_PEDESTAL == 0
+  }
+
+  /// Pedestal subtracted adc in units of
+  /// the pedestal width
+  pub fn get_significance(&self) -> f32 {
+    if self.sigma <= 0.0 {
+      return 0.0;
+    }
+    self.energy/self.sigma
+  }
+}
+
+impl Default for CalibratedTrackerHit {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for CalibratedTrackerHit {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<CalibratedTrackerHit:");
+    repr += &(format!("\n  Layer         : {}" ,self.layer));
+    repr += &(format!("\n  Row           : {}" ,self.row));
+    repr += &(format!("\n  Module        : {}" ,self.module));
+    repr += &(format!("\n  Channel       : {}" ,self.channel));
+    repr += &(format!("\n  ADC           : {}" ,self.adc));
+    repr += &(format!("\n  Pedestal      : {:.2} +- {:.2}", self.pedestal, self.sigma));
+    repr += &(format!("\n  Energy        : {:.2}", self.energy));
+    repr += &(format!("\n  Flags         : {:#06b}>",self.flags));
+    write!(f, "{}", repr)
+  }
+}
+
+/// Pedestals, noise and channel mask for all
+/// tracker strips
+#[derive(Debug, Clone)]
+pub struct TrackerCalibration {
+  /// A hit has to exceed the pedestal by
+  /// threshold_sigma*sigma to not be flagged
+  /// BELOW_THRESHOLD
+  pub threshold_sigma : f32,
+  /// Strips with a pedestal width larger than
+  /// noisy_factor*(median pedestal width) will
+  /// be flagged NOISY
+  pub noisy_factor    : f32,
+  pedestals           : Vec<f32>,
+  sigmas              : Vec<f32>,
+  has_pedestal        : Vec<bool>,
+  /// active strips (not masked)
+  active              : Vec<bool>,
+  median_sigma        : f32,
+}
+
+impl TrackerCalibration {
+
+  /// An empty calibration. All strips are active,
+  /// but no pedestals are loaded
+  pub fn new() -> Self {
+    Self {
+      threshold_sigma : 3.0,
+      noisy_factor    : 3.0,
+      pedestals       : vec![0.0;N_STRIPS],
+      sigmas          : vec![0.0;N_STRIPS],
+      has_pedestal    : vec![false;N_STRIPS],
+      active          : vec![true;N_STRIPS],
+      median_sigma    : 0.0,
+    }
+  }
+
+  /// Load pedestals and (optionally) the channel mask
+  ///
+  /// # Arguments:
+  ///
+  /// * pedestal_file : text file with columns
+  ///                   Layer Row Module Channel Pedestal Sigma
+  /// * mask_file     : json file with the channel masks per module
+  pub fn from_files(pedestal_file : &str, mask_file : Option<&str>) -> io::Result<Self> {
+    let mut cali = Self::new();
+    cali.load_pedestals(pedestal_file)?;
+    if let Some(fname) = mask_file {
+      cali.load_mask(fname)?;
+    }
+    Ok(cali)
+  }
+
+  /// Load pedestal and sigma for each strip from a
+  /// text file
+  ///
+  /// Returns the number of loaded strips
+  pub fn load_pedestals(&mut self, filename : &str) -> io::Result<usize> {
+    info!("Loading tracker pedestals from {filename}");
+    let file  = BufReader::new(File::open(filename)?);
+    let mut n_strips = 0usize;
+    for (k, line) in file.lines().enumerate() {
+      let line = line?;
+      let cols : Vec<&str> = line.split_whitespace().collect();
+      if cols.is_empty() {
+        continue;
+      }
+      // header
+      if cols[0].parse::<u8>().is_err() {
+        continue;
+      }
+      if cols.len() < 6 {
+        return Err(io::Error::new(ErrorKind::InvalidData, format!("Line {} of {} has {} columns, expected 6!", k+1, filename, cols.len())));
+      }
+      let mut ids = [0u8;4];
+      for j in 0..4 {
+        match cols[j].parse::<u8>() {
+          Err(err) => {
+            return Err(io::Error::new(ErrorKind::InvalidData, format!("Can not parse line {} of {}! {err}", k+1, filename)));
+          }
+          Ok(val) => ids[j] = val
+        }
+      }
+      let pedestal = cols[4].parse::<f32>();
+      let sigma    = cols[5].parse::<f32>();
+      if pedestal.is_err() || sigma.is_err() {
+        return Err(io::Error::new(ErrorKind::InvalidData, format!("Can not parse pedestal in line {} of {}!", k+1, filename)));
+      }
+      match get_strip_index(ids[0], ids[1], ids[2], ids[3]) {
+        None => {
+          warn!("Strip {:?} in line {} is out of bounds, ignoring!", ids, k+1);
+        }
+        Some(idx) => {
+          self.pedestals[idx]    = pedestal.unwrap();
+          self.sigmas[idx]       = sigma.unwrap();
+          self.has_pedestal[idx] = true;
+          n_strips += 1;
+        }
+      }
+    }
+    self.update_median_sigma();
+    info!("Loaded pedestals for {} strips, median sigma {:.2}", n_strips, self.median_sigma);
+    Ok(n_strips)
+  }
+
+  /// Load the channel mask from a json file
+  ///
+  /// Modules which are not in the file remain active.
+  /// Returns the number of masked strips.
+  pub fn load_mask(&mut self, filename : &str) -> io::Result<usize> {
+    info!("Loading tracker channel mask from {filename}");
+    let file = BufReader::new(File::open(filename)?);
+    let masks : HashMap<String, String> = match serde_json::from_reader(file) {
+      Err(err) => {
+        return Err(io::Error::new(ErrorKind::InvalidData, format!("Can not decode {}! {err}", filename)));
+      }
+      Ok(masks) => masks
+    };
+    let mut n_masked = 0usize;
+    for (key, value) in masks.iter() {
+      let lrm : Vec<u8> = key.trim().chars().filter_map(|c| c.to_digit(10)).map(|d| d as u8).collect();
+      if lrm.len() != 3 {
+        return Err(io::Error::new(ErrorKind::InvalidData, format!("Key {} in {} is not of the form LRM!", key, filename)));
+      }
+      let mask = match u32::from_str_radix(value.trim().trim_start_matches("0x").trim_start_matches("0X"), 16) {
+        Err(err) => {
+          return Err(io::Error::new(ErrorKind::InvalidData, format!("Can not decode mask {} for {}! {err}", value, key)));
+        }
+        Ok(mask) => mask
+      };
+      for ch in 0..N_CHANNELS {
+        match get_strip_index(lrm[0], lrm[1], lrm[2], ch as u8) {
+          None => {
+            warn!("Module {} is out of bounds, ignoring!", key);
+            break;
+          }
+          Some(idx) => {
+            self.active[idx] = (mask >> ch) & 0x1 == 1;
+            if !self.active[idx] {
+              n_masked += 1;
+            }
+          }
+        }
+      }
+    }
+    info!("Masked {} strips", n_masked);
+    Ok(n_masked)
+  }
+
+  fn update_median_sigma(&mut self) {
+    let mut sigmas : Vec<f32> = self.sigmas.iter()
+      .zip(self.has_pedestal.iter())
+      .filter(|(_, has)| **has)
+      .map(|(s, _)| *s)
+      .collect();
+    if sigmas.is_empty() {
+      self.median_sigma = 0.0;
+      return;
+    }
+    sigmas.sort_by(|a, b| a.total_cmp(b));
+    self.median_sigma = sigmas[sigmas.len()/2];
+  }
+
+  /// The median pedestal width over all strips
+  /// with pedestals
+  pub fn get_median_sigma(&self) -> f32 {
+    self.median_sigma
+  }
+
+  /// Pedestal and sigma for a strip, if available
+  pub fn get_pedestal(&self, layer : u8, row : u8, module : u8, channel : u8) -> Option<(f32, f32)> {
+    let idx = get_strip_index(layer, row, module, channel)?;
+    if !self.has_pedestal[idx] {
+      return None;
+    }
+    Some((self.pedestals[idx], self.sigmas[idx]))
+  }
+
+  /// Check the channel mask. Strips which are out
+  /// of bounds are considered masked.
+  pub fn is_masked(&self, layer : u8, row : u8, module : u8, channel : u8) -> bool {
+    match get_strip_index(layer, row, module, channel) {
+      None      => true,
+      Some(idx) => !self.active[idx]
+    }
+  }
+
+  /// A strip is noisy if its pedestal width exceeds
+  /// noisy_factor times the median width
+  pub fn is_noisy(&self, layer : u8, row : u8, module : u8, channel : u8) -> bool {
+    match self.get_pedestal(layer, row, module, channel) {
+      None             => false,
+      Some((_, sigma)) => self.median_sigma > 0.0 && sigma > self.noisy_factor*self.median_sigma
+    }
+  }
+
+  /// Get the number of strips which are masked or noisy
+  pub fn get_n_bad_strips(&self) -> usize {
+    let mut n_bad = 0usize;
+    for idx in 0..N_STRIPS {
+      if !self.has_pedestal[idx] {
+        continue;
+      }
+      if !self.active[idx]
+      || (self.median_sigma > 0.0 && self.sigmas[idx] > self.noisy_factor*self.median_sigma) {
+        n_bad += 1;
+      }
+    }
+    n_bad
+  }
+
+  /// Pedestal subtraction and flagging for a single strip
+  pub fn calibrate(&self, layer : u8, row : u8, module : u8, channel : u8, adc : u16) -> CalibratedTrackerHit {
+    let mut hit  = CalibratedTrackerHit::new();
+    hit.layer    = layer;
+    hit.row      = row;
+    hit.module   = module;
+    hit.channel  = channel;
+    hit.adc      = adc;
+    if self.is_masked(layer, row, module, channel) {
+      hit.flags |= CalibratedTrackerHit::MASKED;
+    }
+    match self.get_pedestal(layer, row, module, channel) {
+      None => {
+        hit.energy = adc as f32;
+        hit.flags |= CalibratedTrackerHit::NO_PEDESTAL;
+      }
+      Some((pedestal, sigma)) => {
+        hit.pedestal = pedestal;
+        hit.sigma    = sigma;
+        hit.energy   = adc as f32 - pedestal;
+        if self.is_noisy(layer, row, module, channel) {
+          hit.flags |= CalibratedTrackerHit::NOISY;
+        }
+        if hit.energy <= self.threshold_sigma*sigma {
+          hit.flags |= CalibratedTrackerHit::BELOW_THRESHOLD;
+        }
+      }
+    }
+    hit
+  }
+
+  /// Calibrate an (old-style) tracker hit. The layer
+  /// is given by the TrackerEvent
+  pub fn calibrate_hit(&self, layer : u8, hit : &TrackerHit) -> CalibratedTrackerHit {
+    self.calibrate(layer, hit.row, hit.module, hit.channel, hit.adc)
+  }
+
+  pub fn calibrate_hitv2(&self, hit : &TrackerHitV2) -> CalibratedTrackerHit {
+    self.calibrate(hit.layer as u8, hit.row as u8, hit.module as u8, hit.channel as u8, hit.adc)
+  }
+
+  /// Calibrate all hits of a TrackerEvent
+  ///
+  /// All hits are returned, use the flags of
+  /// the CalibratedTrackerHit to select
+  pub fn calibrate_trackerevent(&self, event : &TrackerEvent) -> Vec<CalibratedTrackerHit> {
+    event.hits.iter().map(|h| self.calibrate_hit(event.layer, h)).collect()
+  }
+
+  /// Calibrate all tracker hits of a MergedEvent,
+  /// independent of the version
+  pub fn calibrate_mergedevent(&self, event : &MergedEvent) -> Vec<CalibratedTrackerHit> {
+    let mut hits = Vec::<CalibratedTrackerHit>::new();
+    for te in &event.tracker_events {
+      hits.extend(self.calibrate_trackerevent(te));
+    }
+    for h in &event.tracker_hitsv2 {
+      hits.push(self.calibrate_hitv2(h));
+    }
+    hits
+  }
+
+  /// Calibrate all tracker hits of a MergedEvent
+  /// and return only the good ones
+  pub fn get_good_hits(&self, event : &MergedEvent) -> Vec<CalibratedTrackerHit> {
+    self.calibrate_mergedevent(event).into_iter().filter(|h| h.is_good()).collect()
+  }
+}
+
+impl Default for TrackerCalibration {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for TrackerCalibration {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let n_pedestals = self.has_pedestal.iter().filter(|x| **x).count();
+    let n_masked    = self.active.iter().filter(|x| !**x).count();
+    let mut repr = String::from("<TrackerCalibration:");
+    repr += &(format!("\n  N pedestals     : {}", n_pedestals));
+    repr += &(format!("\n  N masked        : {}", n_masked));
+    repr += &(format!("\n  N bad strips    : {}", self.get_n_bad_strips()));
+    repr += &(format!("\n  median sigma    : {:.2}", self.median_sigma));
+    repr += &(format!("\n  threshold       : {} sigma", self.threshold_sigma));
+    repr += &(format!("\n  noisy above     : {} x median sigma>", self.noisy_factor));
+    write!(f, "{}", repr)
+  }
+}
@@ -0,0 +1,165 @@
+//! Map tracker strips to positions in the detector
+//!
+//! This is a port of event-viewer/tracker_mapping.py.
+//! All coordinates are in mm, in the same frame as the
+//! TOF hit positions (see TofHit::set_paddle), so that
+//! tracker and TOF point clouds can be combined.
+//!
+//! Each module holds 4 detectors with 8 strips each.
+//! The orientation of the strips alternates between
+//! even and odd layers.
+
+use std::fmt;
+
+/// Distance of the strip centers from the
+/// center of the detector (ascending)
+const DX_STRIP_ASC : [f32;8] = [-36.45, -23.16, -13.4, -4.4, 4.4, 13.4, 23.15, 36.45];
+
+/// The geometry of the tracker. The default
+/// values are the design values.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TrackerGeometry {
+  /// z-position of the topmost layer (layer 0)
+  pub layer0_z  : f32,
+  /// distance between layers
+  pub dz_layer  : f32,
+  /// distance of the detector centers from
+  /// the module center (in x and y)
+  pub dx_det    : f32,
+  /// half the distance between module
+  /// centers (in x and y)
+  pub dx_module : f32,
+  /// distance of the center of module 0 of
+  /// row 0 from the center of the layer
+  /// (in x and y)
+  pub module0   : f32,
+  /// shift all positions by this amount, e.g.
+  /// to account for a survey
+  pub offset    : (f32, f32, f32),
+}
+
+impl TrackerGeometry {
+
+  pub fn new() -> Self {
+    Self {
+      layer0_z  : 1184.0,
+      dz_layer  : 100.0,
+      dx_det    : 57.65,
+      dx_module : 120.65,
+      module0   : 603.0,
+      offset    : (0.0, 0.0, 0.0),
+    }
+  }
+
+  /// The center of a module
+  ///
+  /// For even layers, module 0 of row 0 sits at
+  /// min x, max y. Modules ascend in -y direction,
+  /// rows in +x direction.
+  /// For odd layers, module 0 of row 0 sits at
+  /// max x, min y. Modules ascend in -x direction,
+  /// rows in +y direction
+  pub fn get_module_position(&self, layer : u8, row : u8, module : u8) -> (f32, f32, f32) {
+    let x : f32;
+    let y : f32;
+    if layer % 2 == 0 {
+      x = -self.module0 + 2.0*self.dx_module*(row as f32);
+      y =  self.module0 - 2.0*self.dx_module*(module as f32);
+    } else {
+      x =  self.module0 - 2.0*self.dx_module*(module as f32);
+      y = -self.module0 + 2.0*self.dx_module*(row as f32);
+    }
+    let z = self.layer0_z - self.dz_layer*(layer as f32);
+    (x + self.offset.0, y + self.offset.1, z + self.offset.2)
+  }
+
+  /// The center of the detector a strip belongs to
+  pub fn get_detector_position(&self, layer : u8, row : u8, module : u8, channel : u8) -> (f32, f32, f32) {
+    self.get_positions(layer, row, module, channel).0
+  }
+
+  /// The center of a strip
+  pub fn get_strip_position(&self, layer : u8, row : u8, module : u8, channel : u8) -> (f32, f32, f32) {
+    self.get_positions(layer, row, module, channel).1
+  }
+
+  /// Detector and strip center
+  fn get_positions(&self, layer : u8, row : u8, module : u8, channel : u8) -> ((f32, f32, f32), (f32, f32, f32)) {
+    let m_pos  = self.get_module_position(layer, row, module);
+    let ch     = (channel % 32) as usize;
+    let det_x  : f32;
+    let det_y  : f32;
+    let strip_x : f32;
+    let strip_y : f32;
+    // the strips are arranged in 4 groups of 8,
+    // one per detector
+    if layer % 2 == 0 {
+      // 'left' detectors (seen from above) are
+      // on the smaller y side
+      if ch < 8 || ch >= 24 {
+        det_y   = m_pos.1 - self.dx_det;
+        strip_y = det_y;
+        if ch < 8 {
+          det_x   = m_pos.0 - self.dx_det;
+          strip_x = det_x + DX_STRIP_ASC[ch];
+        } else {
+          det_x   = m_pos.0 + self.dx_det;
+          strip_x = det_x + DX_STRIP_ASC[ch - 24];
+        }
+      } else {
+        det_y   = m_pos.1 + self.dx_det;
+        strip_y = det_y;
+        if ch < 16 {
+          det_x   = m_pos.0 - self.dx_det;
+          strip_x = det_x + DX_STRIP_ASC[7 - (ch - 8)];
+        } else {
+          det_x   = m_pos.0 + self.dx_det;
+          strip_x = det_x + DX_STRIP_ASC[7 - (ch - 16)];
+        }
+      }
+    } else {
+      // the detectors closer to the HV connector
+      // are on the smaller y side
+      if ch < 16 {
+        det_y = m_pos.1 - self.dx_det;
+        if ch >= 8 {
+          det_x   = m_pos.0 + self.dx_det;
+          strip_y = det_y + DX_STRIP_ASC[7 - (ch - 8)];
+        } else {
+          det_x   = m_pos.0 - self.dx_det;
+          strip_y = det_y + DX_STRIP_ASC[7 - ch];
+        }
+      } else {
+        det_y = m_pos.1 + self.dx_det;
+        if ch < 24 {
+          det_x   = m_pos.0 + self.dx_det;
+          strip_y = det_y + DX_STRIP_ASC[ch - 16];
+        } else {
+          det_x   = m_pos.0 - self.dx_det;
+          strip_y = det_y + DX_STRIP_ASC[ch - 24];
+        }
+      }
+      strip_x = det_x;
+    }
+    ((det_x, det_y, m_pos.2), (strip_x, strip_y, m_pos.2))
+  }
+}
+
+impl Default for TrackerGeometry {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for TrackerGeometry {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<TrackerGeometry [mm]:");
+    repr += &(format!("\n  z layer 0       : {}", self.layer0_z));
+    repr += &(format!("\n  dz layer        : {}", self.dz_layer));
+    repr += &(format!("\n  dx detector     : {}", self.dx_det));
+    repr += &(format!("\n  dx module       : {}", self.dx_module));
+    repr += &(format!("\n  module 0        : {}", self.module0));
+    repr += &(format!("\n  offset          : {:?}>", self.offset));
+    write!(f, "{}", repr)
+  }
+}
@@ -0,0 +1,450 @@
+//! Integration test
+//!
+//! Round trips through the telemetry 
+//! encoders and decoders
+
+use tof_dataclasses::FromRandom;
+use tof_dataclasses::serialization::Serialization;
+
+use telemetry_dataclasses::packets::{
+  TelemetryHeader,
+  TelemetryPacket,
+  MergedEvent,
+  TrackerPacket,
+  GPSPacket,
+  TrackerTempLeakPacket,
+  TrackerDAQTempPacket,
+  TrackerDAQHSKPacket,
+  TrackerEventIDEchoPacket,
+  MagnetoMeter,
+};
+use telemetry_dataclasses::io::{
+  TelemetryPacketReader,
+  TelemetryPacketWriter,
+  GapsEventReader,
+};
+
+#[test]
+fn serialize_telemetryheader() {
+  for _ in 0..100 {
+    let data = TelemetryHeader::from_random();
+    let test = TelemetryHeader::from_bytestream(&data.to_bytestream(), &mut 0).unwrap();
+    assert_eq!(data, test);
+  }
+}
+
+#[test]
+fn serialize_telemetrypacket() {
+  for _ in 0..100 {
+    let data = TelemetryPacket::from_random();
+    let test = TelemetryPacket::from_bytestream(&data.to_bytestream(), &mut 0).unwrap();
+    assert_eq!(data, test);
+  }
+}
+
+#[test]
+fn serialize_mergedevent() {
+  for _ in 0..100 {
+    let data   = MergedEvent::from_random();
+    let packet = data.to_telemetrypacket();
+    let packet = TelemetryPacket::from_bytestream(&packet.to_bytestream(), &mut 0).unwrap();
+    let mut test = MergedEvent::from_bytestream(&packet.payload, &mut 0).unwrap();
+    test.header  = packet.header;
+    assert_eq!(data.tof_data, test.tof_data);
+    assert!(test.get_tofeventsummary().is_ok());
+    assert_eq!(data.tracker_events, test.tracker_events);
+    assert_eq!(data.tracker_hitsv2, test.tracker_hitsv2);
+    assert_eq!(data.tracker_oscillators, test.tracker_oscillators);
+    assert_eq!(data.tracker_osc_flags, test.tracker_osc_flags);
+    assert_eq!(data.creation_time, test.creation_time);
+    assert_eq!(data.flags0, test.flags0);
+    assert_eq!(data.flags1, test.flags1);
+    assert_eq!(data.tof_delimiter, test.tof_delimiter);
+    assert_eq!(test.header.length as usize, packet.payload.len() + TelemetryHeader::SIZE);
+  }
+}
+
+#[test]
+fn mergedevent_unsupported_version() {
+  let data       = MergedEvent::from_random();
+  let mut stream = data.to_bytestream();
+  stream[0]      = MergedEvent::MAX_VERSION + 1;
+  assert!(MergedEvent::from_bytestream(&stream, &mut 0).is_err());
+}
+
+#[test]
+fn serialize_trackerpacket() {
+  for _ in 0..100 {
+    let data   = TrackerPacket::from_random();
+    let packet = data.to_telemetrypacket();
+    let mut test = TrackerPacket::from_bytestream(&packet.payload, &mut 0).unwrap();
+    test.telemetry_header = packet.header;
+    assert_eq!(data.tracker_header, test.tracker_header);
+    assert_eq!(data.events, test.events);
+  }
+}
+
+#[test]
+fn serialize_gpspacket() {
+  for _ in 0..100 {
+    let data   = GPSPacket::from_random();
+    let packet = data.to_telemetrypacket();
+    let mut test = GPSPacket::from_bytestream(&packet.payload, &mut 0).unwrap();
+    test.telemetry_header = packet.header;
+    assert_eq!(data.tracker_header, test.tracker_header);
+    assert_eq!(data.utc_time, test.utc_time);
+    assert_eq!(data.gps_info, test.gps_info);
+  }
+}
+
+#[test]
+fn serialize_trackertempleakpacket() {
+  for _ in 0..100 {
+    let data   = TrackerTempLeakPacket::from_random();
+    let packet = data.to_telemetrypacket();
+    let mut test = TrackerTempLeakPacket::from_bytestream(&packet.payload, &mut 0).unwrap();
+    assert_eq!(packet.header.length as usize, packet.payload.len() + TelemetryHeader::SIZE);
+    test.telemetry_header = data.telemetry_header;
+    assert_eq!(data, test);
+  }
+}
+
+#[test]
+fn serialize_trackerdaqtemppacket() {
+  for _ in 0..100 {
+    let data   = TrackerDAQTempPacket::from_random();
+    let packet = data.to_telemetrypacket();
+    let mut test = TrackerDAQTempPacket::from_bytestream(&packet.payload, &mut 0).unwrap();
+    test.telemetry_header = packet.header;
+    assert_eq!(data.tracker_header, test.tracker_header);
+    assert_eq!(data.rom_id, test.rom_id);
+    assert_eq!(data.temp, test.temp);
+  }
+}
+
+#[test]
+fn serialize_trackerdaqhskpacket() {
+  for _ in 0..100 {
+    let data   = TrackerDAQHSKPacket::from_random();
+    let packet = data.to_telemetrypacket();
+    let mut test = TrackerDAQHSKPacket::from_bytestream(&packet.payload, &mut 0).unwrap();
+    test.telemetry_header = packet.header;
+    assert_eq!(data.tracker_header, test.tracker_header);
+    assert_eq!(data.temp, test.temp);
+  }
+}
+
+#[test]
+fn serialize_trackereventidechopacket() {
+  for _ in 0..100 {
+    let data   = TrackerEventIDEchoPacket::from_random();
+    let packet = data.to_telemetrypacket();
+    let mut test = TrackerEventIDEchoPacket::from_bytestream(&packet.payload, &mut 0).unwrap();
+    assert_eq!(packet.header.length as usize, packet.payload.len() + TelemetryHeader::SIZE);
+    test.telemetry_header = data.telemetry_header;
+    assert_eq!(data, test);
+  }
+}
+
+#[test]
+fn serialize_magnetometer() {
+  for _ in 0..100 {
+    let data   = MagnetoMeter::from_random();
+    let test   = MagnetoMeter::from_bytestream(&data.to_bytestream(), &mut 0).unwrap();
+    assert_eq!(data, test);
+    let packet = data.to_telemetrypacket();
+    assert_eq!(packet.to_bytestream(), data.to_bytestream());
+  }
+}
+
+#[test]
+fn write_read_telemetrypackets() {
+  let dir = std::env::temp_dir().join(format!("telemetry-test-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  let mut packets = Vec::<TelemetryPacket>::new();
+  let mut writer  = TelemetryPacketWriter::new(dir.display().to_string());
+  writer.pkts_per_file = 10;
+  for _ in 0..25 {
+    let packet = MergedEvent::from_random().to_telemetrypacket();
+    writer.add_telemetry_packet(&packet);
+    packets.push(packet);
+  }
+  drop(writer);
+  let reader = TelemetryPacketReader::new(dir.display().to_string());
+  assert_eq!(reader.filenames.len(), 3);
+  let test : Vec<TelemetryPacket> = reader.collect();
+  assert_eq!(packets, test);
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn read_gapsevents() {
+  let dir = std::env::temp_dir().join(format!("telemetry-test-gapsevents-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  let mut events = Vec::<MergedEvent>::new();
+  let mut writer = TelemetryPacketWriter::new(dir.display().to_string());
+  writer.pkts_per_file = 10;
+  for k in 0..30 {
+    // interleave with other packets, which 
+    // need to be ignored
+    if k % 3 == 0 {
+      writer.add_telemetry_packet(&GPSPacket::from_random().to_telemetrypacket());
+    }
+    let mut event = MergedEvent::from_random();
+    if k % 5 == 0 {
+      // broken tof data
+      event.tof_data = vec![1,2,3,4];
+    }
+    writer.add_telemetry_packet(&event.to_telemetrypacket());
+    events.push(event);
+  }
+  drop(writer);
+  let mut reader = GapsEventReader::new(dir.display().to_string());
+  let mut n_events = 0;
+  for (data, test) in events.iter().zip(&mut reader) {
+    assert_eq!(data.event_id, test.event_id);
+    assert_eq!(data.tracker_events, test.tracker);
+    assert_eq!(data.tracker_hitsv2, test.tracker_hitsv2);
+    if data.tof_data.len() > 4 {
+      assert_eq!(test.tof.event_id, data.event_id);
+    }
+    n_events += 1;
+  }
+  assert_eq!(n_events, 30);
+  assert!(reader.next().is_none());
+  assert_eq!(reader.stats.n_merged_events, 30);
+  assert_eq!(reader.stats.n_gaps_events, 30);
+  assert_eq!(reader.stats.n_err_tofpacket, 6);
+  assert_eq!(reader.stats.get_n_errors(), 6);
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn tracker_calibration() {
+  use telemetry_dataclasses::tracker::{
+    TrackerCalibration,
+    CalibratedTrackerHit,
+  };
+  let resources = format!("{}/../../../../tracker/resources", env!("CARGO_MANIFEST_DIR"));
+  let pedestals = format!("{}/SiLi-pedestals.txt", resources);
+  let mask      = format!("{}/mapping/channel-mask-Mar24.json", resources);
+  let cali      = TrackerCalibration::from_files(&pedestals, Some(&mask)).unwrap();
+  let (ped, sigma) = cali.get_pedestal(0,0,0,0).unwrap();
+  assert_eq!(ped, 118.503);
+  assert_eq!(sigma, 2.726);
+  // at pedestal
+  let hit = cali.calibrate(0, 0, 0, 0, 118);
+  assert!(hit.is_below_threshold());
+  assert!(!hit.is_good());
+  // well above threshold
+  let hit = cali.calibrate(0, 0, 0, 0, 500);
+  assert!(hit.is_good());
+  assert_eq!(hit.energy, 500.0 - 118.503);
+  // module 000 has mask 0xF7FFFFFF
+  assert!(cali.calibrate(0, 0, 0, 27, 500).is_masked());
+  // module 002 is masked entirely
+  assert!(cali.is_masked(0, 0, 2, 5));
+  // no pedestals for layer 6
+  let hit = cali.calibrate(6, 0, 0, 0, 500);
+  assert_eq!(hit.flags & CalibratedTrackerHit::NO_PEDESTAL, CalibratedTrackerHit::NO_PEDESTAL);
+}
+
+#[test]
+fn tracker_geometry() {
+  use telemetry_dataclasses::tracker::TrackerGeometry;
+  use telemetry_dataclasses::packets::{
+    TrackerEvent,
+    TrackerHit,
+  };
+  let geo = TrackerGeometry::new();
+  // reference values from event-viewer/tracker_mapping.py
+  // (layer, row, module, channel) -> strip, detector
+  let reference = [
+    ((0, 1, 2,  3), (-423.75,   62.75, 1184.0), (-419.35,   62.75, 1184.0)),
+    ((3, 0, 5, 20), (-545.85, -540.95,  884.0), (-545.85, -545.35,  884.0)),
+    ((1, 2, 1, 10), ( 419.35, -164.65, 1084.0), ( 419.35, -178.05, 1084.0)),
+    ((2, 3, 3, 27), ( 174.15, -178.55,  984.0), ( 178.55, -178.55,  984.0)),
+    ((5, 4, 0, 12), ( 660.65,  300.15,  684.0), ( 660.65,  304.55,  684.0)),
+    ((4, 5, 4, 30), ( 684.3 , -419.85,  784.0), ( 661.15, -419.85,  784.0)),
+  ];
+  let close = |a : (f32,f32,f32), b : (f32,f32,f32)| {
+    (a.0 - b.0).abs() < 0.01 && (a.1 - b.1).abs() < 0.01 && (a.2 - b.2).abs() < 0.01
+  };
+  for (strip, spos, dpos) in reference {
+    let (l, r, m, c) = strip;
+    let s = geo.get_strip_position(l, r, m, c);
+    let d = geo.get_detector_position(l, r, m, c);
+    assert!(close(s, spos), "strip {:?} : {:?} != {:?}", strip, s, spos);
+    assert!(close(d, dpos), "detector {:?} : {:?} != {:?}", strip, d, dpos);
+  }
+  let mut ev = TrackerEvent::new();
+  ev.layer   = 3;
+  let mut hit = TrackerHit::new();
+  hit.row     = 0;
+  hit.module  = 5;
+  hit.channel = 20;
+  hit.adc     = 1000;
+  ev.hits.push(hit);
+  let pc = ev.get_pointcloud(&geo);
+  assert_eq!(pc.len(), 1);
+  assert!(close((pc[0].0, pc[0].1, pc[0].2), (-545.85, -540.95, 884.0)));
+  assert_eq!(pc[0].4, 1000.0);
+}
+
+#[test]
+fn tracker_event_timestamp() {
+  use telemetry_dataclasses::packets::TrackerEvent;
+  // the lower 32 bits of the 48 bit timestamp come 
+  // first, followed by the upper 16 bits
+  let mut stream = Vec::<u8>::new();
+  stream.extend_from_slice(&0x89abcdefu32.to_le_bytes());
+  stream.extend_from_slice(&0x0123u16.to_le_bytes());
+  stream.push(2); // layer
+  stream.push(0); // no hits
+  let ev = TrackerEvent::from_bytestream(&stream, &mut 0).unwrap();
+  assert_eq!(ev.event_time, 0x0123_89ab_cdef);
+  assert_eq!(ev.layer, 2);
+  assert_eq!(ev.to_bytestream(), stream);
+}
+
+#[test]
+fn clock_model() {
+  use tof_dataclasses::events::TofEventSummary;
+  use telemetry_dataclasses::timing::{
+    ClockModel,
+    TimeQuality,
+    MTB_ROLLOVER,
+  };
+  // GCU clock runs 100 ppm fast and is 
+  // offset by ~1000.3 s w.r.t UTC. The TIU
+  // GPS seconds are 18 s behind UTC
+  let gcu0   = 1631030675.0;
+  let to_utc = |gcu : f64| { gcu0 - 1000.3 + (gcu - gcu0)*(1.0 - 1e-4) };
+  let mut model = ClockModel::new();
+  // one GPS packet every 16 GCU ticks (1.024 s)
+  for k in 0..600 {
+    let mut gps = GPSPacket::new();
+    gps.telemetry_header.timestamp = 16*k;
+    gps.utc_time = to_utc(gps.telemetry_header.get_gcutime()).floor() as u32;
+    // gap in the GPS packets
+    if k > 300 && k < 400 {
+      gps.utc_time = 0;
+    }
+    model.add_gps_packet(&gps);
+  }
+  assert_eq!(model.get_n_anchors(), 501);
+  assert_eq!(model.get_unlocked_periods().len(), 1);
+  let make_event = |utc : f64| {
+    let mut ev      = TofEventSummary::new();
+    let ts48        = ((utc - 18.0)*1e5) as u64;
+    ev.timestamp32  = (ts48 & 0xffffffff) as u32;
+    ev.timestamp16  = ((ts48 >> 32) & 0xffff) as u16;
+    ev
+  };
+  for k in 0..100 {
+    let gcu = gcu0 + 10.0 + k as f64;
+    assert!(model.add_mtb_reference(gcu, &make_event(to_utc(gcu))));
+  }
+  assert_eq!(model.get_mtb_offset(), Some(18));
+  // events with PPS
+  let gcu   = gcu0 + 100.0;
+  let etime = model.get_event_time(gcu, &make_event(to_utc(gcu)));
+  assert_eq!(etime.quality, TimeQuality::GpsPps);
+  assert!((etime.utc - to_utc(gcu)).abs() < 1e-4);
+  // missing PPS, the MTB clock rolled over twice
+  let etime = model.get_event_time(gcu, &make_event(to_utc(gcu) - 2.0*MTB_ROLLOVER));
+  assert_eq!(etime.quality, TimeQuality::MtbExtrapolated);
+  assert!((etime.utc - to_utc(gcu)).abs() < 1e-4);
+  // no GPS second from the TIU
+  let mut ev = TofEventSummary::new();
+  ev.timestamp32 = 1000;
+  let etime = model.get_event_time(gcu, &ev);
+  assert_eq!(etime.quality, TimeQuality::Gcu);
+  assert!((etime.utc - to_utc(gcu)).abs() < 1.0);
+  // no GPS lock
+  let gcu   = gcu0 + 350.0;
+  let etime = model.get_event_time(gcu, &make_event(to_utc(gcu)));
+  assert_eq!(etime.quality, TimeQuality::NoGpsLock);
+  assert!(!etime.has_gps_lock());
+}
+
+#[test]
+fn magfield_series() {
+  use tof_dataclasses::monitoring::RBMoniData;
+  use tof_dataclasses::series::MoniSeries;
+  use telemetry_dataclasses::magfield::{
+    MagFieldSample,
+    MagFieldSeries,
+    GONDOLA_MAG_ID,
+  };
+  let mut series = MagFieldSeries::new();
+  // field pointing north (+x) and down, 
+  // payload level
+  for k in 0..10 {
+    let mut mag  = MagnetoMeter::new();
+    mag.header.timestamp = k;
+    mag.mag_x    = 30 + (k as u16 % 2)*2;
+    mag.mag_z    = (-40i16) as u16;
+    mag.acc_z    = (-1i16) as u16;
+    series.add_magnetometer(&mag);
+  }
+  let mut moni = RBMoniData::new();
+  moni.board_id = 5;
+  moni.mag_x    = 0.0;
+  moni.mag_y    = 0.5;
+  moni.mag_z    = 0.0;
+  series.add_rbmonidata(&moni, 100.0);
+  assert_eq!(series.get_board_ids().len(), 2);
+  assert_eq!(series.get_times(GONDOLA_MAG_ID).unwrap().len(), 10);
+  let b_tot = series.get_var_for_board("b_tot", &GONDOLA_MAG_ID).unwrap();
+  assert!((b_tot[0] - 50.0).abs() < 1e-3);
+  let smoothed = series.get_smoothed("b_x", GONDOLA_MAG_ID, 2).unwrap();
+  assert!(smoothed[1..9].iter().all(|b| (b - 31.0).abs() < 0.7));
+  let dip = series.get_var_for_board("dip", &GONDOLA_MAG_ID).unwrap();
+  assert!((dip[0] - 53.13).abs() < 0.01);
+  let heading = series.get_var_for_board("heading", &GONDOLA_MAG_ID).unwrap();
+  assert!(heading[0].abs() < 1e-3);
+  // RB reports Gauss
+  let rb = series.get_last_moni(5).unwrap();
+  assert!((rb.b_y - 50.0).abs() < 1e-3);
+  assert!(rb.calibrated);
+  assert!(rb.roll.is_nan());
+  // no calibration loaded for the gondola
+  // magnetometer, so the readings are raw
+  let gondola = series.get_last_moni(GONDOLA_MAG_ID).unwrap();
+  assert!(!gondola.calibrated);
+  assert!((rb.get_heading() - 90.0).abs() < 1e-3);
+  assert!(rb.get_dip().abs() < 1e-3);
+  assert!(MagFieldSample::new().get_field_zenith().is_nan());
+}
+
+#[test]
+fn magfield_gondola_calibration() {
+  use tof_dataclasses::series::MoniSeries;
+  use telemetry_dataclasses::magfield::{
+    MagCalibration,
+    MagFieldSeries,
+    GONDOLA_MAG_ID,
+  };
+  let fname = std::env::temp_dir().join("magfield_gondola_calibration.json");
+  let fname = fname.to_str().unwrap();
+  std::fs::write(fname, r#"{"scale" : 0.5, "offset" : [5.0, 0.0, 0.0]}"#).unwrap();
+  let cali = MagCalibration::for_gondola(fname).unwrap();
+  assert!(cali.calibrated);
+  assert_eq!(cali.gain, [1.0;3]);
+  assert_eq!(cali.acc_scale, 1.0);
+  let mut series = MagFieldSeries::new();
+  series.load_gondola_calibration(fname).unwrap();
+  let mut mag  = MagnetoMeter::new();
+  mag.mag_x    = 30;
+  mag.mag_z    = (-40i16) as u16;
+  series.add_magnetometer(&mag);
+  let gondola = series.get_last_moni(GONDOLA_MAG_ID).unwrap();
+  assert!(gondola.calibrated);
+  assert!((gondola.b_x - 10.0).abs() < 1e-3);
+  assert!((gondola.b_z + 20.0).abs() < 1e-3);
+  std::fs::write(fname, "not json").unwrap();
+  assert!(MagCalibration::for_gondola(fname).is_err());
+  assert!(MagCalibration::for_gondola("/does/not/exist.json").is_err());
+  std::fs::remove_file(fname).unwrap();
+}
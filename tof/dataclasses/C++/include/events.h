@@ -188,6 +188,12 @@ struct RBEventHeader {
   u16  fpga_temp             ;
   u32  timestamp32           ;
   u16  timestamp16           ;
+  // V1 - sine fit to the ch9 clock
+  Gaps::ProtocolVersion version;
+  f32  sine_fit_amp          ;
+  f32  sine_fit_freq         ;
+  f32  sine_fit_phase        ;
+  f32  sine_fit_rms          ;
   
   RBEventHeader();
  
@@ -531,6 +537,21 @@ struct TofHit  {
   f32 baseline_b_rms;
   f32 phase;
 
+  // V2 variables - the secondary pulses
+  // are skipped when reading
+  u8 pileup;
+  u8 n_pulses_a;
+  u8 n_pulses_b;
+  // V3 variables - edep is serialized 
+  // in place of charge_min_i
+  f32  edep;
+  bool energy_calibrated;
+  bool saturated_a;
+  bool saturated_b;
+  // template fit chi2/ndof, 0 for cfd
+  f32  fit_chi2_a;
+  f32  fit_chi2_b;
+
   u32 timestamp32;
   u16 timestamp16;
   // don't serialize
@@ -546,6 +567,7 @@ struct TofHit  {
   f32 get_charge_a()     const;
   f32 get_charge_b()     const;
   f32 get_charge_min_i() const;
+  f32 get_edep()         const;
   f32 get_x_pos()        const;
   f32 get_t_avg()        const;
   f32 get_t0()           const;
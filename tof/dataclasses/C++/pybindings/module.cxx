@@ -617,7 +617,19 @@ PYBIND11_MODULE(gaps_tof, m) {
         .def_property_readonly("charge_b",      &TofHit::get_charge_b,
                "Reconstructed charge for side B")
         .def_property_readonly("charge_min_i",  &TofHit::get_charge_min_i,
-               "Reconstructed paddle charge in units of MinI") 
+               "Reconstructed paddle charge in units of MinI (NaN for V3 hits)") 
+        .def_property_readonly("edep",          &TofHit::get_edep,
+               "Energy deposition [MeV], calibrated if energy_calibrated is set") 
+        .def_readonly("energy_calibrated", &TofHit::energy_calibrated,
+               "The hit carries a calibrated energy deposition (V3)")
+        .def_readonly("saturated_a",   &TofHit::saturated_a,
+               "The pulse on side A reached the saturation level (V3)")
+        .def_readonly("saturated_b",   &TofHit::saturated_b,
+               "The pulse on side B reached the saturation level (V3)")
+        .def_readonly("fit_chi2_a",    &TofHit::fit_chi2_a,
+               "Template fit chi2/ndof for side A, 0 for cfd (V3)")
+        .def_readonly("fit_chi2_b",    &TofHit::fit_chi2_b,
+               "Template fit chi2/ndof for side B, 0 for cfd (V3)")
         .def_property_readonly("x_pos",         &TofHit::get_x_pos,
                "Reconstructed position along the paddle")
         .def_property_readonly("t_avg",         &TofHit::get_t_avg,
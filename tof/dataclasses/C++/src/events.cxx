@@ -64,6 +64,11 @@ RBEventHeader::RBEventHeader() {
   fpga_temp          = 0;
   timestamp16        = 0; 
   timestamp32        = 0; 
+  version            = Gaps::ProtocolVersion::Unknown;
+  sine_fit_amp       = 0;
+  sine_fit_freq      = 0;
+  sine_fit_phase     = 0;
+  sine_fit_rms       = 0;
 }
 
 /*************************************/
@@ -134,6 +139,14 @@ RBEventHeader RBEventHeader::from_bytestream(const Vec<u8> &stream,
   header.fpga_temp           = Gaps::parse_u16(stream, pos);  
   header.timestamp32         = Gaps::parse_u32(stream, pos);
   header.timestamp16         = Gaps::parse_u16(stream, pos);
+  // the version is in bits 13 and 14 of the channel mask
+  header.version             = (Gaps::ProtocolVersion)((header.channel_mask >> 7) & 0xc0);
+  if (header.version == Gaps::ProtocolVersion::V1) {
+    header.sine_fit_amp      = Gaps::parse_f32(stream, pos);
+    header.sine_fit_freq     = Gaps::parse_f32(stream, pos);
+    header.sine_fit_phase    = Gaps::parse_f32(stream, pos);
+    header.sine_fit_rms      = Gaps::parse_f32(stream, pos);
+  }
   u16 tail                   = Gaps::parse_u16(stream, pos);
   if (tail != RBEventHeader::TAIL) {
     log_error("Tail signature incorrect! Got tail " << tail);
@@ -1010,10 +1023,22 @@ f32 TofHit::get_charge_b() const {
 }
 
 f32 TofHit::get_charge_min_i() const {
+  if (version == Gaps::ProtocolVersion::V3) {
+    // the field holds edep instead
+    return std::nanf("");
+  }
   f32 prec = 0.002;// minI
   return prec*charge_min_i - 10;
 }
 
+f32 TofHit::get_edep() const {
+  if (energy_calibrated) {
+    return edep;
+  }
+  // Philip's energy deposition based on peak height
+  return (1.29/34.3)*(get_peak_a() + get_peak_b())/2.0;
+}
+
 f32 TofHit::get_x_pos() const {
   // FIXME - check if it is really in the middle
   if (version == Gaps::ProtocolVersion::Unknown) {
@@ -1063,13 +1088,33 @@ TofHit TofHit::from_bytestream(const Vec<u8> &bytestream,
  hit.peak_b_f32     = Gaps::parse_f16(bytestream, pos); 
  hit.charge_a_f32   = Gaps::parse_f16(bytestream, pos); 
  hit.charge_b_f32   = Gaps::parse_f16(bytestream, pos); 
- hit.charge_min_i   = Gaps::parse_u16(bytestream, pos); 
+ if (hit.version == Gaps::ProtocolVersion::V3) {
+   hit.edep         = Gaps::parse_f16(bytestream, pos);
+ } else {
+   hit.charge_min_i = Gaps::parse_u16(bytestream, pos); 
+ }
  hit.baseline_a     = Gaps::parse_f16(bytestream, pos);
  hit.baseline_a_rms = Gaps::parse_f16(bytestream, pos);
  hit.phase          = Gaps::parse_f16(bytestream, pos);
  pos += 1; // skip version
  hit.baseline_b     = Gaps::parse_f16(bytestream, pos);
  hit.baseline_b_rms = Gaps::parse_f16(bytestream, pos);
+ if (hit.version == Gaps::ProtocolVersion::V2 
+  || hit.version == Gaps::ProtocolVersion::V3) {
+   hit.pileup     = Gaps::parse_u8(bytestream, pos);
+   hit.n_pulses_a = Gaps::parse_u8(bytestream, pos);
+   hit.n_pulses_b = Gaps::parse_u8(bytestream, pos);
+   // 6 bytes (time, peak, charge) per secondary pulse
+   pos += 6*(hit.n_pulses_a + hit.n_pulses_b);
+ }
+ if (hit.version == Gaps::ProtocolVersion::V3) {
+   u8 flags              = Gaps::parse_u8(bytestream, pos);
+   hit.energy_calibrated = (flags & 0x1) != 0;
+   hit.saturated_a       = (flags & 0x2) != 0;
+   hit.saturated_b       = (flags & 0x4) != 0;
+   hit.fit_chi2_a        = Gaps::parse_f16(bytestream, pos);
+   hit.fit_chi2_b        = Gaps::parse_f16(bytestream, pos);
+ }
  
  // FIXME checks - packetlength, checksum ?
  u16 tail = Gaps::parse_u16(bytestream, pos);
@@ -1099,6 +1144,11 @@ std::string TofHit::to_string() const {
   repr += std::format("\n  >> charge     A | B  : {} {}",get_charge_a(), get_charge_b());
   repr += std::format("\n  >> baseline   A | B  : {} {}",baseline_a, baseline_b);
   repr += std::format("\n  >> base. rms  A | B  : {} {}",baseline_a_rms, baseline_b_rms);
+  repr += std::format("\n  >> edep [MeV]        : {} (calibrated {})", get_edep(), energy_calibrated);
+  if (version == Gaps::ProtocolVersion::V3) {
+    repr += std::format("\n  >> saturated  A | B  : {} {}", saturated_a, saturated_b);
+    repr += std::format("\n  >> fit chi2   A | B  : {} {}", fit_chi2_a, fit_chi2_b);
+  }
   //repr += "\n  >>  height A | B  : "     + std::to_string(get_peak_a()      )
   //     +  " " + std::to_string(get_time_a());
   //repr += "\n  >>  charge A | B  : "     + std::to_string(get_charge_a()    )
@@ -1,5 +1,6 @@
 use criterion::{
-    //use tof_dataclasses::events::TofEvent;black_box, 
+    //use tof_dataclasses::events::TofEvent;
+    black_box, 
     criterion_group,
     criterion_main,
     Criterion
@@ -30,6 +31,14 @@ use tof_dataclasses::serialization::{
     Packable
 };
 use tof_dataclasses::FromRandom;
+use tof_dataclasses::calibrations::{
+    RBCalibrations,
+    RBWaveforms,
+};
+use tof_dataclasses::constants::{
+    NWORDS,
+    NCHN,
+};
 
 // setup tests
 
@@ -125,6 +134,71 @@ fn pack_rbevent() {
   let _ : RBEvent = ev.pack().unpack().unwrap();
 }
 
+// waveform calibration for all channels of an event
+
+fn calibration_event() -> RBEvent {
+  let mut ev = RBEvent::new();
+  ev.header.stop_cell = 717;
+  for ch in 0..NCHN {
+    ev.adc[ch] = (0..NWORDS).map(|k| ((k*7 + ch*13) % 4096) as u16).collect();
+  }
+  ev
+}
+
+/// The sample by sample calibration as it was done 
+/// before the calibration kernels, as reference
+fn calibrate_event_per_sample(cali     : &RBCalibrations,
+                              ev       : &RBEvent,
+                              voltages : &mut Vec<Vec<f32>>,
+                              times    : &mut Vec<Vec<f32>>) {
+  let stop_cell = ev.header.stop_cell as usize;
+  for ch in 0..NCHN {
+    let mut value : f32;
+    for k in 0..NWORDS {
+      value  = ev.adc[ch][k] as f32;
+      value -= cali.v_offsets[ch][(k + (stop_cell)) %NWORDS];
+      value -= cali.v_dips   [ch][k];
+      value *= cali.v_inc    [ch][(k + (stop_cell)) %NWORDS];
+      voltages[ch][k] = value;
+    }
+    for k in 1..NWORDS { 
+      times[ch][k] = times[ch][k-1] + cali.tbin[ch][(k-1+(stop_cell))%NWORDS];
+    }
+  }
+}
+
+fn bench_calibrate_event_per_sample(c: &mut Criterion) {
+  let cali         = RBCalibrations::from_random();
+  let ev           = calibration_event();
+  let mut voltages = vec![vec![0.0f32;NWORDS];NCHN];
+  let mut times    = vec![vec![0.0f32;NWORDS];NCHN];
+  c.bench_function("calibrate_event_per_sample", |b|
+                   b.iter(|| calibrate_event_per_sample(&cali, black_box(&ev), &mut voltages, &mut times)));
+}
+
+fn bench_calibrate_event_per_channel(c: &mut Criterion) {
+  let cali         = RBCalibrations::from_random();
+  let ev           = calibration_event();
+  let mut voltages = vec![0.0f32;NWORDS];
+  let mut times    = vec![0.0f32;NWORDS];
+  c.bench_function("calibrate_event_per_channel", |b|
+                   b.iter(|| {
+                     for ch in 0..NCHN {
+                       cali.voltages(ch + 1, ev.header.stop_cell as usize, black_box(&ev.adc[ch]), &mut voltages);
+                       cali.nanoseconds(ch + 1, ev.header.stop_cell as usize, &mut times);
+                     }
+                   }));
+}
+
+fn bench_calibrate_event_batch(c: &mut Criterion) {
+  let cali         = RBCalibrations::from_random();
+  let ev           = calibration_event();
+  let mut voltages : RBWaveforms = [[0.0f32;NWORDS];NCHN];
+  let mut times    : RBWaveforms = [[0.0f32;NWORDS];NCHN];
+  c.bench_function("calibrate_event_batch", |b|
+                   b.iter(|| cali.calibrate_event(black_box(&ev), &mut voltages, &mut times)));
+}
+
 fn bench_tpreader_read(c: &mut Criterion) {
   c.bench_function("tpreader_read", |b| 
                    b.iter(|| tpreader_read()));
@@ -223,6 +297,9 @@ cfg_if::cfg_if! {
                      bench_pack_tofevent_best,
                      bench_pack_tofevent_average,
                      bench_pack_rbevent,
+                     bench_calibrate_event_per_sample,
+                     bench_calibrate_event_per_channel,
+                     bench_calibrate_event_batch,
                      );
     criterion_main!(benches, benches_random);
   } else {
@@ -184,6 +184,7 @@ pub enum Variable {
   AvailableDiskSpace,
   DataMangling,
   MoniData,
+  ChannelNoise,
 }
 
 impl fmt::Display for Variable {
@@ -200,6 +201,7 @@ impl fmt::Display for Variable {
       Variable::AvailableDiskSpace => repr = "AvailableDiskSpace",
       Variable::DataMangling       => repr = "DataMangling",
       Variable::MoniData           => repr = "MoniData",
+      Variable::ChannelNoise       => repr = "ChannelNoise",
     };
     write!(f, "{}", repr)
   }
@@ -565,6 +567,8 @@ pub struct TofAlertManifest {
   pub cpu_core1_temp  : TofAlertConfig,
   pub cpu_hk_too_old  : TofAlertConfig,
   pub cpu_disk        : TofAlertConfig,
+  pub rb_ch_noisy     : TofAlertConfig,
+  pub rb_ch_dead      : TofAlertConfig,
   pub rb01_temp       : TofAlertConfig,
   pub rb02_temp       : TofAlertConfig,  
   pub rb03_temp       : TofAlertConfig,  
@@ -703,6 +707,13 @@ impl TofAlertManifest {
       cpu_core1_temp  : TofAlertConfig::new(),
       cpu_hk_too_old  : TofAlertConfig::new(),
       cpu_disk        : TofAlertConfig::new(),
+      // noise of the RB channels [mV]
+      rb_ch_noisy     : TofAlertConfig {
+                          max_allowed : Some(5.0),
+                          ..TofAlertConfig::new()},
+      rb_ch_dead      : TofAlertConfig {
+                          min_allowed : Some(0.1),
+                          ..TofAlertConfig::new()},
       rb01_temp       : TofAlertConfig::new(),
       rb02_temp       : TofAlertConfig::new(),  
       rb03_temp       : TofAlertConfig::new(),  
@@ -840,6 +851,8 @@ impl TofAlertManifest {
                     "cpu_core1_temp",
                     "cpu_hk_too_old",
                     "cpu_disk",
+                    "rb_ch_noisy",
+                    "rb_ch_dead",
                     "rb01_temp",
                     "rb02_temp",  
                     "rb03_temp",  
@@ -979,6 +992,8 @@ impl TofAlertManifest {
       "cpu_core1_temp"  => Some(self.cpu_core1_temp.clone()),
       "cpu_hk_too_old"  => Some(self.cpu_hk_too_old.clone()),
       "cpu_disk"        => Some(self.cpu_disk.clone()),
+      "rb_ch_noisy"     => Some(self.rb_ch_noisy.clone()),
+      "rb_ch_dead"      => Some(self.rb_ch_dead.clone()),
       "rb01_temp"       => Some(self.rb01_temp.clone()),
       "rb02_temp"       => Some(self.rb02_temp.clone()),  
       "rb03_temp"       => Some(self.rb03_temp.clone()),  
@@ -1292,6 +1307,26 @@ pub fn load_alerts<'a>(manifest : TofAlertManifest) -> HashMap<&'a str, TofAlert
                                                         component    : Component::System,
                                                         n_paged      : 0,
                                                         triggered    : None}),
+                                    ("rb_ch_noisy", TofAlert {
+                                                        key          : "rb_ch_noisy",
+                                                        descr        : "The noise of one or more RB channels is excessive!",
+                                                        whattodo     : vec!["Check the RBChannelNoiseMoniData for the affected boards and channels", "If it is a single channel, consider masking it", "If it is a whole RAT, check the power (PB) and the preamp bias"],
+                                                        config       : TofAlertConfig::new(),
+                                                        variable     : Variable::ChannelNoise,
+                                                        outofbound   : OutOfBound::TooHigh,
+                                                        component    : Component::System,
+                                                        n_paged      : 0,
+                                                        triggered    : None}),
+                                    ("rb_ch_dead", TofAlert {
+                                                        key          : "rb_ch_dead",
+                                                        descr        : "One or more RB channels seem dead (flat baseline)!",
+                                                        whattodo     : vec!["Check the RBChannelNoiseMoniData for the affected boards and channels", "Check the preamp bias of the paddle", "Not critical for a single channel, take notes"],
+                                                        config       : TofAlertConfig::new(),
+                                                        variable     : Variable::ChannelNoise,
+                                                        outofbound   : OutOfBound::TooLow,
+                                                        component    : Component::System,
+                                                        n_paged      : 0,
+                                                        triggered    : None}),
 
                                     // 40x 3 RB alerts. I am only doing this to celebrate me being
                                     // miserable on basically ny's eve. May the gods have mercy and 
@@ -15,7 +15,7 @@ extern crate smoothed_z_score;
 use smoothed_z_score::{Peak, PeaksDetector, PeaksFilter};
 
 /// Return the bin with the maximum ADC value
-pub fn get_max_bin(voltages    : &Vec<f32>,
+pub fn get_max_bin(voltages    : &[f32],
                    lower_bound : usize,
                    window      : usize) -> Result<usize, WaveformError> {
   if lower_bound >= voltages.len() {
@@ -42,8 +42,8 @@ pub fn get_max_bin(voltages    : &Vec<f32>,
 ///
 ///
 ///
-pub fn interpolate_time (voltages      : &Vec<f32>,
-                         nanoseconds   : &Vec<f32>, 
+pub fn interpolate_time (voltages      : &[f32],
+                         nanoseconds   : &[f32], 
                          mut threshold : f32,
                          mut idx       : usize,
                          size          : usize) -> Result<f32, WaveformError> {
@@ -85,8 +85,8 @@ pub fn interpolate_time (voltages      : &Vec<f32>,
 /// # Arguments:
 ///
 /// * impedance : typically this is 
-pub fn integrate(voltages     : &Vec<f32>,
-                 nanoseconds  : &Vec<f32>,
+pub fn integrate(voltages     : &[f32],
+                 nanoseconds  : &[f32],
                  lo_bin       : usize,
                  upper_bin    : usize,
                  impedance    : f32) -> Result<f32, WaveformError>  {
@@ -119,7 +119,7 @@ pub fn integrate(voltages     : &Vec<f32>,
 /// Given a time in ns, find the bin most closely corresponding to that time
 /// # Arguments
 /// 
-pub fn time2bin(nanoseconds : &Vec<f32>,
+pub fn time2bin(nanoseconds : &[f32],
                 t_ns        : f32) -> Result<usize, WaveformError> {
   for n in 0..nanoseconds.len() {
     if nanoseconds[n] > t_ns {
@@ -144,7 +144,7 @@ pub fn time2bin(nanoseconds : &Vec<f32>,
 ///
 /// # Return
 /// pedestal value with error (quadratic error)
-pub fn calculate_pedestal(voltages      : &Vec<f32>,
+pub fn calculate_pedestal(voltages      : &[f32],
                           threshold     : f32,
                           ped_begin_bin : usize,
                           ped_range_bin : usize) -> (f32,f32) {
@@ -166,8 +166,8 @@ pub fn calculate_pedestal(voltages      : &Vec<f32>,
 ///
 /// The peaks have to be sane
 /// FIXME: Maybe introduce a separate check?
-pub fn cfd_simple(voltages    : &Vec<f32>,
-                  nanoseconds : &Vec<f32>,
+pub fn cfd_simple(voltages    : &[f32],
+                  nanoseconds : &[f32],
                   cfd_frac    : f32,
                   start_peak  : usize,
                   end_peak    : usize) -> Result<f32, WaveformError> {
@@ -233,8 +233,8 @@ pub fn cfd_simple(voltages    : &Vec<f32>,
 /// 
 /// Vec<(peak_begin_bin, peak_end_bin)>
 ///
-pub fn find_peaks(voltages       : &Vec<f32>,
-                  nanoseconds    : &Vec<f32>,
+pub fn find_peaks(voltages       : &[f32],
+                  nanoseconds    : &[f32],
                   start_time     : f32,
                   window_size    : f32,
                   min_peak_width : usize,
@@ -432,8 +432,8 @@ fn find_sequence_ranges(vec: Vec<usize>) -> Vec<(usize, usize)> {
 ///                   putting the influence option at 1 is least robust.
 ///                   For non-stationary data, the influence option should
 ///                   therefore be put between 0 and 1.
-pub fn find_peaks_zscore(nanoseconds    : &Vec<f32>,
-                         voltages       : &Vec<f32>,
+pub fn find_peaks_zscore(nanoseconds    : &[f32],
+                         voltages       : &[f32],
                          start_time     : f32,
                          window_size    : f32,
                          lag            : usize,
@@ -447,11 +447,11 @@ pub fn find_peaks_zscore(nanoseconds    : &Vec<f32>,
   ranged_voltage.extend_from_slice(&voltages[start_bin..=end_bin]);
   //30, 5.0, 0.0
 
-  let output: Vec<_> = voltages
-            .into_iter()
+  let output: Vec<_> = ranged_voltage
+            .iter()
             .enumerate()
             .peaks(PeaksDetector::new(lag, threshold, influence), |e| *e.1 as f64)
-            .map(|((i, _), p)| (i, p))
+            .map(|((i, _), p)| (i + start_bin, p))
             .collect();
   // we ignore low peaks
   if output.len() == 0 {
@@ -463,9 +463,31 @@ pub fn find_peaks_zscore(nanoseconds    : &Vec<f32>,
       peak_high.push(k.0);
     }
   }
-  if peaks.len() > 0 {
+  if peak_high.len() > 0 {
     peaks = find_sequence_ranges(peak_high); 
   }
   Ok(peaks)
 }
 
+
+#[cfg(feature = "advanced-algorithms")]
+#[test]
+fn find_peaks_zscore_window() {
+  let nanoseconds : Vec<f32> = (0..1024).map(|k| 0.5*k as f32).collect();
+  // small alternating baseline, so the moving 
+  // standard deviation does not vanish
+  let mut voltages : Vec<f32> = (0..1024).map(|k| (k % 2) as f32).collect();
+  // one pulse before the window [250ns, 350ns] 
+  // (bins 500-700) and one inside
+  for k in 200..205 {
+    voltages[k] = 100.0;
+  }
+  for k in 600..605 {
+    voltages[k] = 100.0;
+  }
+  let peaks = find_peaks_zscore(&nanoseconds, &voltages,
+                                250.0, 100.0, 30, 5.0, 0.0).unwrap();
+  // only the pulse in the window is found, with 
+  // its bins in the full waveform
+  assert_eq!(peaks, vec![(600, 604)]);
+}
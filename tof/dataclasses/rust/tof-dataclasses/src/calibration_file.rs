@@ -0,0 +1,91 @@
+//! Common parts of the calibrations which are
+//! stored as JSON files
+//!
+//! * CalibrationHeader - version, time of creation and
+//!   description of a calibration. It gets flattened
+//!   into the JSON of the calibration.
+//! * JsonFile - to_file/from_file for everything which
+//!   can be (de)serialized with serde
+
+use std::fs;
+use std::error::Error;
+
+use serde::{
+  Serialize,
+  Deserialize,
+};
+use serde::de::DeserializeOwned;
+
+/// Version, time of creation and description
+/// of a calibration
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationHeader {
+  /// Gets incremented with each update of the
+  /// calibration (see update)
+  pub version     : u32,
+  /// Time of creation (UTC, RFC 3339)
+  pub created     : String,
+  /// Free text, e.g. the runs the calibration
+  /// was derived from
+  pub description : String,
+}
+
+impl CalibrationHeader {
+
+  pub fn new() -> Self {
+    Self {
+      version     : 0,
+      created     : chrono::Utc::now().to_rfc3339(),
+      description : String::from(""),
+    }
+  }
+
+  /// Update with the header of newer constants,
+  /// which get merged into the calibration.
+  ///
+  /// The version is incremented beyond both
+  /// versions, time of creation and description
+  /// are taken from the newer header.
+  pub fn update(&mut self, newer : &CalibrationHeader) {
+    self.version     = self.version.max(newer.version) + 1;
+    self.created     = newer.created.clone();
+    self.description = newer.description.clone();
+  }
+}
+
+/// Save/load as JSON file
+pub trait JsonFile : Serialize + DeserializeOwned {
+
+  /// Indent the JSON. Switch off for large
+  /// files which nobody will read by eye.
+  const PRETTY : bool = true;
+
+  /// Save as JSON
+  fn to_file(&self, filename : &str) -> Result<(), Box<dyn Error>> {
+    let json = if Self::PRETTY {
+      serde_json::to_string_pretty(self)?
+    } else {
+      serde_json::to_string(self)?
+    };
+    fs::write(filename, json)?;
+    Ok(())
+  }
+
+  fn from_file(filename : &str) -> Result<Self, Box<dyn Error>> {
+    let json = fs::read_to_string(filename)?;
+    let data = serde_json::from_str(&json)?;
+    Ok(data)
+  }
+}
+
+#[test]
+fn calibration_header_update() {
+  let mut header    = CalibrationHeader::new();
+  header.version    = 3;
+  let mut newer     = CalibrationHeader::new();
+  newer.version     = 1;
+  newer.description = String::from("runs 100-200");
+  header.update(&newer);
+  assert_eq!(header.version, 4);
+  assert_eq!(header.description, "runs 100-200");
+}
@@ -0,0 +1,519 @@
+//! Quality report for the RB calibration
+//!
+//! RBCalibrations::calibrate derives the voltage and
+//! timing constants from the calibration data, but
+//! does not tell if the result is sane. The
+//! RBCalibrationQuality summarizes per channel
+//!
+//! * the residuals of the calibrated voltage calibration
+//!   data with respect to the input voltage d_v
+//! * the number of cells with outlier constants
+//! * the sum of the cell widths, which should be close
+//!   to the nominal sampling window of 1024 cells at
+//!   2 GHz (512 ns)
+//! * the χ²/ndf of a sine fit to the calibrated timing
+//!   calibration data
+//! * the noise of the calibrated no input data
+//!
+//! The report is created on the RB right after the
+//! calibration (the data is usually not sent along) and
+//! published as its own packet, with rb_id and
+//! timestamp of the calibration it belongs to.
+//! RBCalibrationQualityCuts decides if the calibration
+//! can be used.
+
+use std::fmt;
+use std::f32::consts::PI;
+
+use crate::constants::{
+  NWORDS,
+  NCHN,
+};
+use crate::calibrations::RBCalibrations;
+use crate::serialization::{
+  Serialization,
+  Packable,
+  parse_u8,
+  parse_u16,
+  parse_u32,
+  parse_f32,
+  SerializationError
+};
+use crate::packets::PacketType;
+
+cfg_if::cfg_if! {
+  if #[cfg(feature = "random")]  {
+    use crate::FromRandom;
+    extern crate rand;
+    use rand::Rng;
+  }
+}
+
+/// Cells with a voltage increment deviating more
+/// than this (relative) from the channel median
+/// are outliers
+const MAX_VINC_DEVIATION : f32 = 0.2;
+/// Cells with a width deviating more than this
+/// (relative) from the nominal cell width are
+/// outliers
+const MAX_TBIN_DEVIATION : f32 = 0.5;
+/// Noise [mV] assumed for the sine fit χ²
+/// if there is no no input data
+const DEFAULT_SIGMA      : f32 = 1.0;
+
+/// Fit a*sin(ωt) + b*cos(ωt) + c with fixed ω
+/// (linear least squares)
+///
+/// # Returns
+///
+/// sum of the squared residuals, number of points
+fn fit_sine_fixed_freq(times  : &[f32],
+                       volts  : &[f32],
+                       omega  : f32) -> Option<(f64, usize)> {
+  let n = times.len();
+  if n < 4 {
+    return None;
+  }
+  // normal equations, basis (sin, cos, 1)
+  let mut m = [[0.0f64;3];3];
+  let mut y = [0.0f64;3];
+  for (t, v) in times.iter().zip(volts.iter()) {
+    let basis = [(omega*t).sin() as f64, (omega*t).cos() as f64, 1.0];
+    for i in 0..3 {
+      for j in 0..3 {
+        m[i][j] += basis[i]*basis[j];
+      }
+      y[i] += basis[i]*(*v as f64);
+    }
+  }
+  let det = |a : &[[f64;3];3]| {
+      a[0][0]*(a[1][1]*a[2][2] - a[1][2]*a[2][1])
+    - a[0][1]*(a[1][0]*a[2][2] - a[1][2]*a[2][0])
+    + a[0][2]*(a[1][0]*a[2][1] - a[1][1]*a[2][0])
+  };
+  let d = det(&m);
+  if d.abs() < f64::EPSILON {
+    return None;
+  }
+  // Cramer's rule
+  let mut pars = [0.0f64;3];
+  for (k, par) in pars.iter_mut().enumerate() {
+    let mut mk = m;
+    for i in 0..3 {
+      mk[i][k] = y[i];
+    }
+    *par = det(&mk)/d;
+  }
+  let mut chi2 = 0.0f64;
+  for (t, v) in times.iter().zip(volts.iter()) {
+    let fit = pars[0]*(omega*t).sin() as f64 + pars[1]*(omega*t).cos() as f64 + pars[2];
+    chi2   += (*v as f64 - fit).powi(2);
+  }
+  Some((chi2, n))
+}
+
+/// Quality metrics of a RBCalibration, per channel
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RBCalibrationQuality {
+  pub rb_id           : u8,
+  /// Timestamp of the calibration this
+  /// report belongs to
+  pub timestamp       : u32,
+  /// Number of no input events
+  pub n_noi           : u16,
+  /// Number of voltage calibration events
+  pub n_vcal          : u16,
+  /// Number of timing calibration events
+  pub n_tcal          : u16,
+  /// RMS of the calibrated voltage calibration
+  /// data around d_v [mV] (NaN without data)
+  pub vcal_residual   : [f32;NCHN],
+  /// Number of cells with non-finite or
+  /// outlier calibration constants
+  pub n_outlier_cells : [u16;NCHN],
+  /// Sum of the cell widths [ns]
+  pub tbin_sum        : [f32;NCHN],
+  /// Mean χ²/ndf of a sine fit to the calibrated
+  /// timing calibration data (NaN without data)
+  pub sine_chi2       : [f32;NCHN],
+  /// Mean RMS of the calibrated no input
+  /// data [mV] (NaN without data)
+  pub noise           : [f32;NCHN],
+}
+
+impl RBCalibrationQuality {
+
+  pub fn new() -> Self {
+    Self {
+      rb_id           : 0,
+      timestamp       : 0,
+      n_noi           : 0,
+      n_vcal          : 0,
+      n_tcal          : 0,
+      vcal_residual   : [f32::NAN;NCHN],
+      n_outlier_cells : [0;NCHN],
+      tbin_sum        : [0.0;NCHN],
+      sine_chi2       : [f32::NAN;NCHN],
+      noise           : [f32::NAN;NCHN],
+    }
+  }
+
+  /// Evaluate a calibration. The metrics which need
+  /// the calibration data are NaN if the data has
+  /// been discarded.
+  pub fn from_calibration(cali : &RBCalibrations) -> Self {
+    let mut quality   = Self::new();
+    quality.rb_id     = cali.rb_id;
+    quality.timestamp = cali.timestamp;
+    quality.n_noi     = cali.noi_data.len()  as u16;
+    quality.n_vcal    = cali.vcal_data.len() as u16;
+    quality.n_tcal    = cali.tcal_data.len() as u16;
+    let nominal_tbin  = 1.0/RBCalibrations::NOMINALFREQ;
+    let omega         = 2.0*PI*RBCalibrations::CALFREQ;
+    let mut volts     = vec![0.0f32;NWORDS];
+    let mut times     = vec![0.0f32;NWORDS];
+    for ch in 0..NCHN {
+      // the constants
+      let mut v_inc = cali.v_inc[ch].to_vec();
+      v_inc.sort_by(|a, b| a.total_cmp(b));
+      let v_inc_median = v_inc[NWORDS/2];
+      let mut n_outliers = 0u16;
+      for k in 0..NWORDS {
+        if !cali.v_offsets[ch][k].is_finite()
+        || !cali.v_dips[ch][k].is_finite()
+        || !cali.v_inc[ch][k].is_finite()
+        || !cali.tbin[ch][k].is_finite()
+        || (cali.v_inc[ch][k]/v_inc_median - 1.0).abs() > MAX_VINC_DEVIATION
+        || (cali.tbin[ch][k]/nominal_tbin  - 1.0).abs() > MAX_TBIN_DEVIATION {
+          n_outliers += 1;
+        }
+      }
+      quality.n_outlier_cells[ch] = n_outliers;
+      quality.tbin_sum[ch]        = cali.tbin[ch].iter().sum();
+      // no input data
+      // (events with incomplete traces are skipped)
+      let mut rms_sum = 0.0f32;
+      let mut n_noi   = 0usize;
+      for ev in cali.noi_data.iter().filter(|ev| ev.adc[ch].len() == NWORDS) {
+        cali.voltages_into(ch + 1, ev.header.stop_cell as usize, &ev.adc[ch], &mut volts);
+        let good  = &volts[RBCalibrations::NSKIP..];
+        let mean  = good.iter().sum::<f32>()/good.len() as f32;
+        rms_sum  += (good.iter().map(|v| (v - mean).powi(2)).sum::<f32>()/good.len() as f32).sqrt();
+        n_noi    += 1;
+      }
+      if n_noi > 0 {
+        quality.noise[ch] = rms_sum/n_noi as f32;
+      }
+      // voltage calibration data
+      let mut res2  = 0.0f64;
+      let mut n_res = 0usize;
+      for ev in cali.vcal_data.iter().filter(|ev| ev.adc[ch].len() == NWORDS) {
+        cali.voltages_into(ch + 1, ev.header.stop_cell as usize, &ev.adc[ch], &mut volts);
+        for v in &volts[RBCalibrations::NSKIP..] {
+          res2  += ((v - cali.d_v) as f64).powi(2);
+          n_res += 1;
+        }
+      }
+      if n_res > 0 {
+        quality.vcal_residual[ch] = (res2/n_res as f64).sqrt() as f32;
+      }
+      // timing calibration data
+      if !cali.tcal_data.is_empty() {
+        let sigma = if quality.noise[ch].is_finite() && quality.noise[ch] > 0.0 {
+          quality.noise[ch]
+        } else {
+          DEFAULT_SIGMA
+        };
+        let mut chi2_sum = 0.0f64;
+        let mut n_fits   = 0usize;
+        let mut fit_t    = Vec::<f32>::with_capacity(NWORDS);
+        let mut fit_v    = Vec::<f32>::with_capacity(NWORDS);
+        for ev in cali.tcal_data.iter().filter(|ev| ev.adc[ch].len() == NWORDS) {
+          let stop_cell = ev.header.stop_cell as usize;
+          cali.voltages_into(ch + 1, stop_cell, &ev.adc[ch], &mut volts);
+          cali.nanoseconds_into(ch + 1, stop_cell, &mut times);
+          fit_t.clear();
+          fit_v.clear();
+          // same selection as for the timing calibration
+          for k in RBCalibrations::NSKIP..NWORDS {
+            if volts[k].abs() <= RBCalibrations::SINMAX as f32 {
+              fit_t.push(times[k]);
+              fit_v.push(volts[k]);
+            }
+          }
+          if let Some((chi2, n)) = fit_sine_fixed_freq(&fit_t, &fit_v, omega) {
+            chi2_sum += chi2/((sigma as f64).powi(2)*(n - 3) as f64);
+            n_fits   += 1;
+          }
+        }
+        if n_fits > 0 {
+          quality.sine_chi2[ch] = (chi2_sum/n_fits as f64) as f32;
+        }
+      }
+    }
+    quality
+  }
+}
+
+impl Default for RBCalibrationQuality {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for RBCalibrationQuality {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<RBCalibrationQuality:");
+    repr += &(format!("\n  RB          : {}", self.rb_id));
+    repr += &(format!("\n  timestamp   : {}", self.timestamp));
+    repr += &(format!("\n  N noi/vcal/tcal events : {}/{}/{}", self.n_noi, self.n_vcal, self.n_tcal));
+    repr += "\n  ch | vcal res. [mV] | outliers | tbin sum [ns] | sine chi2 | noise [mV]";
+    for ch in 0..NCHN {
+      repr += &(format!("\n  {}  | {:14.3} | {:8} | {:13.3} | {:9.3} | {:10.3}",
+                        ch + 1,
+                        self.vcal_residual[ch],
+                        self.n_outlier_cells[ch],
+                        self.tbin_sum[ch],
+                        self.sine_chi2[ch],
+                        self.noise[ch]));
+    }
+    repr += ">";
+    write!(f, "{}", repr)
+  }
+}
+
+impl Packable for RBCalibrationQuality {
+  const PACKET_TYPE : PacketType = PacketType::RBCalibrationQuality;
+}
+
+impl Serialization for RBCalibrationQuality {
+  const HEAD : u16   = 0xAAAA;
+  const TAIL : u16   = 0x5555;
+  const SIZE : usize = 4 + 1 + 4 + 3*2 + NCHN*(4 + 2 + 4 + 4 + 4);
+
+  fn to_bytestream(&self) -> Vec<u8> {
+    let mut stream = Vec::<u8>::with_capacity(Self::SIZE);
+    stream.extend_from_slice(&Self::HEAD.to_le_bytes());
+    stream.push(self.rb_id);
+    stream.extend_from_slice(&self.timestamp.to_le_bytes());
+    stream.extend_from_slice(&self.n_noi    .to_le_bytes());
+    stream.extend_from_slice(&self.n_vcal   .to_le_bytes());
+    stream.extend_from_slice(&self.n_tcal   .to_le_bytes());
+    for ch in 0..NCHN {
+      stream.extend_from_slice(&self.vcal_residual[ch]  .to_le_bytes());
+      stream.extend_from_slice(&self.n_outlier_cells[ch].to_le_bytes());
+      stream.extend_from_slice(&self.tbin_sum[ch]       .to_le_bytes());
+      stream.extend_from_slice(&self.sine_chi2[ch]      .to_le_bytes());
+      stream.extend_from_slice(&self.noise[ch]          .to_le_bytes());
+    }
+    stream.extend_from_slice(&Self::TAIL.to_le_bytes());
+    stream
+  }
+
+  fn from_bytestream(stream : &Vec<u8>,
+                     pos    : &mut usize)
+    -> Result<Self, SerializationError> {
+    let mut quality = Self::new();
+    Self::verify_fixed(stream, pos)?;
+    quality.rb_id     = parse_u8 (stream, pos);
+    quality.timestamp = parse_u32(stream, pos);
+    quality.n_noi     = parse_u16(stream, pos);
+    quality.n_vcal    = parse_u16(stream, pos);
+    quality.n_tcal    = parse_u16(stream, pos);
+    for ch in 0..NCHN {
+      quality.vcal_residual[ch]   = parse_f32(stream, pos);
+      quality.n_outlier_cells[ch] = parse_u16(stream, pos);
+      quality.tbin_sum[ch]        = parse_f32(stream, pos);
+      quality.sine_chi2[ch]       = parse_f32(stream, pos);
+      quality.noise[ch]           = parse_f32(stream, pos);
+    }
+    *pos += 2;
+    Ok(quality)
+  }
+}
+
+#[cfg(feature = "random")]
+impl FromRandom for RBCalibrationQuality {
+  fn from_random() -> Self {
+    let mut quality   = Self::new();
+    let mut rng       = rand::thread_rng();
+    quality.rb_id     = rng.gen::<u8>();
+    quality.timestamp = rng.gen::<u32>();
+    quality.n_noi     = rng.gen::<u16>();
+    quality.n_vcal    = rng.gen::<u16>();
+    quality.n_tcal    = rng.gen::<u16>();
+    for ch in 0..NCHN {
+      quality.vcal_residual[ch]   = rng.gen::<f32>();
+      quality.n_outlier_cells[ch] = rng.gen::<u16>();
+      quality.tbin_sum[ch]        = rng.gen::<f32>();
+      quality.sine_chi2[ch]       = rng.gen::<f32>();
+      quality.noise[ch]           = rng.gen::<f32>();
+    }
+    quality
+  }
+}
+
+/// Thresholds for an acceptable RB calibration.
+///
+/// Metrics which are NaN in the quality report
+/// (no calibration data) are not checked. Cuts 
+/// missing in a configuration file take their 
+/// default value (see new()).
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RBCalibrationQualityCuts {
+  /// Maximum RMS of the calibrated voltage
+  /// calibration data around d_v [mV]
+  pub max_vcal_residual      : f32,
+  /// Maximum number of outlier cells per channel
+  pub max_outlier_cells      : u16,
+  /// Maximum deviation of the sum of the cell
+  /// widths from the nominal 512 ns [ns]
+  pub max_tbin_sum_deviation : f32,
+  /// Maximum χ²/ndf of the sine fits
+  pub max_sine_chi2          : f32,
+  /// Maximum noise [mV]
+  pub max_noise              : f32,
+}
+
+impl RBCalibrationQualityCuts {
+
+  pub fn new() -> Self {
+    Self {
+      max_vcal_residual      : 10.0,
+      max_outlier_cells      : 20,
+      max_tbin_sum_deviation : 10.0,
+      max_sine_chi2          : 10.0,
+      max_noise              : 10.0,
+    }
+  }
+
+  /// Check a quality report against the cuts
+  ///
+  /// # Returns
+  ///
+  /// A description of each failed cut, empty
+  /// if the calibration is good
+  pub fn get_failures(&self, quality : &RBCalibrationQuality) -> Vec<String> {
+    let mut failures = Vec::<String>::new();
+    let nominal_sum  = NWORDS as f32/RBCalibrations::NOMINALFREQ;
+    // NaN fails all comparisons, so unavailable
+    // metrics are not rejected
+    for ch in 0..NCHN {
+      if quality.vcal_residual[ch] > self.max_vcal_residual {
+        failures.push(format!("ch {} : vcal residual {:.3} mV > {:.3} mV", ch + 1, quality.vcal_residual[ch], self.max_vcal_residual));
+      }
+      if quality.n_outlier_cells[ch] > self.max_outlier_cells {
+        failures.push(format!("ch {} : {} outlier cells > {}", ch + 1, quality.n_outlier_cells[ch], self.max_outlier_cells));
+      }
+      // the constants are always there, so
+      // NaN here is a broken calibration
+      let tbin_sum_deviation = (quality.tbin_sum[ch] - nominal_sum).abs();
+      if tbin_sum_deviation.is_nan() || tbin_sum_deviation > self.max_tbin_sum_deviation {
+        failures.push(format!("ch {} : tbin sum {:.3} ns, nominal {:.1} ns", ch + 1, quality.tbin_sum[ch], nominal_sum));
+      }
+      if quality.sine_chi2[ch] > self.max_sine_chi2 {
+        failures.push(format!("ch {} : sine fit chi2/ndf {:.3} > {:.3}", ch + 1, quality.sine_chi2[ch], self.max_sine_chi2));
+      }
+      if quality.noise[ch] > self.max_noise {
+        failures.push(format!("ch {} : noise {:.3} mV > {:.3} mV", ch + 1, quality.noise[ch], self.max_noise));
+      }
+    }
+    failures
+  }
+
+  /// Check if a calibration passes all cuts
+  pub fn passes(&self, quality : &RBCalibrationQuality) -> bool {
+    self.get_failures(quality).is_empty()
+  }
+}
+
+impl Default for RBCalibrationQualityCuts {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for RBCalibrationQualityCuts {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<RBCalibrationQualityCuts:");
+    repr += &(format!("\n  max vcal residual : {:.3} mV", self.max_vcal_residual));
+    repr += &(format!("\n  max outlier cells : {}", self.max_outlier_cells));
+    repr += &(format!("\n  max tbin sum dev. : {:.3} ns", self.max_tbin_sum_deviation));
+    repr += &(format!("\n  max sine chi2/ndf : {:.3}", self.max_sine_chi2));
+    repr += &(format!("\n  max noise         : {:.3} mV>", self.max_noise));
+    write!(f, "{}", repr)
+  }
+}
+
+#[cfg(feature = "random")]
+#[test]
+fn pack_rbcalibrationquality() {
+  for _ in 0..100 {
+    let data = RBCalibrationQuality::from_random();
+    let test : RBCalibrationQuality = data.pack().unpack().unwrap();
+    assert_eq!(data, test);
+  }
+}
+
+#[test]
+fn calibration_quality() {
+  use crate::events::RBEvent;
+  // ideal constants, 0.5 ns cells, 1 mV/ADC
+  // and a pedestal of 1000 ADC
+  let mut cali = RBCalibrations::new(7);
+  cali.d_v = 100.0;
+  for ch in 0..NCHN {
+    for k in 0..NWORDS {
+      cali.v_offsets[ch][k] = 1000.0;
+      cali.v_inc[ch][k]     = 1.0;
+      cali.tbin[ch][k]      = 0.5;
+    }
+  }
+  // no input data with alternating noise of 2 ADC,
+  // vcal data at d_v, tcal data a clean sine
+  for n in 0..5 {
+    let mut noi  = RBEvent::new();
+    let mut vcal = RBEvent::new();
+    let mut tcal = RBEvent::new();
+    noi.header.stop_cell  = 100*n;
+    vcal.header.stop_cell = 100*n;
+    tcal.header.stop_cell = 100*n;
+    for ch in 0..NCHN {
+      noi.adc[ch]  = (0..NWORDS).map(|k| 1000 + 2*(k % 2) as u16).collect();
+      vcal.adc[ch] = vec![1100;NWORDS];
+      tcal.adc[ch] = (0..NWORDS).map(|k| (1000.0 + 50.0*(2.0*PI*0.025*0.5*k as f32).sin()).round() as u16).collect();
+    }
+    cali.noi_data.push(noi);
+    cali.vcal_data.push(vcal);
+    cali.tcal_data.push(tcal);
+  }
+  // broken cells
+  cali.tbin[2][10]  = f32::NAN;
+  cali.v_inc[4][20] = 10.0;
+  let quality = RBCalibrationQuality::from_calibration(&cali);
+  assert_eq!(quality.rb_id, 7);
+  assert_eq!(quality.n_tcal, 5);
+  assert_eq!(quality.n_outlier_cells[0], 0);
+  assert_eq!(quality.n_outlier_cells[2], 1);
+  assert_eq!(quality.n_outlier_cells[4], 1);
+  assert!((quality.tbin_sum[0] - 512.0).abs() < 1e-3);
+  assert!(quality.tbin_sum[2].is_nan());
+  assert!((quality.noise[0] - 1.0).abs() < 1e-3);
+  assert!(quality.vcal_residual[0] < 1e-3);
+  assert!(quality.vcal_residual[4] > 10.0);
+  assert!(quality.sine_chi2[0] < 1.0);
+  let cuts = RBCalibrationQualityCuts::new();
+  let failures = cuts.get_failures(&quality);
+  // NaN tbin sum in ch 3, vcal residual in ch5
+  assert_eq!(failures.len(), 2);
+  assert!(failures[0].starts_with("ch 3"));
+  assert!(failures[1].starts_with("ch 5"));
+  // data discarded - only the constants are checked
+  cali.discard_data();
+  cali.tbin[2][10] = 0.5;
+  let quality = RBCalibrationQuality::from_calibration(&cali);
+  assert!(quality.noise[0].is_nan());
+  assert!(quality.sine_chi2[0].is_nan());
+  assert!(cuts.passes(&quality));
+}
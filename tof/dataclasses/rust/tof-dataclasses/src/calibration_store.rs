@@ -0,0 +1,473 @@
+//! Bookkeeping of the RB calibrations
+//!
+//! ReadoutBoard::load_latest_calibration always picks
+//! the newest calibration file, which is right for
+//! data taking, but not for the analysis of older
+//! runs. The CalibrationStore keeps a manifest
+//! (CALIBRATION_STORE_MANIFEST, JSON) in the calibration
+//! directory with a record for each calibration file,
+//! with the RB id, the time of the calibration, the
+//! software version which took it, a summary of its
+//! RBCalibrationQuality and the interval it is valid
+//! for.
+//!
+//! A calibration is valid from its own timestamp (or
+//! its first run) on, until it is superseded by a newer
+//! one. The validity can be restricted with valid_until
+//! and last_run, and calibrations which should never be
+//! used (e.g. rejected by the quality checks) have
+//! accepted set to false.
+//!
+//! The calibration files are referenced relative to
+//! the directory of the manifest, so the whole
+//! directory can be moved.
+
+use std::fmt;
+use std::fs;
+use std::error::Error;
+use std::path::Path;
+
+use glob::glob;
+
+use serde::{
+  Serialize,
+  Deserialize,
+};
+
+use crate::constants::NWORDS;
+use crate::calibrations::RBCalibrations;
+use crate::calibration_quality::RBCalibrationQuality;
+use crate::calibration_file::JsonFile;
+use crate::errors::SerializationError;
+
+/// Name of the manifest in the calibration directory
+pub const CALIBRATION_STORE_MANIFEST : &str = "calibrations.json";
+
+/// Current format version of the manifest
+pub const CALIBRATION_STORE_VERSION  : u32 = 1;
+
+/// The worst value over all channels of each metric
+/// of a RBCalibrationQuality.
+///
+/// Metrics which are not available (e.g. because the
+/// calibration data was discarded) are None.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationQualitySummary {
+  /// Largest RMS of the voltage calibration
+  /// residuals [mV]
+  pub max_vcal_residual      : Option<f32>,
+  /// Largest number of outlier cells
+  pub max_outlier_cells      : u16,
+  /// Largest deviation of the sum of the cell
+  /// widths from the nominal window [ns]
+  pub max_tbin_sum_deviation : Option<f32>,
+  /// Largest mean χ²/ndf of the sine fits
+  pub max_sine_chi2          : Option<f32>,
+  /// Largest noise [mV]
+  pub max_noise              : Option<f32>,
+}
+
+impl CalibrationQualitySummary {
+
+  /// Largest finite value, None if there is none
+  fn get_max(values : impl Iterator<Item = f32>) -> Option<f32> {
+    values.filter(|x| x.is_finite()).reduce(f32::max)
+  }
+}
+
+impl From<&RBCalibrationQuality> for CalibrationQualitySummary {
+  fn from(quality : &RBCalibrationQuality) -> Self {
+    let nominal_sum = NWORDS as f32/RBCalibrations::NOMINALFREQ;
+    Self {
+      max_vcal_residual      : Self::get_max(quality.vcal_residual.iter().cloned()),
+      max_outlier_cells      : quality.n_outlier_cells.iter().cloned().max().unwrap_or(0),
+      max_tbin_sum_deviation : Self::get_max(quality.tbin_sum.iter().map(|s| (s - nominal_sum).abs())),
+      max_sine_chi2          : Self::get_max(quality.sine_chi2.iter().cloned()),
+      max_noise              : Self::get_max(quality.noise.iter().cloned()),
+    }
+  }
+}
+
+impl fmt::Display for CalibrationQualitySummary {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let fmt_opt = |x : Option<f32>| x.map_or(String::from("--"), |x| format!("{:.2}", x));
+    write!(f, "<CalibrationQualitySummary: vcal res. {}, outliers {}, tbin sum dev. {}, sine χ² {}, noise {}>",
+           fmt_opt(self.max_vcal_residual),
+           self.max_outlier_cells,
+           fmt_opt(self.max_tbin_sum_deviation),
+           fmt_opt(self.max_sine_chi2),
+           fmt_opt(self.max_noise))
+  }
+}
+
+/// A single calibration file in the store
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationRecord {
+  pub rb_id            : u8,
+  /// Time the calibration was taken
+  /// (RBCalibrations::timestamp)
+  pub timestamp        : u32,
+  /// The calibration file, relative to the
+  /// directory of the store
+  pub file             : String,
+  /// Version of the software which recorded the
+  /// calibration (empty if unknown)
+  pub software_version : String,
+  /// Calibrations which are not accepted are kept
+  /// in the store, but never selected
+  pub accepted         : bool,
+  /// Summary of the quality report (if there was
+  /// one)
+  pub quality          : Option<CalibrationQualitySummary>,
+  /// Valid from this time on (unix timestamp)
+  pub valid_from       : u32,
+  /// Valid until this time (including), None if
+  /// valid until superseded
+  pub valid_until      : Option<u32>,
+  /// Valid from this run on. Calibrations taken
+  /// outside of a run don't have one and can only
+  /// be found by time (unless it is set here).
+  pub first_run        : Option<u32>,
+  /// Last run (including) the calibration is
+  /// valid for, None if valid until superseded
+  pub last_run         : Option<u32>,
+}
+
+impl CalibrationRecord {
+
+  pub fn new(rb_id : u8, timestamp : u32, file : &str) -> Self {
+    Self {
+      rb_id,
+      timestamp,
+      file             : String::from(file),
+      software_version : String::from(""),
+      accepted         : true,
+      quality          : None,
+      valid_from       : timestamp,
+      valid_until      : None,
+      first_run        : None,
+      last_run         : None,
+    }
+  }
+
+  pub fn is_valid_for_time(&self, time : u32) -> bool {
+    self.accepted
+    && time >= self.valid_from
+    && time <= self.valid_until.unwrap_or(u32::MAX)
+  }
+
+  pub fn is_valid_for_run(&self, run_id : u32) -> bool {
+    self.accepted
+    && self.first_run.is_some_and(|first| run_id >= first)
+    && run_id <= self.last_run.unwrap_or(u32::MAX)
+  }
+}
+
+impl fmt::Display for CalibrationRecord {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let fmt_opt = |x : Option<u32>| x.map_or(String::from("--"), |x| x.to_string());
+    let mut repr = String::from("<CalibrationRecord:");
+    repr += &(format!("\n  RB          : {}", self.rb_id));
+    repr += &(format!("\n  timestamp   : {}", self.timestamp));
+    repr += &(format!("\n  file        : {}", self.file));
+    repr += &(format!("\n  sw version  : {}", self.software_version));
+    repr += &(format!("\n  accepted    : {}", self.accepted));
+    repr += &(format!("\n  valid time  : {} - {}", self.valid_from, fmt_opt(self.valid_until)));
+    repr += &(format!("\n  valid runs  : {} - {}", fmt_opt(self.first_run), fmt_opt(self.last_run)));
+    match self.quality {
+      None    => repr += "\n  quality     : -->",
+      Some(q) => repr += &(format!("\n  quality     : {}>", q)),
+    }
+    write!(f, "{}", repr)
+  }
+}
+
+/// All calibrations in a calibration directory
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationStore {
+  /// Format version of the manifest
+  pub version   : u32,
+  /// In the order they were added
+  pub records   : Vec<CalibrationRecord>,
+  /// Directory of the manifest and the base for
+  /// the file names in the records
+  #[serde(skip)]
+  pub directory : String,
+}
+
+impl CalibrationStore {
+
+  pub fn new(directory : &str) -> Self {
+    Self {
+      version   : CALIBRATION_STORE_VERSION,
+      records   : Vec::<CalibrationRecord>::new(),
+      directory : String::from(directory),
+    }
+  }
+
+  /// Open the store in a directory. If there is no
+  /// manifest yet, the store is empty.
+  pub fn open(directory : &str) -> Result<Self, Box<dyn Error>> {
+    let manifest = Self::get_manifest_path(directory);
+    if !Path::new(&manifest).exists() {
+      return Ok(Self::new(directory));
+    }
+    let mut store = Self::from_file(&manifest)?;
+    if store.version > CALIBRATION_STORE_VERSION {
+      return Err(format!("{} has version {}, but only versions up to {} are supported!", manifest, store.version, CALIBRATION_STORE_VERSION).into());
+    }
+    store.directory = String::from(directory);
+    Ok(store)
+  }
+
+  /// The path of the manifest for a directory
+  pub fn get_manifest_path(directory : &str) -> String {
+    format!("{}/{}", directory.trim_end_matches('/'), CALIBRATION_STORE_MANIFEST)
+  }
+
+  /// Write the manifest to the store directory
+  ///
+  /// The manifest gets replaced in one go, so that
+  /// readers never see a partially written file.
+  pub fn save(&self) -> Result<(), Box<dyn Error>> {
+    let manifest = Self::get_manifest_path(&self.directory);
+    let tmp_file = format!("{}.part", manifest);
+    self.to_file(&tmp_file)?;
+    fs::rename(&tmp_file, &manifest)?;
+    Ok(())
+  }
+
+  /// Add a record. A record for a calibration which
+  /// is already in the store (same board and
+  /// timestamp) replaces the existing one.
+  pub fn add(&mut self, record : CalibrationRecord) {
+    self.records.retain(|r| r.rb_id != record.rb_id || r.timestamp != record.timestamp);
+    self.records.push(record);
+  }
+
+  /// Add a calibration file
+  ///
+  /// # Arguments
+  ///
+  /// * filename         : the calibration file. If it is inside of
+  ///   the store directory, the record will have the relative path.
+  /// * cali             : the calibration in the file
+  /// * quality          : the quality report for the calibration
+  /// * software_version : the version of the software taking the
+  ///   calibration
+  /// * accepted         : false if it should not be used
+  /// * first_run        : the run the calibration was taken for
+  pub fn add_calibration(&mut self,
+                         filename         : &str,
+                         cali             : &RBCalibrations,
+                         quality          : Option<&RBCalibrationQuality>,
+                         software_version : &str,
+                         accepted         : bool,
+                         first_run        : Option<u32>) {
+    let mut record = CalibrationRecord::new(cali.rb_id, cali.timestamp, &self.get_relative_path(filename));
+    record.software_version = String::from(software_version);
+    record.accepted         = accepted;
+    record.quality          = quality.map(CalibrationQualitySummary::from);
+    record.first_run        = first_run;
+    self.add(record);
+  }
+
+  /// Paths inside of the store directory relative to
+  /// it, everything else as it is
+  fn get_relative_path(&self, filename : &str) -> String {
+    match Path::new(filename).strip_prefix(&self.directory) {
+      Ok(rel) if !self.directory.is_empty() => rel.to_string_lossy().to_string(),
+      _ => String::from(filename),
+    }
+  }
+
+  /// The full path of the file of a record
+  pub fn get_path(&self, record : &CalibrationRecord) -> String {
+    if Path::new(&record.file).is_absolute() || self.directory.is_empty() {
+      record.file.clone()
+    } else {
+      format!("{}/{}", self.directory.trim_end_matches('/'), record.file)
+    }
+  }
+
+  /// Read the calibration of a record (without the
+  /// calibration data)
+  pub fn load(&self, record : &CalibrationRecord) -> Result<RBCalibrations, SerializationError> {
+    RBCalibrations::from_file(self.get_path(record), true)
+  }
+
+  /// The newest accepted calibration of a board
+  pub fn get_latest(&self, rb_id : u8) -> Option<&CalibrationRecord> {
+    self.records.iter()
+      .filter(|r| r.rb_id == rb_id && r.accepted)
+      .max_by_key(|r| r.timestamp)
+  }
+
+  /// The calibration of a board which is valid at a
+  /// given time (unix timestamp)
+  ///
+  /// If several calibrations are valid, the one which
+  /// became valid last wins (and of these the last
+  /// added).
+  pub fn get_for_time(&self, rb_id : u8, time : u32) -> Option<&CalibrationRecord> {
+    self.records.iter()
+      .filter(|r| r.rb_id == rb_id && r.is_valid_for_time(time))
+      .max_by_key(|r| r.valid_from)
+  }
+
+  /// The calibration of a board which is valid for
+  /// a run
+  ///
+  /// If several calibrations are valid, the one with
+  /// the latest first run wins (and of these the
+  /// newest).
+  pub fn get_for_run(&self, rb_id : u8, run_id : u32) -> Option<&CalibrationRecord> {
+    self.records.iter()
+      .filter(|r| r.rb_id == rb_id && r.is_valid_for_run(run_id))
+      .max_by_key(|r| (r.first_run, r.timestamp))
+  }
+
+  /// Create a store for a calibration directory from
+  /// the calibration files in it (and its
+  /// subdirectories), e.g. for the calibrations taken
+  /// before there was a store.
+  ///
+  /// Calibrations in a "rejected" directory are not
+  /// accepted. The "latest" link is ignored, as well
+  /// as copies of the same calibration. The records
+  /// don't have quality, software version or runs.
+  pub fn scan(directory : &str) -> Result<Self, Box<dyn Error>> {
+    let mut store = Self::new(directory);
+    let pattern   = format!("{}/**/RB*.cali.tof.gaps", directory.trim_end_matches('/'));
+    let mut files : Vec<_> = glob(&pattern)?.filter_map(|f| f.ok()).collect();
+    files.sort();
+    for path in files {
+      let rel_path = store.get_relative_path(&path.to_string_lossy());
+      if Path::new(&rel_path).components().any(|c| c.as_os_str() == "latest") {
+        continue;
+      }
+      let cali = match RBCalibrations::from_file(path.to_string_lossy().to_string(), true) {
+        Err(err) => {
+          error!("Unable to read calibration from {}! {err}", path.display());
+          continue;
+        }
+        Ok(cali) => cali
+      };
+      if store.records.iter().any(|r| r.rb_id == cali.rb_id && r.timestamp == cali.timestamp) {
+        debug!("{} is a copy of a known calibration, skipping!", path.display());
+        continue;
+      }
+      let mut record = CalibrationRecord::new(cali.rb_id, cali.timestamp, &rel_path);
+      record.accepted = !Path::new(&rel_path).components().any(|c| c.as_os_str() == "rejected");
+      store.records.push(record);
+    }
+    Ok(store)
+  }
+
+  /// Number of calibrations in the store
+  pub fn len(&self) -> usize {
+    self.records.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.records.is_empty()
+  }
+}
+
+impl JsonFile for CalibrationStore {}
+
+impl fmt::Display for CalibrationStore {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut rb_ids : Vec<u8> = self.records.iter().map(|r| r.rb_id).collect();
+    rb_ids.sort();
+    rb_ids.dedup();
+    let mut repr = String::from("<CalibrationStore:");
+    repr += &(format!("\n  directory    : {}", self.directory));
+    repr += &(format!("\n  version      : {}", self.version));
+    repr += &(format!("\n  calibrations : {} ({} not accepted)", self.records.len(), self.records.iter().filter(|r| !r.accepted).count()));
+    repr += &(format!("\n  RBs          : {:?}>", rb_ids));
+    write!(f, "{}", repr)
+  }
+}
+
+#[test]
+fn calibration_store_validity() {
+  let mut store = CalibrationStore::new("/data/calib");
+  let mut first = CalibrationRecord::new(5, 1000, "240101_120000UTC/RB05_240101_120000UTC.cali.tof.gaps");
+  first.first_run = Some(100);
+  store.add(first);
+  let mut second = CalibrationRecord::new(5, 2000, "240102_120000UTC/RB05_240102_120000UTC.cali.tof.gaps");
+  second.first_run = Some(200);
+  store.add(second.clone());
+  // rejected, never used
+  let mut bad = CalibrationRecord::new(5, 3000, "rejected/RB05_240103_120000UTC.cali.tof.gaps");
+  bad.first_run = Some(300);
+  bad.accepted  = false;
+  store.add(bad);
+  // taken outside of a run
+  store.add(CalibrationRecord::new(5, 4000, "/elsewhere/RB05_240104_120000UTC.cali.tof.gaps"));
+  assert_eq!(store.len(), 4);
+  assert!(store.get_for_run(5, 99).is_none());
+  assert_eq!(store.get_for_run(5, 150).unwrap().timestamp, 1000);
+  assert_eq!(store.get_for_run(5, 250).unwrap().timestamp, 2000);
+  assert_eq!(store.get_for_run(5, 350).unwrap().timestamp, 2000);
+  assert!(store.get_for_run(7, 150).is_none());
+  assert!(store.get_for_time(5, 999).is_none());
+  assert_eq!(store.get_for_time(5, 1500).unwrap().timestamp, 1000);
+  assert_eq!(store.get_for_time(5, 3500).unwrap().timestamp, 2000);
+  assert_eq!(store.get_for_time(5, 4500).unwrap().timestamp, 4000);
+  assert_eq!(store.get_latest(5).unwrap().timestamp, 4000);
+  // restrict the validity of the second one
+  second.last_run    = Some(249);
+  second.valid_until = Some(2500);
+  store.add(second);
+  assert_eq!(store.len(), 4);
+  assert_eq!(store.get_for_run(5, 250).unwrap().timestamp, 1000);
+  assert_eq!(store.get_for_time(5, 3500).unwrap().timestamp, 1000);
+  assert_eq!(store.get_path(store.get_for_run(5, 150).unwrap()), "/data/calib/240101_120000UTC/RB05_240101_120000UTC.cali.tof.gaps");
+  assert_eq!(store.get_path(store.get_latest(5).unwrap()), "/elsewhere/RB05_240104_120000UTC.cali.tof.gaps");
+}
+
+#[test]
+#[cfg(feature = "random")]
+fn calibration_store_files() {
+  use crate::FromRandom;
+  use crate::serialization::{
+    Packable,
+    Serialization,
+  };
+  let dir = std::env::temp_dir().join(format!("tof-calibration-store-{}", std::process::id()));
+  let dir = dir.to_string_lossy().to_string();
+  fs::create_dir_all(format!("{}/240101_120000UTC/rejected", dir)).unwrap();
+  let mut cali      = RBCalibrations::from_random();
+  cali.rb_id        = 3;
+  cali.timestamp    = 1000;
+  let good_file     = format!("{}/240101_120000UTC/RB03_240101_120000UTC.cali.tof.gaps", dir);
+  fs::write(&good_file, cali.pack().to_bytestream()).unwrap();
+  let mut quality   = RBCalibrationQuality::new();
+  quality.noise[2]  = 2.5;
+  let mut store = CalibrationStore::open(&dir).unwrap();
+  assert!(store.is_empty());
+  store.add_calibration(&good_file, &cali, Some(&quality), "0.10.0", true, Some(42));
+  cali.timestamp    = 2000;
+  let bad_file      = format!("{}/240101_120000UTC/rejected/RB03_240101_130000UTC.cali.tof.gaps", dir);
+  fs::write(&bad_file, cali.pack().to_bytestream()).unwrap();
+  store.add_calibration(&bad_file, &cali, None, "0.10.0", false, Some(42));
+  store.save().unwrap();
+
+  let store = CalibrationStore::open(&dir).unwrap();
+  assert_eq!(store.len(), 2);
+  let record = store.get_for_run(3, 43).unwrap();
+  assert_eq!(record.file, "240101_120000UTC/RB03_240101_120000UTC.cali.tof.gaps");
+  assert_eq!(record.quality.unwrap().max_noise, Some(2.5));
+  assert_eq!(record.quality.unwrap().max_vcal_residual, None);
+  let loaded = store.load(record).unwrap();
+  assert_eq!(loaded.timestamp, 1000);
+  assert_eq!(loaded.v_inc[5], cali.v_inc[5]);
+
+  let scanned = CalibrationStore::scan(&dir).unwrap();
+  assert_eq!(scanned.len(), 2);
+  assert_eq!(scanned.get_latest(3).unwrap().timestamp, 1000);
+  assert!(scanned.records.iter().any(|r| r.timestamp == 2000 && !r.accepted));
+  fs::remove_dir_all(dir).unwrap();
+}
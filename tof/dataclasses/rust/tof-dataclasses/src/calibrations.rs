@@ -14,6 +14,7 @@ use std::fs::File;
 use std::io::{self, BufRead, BufReader};
 use std::path::Path;
 use std::fmt;
+use std::thread;
 use half::f16;
 use chrono::{
   //DateTime,
@@ -513,6 +514,63 @@ fn roll<T: Clone>(vec: &mut Vec<T>, offset: isize) {
 
 /***********************************/
 
+/// Calibrated voltages or times of all channels 
+/// of an RBEvent, \[channel 0-8\]\[sample\]
+pub type RBWaveforms = [[f32;NWORDS];NCHN];
+
+/// Apply the voltage calibration constants of a single
+/// channel.
+///
+/// The constants for offset and increment are indexed
+/// by the DRS4 cell, which is the sample rolled by the 
+/// stop cell. Instead of a modulo for every sample, the 
+/// waveform is split in the part before and after the
+/// cell index wraps around, so that the inner loops 
+/// run over contiguous slices and can be vectorized.
+fn vcal_channel<T: Copy + Into<f32>>(adc       : &[T],
+                                     offsets   : &[f32;NWORDS],
+                                     dips      : &[f32;NWORDS],
+                                     incs      : &[f32;NWORDS],
+                                     stop_cell : usize,
+                                     waveform  : &mut [f32]) {
+  let nsamples  = usize::min(usize::min(adc.len(), waveform.len()), NWORDS);
+  let stop_cell = stop_cell % NWORDS;
+  let split     = usize::min(NWORDS - stop_cell, nsamples);
+  // (first sample, first cell, number of samples)
+  for (sample, cell, len) in [(0, stop_cell, split), (split, 0, nsamples - split)] {
+    let wf  = &mut waveform[sample..sample + len];
+    let raw = &adc[sample..sample + len];
+    let dip = &dips[sample..sample + len];
+    let off = &offsets[cell..cell + len];
+    let inc = &incs[cell..cell + len];
+    for ((((w, a), d), o), i) in wf.iter_mut().zip(raw).zip(dip).zip(off).zip(inc) {
+      *w = ((*a).into() - o - d) * i;
+    }
+  }
+}
+
+/// Apply the timing calibration of a single channel, 
+/// the times are the running sum of the cell widths 
+/// starting at the stop cell. The first time bin is
+/// not changed.
+fn tcal_channel(tbin      : &[f32;NWORDS],
+                stop_cell : usize,
+                times     : &mut [f32]) {
+  let nsamples  = usize::min(times.len(), NWORDS);
+  if nsamples == 0 {
+    return;
+  }
+  let stop_cell = stop_cell % NWORDS;
+  let widths    = tbin[stop_cell..].iter().chain(tbin[..stop_cell].iter());
+  let mut t     = times[0];
+  for (time, width) in times[1..nsamples].iter_mut().zip(widths) {
+    t    += width;
+    *time = t;
+  }
+}
+
+/***********************************/
+
 /// smaller packet to bring through the gcu
 #[derive(Debug, Clone, PartialEq)]
 pub struct RBCalibrationsFlightT {
@@ -861,25 +919,27 @@ impl RBCalibrations {
       -> (Vec<Vec<Vec<f32>>>,Vec<isize>) {
     let nevents          = data.len();
     let mut traces       = Vec::<Vec::<Vec::<f32>>>::new();
-    let mut trace        = Vec::<f32>::with_capacity(NWORDS);
     let mut stop_cells   = Vec::<isize>::new();
-    let mut empty_events = Vec::<Vec::<f32>>::new();
-    for _ in 0..nevents {
-        empty_events.push(trace.clone());
+    let empty_events     = vec![Vec::<f32>::with_capacity(NWORDS); nevents];
+    for ev in data.iter() {
+      stop_cells.push(ev.header.stop_cell as isize);
     }
-    for ch in 0..NCHN {
+    for _ in 0..NCHN {
       traces.push(empty_events.clone());
-      for (k,ev) in data.iter().enumerate() {
-        trace.clear();
-        stop_cells.push(ev.header.stop_cell as isize);
-        for k in 0..NWORDS {
-          trace.push(ev.adc[ch][k] as f32);
-        }
-        self.voltages(ch + 1, ev.header.stop_cell as usize,
-                      &ev.adc[ch], &mut trace);
-        traces[ch][k] = trace.clone();
-      }
     }
+    // the channels are independent, so each of 
+    // them gets its own thread
+    thread::scope(|scope| {
+      for (ch, ch_traces) in traces.iter_mut().enumerate() {
+        scope.spawn(move || {
+          for (ev, trace) in data.iter().zip(ch_traces.iter_mut()) {
+            trace.resize(NWORDS, 0.0);
+            self.voltages_into(ch + 1, ev.header.stop_cell as usize,
+                               &ev.adc[ch], trace);
+          }
+        });
+      }
+    });
     (traces, stop_cells)
   }
 
@@ -888,15 +948,13 @@ impl RBCalibrations {
                               adc       : &Vec<f32>,
                               channel   : usize,  
                               stop_cell : usize) -> Vec<f32> {
-    let mut waveform = Vec::<f32>::with_capacity(adc.len());
-    let mut value : f32;
-    for k in 0..adc.len() {
-      value  = adc[k] as f32;
-      value -= self.v_offsets[channel][(k + (stop_cell)) %NWORDS];
-      value -= self.v_dips   [channel][k];
-      value *= self.v_inc    [channel][(k + (stop_cell)) %NWORDS];
-      waveform.push(value);
-    } 
+    let mut waveform = vec![0.0f32; usize::min(adc.len(), NWORDS)];
+    vcal_channel(adc.as_slice(),
+                 &self.v_offsets[channel],
+                 &self.v_dips[channel],
+                 &self.v_inc[channel],
+                 stop_cell,
+                 &mut waveform);
     waveform
   }
 
@@ -1359,14 +1417,44 @@ impl RBCalibrations {
       return;
     }
 
-    let mut value : f32; 
-    for k in 0..NWORDS {
-      value  = adc[k] as f32;
-      value -= self.v_offsets[channel -1][(k + (stop_cell)) %NWORDS];
-      value -= self.v_dips   [channel -1][k];
-      value *= self.v_inc    [channel -1][(k + (stop_cell)) %NWORDS];
-      waveform[k] = value;
+    vcal_channel(adc.as_slice(),
+                 &self.v_offsets[channel -1],
+                 &self.v_dips   [channel -1],
+                 &self.v_inc    [channel -1],
+                 stop_cell,
+                 waveform);
+  }
+
+  /// Apply the voltage calibration to a single channel,
+  /// same as voltages, but works on any contiguous
+  /// buffer, e.g. \[f32;NWORDS\].
+  ///
+  /// # Arguments
+  ///
+  /// * channel   : Channel id 1-9
+  /// * stop_cell : This channels stop cell 
+  /// * adc       : Uncalibrated channel data
+  /// * waveform  : Buffer for the calibrated waveform,
+  ///               same size as adc
+  pub fn voltages_into(&self,
+                       channel   : usize,
+                       stop_cell : usize,
+                       adc       : &[u16],
+                       waveform  : &mut [f32]) {
+    if channel > 9 || channel == 0 {
+      error!("There is no channel larger than 9 and no channel 0! Channel {channel} was requested. Can not perform voltage calibration!");
+      return;
+    }
+    if adc.len() != waveform.len() {
+      error!("Ch{} has {} adc values, however we are expecting {}!", channel,  adc.len(), waveform.len());
+      return;
     }
+    vcal_channel(adc,
+                 &self.v_offsets[channel -1],
+                 &self.v_dips   [channel -1],
+                 &self.v_inc    [channel -1],
+                 stop_cell,
+                 waveform);
   }
   
   /// Apply the timing calibration to a single channel 
@@ -1382,12 +1470,70 @@ impl RBCalibrations {
                      channel   : usize,
                      stop_cell : usize,
                      times     : &mut Vec<f32>) {
+    self.nanoseconds_into(channel, stop_cell, times);
+  }
+
+  /// Apply the timing calibration to a single channel,
+  /// same as nanoseconds, but works on any contiguous
+  /// buffer, e.g. \[f32;NWORDS\]. 
+  ///
+  /// The first time bin is not changed (usually 0).
+  ///
+  /// # Arguments
+  ///
+  /// * channel   : Channel id 1-9
+  /// * stop_cell : This channels stop cell 
+  /// * times     : Buffer for the waveform times [ns]
+  pub fn nanoseconds_into(&self,
+                          channel   : usize,
+                          stop_cell : usize,
+                          times     : &mut [f32]) {
     if channel > 9 || channel == 0 {
       error!("There is no channel larger than 9 and no channel 0! Channel {channel} was requested. Can not perform timing calibration!");
+      return;
     }
-    for k in 1..NWORDS { 
-      times[k] = times[k-1] + self.tbin[channel -1][(k-1+(stop_cell))%NWORDS];
-    }
+    tcal_channel(&self.tbin[channel -1], stop_cell, times);
+  }
+
+  /// Voltage and timing calibration for all channels of
+  /// an event at once.
+  ///
+  /// Channels without (or with incomplete) data are 
+  /// skipped, their buffers are not changed. 
+  ///
+  /// # Arguments
+  ///
+  /// * event     : RBEvent with the adc data
+  /// * voltages  : Buffer for the calibrated voltages [mV]
+  ///               \[channel 0-8\]\[sample\]
+  /// * times     : Buffer for the waveform times [ns]
+  ///               \[channel 0-8\]\[sample\]
+  ///
+  /// # Returns
+  ///
+  /// Bitmask of the calibrated channels (bit 0 for 
+  /// channel 1, same as the event header channel mask)
+  pub fn calibrate_event(&self,
+                         event    : &RBEvent,
+                         voltages : &mut RBWaveforms,
+                         times    : &mut RBWaveforms) -> u16 {
+    let stop_cell = event.header.stop_cell as usize;
+    let mut mask  = 0u16;
+    for (ch, adc) in event.adc.iter().enumerate().take(NCHN) {
+      if adc.len() != NWORDS {
+        continue;
+      }
+      vcal_channel(adc.as_slice(),
+                   &self.v_offsets[ch],
+                   &self.v_dips[ch],
+                   &self.v_inc[ch],
+                   stop_cell,
+                   &mut voltages[ch]);
+      times[ch][0] = 0.0;
+      tcal_channel(&self.tbin[ch], stop_cell, &mut times[ch]);
+      mask |= 1 << ch;
+    }
+    mask
   }
 
   pub fn new(rb_id : u8) -> Self {
@@ -1907,3 +2053,49 @@ fn assemble_flightcal() {
   }
 }
 
+
+#[cfg(feature = "random")]
+#[test]
+fn calibration_kernels() {
+  let cali    = RBCalibrations::from_random();
+  let mut rng = rand::thread_rng();
+  let mut ev  = RBEvent::new();
+  for ch in 0..NCHN {
+    ev.adc[ch] = (0..NWORDS).map(|_| rng.gen::<u16>() & 0x3fff).collect();
+  }
+  // channel 5 is not read out
+  ev.adc[4].clear();
+  let mut voltages = [[0.0f32;NWORDS];NCHN];
+  let mut times    = [[0.0f32;NWORDS];NCHN];
+  for stop_cell in [0usize, 1, 511, 1022, 1023] {
+    ev.header.stop_cell = stop_cell as u16;
+    let mask = cali.calibrate_event(&ev, &mut voltages, &mut times);
+    assert_eq!(mask, 0x1ff & !(1 << 4));
+    for ch in 0..NCHN {
+      if ch == 4 {
+        continue;
+      }
+      // the sample by sample calculation
+      let mut time = 0.0f32;
+      for k in 0..NWORDS {
+        let mut value = ev.adc[ch][k] as f32;
+        value -= cali.v_offsets[ch][(k + stop_cell) % NWORDS];
+        value -= cali.v_dips   [ch][k];
+        value *= cali.v_inc    [ch][(k + stop_cell) % NWORDS];
+        assert_eq!(voltages[ch][k], value);
+        if k > 0 {
+          time += cali.tbin[ch][(k - 1 + stop_cell) % NWORDS];
+        }
+        assert_eq!(times[ch][k], time);
+      }
+      let mut wf = vec![0.0f32; NWORDS];
+      let mut ts = vec![0.0f32; NWORDS];
+      cali.voltages(ch + 1, stop_cell, &ev.adc[ch], &mut wf);
+      cali.nanoseconds(ch + 1, stop_cell, &mut ts);
+      assert_eq!(wf.as_slice(), voltages[ch].as_slice());
+      assert_eq!(ts.as_slice(), times[ch].as_slice());
+      let adc_f32 : Vec<f32> = ev.adc[ch].iter().map(|a| *a as f32).collect();
+      assert_eq!(cali.apply_vcal_constants(&adc_f32, ch, stop_cell).as_slice(), voltages[ch].as_slice());
+    }
+  }
+}
@@ -0,0 +1,220 @@
+//! Alignment of the hit times of different RBs
+//!
+//! All RBs sample the same (20 MHz) clock in channel 9.
+//! Since the sampling windows of the boards start at
+//! different phases of that clock, hit times of different
+//! boards can only be compared after shifting them by the
+//! phase difference of their channel 9 sine fits with
+//! respect to a reference board
+//!
+//! t_aligned = t + (phi_rb - phi_ref)/(2 pi f)
+//!
+//! The correction is wrapped into one clock period.
+//! Constant offsets between the boards (e.g. cable
+//! lengths) are not part of this correction, they are
+//! taken care of by the paddle timing calibration
+//! (which therefore has to be done with aligned events).
+
+use std::fmt;
+use std::f32::consts::PI;
+
+use crate::events::{
+  RBEvent,
+  RBEventHeader,
+};
+
+/// Outcome of the alignment of a single event
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct ClockAlignmentResult {
+  /// The board the other boards were aligned to,
+  /// None if no board had a good sine fit
+  pub reference : Option<u8>,
+  /// Boards with hits which have been aligned
+  /// (including the reference board)
+  pub n_aligned : usize,
+  /// Boards with hits which could not be aligned,
+  /// because of a bad or missing sine fit. The hit
+  /// times of these boards are not changed.
+  pub n_failed  : usize,
+}
+
+impl fmt::Display for ClockAlignmentResult {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<ClockAlignmentResult:");
+    match self.reference {
+      None     => repr += "\n  reference RB : --",
+      Some(rb) => repr += &(format!("\n  reference RB : {}", rb)),
+    }
+    repr += &(format!("\n  aligned RBs  : {}", self.n_aligned));
+    repr += &(format!("\n  failed RBs   : {}>", self.n_failed));
+    write!(f, "{}", repr)
+  }
+}
+
+/// Shift the hit times of the RBEvents of an event to
+/// the clock of a reference board.
+///
+/// Needs the sine fit results in the RBEventHeaders,
+/// so it has to run after the waveform analysis.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockAligner {
+  /// RBs to use as reference, in order of preference.
+  /// If none of them has a good sine fit in the event
+  /// (or the list is empty), the board with the lowest
+  /// id and a good fit is used instead.
+  pub reference_rbs : Vec<u8>,
+  /// Minimum amplitude [mV] of a good sine fit
+  pub min_amplitude : f32,
+  /// Maximum amplitude [mV] of a good sine fit
+  pub max_amplitude : f32,
+  /// Maximum rms of the fit residuals relative to
+  /// the amplitude for a good sine fit
+  pub max_rel_rms   : f32,
+}
+
+impl ClockAligner {
+
+  pub fn new() -> Self {
+    Self {
+      reference_rbs : Vec::<u8>::new(),
+      min_amplitude : 10.0,
+      max_amplitude : 1000.0,
+      max_rel_rms   : 0.2,
+    }
+  }
+
+  /// Check the quality of the sine fit of a board
+  pub fn is_good_fit(&self, header : &RBEventHeader) -> bool {
+    header.has_sine_fit()
+    && header.sine_fit_phase.is_finite()
+    && header.sine_fit_amp >= self.min_amplitude
+    && header.sine_fit_amp <= self.max_amplitude
+    && header.sine_fit_rms <= self.max_rel_rms*header.sine_fit_amp
+  }
+
+  /// Pick the reference board for the event
+  ///
+  /// # Returns
+  ///
+  /// The index of the reference board in rb_events
+  pub fn get_reference(&self, rb_events : &[RBEvent]) -> Option<usize> {
+    for rb_id in &self.reference_rbs {
+      let candidate = rb_events.iter().position(|ev| ev.header.rb_id == *rb_id && self.is_good_fit(&ev.header));
+      if candidate.is_some() {
+        return candidate;
+      }
+    }
+    rb_events.iter()
+      .enumerate()
+      .filter(|(_, ev)| self.is_good_fit(&ev.header))
+      .min_by_key(|(_, ev)| ev.header.rb_id)
+      .map(|(k, _)| k)
+  }
+
+  /// The time [ns] which has to be added to the hit
+  /// times of a board to align them to the reference
+  /// board. Wrapped into [-T/2, T/2] with the clock
+  /// period T.
+  pub fn get_correction(header : &RBEventHeader, reference : &RBEventHeader) -> f32 {
+    let period = 1.0/reference.sine_fit_freq;
+    let dt     = (header.sine_fit_phase - reference.sine_fit_phase)/(2.0*PI*reference.sine_fit_freq);
+    dt - period*(dt/period).round()
+  }
+
+  /// Align the hit times of all boards with a good
+  /// sine fit to the reference board.
+  ///
+  /// Hits of boards without a good fit stay as they
+  /// are. If no board has a good fit, nothing will
+  /// be changed.
+  pub fn align(&self, rb_events : &mut [RBEvent]) -> ClockAlignmentResult {
+    let mut result = ClockAlignmentResult::default();
+    let reference  = match self.get_reference(rb_events) {
+      None => {
+        result.n_failed = rb_events.iter().filter(|ev| !ev.hits.is_empty()).count();
+        return result;
+      }
+      Some(k) => rb_events[k].header.clone()
+    };
+    result.reference = Some(reference.rb_id);
+    for ev in rb_events.iter_mut() {
+      if ev.hits.is_empty() {
+        continue;
+      }
+      if !self.is_good_fit(&ev.header) {
+        result.n_failed += 1;
+        continue;
+      }
+      let dt = Self::get_correction(&ev.header, &reference);
+      for h in ev.hits.iter_mut() {
+        // don't move paddle ends without pulse
+        if h.get_time_a() != 0.0 {
+          h.set_time_a(h.get_time_a() + dt);
+        }
+        if h.get_time_b() != 0.0 {
+          h.set_time_b(h.get_time_b() + dt);
+        }
+      }
+      result.n_aligned += 1;
+    }
+    result
+  }
+}
+
+impl Default for ClockAligner {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for ClockAligner {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<ClockAligner:");
+    repr += &(format!("\n  reference RBs : {:?}", self.reference_rbs));
+    repr += &(format!("\n  amplitude     : {:.1} - {:.1} mV", self.min_amplitude, self.max_amplitude));
+    repr += &(format!("\n  max rel. rms  : {:.3}>", self.max_rel_rms));
+    write!(f, "{}", repr)
+  }
+}
+
+#[test]
+fn clock_alignment() {
+  use crate::events::TofHit;
+  let freq   = 0.02f32;
+  let mut rb_events = Vec::<RBEvent>::new();
+  // board 3 : reference, board 5 : 5ns later,
+  // board 7 : 45ns later (= 5ns earlier), board 9 : bad fit
+  for (rb_id, delay) in [(5u8, 5.0f32), (3, 0.0), (7, 45.0), (9, 10.0)] {
+    let mut ev = RBEvent::new();
+    ev.header.rb_id = rb_id;
+    ev.header.set_sine_fit((100.0, freq, 0.3 + 2.0*PI*freq*delay));
+    ev.header.sine_fit_rms = 5.0;
+    let mut hit = TofHit::new();
+    hit.set_time_a(100.0);
+    hit.set_time_b(0.0);
+    ev.hits.push(hit);
+    rb_events.push(ev);
+  }
+  rb_events[3].header.sine_fit_rms = 50.0;
+  let mut aligner = ClockAligner::new();
+  let result = aligner.align(&mut rb_events);
+  assert_eq!(result.reference, Some(3));
+  assert_eq!(result.n_aligned, 3);
+  assert_eq!(result.n_failed, 1);
+  // f16 times
+  assert!((rb_events[0].hits[0].get_time_a() - 105.0).abs() < 0.1);
+  assert!((rb_events[1].hits[0].get_time_a() - 100.0).abs() < 0.1);
+  assert!((rb_events[2].hits[0].get_time_a() -  95.0).abs() < 0.1);
+  assert!((rb_events[3].hits[0].get_time_a() - 100.0).abs() < 0.1);
+  assert_eq!(rb_events[0].hits[0].get_time_b(), 0.0);
+  // preferred reference board
+  aligner.reference_rbs = vec![9, 7];
+  assert_eq!(aligner.get_reference(&rb_events), Some(2));
+  // no good fit at all
+  for ev in rb_events.iter_mut() {
+    ev.header.sine_fit_amp = 1.0;
+  }
+  let result = aligner.align(&mut rb_events);
+  assert_eq!(result.reference, None);
+  assert_eq!(result.n_failed, 4);
+}
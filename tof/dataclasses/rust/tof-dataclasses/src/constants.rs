@@ -19,6 +19,10 @@ pub const C_LIGHT_PADDLE : f32 = 15.4;
 /// This value is in cm/ns
 pub const C_LIGHT_CABLE : f32 = 24.6;
 
+/// Speed of light in vacuum
+/// This value is in mm/ns
+pub const C_LIGHT_VACUUM : f32 = 299.792_46;
+
 /// Number of AVAILABLE slots for LocalTriggerBoards
 pub const N_LTBS : usize = 25;
 
@@ -40,3 +44,24 @@ pub const MASK_CMD_32BIT : u32 = 0xFFFFFFFF;
 /// Padding for 32 bits commands (byte packets)
 ///
 pub const PAD_CMD_32BIT  : u32 = 0x00000000;
+
+/// Paddle ids of the TOF regions. Each constant is
+/// the last paddle id (inclusive) of its region, the
+/// regions follow each other in this order, starting
+/// with paddle id 1.
+///
+/// Cube top
+pub const MAX_PID_CUBE_TOP        : u8 = 12;
+/// Cube bottom
+pub const MAX_PID_CUBE_BOT        : u8 = 24;
+/// Cube sides
+pub const MAX_PID_CUBE_SIDE       : u8 = 56;
+/// Cube corners. The paddles up to here belong to
+/// the inner TOF (cube)
+pub const MAX_PID_CUBE            : u8 = 60;
+/// Umbrella center
+pub const MAX_PID_UMBRELLA_CENTER : u8 = 72;
+/// Rest of the umbrella
+pub const MAX_PID_UMBRELLA        : u8 = 108;
+/// Cortina, the last paddle of the TOF
+pub const MAX_PID_CORTINA         : u8 = 160;
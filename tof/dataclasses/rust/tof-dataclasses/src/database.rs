@@ -26,6 +26,14 @@ use schema::tof_db_rat::dsl::*;
 use schema::tof_db_dsicard::dsl::*;
 
 use crate::calibrations::RBCalibrations;
+use crate::calibration_store::CalibrationStore;
+use crate::calibration_file::JsonFile;
+use crate::pulse_template::PulseTemplateLibrary;
+use crate::timing_calibration::TimingCalibration;
+use crate::energy_calibration::{
+  EnergyCalibration,
+  PaddleGain,
+};
 //use crate::constants::HUMAN_TIMESTAMP_FORMAT;
 use crate::DsiLtbRBMapping;
 pub use crate::RbChPidMapping;
@@ -1030,6 +1038,15 @@ pub struct ReadoutBoard {
   // or maybe in the future?
   pub calib_file_path : String,
   pub calibration     : RBCalibrations,       
+  /// Pulse templates for the connected paddle 
+  /// ends (only needed for the template fit)
+  pub templates       : PulseTemplateLibrary,
+  /// Timing offsets for the connected paddles,
+  /// which get subtracted from the hit times
+  pub timing          : TimingCalibration,
+  /// Gain constants for the connected paddles, 
+  /// valid for the current run
+  pub gains           : HashMap<u8, PaddleGain>,
 }
 
 impl ReadoutBoard {
@@ -1050,6 +1067,9 @@ impl ReadoutBoard {
       paddle78_chA    : 0,
       calib_file_path : String::from(""),
       calibration     : RBCalibrations::new(0),
+      templates       : PulseTemplateLibrary::new(),
+      timing          : TimingCalibration::new(),
+      gains           : HashMap::<u8, PaddleGain>::new(),
     }
   }
 
@@ -1274,6 +1294,99 @@ impl ReadoutBoard {
     }
     Ok(())
   }
+
+  /// Load the calibration which is valid for a run
+  /// from a calibration store
+  pub fn load_calibration_for_run(&mut self, store : &CalibrationStore, run_id : u32) -> Result<(), Box<dyn std::error::Error>> {
+    let record = store.get_for_run(self.rb_id, run_id)
+      .ok_or(format!("No calibration for RB {} valid for run {} in {}!", self.rb_id, run_id, store.directory))?;
+    info!("Loading calibration for run {} from file: {}", run_id, record.file);
+    self.calibration = store.load(record)?;
+    Ok(())
+  }
+
+  /// Load the calibration which is valid at a time
+  /// (unix timestamp) from a calibration store
+  pub fn load_calibration_for_time(&mut self, store : &CalibrationStore, time : u32) -> Result<(), Box<dyn std::error::Error>> {
+    let record = store.get_for_time(self.rb_id, time)
+      .ok_or(format!("No calibration for RB {} valid at {} in {}!", self.rb_id, time, store.directory))?;
+    info!("Loading calibration for {} from file: {}", time, record.file);
+    self.calibration = store.load(record)?;
+    Ok(())
+  }
+
+  /// Load the pulse templates for the paddle ends 
+  /// connected to this board from a template library
+  /// file. Returns the number of loaded templates.
+  pub fn load_templates(&mut self, filename : &str) -> Result<usize, Box<dyn std::error::Error>> {
+    let lib = PulseTemplateLibrary::from_file(filename)?;
+    self.templates = PulseTemplateLibrary::new();
+    for pid in self.get_paddle_ids() {
+      if pid == 0 {
+        continue;
+      }
+      for pend in [1000 + pid as u16, 2000 + pid as u16] {
+        match lib.get(pend) {
+          None => {
+            warn!("No pulse template for paddle end {} (RB {})!", pend, self.rb_id);
+          }
+          Some(template) => {
+            self.templates.insert(pend, template.clone());
+          }
+        }
+      }
+    }
+    Ok(self.templates.len())
+  }
+  
+  /// Load the timing constants for the paddles 
+  /// connected to this board from a timing calibration
+  /// file. Returns the number of paddles with constants.
+  pub fn load_timing_calibration(&mut self, filename : &str) -> Result<usize, Box<dyn std::error::Error>> {
+    let cali = TimingCalibration::from_file(filename)?;
+    self.timing           = TimingCalibration::new();
+    self.timing.header    = cali.header.clone();
+    self.timing.constants = self.select_paddle_constants("timing constants", |pid| cali.get(pid).copied());
+    Ok(self.timing.len())
+  }
+
+  /// Load the gain constants for the paddles 
+  /// connected to this board, which are valid for 
+  /// the given run, from an energy calibration file.
+  /// Returns the number of paddles with constants.
+  pub fn load_energy_calibration(&mut self, filename : &str, run_id : u32) -> Result<usize, Box<dyn std::error::Error>> {
+    let cali = EnergyCalibration::from_file(filename)?;
+    let what = format!("gain constants for run {}", run_id);
+    self.gains = self.select_paddle_constants(&what, |pid| cali.get(pid, run_id).copied());
+    Ok(self.gains.len())
+  }
+
+  /// Get the constants for each paddle connected to 
+  /// this board from a calibration, warning about the
+  /// paddles without constants.
+  ///
+  /// # Arguments:
+  ///
+  /// * what : name of the constants for the warnings
+  /// * get  : the constants of a paddle id
+  fn select_paddle_constants<T, F>(&self, what : &str, get : F) -> HashMap<u8, T>
+    where F : Fn(u8) -> Option<T> {
+    let mut constants = HashMap::<u8, T>::new();
+    for pid in self.get_paddle_ids() {
+      if pid == 0 {
+        continue;
+      }
+      match get(pid) {
+        None => {
+          warn!("No {} for paddle {} (RB {})!", what, pid, self.rb_id);
+        }
+        Some(c) => {
+          constants.insert(pid, c);
+        }
+      }
+    }
+    constants
+  }
 }
 
 impl fmt::Display for ReadoutBoard {
@@ -0,0 +1,795 @@
+//! Paddle energy calibration with minimum ionizing
+//! particles
+//!
+//! The charges measured at the paddle ends depend on
+//! the light yield of the paddle, the gain of the SiPMs
+//! and the electronics, so they differ from paddle end
+//! to paddle end. Here, they get converted to energy by
+//! the most probable energy deposition of minimum
+//! ionizing particles (MIPs), e.g. cosmic muons.
+//!
+//! The MipCalibrator collects the charges of the hits
+//! of tracks with β ≈ 1, corrected for the path length
+//! through the paddle (using the track direction), for
+//! each paddle end. A Landau distribution convoluted
+//! with a Gaussian is fit to each of these distributions.
+//! The gain of a paddle end is then the energy deposition
+//! of a perpendicular MIP (MIP_DEDX times the paddle
+//! thickness) divided by the fitted most probable
+//! charge.
+//!
+//! The gains are stored in a (versioned) JSON file,
+//! together with the range of runs they are valid for.
+//! A file can hold several sets of constants for the
+//! same paddle with different validity ranges. The
+//! analysis engine sets the calibrated energy deposition
+//! of the hits (TofHit::set_edep), which is then
+//! returned by TofHit::get_edep.
+//!
+//! All energies are in MeV, the charges are in the
+//! units of the waveform analysis.
+
+use std::fmt;
+use std::collections::HashMap;
+
+use serde::{
+  Serialize,
+  Deserialize,
+};
+
+use crate::calibration_file::{
+  CalibrationHeader,
+  JsonFile,
+};
+use crate::errors::ReconstructionError;
+use crate::events::{
+  TofEventSummary,
+  TofHit,
+};
+use crate::reconstruction::{
+  PaddleGeometry,
+  TofTrackFitter,
+  MIP_DEDX,
+};
+#[cfg(feature="database")]
+use crate::database::Paddle;
+
+/// Position of the maximum of the Landau density
+/// (for location 0 and width 1)
+const LANDAU_MPV_SHIFT : f64 = -0.22278298;
+
+/// Gain constants for a single paddle
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaddleGain {
+  /// Energy per unit charge for the A side [MeV]
+  pub gain_a    : f32,
+  /// Energy per unit charge for the B side [MeV]
+  pub gain_b    : f32,
+  /// Fitted most probable charge of a perpendicular
+  /// MIP at the A side
+  pub mip_a     : f32,
+  /// Fitted most probable charge of a perpendicular
+  /// MIP at the B side
+  pub mip_b     : f32,
+  /// Number of hits the constants were derived from
+  pub n_entries : u32,
+  /// First run the constants are valid for
+  pub first_run : u32,
+  /// Last run (including) the constants are valid for
+  pub last_run  : u32,
+}
+
+impl PaddleGain {
+
+  pub fn new() -> Self {
+    Self {
+      gain_a    : 0.0,
+      gain_b    : 0.0,
+      mip_a     : 0.0,
+      mip_b     : 0.0,
+      n_entries : 0,
+      first_run : 0,
+      last_run  : u32::MAX,
+    }
+  }
+
+  pub fn is_valid_for(&self, run_id : u32) -> bool {
+    run_id >= self.first_run && run_id <= self.last_run
+  }
+
+  /// Energy deposition [MeV] for the given charges
+  ///
+  /// This is the geometric mean of the energies
+  /// measured at the two paddle ends, which does
+  /// not depend on the position along the paddle
+  /// (for an exponential attenuation of the light).
+  /// If only one side has a positive charge, its
+  /// energy is used.
+  pub fn get_edep(&self, charge_a : f32, charge_b : f32) -> f32 {
+    let e_a = self.gain_a*charge_a;
+    let e_b = self.gain_b*charge_b;
+    match (e_a > 0.0, e_b > 0.0) {
+      (true,  true)  => (e_a*e_b).sqrt(),
+      (true,  false) => e_a,
+      (false, true)  => e_b,
+      (false, false) => 0.0,
+    }
+  }
+
+  /// Set the calibrated energy deposition of the hit
+  pub fn apply(&self, hit : &mut TofHit) {
+    hit.set_edep(self.get_edep(hit.get_charge_a(), hit.get_charge_b()));
+  }
+}
+
+impl Default for PaddleGain {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for PaddleGain {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "<PaddleGain: gain A/B {:.4}/{:.4} [MeV], MIP A/B {:.2}/{:.2}, {} entries, runs {}-{}>",
+           self.gain_a,
+           self.gain_b,
+           self.mip_a,
+           self.mip_b,
+           self.n_entries,
+           self.first_run,
+           self.last_run)
+  }
+}
+
+/// Gain constants for all paddles, with validity
+/// ranges
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EnergyCalibration {
+  /// Version, time of creation and description
+  #[serde(flatten)]
+  pub header      : CalibrationHeader,
+  /// Constants by paddle id, sorted by first_run
+  pub gains       : HashMap<u8, Vec<PaddleGain>>,
+}
+
+impl EnergyCalibration {
+
+  pub fn new() -> Self {
+    Self {
+      header      : CalibrationHeader::new(),
+      gains       : HashMap::<u8, Vec<PaddleGain>>::new(),
+    }
+  }
+
+  /// Get the constants of a paddle for a run
+  ///
+  /// If several validity ranges include the run,
+  /// the one starting with the latest run wins
+  /// (and of these the last inserted).
+  pub fn get(&self, paddle_id : u8, run_id : u32) -> Option<&PaddleGain> {
+    self.gains.get(&paddle_id)?
+      .iter()
+      .rev()
+      .find(|g| g.is_valid_for(run_id))
+  }
+
+  /// Add constants for a paddle
+  pub fn insert(&mut self, paddle_id : u8, gain : PaddleGain) {
+    let gains = self.gains.entry(paddle_id).or_default();
+    gains.push(gain);
+    // stable, so the insertion order is kept for
+    // the same first run
+    gains.sort_by_key(|g| g.first_run);
+  }
+
+  /// Add all constants of another calibration
+  /// (e.g. from newer runs)
+  pub fn merge(&mut self, other : &EnergyCalibration) {
+    self.header.update(&other.header);
+    for (pid, gains) in &other.gains {
+      for g in gains {
+        self.insert(*pid, *g);
+      }
+    }
+  }
+
+  /// The constants which are valid for a run, by
+  /// paddle id
+  pub fn get_for_run(&self, run_id : u32) -> HashMap<u8, PaddleGain> {
+    let mut gains = HashMap::<u8, PaddleGain>::new();
+    for pid in self.gains.keys() {
+      if let Some(g) = self.get(*pid, run_id) {
+        gains.insert(*pid, *g);
+      }
+    }
+    gains
+  }
+
+  /// Set the calibrated energy deposition of the
+  /// hit. Returns false if there are no constants
+  /// for the paddle and run.
+  pub fn apply(&self, hit : &mut TofHit, run_id : u32) -> bool {
+    match self.get(hit.paddle_id, run_id) {
+      None => false,
+      Some(g) => {
+        g.apply(hit);
+        true
+      }
+    }
+  }
+
+  /// Number of paddles with constants
+  pub fn len(&self) -> usize {
+    self.gains.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.gains.is_empty()
+  }
+}
+
+impl JsonFile for EnergyCalibration {}
+
+impl fmt::Display for EnergyCalibration {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut pids : Vec<&u8> = self.gains.keys().collect();
+    pids.sort();
+    let mut repr = String::from("<EnergyCalibration:");
+    repr += &(format!("\n  version     : {}", self.header.version));
+    repr += &(format!("\n  created     : {}", self.header.created));
+    repr += &(format!("\n  description : {}", self.header.description));
+    for pid in pids {
+      for g in &self.gains[pid] {
+        repr += &(format!("\n  {:3} : {}", pid, g));
+      }
+    }
+    repr += ">";
+    write!(f, "{}", repr)
+  }
+}
+
+/// Landau probability density
+///
+/// Uses the rational approximations of CERNLIB
+/// (G110, DENLAN).
+///
+/// # Arguments:
+///   * x     : the variable
+///   * x0    : location parameter (the most probable
+///     value is at x0 - 0.22278*width)
+///   * width : scale parameter
+pub fn landau_pdf(x : f64, x0 : f64, width : f64) -> f64 {
+  const P1 : [f64;5] = [0.4259894875, -0.1249762550, 0.03984243700, -0.006298287635, 0.001511162253];
+  const Q1 : [f64;5] = [1.0, -0.3388260629, 0.09594393323, -0.01608042283, 0.003778942063];
+  const P2 : [f64;5] = [0.1788541609, 0.1173957403, 0.01488850518, -0.001394989411, 0.0001283617211];
+  const Q2 : [f64;5] = [1.0, 0.7428795082, 0.3153932961, 0.06694219548, 0.008790609714];
+  const P3 : [f64;5] = [0.1788544503, 0.09359161662, 0.006325387654, 0.00006611667319, -0.000002031049101];
+  const Q3 : [f64;5] = [1.0, 0.6097809921, 0.2560616665, 0.04746722384, 0.006957301675];
+  const P4 : [f64;5] = [0.9874054407, 118.6723273, 849.2794360, -743.7792444, 427.0262186];
+  const Q4 : [f64;5] = [1.0, 106.8615961, 337.6496214, 2016.712389, 1597.063511];
+  const P5 : [f64;5] = [1.003675074, 167.5702434, 4789.711289, 21217.86767, -22324.94910];
+  const Q5 : [f64;5] = [1.0, 156.9424537, 3745.310488, 9834.698876, 66924.28357];
+  const P6 : [f64;5] = [1.000827619, 664.9143136, 62972.92665, 475554.6998, -5743609.109];
+  const Q6 : [f64;5] = [1.0, 651.4101098, 56974.73333, 165917.4725, -2815759.939];
+  const A1 : [f64;3] = [0.04166666667, -0.01996527778, 0.02709538966];
+  const A2 : [f64;2] = [-1.845568670, -4.284640743];
+  // ratio of two polynomials of 4th degree
+  fn ratio(p : &[f64;5], q : &[f64;5], x : f64) -> f64 {
+    (p[0] + (p[1] + (p[2] + (p[3] + p[4]*x)*x)*x)*x)
+      /(q[0] + (q[1] + (q[2] + (q[3] + q[4]*x)*x)*x)*x)
+  }
+  if width <= 0.0 {
+    return 0.0;
+  }
+  let v = (x - x0)/width;
+  let density = if v < -5.5 {
+    let u = (v + 1.0).exp();
+    if u < 1e-10 {
+      return 0.0;
+    }
+    0.3989422803*((-1.0/u).exp()/u.sqrt())*(1.0 + (A1[0] + (A1[1] + A1[2]*u)*u)*u)
+  } else if v < -1.0 {
+    let u = (-v - 1.0).exp();
+    (-u).exp()*u.sqrt()*ratio(&P1, &Q1, v)
+  } else if v < 1.0 {
+    ratio(&P2, &Q2, v)
+  } else if v < 5.0 {
+    ratio(&P3, &Q3, v)
+  } else if v < 12.0 {
+    let u = 1.0/v;
+    u*u*ratio(&P4, &Q4, u)
+  } else if v < 50.0 {
+    let u = 1.0/v;
+    u*u*ratio(&P5, &Q5, u)
+  } else if v < 300.0 {
+    let u = 1.0/v;
+    u*u*ratio(&P6, &Q6, u)
+  } else {
+    let u = 1.0/(v - v*v.ln()/(v + 1.0));
+    u*u*(1.0 + (A2[0] + A2[1]*u)*u)
+  };
+  density/width
+}
+
+/// Landau density with most probable value mpv,
+/// convoluted with a Gaussian of width sigma
+/// (numerical integration over ±5 sigma)
+pub fn landau_gauss_pdf(x : f64, mpv : f64, width : f64, sigma : f64) -> f64 {
+  let x0 = mpv - LANDAU_MPV_SHIFT*width;
+  if sigma <= 0.0 {
+    return landau_pdf(x, x0, width);
+  }
+  let n_steps = 50;
+  let step    = 10.0*sigma/(n_steps as f64);
+  let mut sum = 0.0;
+  for k in 0..n_steps {
+    let y = x - 5.0*sigma + (k as f64 + 0.5)*step;
+    let g = (-0.5*((x - y)/sigma).powi(2)).exp();
+    sum  += landau_pdf(y, x0, width)*g;
+  }
+  sum*step/(sigma*(2.0*std::f64::consts::PI).sqrt())
+}
+
+/// Result of a Landau-Gauss fit
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LandauGaussFit {
+  /// Most probable value of the Landau
+  pub mpv       : f32,
+  /// Scale parameter of the Landau
+  pub width     : f32,
+  /// Width of the Gaussian
+  pub sigma     : f32,
+  /// Number of values in the fit range
+  pub n_entries : u32,
+  /// χ²/ndof of the fitted histogram
+  pub chi2      : f32,
+}
+
+impl fmt::Display for LandauGaussFit {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "<LandauGaussFit: mpv {:.3}, width {:.3}, sigma {:.3}, {} entries, χ²/ndof {:.2}>",
+           self.mpv,
+           self.width,
+           self.sigma,
+           self.n_entries,
+           self.chi2)
+  }
+}
+
+/// Fit a Landau convoluted with a Gaussian to a
+/// distribution (binned maximum likelihood)
+///
+/// The fit range goes from 0.3 to 3 times the peak
+/// of the distribution, to exclude noise and the far
+/// tail.
+///
+/// # Arguments:
+///   * values : e.g. the path length corrected charges
+///   * n_bins : number of bins of the histogram in the
+///     fit range
+pub fn fit_landau_gauss(values : &[f32], n_bins : usize) -> Option<LandauGaussFit> {
+  let mut sorted : Vec<f32> = values.iter().cloned().filter(|x| x.is_finite() && *x > 0.0).collect();
+  if sorted.len() < 10 || n_bins < 5 {
+    return None;
+  }
+  sorted.sort_by(|a, b| a.total_cmp(b));
+  // the peak of a coarse histogram up to twice the
+  // median as starting value
+  let median = sorted[sorted.len()/2] as f64;
+  let coarse = histogram(&sorted, 0.0, 2.0*median, 20);
+  let i_max  = coarse.iter().enumerate().max_by_key(|(_, n)| **n).map(|(i, _)| i)?;
+  let peak   = (i_max as f64 + 0.5)*0.1*median;
+  let lo     = 0.3*peak;
+  let hi     = 3.0*peak;
+  let counts = histogram(&sorted, lo, hi, n_bins);
+  let n_fit  : u32 = counts.iter().sum();
+  if n_fit < 10 {
+    return None;
+  }
+  let bw      = (hi - lo)/(n_bins as f64);
+  let centers : Vec<f64> = (0..n_bins).map(|k| lo + (k as f64 + 0.5)*bw).collect();
+  // expected fractions of the entries in the fit range
+  let model = |par : &[f64;3]| -> Option<Vec<f64>> {
+    let shape : Vec<f64> = centers.iter()
+      .map(|x| landau_gauss_pdf(*x, par[0], par[1].exp(), par[2].exp()))
+      .collect();
+    let norm : f64 = shape.iter().sum();
+    if norm <= 0.0 || !norm.is_finite() {
+      return None;
+    }
+    Some(shape.iter().map(|s| s/norm).collect())
+  };
+  let nll = |par : &[f64;3]| -> f64 {
+    match model(par) {
+      None    => f64::MAX,
+      Some(p) => {
+        counts.iter().zip(p)
+          .map(|(n, p)| -(*n as f64)*p.max(1e-300).ln())
+          .sum()
+      }
+    }
+  };
+  let start = [peak, (0.1*peak).ln(), (0.1*peak).ln()];
+  let step  = [0.1*peak, 0.5, 0.5];
+  let par   = minimize(nll, start, step, 500);
+  let p     = model(&par)?;
+  let mut chi2 = 0.0;
+  let mut ndof = 0i32;
+  for (n, p) in counts.iter().zip(p) {
+    let expected = p*(n_fit as f64);
+    if expected > 0.0 && *n > 0 {
+      chi2 += (*n as f64 - expected).powi(2)/expected;
+      ndof += 1;
+    }
+  }
+  ndof -= 3;
+  if par[0] <= lo || par[0] >= hi {
+    return None;
+  }
+  Some(LandauGaussFit {
+    mpv       : par[0] as f32,
+    width     : par[1].exp() as f32,
+    sigma     : par[2].exp() as f32,
+    n_entries : n_fit,
+    chi2      : if ndof > 0 {(chi2/ndof as f64) as f32} else {f32::NAN},
+  })
+}
+
+/// Histogram of sorted values
+fn histogram(sorted : &[f32], lo : f64, hi : f64, n_bins : usize) -> Vec<u32> {
+  let mut counts = vec![0u32; n_bins];
+  let bw = (hi - lo)/(n_bins as f64);
+  for x in sorted {
+    let x = *x as f64;
+    if x < lo || x >= hi {
+      continue;
+    }
+    let k = ((x - lo)/bw) as usize;
+    counts[k.min(n_bins - 1)] += 1;
+  }
+  counts
+}
+
+/// Nelder-Mead minimization
+fn minimize<F>(f : F, start : [f64;3], step : [f64;3], max_iter : usize) -> [f64;3]
+  where F : Fn(&[f64;3]) -> f64 {
+  let mut simplex = vec![start; 4];
+  for k in 0..3 {
+    simplex[k + 1][k] += step[k];
+  }
+  let mut values : Vec<f64> = simplex.iter().map(&f).collect();
+  for _ in 0..max_iter {
+    // order the vertices best to worst
+    let mut order : Vec<usize> = (0..4).collect();
+    order.sort_by(|a, b| values[*a].total_cmp(&values[*b]));
+    simplex = order.iter().map(|i| simplex[*i]).collect();
+    values  = order.iter().map(|i| values[*i]).collect();
+    if (values[3] - values[0]).abs() <= 1e-8*(values[0].abs() + 1e-12) {
+      break;
+    }
+    let mut centroid = [0.0f64;3];
+    for vertex in simplex.iter().take(3) {
+      for k in 0..3 {
+        centroid[k] += vertex[k]/3.0;
+      }
+    }
+    let towards = |coeff : f64| -> [f64;3] {
+      let mut p = [0.0f64;3];
+      for k in 0..3 {
+        p[k] = centroid[k] + coeff*(simplex[3][k] - centroid[k]);
+      }
+      p
+    };
+    let reflected = towards(-1.0);
+    let f_r       = f(&reflected);
+    if f_r < values[0] {
+      let expanded = towards(-2.0);
+      let f_e      = f(&expanded);
+      if f_e < f_r {
+        simplex[3] = expanded;
+        values[3]  = f_e;
+      } else {
+        simplex[3] = reflected;
+        values[3]  = f_r;
+      }
+    } else if f_r < values[2] {
+      simplex[3] = reflected;
+      values[3]  = f_r;
+    } else {
+      let contracted = towards(0.5);
+      let f_c        = f(&contracted);
+      if f_c < values[3] {
+        simplex[3] = contracted;
+        values[3]  = f_c;
+      } else {
+        // shrink towards the best vertex
+        let best = simplex[0];
+        for i in 1..4 {
+          for (x, b) in simplex[i].iter_mut().zip(best) {
+            *x = b + 0.5*(*x - b);
+          }
+          values[i] = f(&simplex[i]);
+        }
+      }
+    }
+  }
+  let best = (0..4).min_by(|a, b| values[*a].total_cmp(&values[*b])).unwrap_or(0);
+  simplex[best]
+}
+
+/// Derive the paddle gains from minimum ionizing
+/// particles
+///
+/// Add the events with add_event, then get the
+/// calibration with fit. The paddle information
+/// has to be set for the events
+/// (TofEventSummary::set_paddles), since they are
+/// needed for the track fit.
+#[derive(Debug, Clone)]
+pub struct MipCalibrator {
+  /// Paddle geometries, by paddle id
+  pub paddles     : HashMap<u8, PaddleGeometry>,
+  pub fitter      : TofTrackFitter,
+  /// Tracks with β in [beta_min, beta_max] are
+  /// considered minimum ionizing
+  pub beta_min    : f32,
+  pub beta_max    : f32,
+  /// Maximum χ²/ndof of the track fit
+  pub max_chi2    : f32,
+  /// Hits with a smaller cosine of the incident
+  /// angle are not used
+  pub min_cos     : f32,
+  /// Minimum number of hits for a paddle end in
+  /// the fit range to get calibrated
+  pub min_entries : u32,
+  /// Number of bins for the Landau-Gauss fit
+  pub n_bins      : usize,
+  /// Path length corrected charges, by paddle id
+  charges_a       : HashMap<u8, Vec<f32>>,
+  charges_b       : HashMap<u8, Vec<f32>>,
+  n_events        : usize,
+}
+
+impl MipCalibrator {
+
+  pub fn new() -> Self {
+    Self {
+      paddles     : HashMap::<u8, PaddleGeometry>::new(),
+      fitter      : TofTrackFitter::new(),
+      beta_min    : 0.8,
+      beta_max    : 1.2,
+      max_chi2    : 10.0,
+      min_cos     : 0.5,
+      min_entries : 200,
+      n_bins      : 50,
+      charges_a   : HashMap::<u8, Vec<f32>>::new(),
+      charges_b   : HashMap::<u8, Vec<f32>>::new(),
+      n_events    : 0,
+    }
+  }
+
+  /// Get the paddle geometries from the database
+  #[cfg(feature="database")]
+  pub fn set_paddles(&mut self, paddles : &HashMap<u8, Paddle>) {
+    for (pid, pdl) in paddles {
+      self.paddles.insert(*pid, PaddleGeometry::from_paddle(pdl));
+    }
+  }
+
+  /// Number of events which were used
+  pub fn get_n_events(&self) -> usize {
+    self.n_events
+  }
+
+  /// Add the charges of a hit, which was crossed by
+  /// a MIP with the given direction
+  ///
+  /// Returns false if the paddle geometry is not
+  /// known or the track is too inclined.
+  pub fn add_hit(&mut self, hit : &TofHit, direction : &[f32;3]) -> bool {
+    let geo = match self.paddles.get(&hit.paddle_id) {
+      None      => return false,
+      Some(geo) => geo
+    };
+    let path = geo.get_path_length(direction, 0.0);
+    if !path.is_finite() || geo.thickness/path < self.min_cos {
+      return false;
+    }
+    let scale = geo.thickness/path;
+    if hit.get_charge_a() > 0.0 {
+      self.charges_a.entry(hit.paddle_id).or_default().push(hit.get_charge_a()*scale);
+    }
+    if hit.get_charge_b() > 0.0 {
+      self.charges_b.entry(hit.paddle_id).or_default().push(hit.get_charge_b()*scale);
+    }
+    true
+  }
+
+  /// Add an event to the calibration sample
+  ///
+  /// Returns false if the event can't be used, that
+  /// is if the track fit fails or the particle is not
+  /// minimum ionizing.
+  pub fn add_event(&mut self, event : &TofEventSummary) -> bool {
+    let track = match self.fitter.fit(event) {
+      Err(_)    => return false,
+      Ok(track) => track
+    };
+    // (beta and χ² are NaN if they could not be
+    // reconstructed)
+    if !(self.beta_min..=self.beta_max).contains(&track.beta) {
+      return false;
+    }
+    if !(0.0..=self.max_chi2).contains(&track.get_reduced_chi2()) {
+      return false;
+    }
+    for h in &event.hits {
+      self.add_hit(h, &track.direction);
+    }
+    self.n_events += 1;
+    true
+  }
+
+  /// Fit the MIP peaks and get the gains
+  ///
+  /// Only paddles for which both ends could be fit
+  /// get constants.
+  ///
+  /// # Arguments:
+  ///   * first_run : first run the constants are
+  ///     valid for
+  ///   * last_run  : last run the constants are valid
+  ///     for
+  pub fn fit(&self, first_run : u32, last_run : u32) -> Result<EnergyCalibration, ReconstructionError> {
+    let mut cali = EnergyCalibration::new();
+    let mut pids : Vec<&u8> = self.charges_a.keys().collect();
+    pids.sort();
+    for pid in pids {
+      let (charges_a, charges_b) = match self.charges_b.get(pid) {
+        None            => continue,
+        Some(charges_b) => (&self.charges_a[pid], charges_b)
+      };
+      let fit_a = fit_landau_gauss(charges_a, self.n_bins);
+      let fit_b = fit_landau_gauss(charges_b, self.n_bins);
+      let (fit_a, fit_b) = match (fit_a, fit_b) {
+        (Some(a), Some(b)) => (a, b),
+        _ => {
+          warn!("Landau-Gauss fit failed for paddle {}!", pid);
+          continue;
+        }
+      };
+      if fit_a.n_entries < self.min_entries || fit_b.n_entries < self.min_entries {
+        debug!("Not enough entries for paddle {} ({}/{})", pid, fit_a.n_entries, fit_b.n_entries);
+        continue;
+      }
+      let e_mip     = MIP_DEDX*self.paddles[pid].thickness;
+      let mut gain  = PaddleGain::new();
+      gain.gain_a    = e_mip/fit_a.mpv;
+      gain.gain_b    = e_mip/fit_b.mpv;
+      gain.mip_a     = fit_a.mpv;
+      gain.mip_b     = fit_b.mpv;
+      gain.n_entries = fit_a.n_entries.min(fit_b.n_entries);
+      gain.first_run = first_run;
+      gain.last_run  = last_run;
+      cali.insert(*pid, gain);
+    }
+    if cali.is_empty() {
+      return Err(ReconstructionError::NotEnoughHits);
+    }
+    cali.header.description = format!("MIP calibration from {} events", self.n_events);
+    Ok(cali)
+  }
+}
+
+impl Default for MipCalibrator {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for MipCalibrator {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<MipCalibrator:");
+    repr += &(format!("\n  paddles     : {}", self.paddles.len()));
+    repr += &(format!("\n  events      : {}", self.n_events));
+    repr += &(format!("\n  beta        : {} - {}", self.beta_min, self.beta_max));
+    repr += &(format!("\n  max χ²/ndof : {}", self.max_chi2));
+    repr += &(format!("\n  min cos     : {}", self.min_cos));
+    repr += &(format!("\n  min entries : {}>", self.min_entries));
+    write!(f, "{}", repr)
+  }
+}
+
+#[test]
+fn landau_pdf_normalization() {
+  // integral over the relevant range and position
+  // of the maximum
+  let mut sum   = 0.0;
+  let mut max   = (0.0, 0.0);
+  let step      = 0.001;
+  let mut x     = -10.0;
+  while x < 1000.0 {
+    let p = landau_pdf(x, 0.0, 1.0);
+    sum  += p*step;
+    if p > max.1 {
+      max = (x, p);
+    }
+    x += step;
+  }
+  assert!((sum - 1.0).abs() < 0.002, "{}", sum);
+  assert!((max.0 - LANDAU_MPV_SHIFT).abs() < 0.002, "{:?}", max);
+  assert!((max.1 - 0.1805).abs() < 0.001, "{:?}", max);
+}
+
+#[test]
+fn energy_calibration_validity() {
+  let mut cali   = EnergyCalibration::new();
+  let mut gain   = PaddleGain::new();
+  gain.gain_a    = 0.1;
+  gain.gain_b    = 0.4;
+  gain.first_run = 100;
+  cali.insert(5, gain);
+  gain.gain_a    = 0.2;
+  gain.first_run = 200;
+  gain.last_run  = 299;
+  cali.insert(5, gain);
+  assert!(cali.get(5, 99).is_none());
+  assert_eq!(cali.get(5, 150).unwrap().gain_a, 0.1);
+  assert_eq!(cali.get(5, 250).unwrap().gain_a, 0.2);
+  assert_eq!(cali.get(5, 300).unwrap().gain_a, 0.1);
+  assert_eq!(cali.get_for_run(250).len(), 1);
+
+  let mut hit = TofHit::new();
+  hit.paddle_id = 5;
+  hit.set_charge_a(20.0);
+  hit.set_charge_b(10.0);
+  assert!(!hit.is_energy_calibrated());
+  assert!(!cali.apply(&mut hit, 50));
+  assert!(cali.apply(&mut hit, 150));
+  assert!(hit.is_energy_calibrated());
+  // geometric mean of 2 and 4 MeV
+  assert!((hit.get_edep() - 8.0f32.sqrt()).abs() < 0.01);
+  // only one side
+  hit.set_charge_b(0.0);
+  cali.apply(&mut hit, 150);
+  assert!((hit.get_edep() - 2.0).abs() < 0.01);
+
+  let mut other = EnergyCalibration::new();
+  gain.first_run = 300;
+  gain.last_run  = u32::MAX;
+  other.insert(5, gain);
+  other.insert(6, gain);
+  cali.merge(&other);
+  assert_eq!(cali.header.version, 1);
+  assert_eq!(cali.len(), 2);
+  assert_eq!(cali.gains[&5].len(), 3);
+  assert_eq!(cali.get(5, 400).unwrap().first_run, 300);
+}
+
+#[test]
+#[cfg(feature = "random")]
+fn landau_gauss_fit() {
+  use rand::Rng;
+  let mut rng = rand::thread_rng();
+  let mpv     = 40.0f64;
+  let width   = 3.0f64;
+  let sigma   = 4.0f64;
+  let x0      = mpv - LANDAU_MPV_SHIFT*width;
+  let mut values = Vec::<f32>::new();
+  while values.len() < 20000 {
+    // Landau by rejection sampling, then
+    // gaussian smearing (Box-Muller)
+    let v = rng.gen_range(-4.0..40.0f64);
+    if rng.gen_range(0.0..0.19f64) > landau_pdf(v, 0.0, 1.0) {
+      continue;
+    }
+    let u1 : f64 = rng.gen_range(1e-12..1.0);
+    let u2 : f64 = rng.gen_range(0.0..1.0);
+    let g = (-2.0*u1.ln()).sqrt()*(2.0*std::f64::consts::PI*u2).cos();
+    values.push((x0 + v*width + g*sigma) as f32);
+  }
+  let fit = fit_landau_gauss(&values, 50).unwrap();
+  assert!((fit.mpv as f64 - mpv).abs() < 1.0, "{}", fit);
+  assert!((fit.width as f64 - width).abs() < 1.0, "{}", fit);
+  assert!((fit.sigma as f64 - sigma).abs() < 1.5, "{}", fit);
+}
@@ -132,7 +132,9 @@ impl Error for RunError {
 #[derive(Debug, Copy, Clone, serde::Deserialize, serde::Serialize)]
 #[repr(u8)]
 pub enum EventError {
-  EventIdMismatch
+  EventIdMismatch,
+  /// A trigger hit could not be mapped to a paddle
+  UnknownTriggerHit,
 }
 
 impl fmt::Display for EventError {
@@ -166,7 +168,10 @@ pub enum SerializationError {
   WrongByteSize,
   JsonDecodingError,
   TomlDecodingError,
-  Disconnected
+  Disconnected,
+  /// The version byte of the stream is not known
+  /// to the decoder
+  UnsupportedVersion
 }
 
 impl fmt::Display for SerializationError {
@@ -254,6 +259,7 @@ pub enum WaveformError {
   OutOfRangeLowerBound,
   DidNotCrossThreshold,
   TooSpiky,
+  FitFailed,
 }
 
 impl fmt::Display for WaveformError {
@@ -308,7 +314,8 @@ pub enum AnalysisError {
   MissingChannel,
   NoChannel9,
   InputBroken,
-  DataMangling
+  DataMangling,
+  UnknownAnalyzer,
 }
 
 impl fmt::Display for AnalysisError {
@@ -391,3 +398,48 @@ impl fmt::Display for CmdError {
 
 impl Error for CmdError {
 }
+
+////////////////////////////////////////
+
+#[derive(Debug, Copy, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[repr(u8)]
+pub enum ReconstructionError {
+  PaddlesNotSet,
+  NotEnoughHits,
+  NoOuterHits,
+  NoInnerHits,
+  DegenerateTrack,
+}
+
+impl fmt::Display for ReconstructionError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let disp = serde_json::to_string(self).unwrap_or(
+      String::from("Error: cannot unwrap this ReconstructionError"));
+    write!(f, "<ReconstructionError : {}>", disp)
+  }
+}
+
+impl Error for ReconstructionError {
+}
+
+////////////////////////////////////////
+
+#[derive(Debug, Copy, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[repr(u8)]
+pub enum SelectionError {
+  UnknownVariable,
+  UnknownOperator,
+  InvalidValue,
+  TooManyRules,
+}
+
+impl fmt::Display for SelectionError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let disp = serde_json::to_string(self).unwrap_or(
+      String::from("Error: cannot unwrap this SelectionError"));
+    write!(f, "<SelectionError : {}>", disp)
+  }
+}
+
+impl Error for SelectionError {
+}
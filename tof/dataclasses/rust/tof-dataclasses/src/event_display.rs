@@ -0,0 +1,560 @@
+//! Export of TofEventSummaries for event displays
+//!
+//! A scene is a self-contained JSON description of the
+//! detector geometry (all paddles and panels) together
+//! with one or more events. Each event carries the TOF
+//! hits (position, time, energy deposition), the
+//! reconstructed track (if any) and optionally the
+//! tracker hits.
+//!
+//! Viewers should not need any other input than the
+//! scene, so hit positions are computed here from the
+//! geometry, independently of TofEventSummary::set_paddles.
+//!
+//! The layout of the scene is versioned with
+//! EVENT_DISPLAY_SCHEMA_VERSION, which has to be
+//! increased whenever a field is changed or removed.
+//! All positions and dimensions are in mm, all times
+//! in ns.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{
+  self,
+  Write,
+};
+use std::collections::HashMap;
+
+use serde::{
+  Serialize,
+  Deserialize,
+};
+
+use crate::events::{
+  TofEventSummary,
+  TofHit,
+};
+use crate::reconstruction::{
+  TofTrack,
+  TofTrackFitter,
+};
+#[cfg(feature="database")]
+use crate::database::{
+  Paddle,
+  DBPanel,
+};
+
+/// Version of the scene layout. Viewers should check
+/// this before reading a scene.
+pub const EVENT_DISPLAY_SCHEMA_VERSION : u32 = 1;
+
+/// JSON has no NaN, serde_json writes it as null.
+/// Read null back as NaN, so that a scene survives
+/// the roundtrip.
+mod nan_as_null {
+  use serde::{
+    Deserialize,
+    Deserializer,
+    Serializer,
+  };
+
+  pub fn serialize<S : Serializer>(value : &f32, serializer : S) -> Result<S::Ok, S::Error> {
+    if value.is_finite() {
+      serializer.serialize_f32(*value)
+    } else {
+      serializer.serialize_none()
+    }
+  }
+
+  pub fn deserialize<'de, D : Deserializer<'de>>(deserializer : D) -> Result<f32, D::Error> {
+    Ok(Option::<f32>::deserialize(deserializer)?.unwrap_or(f32::NAN))
+  }
+}
+
+/// Geometry of a single paddle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisplayPaddle {
+  pub paddle_id   : u8,
+  pub panel_id    : u16,
+  pub volume_id   : i64,
+  /// Center of the paddle (L0 coordinates)
+  pub center      : [f32;3],
+  /// Center of the A side end of the paddle
+  pub end_a       : [f32;3],
+  /// Center of the B side end of the paddle
+  pub end_b       : [f32;3],
+  /// Unit vector along the paddle from A -> B
+  pub principal   : [f32;3],
+  /// Unit vector normal to the paddle surface
+  pub normal      : [f32;3],
+  /// Length (along the principal axis)
+  pub length      : f32,
+  pub width       : f32,
+  pub height      : f32,
+  /// Harting cable length to the RB (cm), needed
+  /// to get the hit time
+  pub cable_len   : f32,
+}
+
+impl DisplayPaddle {
+
+  pub fn new() -> Self {
+    Self {
+      paddle_id   : 0,
+      panel_id    : 0,
+      volume_id   : 0,
+      center      : [0.0;3],
+      end_a       : [0.0;3],
+      end_b       : [0.0;3],
+      principal   : [1.0, 0.0, 0.0],
+      normal      : [0.0, 0.0, 1.0],
+      length      : 0.0,
+      width       : 0.0,
+      height      : 0.0,
+      cable_len   : 0.0,
+    }
+  }
+
+  /// Convert the database paddle (cm) to mm
+  #[cfg(feature="database")]
+  pub fn from_paddle(paddle : &Paddle) -> Self {
+    let pr = paddle.principal();
+    Self {
+      paddle_id   : paddle.paddle_id as u8,
+      panel_id    : paddle.panel_id as u16,
+      volume_id   : paddle.volume_id,
+      center      : [paddle.global_pos_x_l0*10.0,
+                     paddle.global_pos_y_l0*10.0,
+                     paddle.global_pos_z_l0*10.0],
+      end_a       : [paddle.global_pos_x_l0_A*10.0,
+                     paddle.global_pos_y_l0_A*10.0,
+                     paddle.global_pos_z_l0_A*10.0],
+      end_b       : [paddle.global_pos_x_l0_B*10.0,
+                     paddle.global_pos_y_l0_B*10.0,
+                     paddle.global_pos_z_l0_B*10.0],
+      principal   : [pr.0, pr.1, pr.2],
+      normal      : [paddle.normal_x, paddle.normal_y, paddle.normal_z],
+      length      : paddle.length*10.0,
+      width       : paddle.width*10.0,
+      height      : paddle.height*10.0,
+      cable_len   : paddle.cable_len,
+    }
+  }
+
+  /// Set paddle length and cable length of the hit and
+  /// calculate its position from the time difference
+  /// at the paddle ends (same as TofHit::set_paddle)
+  pub fn place_hit(&self, hit : &mut TofHit) {
+    hit.paddle_len = self.length;
+    hit.cable_len  = self.cable_len;
+    let rel_pos    = hit.get_pos() - self.length/2.0;
+    hit.x          = self.center[0] + self.principal[0]*rel_pos;
+    hit.y          = self.center[1] + self.principal[1]*rel_pos;
+    hit.z          = self.center[2] + self.principal[2]*rel_pos;
+  }
+}
+
+impl Default for DisplayPaddle {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// A panel, that is a group of paddles facing in the
+/// same direction
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisplayPanel {
+  pub panel_id    : u16,
+  pub description : String,
+  pub normal      : [f32;3],
+  pub paddle_ids  : Vec<u8>,
+}
+
+impl DisplayPanel {
+
+  pub fn new() -> Self {
+    Self {
+      panel_id    : 0,
+      description : String::from(""),
+      normal      : [0.0;3],
+      paddle_ids  : Vec::<u8>::new(),
+    }
+  }
+
+  #[cfg(feature="database")]
+  pub fn from_db_panel(panel : &DBPanel) -> Self {
+    let pids = [panel.paddle0_id, panel.paddle1_id, panel.paddle2_id,
+                panel.paddle3_id, panel.paddle4_id, panel.paddle5_id,
+                panel.paddle6_id, panel.paddle7_id, panel.paddle8_id,
+                panel.paddle9_id, panel.paddle10_id, panel.paddle11_id];
+    Self {
+      panel_id    : panel.panel_id as u16,
+      description : panel.description.clone(),
+      normal      : [panel.normal_x as f32, panel.normal_y as f32, panel.normal_z as f32],
+      paddle_ids  : pids.iter().flatten().map(|p| *p as u8).collect(),
+    }
+  }
+}
+
+impl Default for DisplayPanel {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// The TOF geometry, as it is needed by an event display
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct DetectorGeometry {
+  pub paddles : Vec<DisplayPaddle>,
+  pub panels  : Vec<DisplayPanel>,
+}
+
+impl DetectorGeometry {
+
+  pub fn new() -> Self {
+    Self {
+      paddles : Vec::<DisplayPaddle>::new(),
+      panels  : Vec::<DisplayPanel>::new(),
+    }
+  }
+
+  #[cfg(feature="database")]
+  pub fn from_db(paddles : &Vec<Paddle>, panels : &Vec<DBPanel>) -> Self {
+    let mut geo = Self::new();
+    for pdl in paddles {
+      geo.paddles.push(DisplayPaddle::from_paddle(pdl));
+    }
+    geo.paddles.sort_by_key(|p| p.paddle_id);
+    for pnl in panels {
+      if !pnl.valid() {
+        continue;
+      }
+      geo.panels.push(DisplayPanel::from_db_panel(pnl));
+    }
+    geo.panels.sort_by_key(|p| p.panel_id);
+    geo
+  }
+}
+
+/// A TOF hit with its position
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisplayHit {
+  pub paddle_id   : u8,
+  #[serde(with = "nan_as_null")]
+  pub x           : f32,
+  #[serde(with = "nan_as_null")]
+  pub y           : f32,
+  #[serde(with = "nan_as_null")]
+  pub z           : f32,
+  /// Interaction time
+  #[serde(with = "nan_as_null")]
+  pub t0          : f32,
+  /// Energy deposition (MeV)
+  #[serde(with = "nan_as_null")]
+  pub edep        : f32,
+  /// Position along the paddle, measured from the A side
+  #[serde(with = "nan_as_null")]
+  pub pos         : f32,
+  pub causal      : bool,
+}
+
+impl From<&TofHit> for DisplayHit {
+  fn from(hit : &TofHit) -> Self {
+    Self {
+      paddle_id   : hit.paddle_id,
+      x           : hit.x,
+      y           : hit.y,
+      z           : hit.z,
+      t0          : hit.get_t0(),
+      edep        : hit.get_edep(),
+      pos         : hit.get_pos(),
+      causal      : hit.obeys_causality(),
+    }
+  }
+}
+
+/// A reconstructed (straight) track
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisplayTrack {
+  pub origin       : [f32;3],
+  pub direction    : [f32;3],
+  #[serde(with = "nan_as_null")]
+  pub beta         : f32,
+  #[serde(with = "nan_as_null")]
+  pub beta_err     : f32,
+  /// Zenith angle (deg)
+  #[serde(with = "nan_as_null")]
+  pub zenith       : f32,
+  /// Azimuth angle (deg)
+  #[serde(with = "nan_as_null")]
+  pub azimuth      : f32,
+  #[serde(with = "nan_as_null")]
+  pub reduced_chi2 : f32,
+}
+
+impl From<&TofTrack> for DisplayTrack {
+  fn from(track : &TofTrack) -> Self {
+    Self {
+      origin       : track.origin,
+      direction    : track.direction,
+      beta         : track.beta,
+      beta_err     : track.beta_err,
+      zenith       : track.get_zenith(),
+      azimuth      : track.get_azimuth(),
+      reduced_chi2 : track.get_reduced_chi2(),
+    }
+  }
+}
+
+/// A tracker hit. The tracker dataclasses live in
+/// telemetry-dataclasses, so the caller has to fill
+/// these (including the position) itself.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct DisplayTrackerHit {
+  pub layer       : u8,
+  pub row         : u8,
+  pub module      : u8,
+  pub channel     : u8,
+  pub adc         : u16,
+  pub x           : f32,
+  pub y           : f32,
+  pub z           : f32,
+}
+
+/// A single event of the scene
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct DisplayEvent {
+  pub run_id          : u16,
+  pub event_id        : u32,
+  pub timestamp48     : u64,
+  pub trigger_sources : Vec<String>,
+  pub quality         : u8,
+  pub interesting     : u8,
+  pub hits            : Vec<DisplayHit>,
+  pub track           : Option<DisplayTrack>,
+  pub tracker_hits    : Vec<DisplayTrackerHit>,
+}
+
+/// Geometry and events, ready to be written as JSON
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventDisplayScene {
+  pub schema_version : u32,
+  pub geometry       : DetectorGeometry,
+  pub events         : Vec<DisplayEvent>,
+}
+
+impl EventDisplayScene {
+
+  pub fn new(geometry : DetectorGeometry) -> Self {
+    Self {
+      schema_version : EVENT_DISPLAY_SCHEMA_VERSION,
+      geometry,
+      events         : Vec::<DisplayEvent>::new(),
+    }
+  }
+
+  pub fn to_json(&self) -> Result<String, serde_json::Error> {
+    serde_json::to_string(self)
+  }
+
+  pub fn from_json(json : &str) -> Result<Self, serde_json::Error> {
+    serde_json::from_str(json)
+  }
+}
+
+/// Convert TofEventSummaries into event display scenes
+///
+/// If a track fitter is set, a track will be fit for
+/// every event which does not come with its own track.
+#[derive(Debug, Clone)]
+pub struct EventDisplayExporter {
+  pub geometry : DetectorGeometry,
+  pub fitter   : Option<TofTrackFitter>,
+  paddle_map   : HashMap<u8, usize>,
+}
+
+impl EventDisplayExporter {
+
+  pub fn new(geometry : DetectorGeometry) -> Self {
+    let paddle_map = geometry.paddles.iter()
+      .enumerate()
+      .map(|(k, p)| (p.paddle_id, k))
+      .collect();
+    Self {
+      geometry,
+      fitter   : Some(TofTrackFitter::new()),
+      paddle_map,
+    }
+  }
+
+  #[cfg(feature="database")]
+  pub fn from_db(paddles : &Vec<Paddle>, panels : &Vec<DBPanel>) -> Self {
+    Self::new(DetectorGeometry::from_db(paddles, panels))
+  }
+
+  pub fn get_paddle(&self, pid : u8) -> Option<&DisplayPaddle> {
+    self.paddle_map.get(&pid).map(|k| &self.geometry.paddles[*k])
+  }
+
+  /// Convert a single event
+  ///
+  /// # Arguments:
+  ///   * event        : hits without a paddle in the
+  ///     geometry will be dropped
+  ///   * track        : an already reconstructed track.
+  ///     If None, the track of the event (V3) or 
+  ///     else the fitter (if any) will be used.
+  ///   * tracker_hits : hits of the tracker for this event
+  pub fn get_event(&self,
+                   event        : &TofEventSummary,
+                   track        : Option<&TofTrack>,
+                   tracker_hits : Vec<DisplayTrackerHit>) -> DisplayEvent {
+    let mut placed = event.clone();
+    placed.hits.clear();
+    for h in &event.hits {
+      match self.get_paddle(h.paddle_id) {
+        None => {
+          warn!("Paddle {} not in geometry, dropping hit!", h.paddle_id);
+        }
+        Some(pdl) => {
+          let mut hit = *h;
+          pdl.place_hit(&mut hit);
+          placed.hits.push(hit);
+        }
+      }
+    }
+    placed.paddles_set = true;
+    let track = match track.or(event.track.as_ref()) {
+      Some(trk) => Some(DisplayTrack::from(trk)),
+      None => {
+        match &self.fitter {
+          None => None,
+          Some(fitter) => {
+            match fitter.fit(&placed) {
+              Err(err) => {
+                debug!("No track for event {}! {err}", event.event_id);
+                None
+              }
+              Ok(trk) => Some(DisplayTrack::from(&trk))
+            }
+          }
+        }
+      }
+    };
+    DisplayEvent {
+      run_id          : event.run_id,
+      event_id        : event.event_id,
+      timestamp48     : event.get_timestamp48(),
+      trigger_sources : event.get_trigger_sources().iter().map(|t| t.to_string()).collect(),
+      quality         : event.quality,
+      interesting     : event.interesting,
+      hits            : placed.hits.iter().map(DisplayHit::from).collect(),
+      track,
+      tracker_hits,
+    }
+  }
+
+  /// A scene with the geometry and a single event
+  pub fn export_event(&self,
+                      event        : &TofEventSummary,
+                      track        : Option<&TofTrack>,
+                      tracker_hits : Vec<DisplayTrackerHit>) -> EventDisplayScene {
+    let mut scene = EventDisplayScene::new(self.geometry.clone());
+    scene.events.push(self.get_event(event, track, tracker_hits));
+    scene
+  }
+
+  /// A scene with the geometry and a list of events,
+  /// tracks will be fit if a fitter is set
+  pub fn export_events(&self, events : &[TofEventSummary]) -> EventDisplayScene {
+    let mut scene = EventDisplayScene::new(self.geometry.clone());
+    for ev in events {
+      scene.events.push(self.get_event(ev, None, Vec::<DisplayTrackerHit>::new()));
+    }
+    scene
+  }
+
+  /// Write a scene with a list of events to a JSON file
+  pub fn write_events(&self, events : &[TofEventSummary], filename : &str) -> io::Result<()> {
+    let scene = self.export_events(events);
+    let json  = scene.to_json().map_err(io::Error::other)?;
+    let mut file = File::create(filename)?;
+    file.write_all(json.as_bytes())?;
+    Ok(())
+  }
+}
+
+impl fmt::Display for EventDisplayExporter {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<EventDisplayExporter:");
+    repr += &(format!("\n  schema version : {}", EVENT_DISPLAY_SCHEMA_VERSION));
+    repr += &(format!("\n  n paddles      : {}", self.geometry.paddles.len()));
+    repr += &(format!("\n  n panels       : {}", self.geometry.panels.len()));
+    repr += &(format!("\n  fit tracks     : {}>", self.fitter.is_some()));
+    write!(f, "{}", repr)
+  }
+}
+
+#[cfg(test)]
+fn test_geometry() -> DetectorGeometry {
+  let mut geo = DetectorGeometry::new();
+  // two horizontal layers, the outer one on top
+  for (pid, z) in [(1u8, 0.0f32), (61u8, 1000.0f32)] {
+    let mut pdl    = DisplayPaddle::new();
+    pdl.paddle_id  = pid;
+    pdl.center     = [0.0, 0.0, z];
+    pdl.end_a      = [-900.0, 0.0, z];
+    pdl.end_b      = [900.0, 0.0, z];
+    pdl.length     = 1800.0;
+    pdl.width      = 160.0;
+    pdl.height     = 6.35;
+    geo.paddles.push(pdl);
+  }
+  geo
+}
+
+#[test]
+fn event_display_place_hits() {
+  let exporter  = EventDisplayExporter::new(test_geometry());
+  let mut event = TofEventSummary::new();
+  event.event_id = 42;
+  let mut hit   = TofHit::new();
+  hit.paddle_id = 61;
+  hit.set_time_a(20.0);
+  hit.set_time_b(20.0);
+  event.hits.push(hit);
+  // not in the geometry
+  hit.paddle_id = 100;
+  event.hits.push(hit);
+  let ev = exporter.get_event(&event, None, Vec::<DisplayTrackerHit>::new());
+  assert_eq!(ev.event_id, 42);
+  assert_eq!(ev.hits.len(), 1);
+  // same time at both ends -> center of the paddle
+  assert!(ev.hits[0].x.abs() < 1.0);
+  assert_eq!(ev.hits[0].z, 1000.0);
+  // one hit is not enough for a track
+  assert!(ev.track.is_none());
+}
+
+#[test]
+fn event_display_json_roundtrip() {
+  let exporter  = EventDisplayExporter::new(test_geometry());
+  let mut event = TofEventSummary::new();
+  let mut hit   = TofHit::new();
+  hit.paddle_id = 1;
+  hit.set_time_a(25.0);
+  hit.set_time_b(24.0);
+  event.hits.push(hit);
+  let mut track = TofTrack::new();
+  track.beta    = 0.98;
+  let thit      = DisplayTrackerHit { layer : 3, adc : 120, z : 500.0, ..Default::default() };
+  let scene     = exporter.export_event(&event, Some(&track), vec![thit]);
+  let json      = scene.to_json().unwrap();
+  let test      = EventDisplayScene::from_json(&json).unwrap();
+  assert_eq!(test.schema_version, EVENT_DISPLAY_SCHEMA_VERSION);
+  assert_eq!(test.geometry.paddles.len(), 2);
+  assert_eq!(test.events.len(), 1);
+  assert_eq!(test.events[0].tracker_hits[0], thit);
+  assert_eq!(test.events[0].track.unwrap().beta, 0.98);
+  assert_eq!(test.events[0].hits, scene.events[0].hits);
+}
@@ -10,6 +10,10 @@ pub mod rb_event;
 pub mod rb_eventmemoryview;
 pub mod data_type;
 pub mod tof_hit;
+pub mod quality;
+pub mod selection;
+pub mod trigger_emulator;
+pub mod event_id_check;
 
 pub use master_trigger::{
   MasterTriggerEvent,
@@ -18,9 +22,32 @@ pub use master_trigger::{
 pub use tof_event::{
   TofEvent,
   TofEventHeader,
-  TofEventSummary
+  TofEventSummary,
+  EventQuality,
+};
+pub use quality::{
+  EventClassifier,
+  EventQualityRules,
+};
+pub use selection::{
+  EventSelector,
+  SelectionRule,
+  MAX_SELECTION_RULES,
+};
+pub use trigger_emulator::{
+  TriggerEmulator,
+  TriggerEfficiency,
+  TriggerThresholds,
+};
+pub use event_id_check::{
+  EventIdChecker,
+  EventIdStats,
+  EventStreamChecker,
+};
+pub use tof_hit::{
+  TofHit,
+  TofPulse,
 };
-pub use tof_hit::TofHit;
 pub use data_type::DataType;
 
 #[allow(deprecated)]
@@ -0,0 +1,530 @@
+//! Event id continuity checks
+//!
+//! Keep track of the event ids of a stream of events
+//! (MasterTriggerEvent, TofEventSummary or TofEvent) and
+//! count missing, duplicate and out-of-order event ids,
+//! as well as resets and u32 wraparounds of the MTB
+//! event counter.
+//!
+//! Event ids which are skipped are not counted as missing
+//! right away, but only once they are older than the
+//! checker window. If they arrive before that, they are
+//! counted as out-of-order instead. A step back to an
+//! event id which has neither been skipped nor seen
+//! is a counter reset, even if it is within the window.
+//!
+//! The checkers can be used online (see EventIdHeartbeat)
+//! as well as offline on run files:
+//!
+//! ```ignore
+//! let mut checker = EventStreamChecker::new();
+//! let reader = TofPacketReader::new(String::from("/data/run/"));
+//! for pack in reader {
+//!   checker.add_packet(&pack);
+//! }
+//! checker.finish();
+//! println!("{}", checker);
+//! ```
+
+use std::fmt;
+use std::collections::{
+  HashSet,
+  VecDeque,
+};
+
+use crate::serialization::{
+  Serialization,
+  SerializationError,
+  parse_u32,
+  parse_u64,
+};
+use crate::packets::{
+  TofPacket,
+  PacketType,
+};
+use crate::events::{
+  MasterTriggerEvent,
+  TofEvent,
+  TofEventSummary,
+};
+
+#[cfg(feature="random")]
+use crate::FromRandom;
+#[cfg(feature="random")]
+use rand::Rng;
+
+/// Default number of event ids an event can arrive
+/// late before it is considered lost
+pub const EVID_CHECK_WINDOW   : u32 = 1000;
+
+/// Forward jumps of the event id larger than this
+/// are considered a counter reset rather than a gap
+pub const EVID_CHECK_MAX_JUMP : u32 = 1_000_000;
+
+/// Counters of an EventIdChecker
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EventIdStats {
+  /// Number of seen events (including duplicates)
+  pub n_events       : u64,
+  /// Number of event ids which never arrived
+  pub n_missing      : u64,
+  /// Number of event ids which were seen more than once
+  pub n_duplicates   : u64,
+  /// Number of events which arrived after a newer event
+  pub n_out_of_order : u64,
+  /// Number of gaps (one gap can be several missing ids)
+  pub n_gaps         : u64,
+  /// Number of times the event id jumped (backwards to
+  /// an id which was never skipped or forward by more 
+  /// than EVID_CHECK_MAX_JUMP)
+  pub n_resets       : u32,
+  /// Number of times the event id wrapped around u32::MAX
+  pub n_wraparounds  : u32,
+  pub first_evid     : u32,
+  pub last_evid      : u32,
+}
+
+impl EventIdStats {
+
+  pub fn new() -> Self {
+    Self {
+      n_events       : 0,
+      n_missing      : 0,
+      n_duplicates   : 0,
+      n_out_of_order : 0,
+      n_gaps         : 0,
+      n_resets       : 0,
+      n_wraparounds  : 0,
+      first_evid     : 0,
+      last_evid      : 0,
+    }
+  }
+
+  /// Fraction of the expected events which are missing
+  pub fn get_missing_fraction(&self) -> f64 {
+    let n_expected = self.n_events - self.n_duplicates + self.n_missing;
+    if n_expected == 0 {
+      return 0.0;
+    }
+    self.n_missing as f64 / n_expected as f64
+  }
+
+  /// No problems have been found
+  pub fn is_clean(&self) -> bool {
+    self.n_missing      == 0 &&
+    self.n_duplicates   == 0 &&
+    self.n_out_of_order == 0 &&
+    self.n_resets       == 0
+  }
+}
+
+impl Default for EventIdStats {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Serialization for EventIdStats {
+  const HEAD : u16 = 0xAAAA;
+  const TAIL : u16 = 0x5555;
+  const SIZE : usize = 60;
+
+  fn from_bytestream(stream : &Vec<u8>,
+                     pos    : &mut usize)
+    -> Result<Self, SerializationError> {
+    Self::verify_fixed(stream, pos)?;
+    let mut stats        = EventIdStats::new();
+    stats.n_events       = parse_u64(stream, pos);
+    stats.n_missing      = parse_u64(stream, pos);
+    stats.n_duplicates   = parse_u64(stream, pos);
+    stats.n_out_of_order = parse_u64(stream, pos);
+    stats.n_gaps         = parse_u64(stream, pos);
+    stats.n_resets       = parse_u32(stream, pos);
+    stats.n_wraparounds  = parse_u32(stream, pos);
+    stats.first_evid     = parse_u32(stream, pos);
+    stats.last_evid      = parse_u32(stream, pos);
+    *pos += 2;
+    Ok(stats)
+  }
+
+  fn to_bytestream(&self) -> Vec<u8> {
+    let mut bs = Vec::<u8>::with_capacity(Self::SIZE);
+    bs.extend_from_slice(&Self::HEAD.to_le_bytes());
+    bs.extend_from_slice(&self.n_events.to_le_bytes());
+    bs.extend_from_slice(&self.n_missing.to_le_bytes());
+    bs.extend_from_slice(&self.n_duplicates.to_le_bytes());
+    bs.extend_from_slice(&self.n_out_of_order.to_le_bytes());
+    bs.extend_from_slice(&self.n_gaps.to_le_bytes());
+    bs.extend_from_slice(&self.n_resets.to_le_bytes());
+    bs.extend_from_slice(&self.n_wraparounds.to_le_bytes());
+    bs.extend_from_slice(&self.first_evid.to_le_bytes());
+    bs.extend_from_slice(&self.last_evid.to_le_bytes());
+    bs.extend_from_slice(&Self::TAIL.to_le_bytes());
+    bs
+  }
+}
+
+#[cfg(feature="random")]
+impl FromRandom for EventIdStats {
+  fn from_random() -> Self {
+    let mut rng = rand::thread_rng();
+    Self {
+      n_events       : rng.gen::<u64>(),
+      n_missing      : rng.gen::<u64>(),
+      n_duplicates   : rng.gen::<u64>(),
+      n_out_of_order : rng.gen::<u64>(),
+      n_gaps         : rng.gen::<u64>(),
+      n_resets       : rng.gen::<u32>(),
+      n_wraparounds  : rng.gen::<u32>(),
+      first_evid     : rng.gen::<u32>(),
+      last_evid      : rng.gen::<u32>(),
+    }
+  }
+}
+
+impl fmt::Display for EventIdStats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<EventIdStats:");
+    repr += &(format!("\n  event ids      : {} .. {}", self.first_evid, self.last_evid));
+    repr += &(format!("\n  n events       : {}", self.n_events));
+    repr += &(format!("\n  n missing      : {} ({:.3}%) in {} gaps", self.n_missing, 100.0*self.get_missing_fraction(), self.n_gaps));
+    repr += &(format!("\n  n duplicates   : {}", self.n_duplicates));
+    repr += &(format!("\n  n out of order : {}", self.n_out_of_order));
+    repr += &(format!("\n  n resets       : {}", self.n_resets));
+    repr += &(format!("\n  n wraparounds  : {}>", self.n_wraparounds));
+    write!(f, "{}", repr)
+  }
+}
+
+/// Continuity check for a single stream of event ids
+#[derive(Debug, Clone)]
+pub struct EventIdChecker {
+  pub stats   : EventIdStats,
+  /// Number of event ids an event can arrive late
+  /// before it is considered lost
+  pub window  : u32,
+  /// Largest event id seen so far (modulo wraparound)
+  highest     : Option<u32>,
+  /// Recently seen event ids, to find duplicates
+  seen        : HashSet<u32>,
+  seen_order  : VecDeque<u32>,
+  /// Event ids which have been skipped, but might
+  /// still arrive
+  pending     : HashSet<u32>,
+  pending_ids : VecDeque<u32>,
+}
+
+impl EventIdChecker {
+
+  pub fn new() -> Self {
+    Self::with_window(EVID_CHECK_WINDOW)
+  }
+
+  pub fn with_window(window : u32) -> Self {
+    Self {
+      stats       : EventIdStats::new(),
+      window,
+      highest     : None,
+      seen        : HashSet::<u32>::new(),
+      seen_order  : VecDeque::<u32>::new(),
+      pending     : HashSet::<u32>::new(),
+      pending_ids : VecDeque::<u32>::new(),
+    }
+  }
+
+  /// Forget everything, including the counters
+  pub fn reset(&mut self) {
+    *self = Self::with_window(self.window);
+  }
+
+  /// Number of skipped event ids which might still arrive
+  pub fn get_n_pending(&self) -> usize {
+    self.pending.len()
+  }
+
+  /// Add the next event id of the stream
+  pub fn add(&mut self, evid : u32) {
+    self.stats.n_events += 1;
+    self.stats.last_evid = evid;
+    let highest = match self.highest {
+      None => {
+        self.stats.first_evid = evid;
+        self.highest = Some(evid);
+        self.remember(evid);
+        return;
+      }
+      Some(h) => h
+    };
+    let ahead = evid.wrapping_sub(highest);
+    let back  = highest.wrapping_sub(evid);
+    if ahead == 0 {
+      self.stats.n_duplicates += 1;
+    } else if ahead <= EVID_CHECK_MAX_JUMP {
+      if evid < highest {
+        self.stats.n_wraparounds += 1;
+      }
+      let n_skipped = ahead - 1;
+      if n_skipped > 0 {
+        self.stats.n_gaps += 1;
+        // only the last window ids can still arrive
+        let mut first = 1;
+        if n_skipped > self.window {
+          self.stats.n_missing += (n_skipped - self.window) as u64;
+          first = ahead - self.window;
+        }
+        for k in first..ahead {
+          let id = highest.wrapping_add(k);
+          self.pending.insert(id);
+          self.pending_ids.push_back(id);
+        }
+      }
+      self.highest = Some(evid);
+      self.remember(evid);
+      self.expire();
+    } else if back <= self.window {
+      if self.pending.remove(&evid) {
+        self.stats.n_out_of_order += 1;
+        self.remember(evid);
+      } else if self.seen.contains(&evid) {
+        self.stats.n_duplicates += 1;
+      } else {
+        // nothing was skipped there, so this is
+        // not a late event
+        self.counter_reset(highest, evid);
+      }
+    } else {
+      self.counter_reset(highest, evid);
+    }
+  }
+
+  /// Start over at evid. The skipped event ids 
+  /// won't arrive anymore.
+  fn counter_reset(&mut self, highest : u32, evid : u32) {
+    debug!("Event id jumped from {} to {}!", highest, evid);
+    self.stats.n_resets += 1;
+    self.flush();
+    self.seen.clear();
+    self.seen_order.clear();
+    self.highest = Some(evid);
+    self.remember(evid);
+  }
+
+  /// Count all skipped event ids which did not
+  /// arrive (yet) as missing, e.g. at the end
+  /// of a run
+  pub fn flush(&mut self) {
+    self.stats.n_missing += self.pending.len() as u64;
+    self.pending.clear();
+    self.pending_ids.clear();
+  }
+
+  fn remember(&mut self, evid : u32) {
+    if self.seen.insert(evid) {
+      self.seen_order.push_back(evid);
+    }
+    while self.seen_order.len() > self.window as usize {
+      if let Some(id) = self.seen_order.pop_front() {
+        self.seen.remove(&id);
+      }
+    }
+  }
+
+  /// Skipped event ids which are older than the
+  /// window are lost
+  fn expire(&mut self) {
+    let highest = self.highest.unwrap_or_default();
+    while let Some(id) = self.pending_ids.front() {
+      if highest.wrapping_sub(*id) <= self.window {
+        break;
+      }
+      if self.pending.remove(id) {
+        self.stats.n_missing += 1;
+      }
+      self.pending_ids.pop_front();
+    }
+  }
+}
+
+impl Default for EventIdChecker {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for EventIdChecker {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<EventIdChecker:");
+    repr += &(format!("\n  window    : {}", self.window));
+    repr += &(format!("\n  n pending : {}", self.pending.len()));
+    repr += &(format!("\n  {}>", self.stats));
+    write!(f, "{}", repr)
+  }
+}
+
+/// Event id checks for all event streams
+/// (MasterTriggerEvent, TofEventSummary, TofEvent)
+#[derive(Debug, Clone, Default)]
+pub struct EventStreamChecker {
+  pub mte         : EventIdChecker,
+  pub summary     : EventIdChecker,
+  pub tof_event   : EventIdChecker,
+  /// Packets which could not be unpacked
+  pub n_broken    : u64,
+}
+
+impl EventStreamChecker {
+
+  pub fn new() -> Self {
+    Self {
+      mte         : EventIdChecker::new(),
+      summary     : EventIdChecker::new(),
+      tof_event   : EventIdChecker::new(),
+      n_broken    : 0,
+    }
+  }
+
+  pub fn add_mte(&mut self, event : &MasterTriggerEvent) {
+    self.mte.add(event.event_id);
+  }
+
+  pub fn add_summary(&mut self, event : &TofEventSummary) {
+    self.summary.add(event.event_id);
+  }
+
+  pub fn add_tof_event(&mut self, event : &TofEvent) {
+    self.tof_event.add(event.header.event_id);
+  }
+
+  /// Check the event id of any event packet. Other
+  /// packets are ignored.
+  pub fn add_packet(&mut self, pack : &TofPacket) {
+    match pack.packet_type {
+      PacketType::MasterTrigger => {
+        match pack.unpack::<MasterTriggerEvent>() {
+          Ok(ev)   => self.add_mte(&ev),
+          Err(err) => {
+            error!("Unable to unpack MasterTriggerEvent! {err}");
+            self.n_broken += 1;
+          }
+        }
+      }
+      PacketType::TofEventSummary => {
+        match pack.unpack::<TofEventSummary>() {
+          Ok(ev)   => self.add_summary(&ev),
+          Err(err) => {
+            error!("Unable to unpack TofEventSummary! {err}");
+            self.n_broken += 1;
+          }
+        }
+      }
+      PacketType::TofEvent => {
+        match pack.unpack::<TofEvent>() {
+          Ok(ev)   => self.add_tof_event(&ev),
+          Err(err) => {
+            error!("Unable to unpack TofEvent! {err}");
+            self.n_broken += 1;
+          }
+        }
+      }
+      _ => ()
+    }
+  }
+
+  /// Count all outstanding event ids as missing,
+  /// call this at the end of a run
+  pub fn finish(&mut self) {
+    self.mte.flush();
+    self.summary.flush();
+    self.tof_event.flush();
+  }
+}
+
+impl fmt::Display for EventStreamChecker {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<EventStreamChecker:");
+    repr += &(format!("\n ** MasterTriggerEvent **\n  {}", self.mte.stats));
+    repr += &(format!("\n ** TofEventSummary **\n  {}", self.summary.stats));
+    repr += &(format!("\n ** TofEvent **\n  {}", self.tof_event.stats));
+    repr += &(format!("\n n broken packets : {}>", self.n_broken));
+    write!(f, "{}", repr)
+  }
+}
+
+#[test]
+fn evid_check_gaps_and_duplicates() {
+  let mut checker = EventIdChecker::with_window(10);
+  for evid in [1, 2, 3, 5, 4, 6, 6, 9, 10] {
+    checker.add(evid);
+  }
+  assert_eq!(checker.stats.n_events, 9);
+  assert_eq!(checker.stats.n_duplicates, 1);
+  assert_eq!(checker.stats.n_out_of_order, 1);
+  assert_eq!(checker.stats.n_gaps, 2);
+  // 7 and 8 might still come
+  assert_eq!(checker.stats.n_missing, 0);
+  assert_eq!(checker.get_n_pending(), 2);
+  // now they are out of the window, together
+  // with 11-19
+  checker.add(30);
+  assert_eq!(checker.stats.n_missing, 2 + 9);
+  // late arrival, then duplicate
+  checker.add(29);
+  assert_eq!(checker.stats.n_duplicates, 1);
+  assert_eq!(checker.stats.n_out_of_order, 2);
+  checker.add(29);
+  assert_eq!(checker.stats.n_duplicates, 2);
+  // 20-28 never arrive
+  checker.flush();
+  assert_eq!(checker.get_n_pending(), 0);
+  assert_eq!(checker.stats.n_missing, 2 + 9 + 9);
+}
+
+#[test]
+fn evid_check_reset_and_wraparound() {
+  let mut checker = EventIdChecker::with_window(10);
+  for evid in [u32::MAX - 1, u32::MAX, 0, 1] {
+    checker.add(evid);
+  }
+  assert_eq!(checker.stats.n_wraparounds, 1);
+  assert!(checker.stats.is_clean());
+  checker.add(100000);
+  checker.add(100001);
+  // counter reset of the MTB
+  checker.add(0);
+  checker.add(1);
+  assert_eq!(checker.stats.n_resets, 1);
+  assert_eq!(checker.stats.n_gaps, 1);
+  assert_eq!(checker.stats.n_missing, 99998);
+  assert_eq!(checker.stats.n_duplicates, 0);
+  assert_eq!(checker.stats.first_evid, u32::MAX - 1);
+  assert_eq!(checker.stats.last_evid, 1);
+}
+
+#[cfg(feature = "random")]
+#[test]
+fn serialization_eventidstats() {
+  for _ in 0..100 {
+    let stats = EventIdStats::from_random();
+    let test  = EventIdStats::from_bytestream(&stats.to_bytestream(), &mut 0).unwrap();
+    assert_eq!(stats, test);
+  }
+}
+
+#[test]
+fn evid_check_reset_within_window() {
+  let mut checker = EventIdChecker::with_window(10);
+  for evid in [3, 4, 5, 7] {
+    checker.add(evid);
+  }
+  // the counter restarts shortly after the
+  // start of the run
+  for evid in [0, 1, 2] {
+    checker.add(evid);
+  }
+  assert_eq!(checker.stats.n_resets, 1);
+  assert_eq!(checker.stats.n_out_of_order, 0);
+  assert_eq!(checker.stats.n_duplicates, 0);
+  // 6 won't come anymore
+  assert_eq!(checker.stats.n_missing, 1);
+  assert_eq!(checker.get_n_pending(), 0);
+}
@@ -0,0 +1,314 @@
+//! Event quality classification
+//!
+//! Grade events (EventQuality) based on a set of
+//! rules per grade. The rules are checked from the
+//! highest grade (FourLeafClover) downwards and the
+//! event gets the first grade for which it passes
+//! all the rules. Events which don't even pass
+//! the rules for Silver remain Unknown.
+
+use std::fmt;
+
+use crate::events::{
+  EventQuality,
+  EventStatus,
+  TofEventSummary,
+  TriggerType,
+};
+#[cfg(feature="database")]
+use crate::database::DsiJChPidMapping;
+#[cfg(feature="database")]
+use crate::errors::EventError;
+use crate::constants::MAX_PID_CUBE;
+
+/// The requirements an event has to fulfill to
+/// get a certain EventQuality
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EventQualityRules {
+  /// Minimum number of hits in the complete TOF
+  pub min_n_hits         : u8,
+  /// Maximum number of hits in the complete TOF
+  pub max_n_hits         : u8,
+  /// Minimum number of hits in umbrella + cortina
+  pub min_n_hits_outer   : u8,
+  /// Minimum number of hits in the cube
+  pub min_n_hits_cbe     : u8,
+  /// Allow events where any of the RBEvents
+  /// had sync errors or data corruption
+  pub allow_mangling     : bool,
+  /// Allow events which timed out in the event
+  /// builder or were incomplete otherwise
+  pub allow_incomplete   : bool,
+  /// Maximum number of triggered paddles without
+  /// a HG hit. This is only checked if a
+  /// DsiJChPidMapping is given for the classification.
+  pub max_missing_hg     : u8,
+  /// Maximum number of hits lost due to the DRS
+  /// being busy
+  pub max_lost_hits      : u16,
+  /// Minimum fraction of hits obeying causality.
+  /// This requires the paddle information to be set
+  /// for the event, if not, only a value of 0
+  /// will pass.
+  pub min_frac_causal    : f32,
+  /// At least one of the trigger sources of the
+  /// event has to be in this list. An empty list
+  /// accepts all trigger sources.
+  pub trigger_sources    : Vec<TriggerType>,
+}
+
+impl EventQualityRules {
+
+  /// Rules which accept every event
+  pub fn new() -> Self {
+    Self {
+      min_n_hits         : 0,
+      max_n_hits         : u8::MAX,
+      min_n_hits_outer   : 0,
+      min_n_hits_cbe     : 0,
+      allow_mangling     : true,
+      allow_incomplete   : true,
+      max_missing_hg     : u8::MAX,
+      max_lost_hits      : u16::MAX,
+      min_frac_causal    : 0.0,
+      trigger_sources    : Vec::<TriggerType>::new(),
+    }
+  }
+
+  /// Check if an event passes all the rules
+  ///
+  /// n_missing is the number of triggered paddles
+  /// without HG hit. If None, the corresponding
+  /// rule is not checked.
+  pub fn passes(&self, event : &TofEventSummary, n_missing : Option<usize>) -> bool {
+    let n_hits  = event.hits.len();
+    let n_cbe   = event.hits.iter().filter(|h| h.paddle_id <= MAX_PID_CUBE).count();
+    let n_outer = n_hits - n_cbe;
+    if n_hits  < self.min_n_hits as usize
+    || n_hits  > self.max_n_hits as usize
+    || n_outer < self.min_n_hits_outer as usize
+    || n_cbe   < self.min_n_hits_cbe as usize {
+      return false;
+    }
+    if !self.allow_mangling && is_mangled(event.status) {
+      return false;
+    }
+    if !self.allow_incomplete && is_incomplete(event.status) {
+      return false;
+    }
+    if let Some(n_miss) = n_missing {
+      if n_miss > self.max_missing_hg as usize {
+        return false;
+      }
+    }
+    if event.drs_dead_lost_hits > self.max_lost_hits {
+      return false;
+    }
+    if self.min_frac_causal > 0.0 {
+      if !event.paddles_set || n_hits == 0 {
+        return false;
+      }
+      let n_causal = event.hits.iter().filter(|h| h.obeys_causality()).count();
+      if (n_causal as f32)/(n_hits as f32) < self.min_frac_causal {
+        return false;
+      }
+    }
+    if !self.trigger_sources.is_empty() {
+      let sources = event.get_trigger_sources();
+      if !sources.iter().any(|s| self.trigger_sources.contains(s)) {
+        return false;
+      }
+    }
+    true
+  }
+}
+
+impl Default for EventQualityRules {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for EventQualityRules {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let disp = toml::to_string(self).unwrap_or(
+      String::from("-- DESERIALIZATION ERROR! --"));
+    write!(f, "<EventQualityRules :\n{}>", disp)
+  }
+}
+
+/// Data corruption of any kind
+fn is_mangled(status : EventStatus) -> bool {
+  matches!(status,
+    EventStatus::CRC32Wrong
+    | EventStatus::TailWrong
+    | EventStatus::ChannelIDWrong
+    | EventStatus::CellSyncErrors
+    | EventStatus::ChnSyncErrors
+    | EventStatus::CellAndChnSyncErrors
+    | EventStatus::AnyDataMangling
+    | EventStatus::IncompatibleData)
+}
+
+/// Not all the data for the event is there
+fn is_incomplete(status : EventStatus) -> bool {
+  matches!(status,
+    EventStatus::IncompleteReadout
+    | EventStatus::EventTimeOut
+    | EventStatus::NoChannel9)
+}
+
+/// Assign an EventQuality to events
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EventClassifier {
+  pub silver           : EventQualityRules,
+  pub gold             : EventQualityRules,
+  pub diamond          : EventQualityRules,
+  pub four_leaf_clover : EventQualityRules,
+}
+
+impl EventClassifier {
+
+  pub fn new() -> Self {
+    let mut silver             = EventQualityRules::new();
+    silver.min_n_hits          = 1;
+    silver.allow_mangling      = false;
+
+    let mut gold               = EventQualityRules::new();
+    gold.min_n_hits            = 2;
+    gold.min_n_hits_outer      = 1;
+    gold.min_n_hits_cbe        = 1;
+    gold.allow_mangling        = false;
+    gold.allow_incomplete      = false;
+    gold.max_missing_hg        = 1;
+    gold.min_frac_causal       = 0.5;
+
+    let mut diamond            = gold.clone();
+    diamond.max_missing_hg     = 0;
+    diamond.max_lost_hits      = 0;
+    diamond.min_frac_causal    = 0.8;
+
+    let mut clover             = diamond.clone();
+    clover.min_n_hits          = 4;
+    clover.max_n_hits          = 20;
+    clover.min_n_hits_outer    = 2;
+    clover.min_n_hits_cbe      = 2;
+    clover.min_frac_causal     = 1.0;
+    // no debug (forced) triggers
+    clover.trigger_sources     = vec![TriggerType::Gaps,
+                                      TriggerType::Any,
+                                      TriggerType::Track,
+                                      TriggerType::TrackCentral];
+    Self {
+      silver,
+      gold,
+      diamond,
+      four_leaf_clover : clover,
+    }
+  }
+
+  fn grade(&self, event : &TofEventSummary, n_missing : Option<usize>) -> EventQuality {
+    if self.four_leaf_clover.passes(event, n_missing) {
+      EventQuality::FourLeafClover
+    } else if self.diamond.passes(event, n_missing) {
+      EventQuality::Diamond
+    } else if self.gold.passes(event, n_missing) {
+      EventQuality::Gold
+    } else if self.silver.passes(event, n_missing) {
+      EventQuality::Silver
+    } else {
+      EventQuality::Unknown
+    }
+  }
+
+  /// Get the quality of an event, without checking
+  /// for missing HG hits
+  pub fn classify(&self, event : &TofEventSummary) -> EventQuality {
+    self.grade(event, None)
+  }
+
+  /// Get the quality of an event, including the
+  /// check for triggered paddles without HG hits
+  ///
+  /// Fails if the trigger hits of the event can not
+  /// be mapped to paddles.
+  ///
+  /// WARNING: This uses TofEventSummary::get_missing_paddles_hg
+  /// which is slow
+  #[cfg(feature="database")]
+  pub fn classify_with_mapping(&self, event : &TofEventSummary, pid_map : &DsiJChPidMapping) 
+    -> Result<EventQuality, EventError> {
+    let n_missing = event.get_missing_paddles_hg(pid_map)?.len();
+    Ok(self.grade(event, Some(n_missing)))
+  }
+}
+
+impl Default for EventClassifier {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for EventClassifier {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let disp = toml::to_string(self).unwrap_or(
+      String::from("-- DESERIALIZATION ERROR! --"));
+    write!(f, "<EventClassifier :\n{}>", disp)
+  }
+}
+
+#[cfg(test)]
+fn make_event(pids : &[u8]) -> TofEventSummary {
+  use crate::events::TofHit;
+  let mut event = TofEventSummary::new();
+  for pid in pids {
+    let mut hit    = TofHit::new();
+    hit.paddle_id  = *pid;
+    hit.paddle_len = 1000.0;
+    hit.set_time_a(20.0);
+    hit.set_time_b(20.0);
+    event.hits.push(hit);
+  }
+  event.paddles_set     = true;
+  // Gaps trigger
+  event.trigger_sources = 1 << 5;
+  event
+}
+
+#[test]
+fn classify_events() {
+  let classifier = EventClassifier::new();
+  assert_eq!(classifier.classify(&TofEventSummary::new()), EventQuality::Unknown);
+  assert_eq!(classifier.classify(&make_event(&[1])), EventQuality::Silver);
+  assert_eq!(classifier.classify(&make_event(&[1, 70])), EventQuality::Diamond);
+  assert_eq!(classifier.classify(&make_event(&[1, 2, 70, 120])), EventQuality::FourLeafClover);
+
+  let mut event = make_event(&[1, 2, 70, 120]);
+  event.drs_dead_lost_hits = 3;
+  assert_eq!(classifier.classify(&event), EventQuality::Gold);
+  event.status = EventStatus::EventTimeOut;
+  assert_eq!(classifier.classify(&event), EventQuality::Silver);
+  event.status = EventStatus::AnyDataMangling;
+  assert_eq!(classifier.classify(&event), EventQuality::Unknown);
+
+  // forced trigger
+  let mut event = make_event(&[1, 2, 70, 120]);
+  event.trigger_sources = 1 << 7;
+  assert_eq!(classifier.classify(&event), EventQuality::Diamond);
+
+  // a hit with a time difference larger than the
+  // paddle length allows (3/4 hits causal)
+  let mut event = make_event(&[1, 2, 70, 120]);
+  event.hits[0].set_time_b(40.0);
+  assert_eq!(classifier.classify(&event), EventQuality::Gold);
+  event.paddles_set = false;
+  assert_eq!(classifier.classify(&event), EventQuality::Silver);
+}
+
+#[test]
+fn serialize_classifier() {
+  let classifier = EventClassifier::new();
+  let toml_str   = toml::to_string(&classifier).unwrap();
+  let test : EventClassifier = toml::from_str(&toml_str).unwrap();
+  assert_eq!(classifier, test);
+}
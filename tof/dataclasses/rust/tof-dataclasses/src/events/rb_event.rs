@@ -39,7 +39,9 @@ use crate::serialization::{
   parse_u8,
   parse_u16,
   parse_u32,
+  parse_f32,
 };
+use crate::version::ProtocolVersion;
 
 use crate::events::{
     DataType,
@@ -912,15 +914,29 @@ pub struct RBEventHeader {
   /// hijack that for the version information 
   /// 
   /// Bit 15 will be set 1 in case we are sending
-  /// the DRS_DEADTIME instead of FPGA TEMP
-  ///
-  /// FIXME - make this proper and use ProtocolVersion 
-  ///         instead
+  /// the DRS_DEADTIME instead of FPGA TEMP.
+  /// Bits 13 and 14 hold the ProtocolVersion.
   channel_mask             : u16, 
+  /// Only V1 headers carry the sine fit
+  pub version               : ProtocolVersion,
+  /// Amplitude [mV] of the sine fit to the channel 9 
+  /// clock signal. The sine fit is done by the waveform
+  /// analysis and serialized for ProtocolVersion::V1.
+  pub sine_fit_amp          : f32,
+  /// Frequency [GHz] of the sine fit
+  pub sine_fit_freq         : f32,
+  /// Phase [rad] of the sine fit
+  pub sine_fit_phase        : f32,
+  /// RMS [mV] of the residuals of the sine fit
+  pub sine_fit_rms          : f32,
 }
 
 impl RBEventHeader {
 
+  /// Size in bytes of a V1 header (with the 
+  /// sine fit) including HEAD and TAIL
+  pub const SIZE_V1 : usize = 46;
+
   pub fn new() -> Self {
     Self {
       rb_id                 : 0,  
@@ -941,9 +957,38 @@ impl RBEventHeader {
       timestamp32           : 0,
       timestamp16           : 0,
       deadtime_instead_temp : false,
+      version               : ProtocolVersion::Unknown,
+      sine_fit_amp          : 0.0,
+      sine_fit_freq         : 0.0,
+      sine_fit_phase        : 0.0,
+      sine_fit_rms          : 0.0,
     }
   }
 
+  /// Set the result of the channel 9 sine fit 
+  /// (amplitude, frequency, phase)
+  ///
+  /// This will switch the header to ProtocolVersion::V1,
+  /// so that the fit gets serialized
+  pub fn set_sine_fit(&mut self, fit : (f32, f32, f32)) {
+    self.version        = ProtocolVersion::V1;
+    self.sine_fit_amp   = fit.0;
+    self.sine_fit_freq  = fit.1;
+    self.sine_fit_phase = fit.2;
+  }
+
+  /// The result of the channel 9 sine fit 
+  /// (amplitude, frequency, phase)
+  pub fn get_sine_fit(&self) -> (f32, f32, f32) {
+    (self.sine_fit_amp, self.sine_fit_freq, self.sine_fit_phase)
+  }
+
+  /// Check if the channel 9 sine was fitted for
+  /// this event
+  pub fn has_sine_fit(&self) -> bool {
+    self.sine_fit_freq > 0.0
+  }
+
   /// Set the channel mask with the 9bit number
   ///
   /// Set bit 15 to either 1 or 0 depending on
@@ -969,6 +1014,7 @@ impl RBEventHeader {
   /// used to convey information about if we
   /// stored the drs temperature or deadtime
   pub fn parse_channel_mask(ch_mask : u16) -> (bool, u16) {
+    // bits 9-14 (version) are stripped
     let channel_mask          : u16;
     let deadtime_instead_temp : bool 
       = ch_mask >> 15 == 1;
@@ -976,6 +1022,12 @@ impl RBEventHeader {
     (deadtime_instead_temp, channel_mask)
   }
 
+  /// Get the ProtocolVersion from the serialized
+  /// channel mask (bits 13 and 14)
+  pub fn parse_version(ch_mask : u16) -> ProtocolVersion {
+    ProtocolVersion::from(((ch_mask >> 7) & 0xc0) as u8)
+  }
+
   /// Only get the eventid from a binary stream
   pub fn extract_eventid_from_rbheader(stream :&Vec<u8>) -> u32 {
     // event id is 18 bytes in (including HEAD bytes)
@@ -1113,6 +1165,7 @@ impl Default for RBEventHeader {
 impl fmt::Display for RBEventHeader {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     let mut repr = String::from("<RBEventHeader:");
+    repr += &(format!("\n  version          {}",self.version             )); 
     repr += &(format!("\n  RB ID            {}",self.rb_id               )); 
     repr += &(format!("\n  event id         {}",self.event_id            ));  
     repr += &(format!("\n  ch mask          {}",self.channel_mask        ));  
@@ -1127,6 +1180,9 @@ impl fmt::Display for RBEventHeader {
     repr += &(format!("\n  timestamp16      {}", self.timestamp16            )); 
     repr += &(format!("\n   |-> timestamp48 {}", self.get_timestamp48()      )); 
     repr += &(format!("\n  stop cell        {}", self.stop_cell              )); 
+    if self.has_sine_fit() {
+      repr += &(format!("\n  ch9 sine fit     amp {:.2} freq {:.4} phase {:.4} rms {:.2}", self.sine_fit_amp, self.sine_fit_freq, self.sine_fit_phase, self.sine_fit_rms));
+    }
     //repr += &("\n  dtap0            ".to_owned() + &self.dtap0.to_string()); 
     //repr += &("\n  crc32            ".to_owned() + &self.crc32.to_string()); 
     let mut perfect = true;
@@ -1158,17 +1214,34 @@ impl Serialization for RBEventHeader {
   
   const HEAD : u16   = 0xAAAA;
   const TAIL : u16   = 0x5555;
-  const SIZE : usize = 30; // size in bytes with HEAD and TAIL
+  /// size in bytes with HEAD and TAIL. V1 headers
+  /// are SIZE_V1 bytes long.
+  const SIZE : usize = 30;
 
   fn from_bytestream(stream : &Vec<u8>, pos : &mut usize)
     -> Result<Self, SerializationError> {
     let mut header  = Self::new();
-    Self::verify_fixed(stream, pos)?;
+    // the size depends on the version, which is in 
+    // the channel mask
+    let head_pos    = search_for_u16(Self::HEAD, stream, *pos)?;
+    if stream.len() < head_pos + Self::SIZE {
+      return Err(SerializationError::StreamTooShort);
+    }
+    let version     = Self::parse_version(u16::from_le_bytes([stream[head_pos + 7], stream[head_pos + 8]]));
+    if version == ProtocolVersion::V1 {
+      if stream.len() < head_pos + Self::SIZE_V1 {
+        return Err(SerializationError::StreamTooShort);
+      }
+      *pos = head_pos + 2;
+    } else {
+      Self::verify_fixed(stream, pos)?;
+    }
     header.rb_id                 = parse_u8 (stream, pos);  
     header.event_id              = parse_u32(stream, pos);  
     let ch_mask                  = parse_u16(stream, pos);
     let (deadtime_instead_temp, channel_mask)  
       = Self::parse_channel_mask(ch_mask);
+    header.version               = version;
     header.deadtime_instead_temp = deadtime_instead_temp;
     header.set_channel_mask(channel_mask);
     header.status_byte         = parse_u8 (stream, pos);
@@ -1188,6 +1261,18 @@ impl Serialization for RBEventHeader {
     }
     header.timestamp32           = parse_u32(stream, pos);
     header.timestamp16           = parse_u16(stream, pos);
+    if header.version == ProtocolVersion::V1 {
+      header.sine_fit_amp        = parse_f32(stream, pos);
+      header.sine_fit_freq       = parse_f32(stream, pos);
+      header.sine_fit_phase      = parse_f32(stream, pos);
+      header.sine_fit_rms        = parse_f32(stream, pos);
+      let tail                   = parse_u16(stream, pos);
+      if tail != Self::TAIL {
+        error!("Decoding of TAIL failed for RBEventHeader V1! Got {} instead!", tail);
+        return Err(SerializationError::TailInvalid);
+      }
+      return Ok(header);
+    }
     *pos += 2; // account for tail earlier 
     Ok(header) 
   }
@@ -1198,7 +1283,9 @@ impl Serialization for RBEventHeader {
     stream.extend_from_slice(&Self::HEAD.to_le_bytes());
     stream.extend_from_slice(&self.rb_id             .to_le_bytes());
     stream.extend_from_slice(&self.event_id          .to_le_bytes());
-    let ch_mask = ((self.deadtime_instead_temp as u16) << 15) | self.get_channel_mask();
+    let ch_mask = ((self.deadtime_instead_temp as u16) << 15) 
+                | ((self.version.to_u8() as u16) << 7)
+                | self.get_channel_mask();
     stream.extend_from_slice(&ch_mask                .to_le_bytes());
     stream.extend_from_slice(&self.status_byte       .to_le_bytes());
     stream.extend_from_slice(&self.stop_cell         .to_le_bytes());
@@ -1217,6 +1304,12 @@ impl Serialization for RBEventHeader {
     }
     stream.extend_from_slice(&self.timestamp32       .to_le_bytes());
     stream.extend_from_slice(&self.timestamp16       .to_le_bytes());
+    if self.version == ProtocolVersion::V1 {
+      stream.extend_from_slice(&self.sine_fit_amp    .to_le_bytes());
+      stream.extend_from_slice(&self.sine_fit_freq   .to_le_bytes());
+      stream.extend_from_slice(&self.sine_fit_phase  .to_le_bytes());
+      stream.extend_from_slice(&self.sine_fit_rms    .to_le_bytes());
+    }
     stream.extend_from_slice(&RBEventHeader::TAIL.to_le_bytes());
     stream
   }
@@ -1252,6 +1345,10 @@ impl FromRandom for RBEventHeader {
     header.set_channel_mask(ch_mask);
     header.timestamp32           = rng.gen::<u32>();
     header.timestamp16           = rng.gen::<u16>();
+    if rng.gen::<bool>() {
+      header.set_sine_fit((rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>()));
+      header.sine_fit_rms        = rng.gen::<f32>();
+    }
     header
   }
 }
@@ -1506,6 +1603,7 @@ mod test_rbevents {
       RBEvent,
       RBEventHeader,
   };
+  use crate::version::ProtocolVersion;
   
   #[test]
   fn serialization_rbeventheader() {
@@ -1514,10 +1612,15 @@ mod test_rbevents {
       let head = RBEventHeader::from_random();
       println!("{}",  head);
       let stream = head.to_bytestream();
-      assert_eq!(stream.len(), RBEventHeader::SIZE);
+      let size   = if head.version == ProtocolVersion::V1 {
+        RBEventHeader::SIZE_V1
+      } else {
+        RBEventHeader::SIZE
+      };
+      assert_eq!(stream.len(), size);
       let test = RBEventHeader::from_bytestream(&stream, &mut pos).unwrap();
       println!("{}", test);
-      assert_eq!(pos, RBEventHeader::SIZE);
+      assert_eq!(pos, size);
       assert_eq!(head, test);
       assert_eq!(head.lost_lock()         , test.lost_lock());
       assert_eq!(head.lost_lock_last_sec(), test.lost_lock_last_sec());
@@ -0,0 +1,322 @@
+//! Rule based selection of interesting events
+//!
+//! A SelectionRule is a list of conditions of the
+//! form "<variable> <operator> <value>", e.g.
+//! "tot_edep_cbe > 20" or "n_hits_umb >= 1". An event
+//! passes a rule if it fulfills all of its conditions.
+//! The EventSelector evaluates up to
+//! MAX_SELECTION_RULES rules and stores the result
+//! as a bitmask in TofEventSummary::interesting
+//! (bit k set = rule k passed).
+
+use std::fmt;
+
+use crate::events::TofEventSummary;
+use crate::errors::SelectionError;
+use crate::constants::{
+  MAX_PID_CUBE,
+  MAX_PID_UMBRELLA,
+};
+
+/// The result of the selection is stored as u8
+pub const MAX_SELECTION_RULES : usize = 8;
+
+/// Variables of the TofEventSummary which can be
+/// used in the conditions
+pub const SELECTION_VARIABLES : [&str;14] = [
+  "n_hits",
+  "n_hits_umb",
+  "n_hits_cbe",
+  "n_hits_cor",
+  "tot_edep",
+  "tot_edep_umb",
+  "tot_edep_cbe",
+  "tot_edep_cor",
+  "n_trigger_paddles",
+  "quality",
+  "drs_dead_lost_hits",
+  "trigger_sources",
+  "status",
+  "max_edep",
+];
+
+/// Get a variable (see SELECTION_VARIABLES)
+/// from a TofEventSummary
+///
+/// The hit and energy deposition variables are
+/// calculated from the hits, so that they are
+/// also available for summaries which don't carry
+/// them (ProtocolVersion::Unknown)
+pub fn get_selection_variable(event : &TofEventSummary, varname : &str) -> Option<f32> {
+  match varname {
+    "n_hits"             => Some(event.hits.len() as f32),
+    "n_hits_umb"         => Some(event.hits.iter().filter(|h| h.paddle_id > MAX_PID_CUBE && h.paddle_id <= MAX_PID_UMBRELLA).count() as f32),
+    "n_hits_cbe"         => Some(event.hits.iter().filter(|h| h.paddle_id <= MAX_PID_CUBE).count() as f32),
+    "n_hits_cor"         => Some(event.hits.iter().filter(|h| h.paddle_id > MAX_PID_UMBRELLA).count() as f32),
+    "tot_edep"           => Some(event.get_edep()),
+    "tot_edep_umb"       => Some(event.get_edep_umbrella()),
+    "tot_edep_cbe"       => Some(event.get_edep_cube()),
+    "tot_edep_cor"       => Some(event.get_edep_cortina()),
+    "n_trigger_paddles"  => Some(event.n_trigger_paddles as f32),
+    "quality"            => Some(event.quality as f32),
+    "drs_dead_lost_hits" => Some(event.drs_dead_lost_hits as f32),
+    "trigger_sources"    => Some(event.trigger_sources as f32),
+    "status"             => Some(event.status as u8 as f32),
+    "max_edep"           => Some(event.hits.iter().map(|h| h.get_edep()).fold(0.0, f32::max)),
+    _                    => None
+  }
+}
+
+/// Comparison operators for the conditions
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[repr(u8)]
+pub enum Comparison {
+  Less         = 10u8,
+  LessEqual    = 20u8,
+  Greater      = 30u8,
+  GreaterEqual = 40u8,
+  Equal        = 50u8,
+  NotEqual     = 60u8,
+}
+
+impl Comparison {
+
+  /// Check if the relation "lhs op rhs" holds
+  pub fn compare(&self, lhs : f32, rhs : f32) -> bool {
+    match self {
+      Comparison::Less         => lhs <  rhs,
+      Comparison::LessEqual    => lhs <= rhs,
+      Comparison::Greater      => lhs >  rhs,
+      Comparison::GreaterEqual => lhs >= rhs,
+      Comparison::Equal        => lhs == rhs,
+      Comparison::NotEqual     => lhs != rhs,
+    }
+  }
+}
+
+impl fmt::Display for Comparison {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let r = match self {
+      Comparison::Less         => "<",
+      Comparison::LessEqual    => "<=",
+      Comparison::Greater      => ">",
+      Comparison::GreaterEqual => ">=",
+      Comparison::Equal        => "==",
+      Comparison::NotEqual     => "!=",
+    };
+    write!(f, "{}", r)
+  }
+}
+
+/// A single condition, e.g. "n_hits_umb >= 1"
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectionCondition {
+  pub variable : String,
+  pub op       : Comparison,
+  pub value    : f32,
+}
+
+impl SelectionCondition {
+
+  /// Parse a condition from a string of the form
+  /// "<variable> <operator> <value>"
+  pub fn parse(expr : &str) -> Result<Self, SelectionError> {
+    // the two character operators have to go first
+    let ops = [("<=", Comparison::LessEqual),
+               (">=", Comparison::GreaterEqual),
+               ("==", Comparison::Equal),
+               ("!=", Comparison::NotEqual),
+               ("<" , Comparison::Less),
+               (">" , Comparison::Greater)];
+    for (token, op) in ops {
+      if let Some((lhs, rhs)) = expr.split_once(token) {
+        let variable = lhs.trim().to_string();
+        if !SELECTION_VARIABLES.contains(&variable.as_str()) {
+          error!("Unknown variable {} in condition {}!", variable, expr);
+          return Err(SelectionError::UnknownVariable);
+        }
+        match rhs.trim().parse::<f32>() {
+          Err(err) => {
+            error!("Unable to parse value in condition {}! {}", expr, err);
+            return Err(SelectionError::InvalidValue);
+          }
+          Ok(value) => {
+            return Ok(Self {
+              variable,
+              op,
+              value,
+            });
+          }
+        }
+      }
+    }
+    error!("No comparison operator found in condition {}!", expr);
+    Err(SelectionError::UnknownOperator)
+  }
+
+  pub fn evaluate(&self, event : &TofEventSummary) -> bool {
+    match get_selection_variable(event, &self.variable) {
+      None      => false,
+      Some(val) => self.op.compare(val, self.value)
+    }
+  }
+}
+
+impl fmt::Display for SelectionCondition {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} {} {}", self.variable, self.op, self.value)
+  }
+}
+
+/// A named set of conditions, as it is given
+/// in the settings
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SelectionRule {
+  pub name       : String,
+  /// All conditions need to be fulfilled
+  pub conditions : Vec<String>,
+}
+
+impl SelectionRule {
+
+  pub fn new(name : &str, conditions : &[&str]) -> Self {
+    Self {
+      name       : name.to_string(),
+      conditions : conditions.iter().map(|c| c.to_string()).collect(),
+    }
+  }
+}
+
+impl fmt::Display for SelectionRule {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "<SelectionRule {} : {}>", self.name, self.conditions.join(" && "))
+  }
+}
+
+/// Evaluate a set of rules for each event
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventSelector {
+  names : Vec<String>,
+  rules : Vec<Vec<SelectionCondition>>,
+}
+
+impl EventSelector {
+
+  pub fn new() -> Self {
+    Self {
+      names : Vec::<String>::new(),
+      rules : Vec::<Vec<SelectionCondition>>::new(),
+    }
+  }
+
+  /// Create a selector from a set of rules. Fails
+  /// if any of the rules can not be parsed or
+  /// there are more than MAX_SELECTION_RULES rules
+  pub fn from_rules(rules : &[SelectionRule]) -> Result<Self, SelectionError> {
+    let mut selector = Self::new();
+    for rule in rules {
+      selector.add_rule(rule)?;
+    }
+    Ok(selector)
+  }
+
+  pub fn add_rule(&mut self, rule : &SelectionRule) -> Result<(), SelectionError> {
+    if self.rules.len() >= MAX_SELECTION_RULES {
+      error!("Can not have more than {} selection rules!", MAX_SELECTION_RULES);
+      return Err(SelectionError::TooManyRules);
+    }
+    let mut conditions = Vec::<SelectionCondition>::new();
+    for expr in &rule.conditions {
+      conditions.push(SelectionCondition::parse(expr)?);
+    }
+    self.names.push(rule.name.clone());
+    self.rules.push(conditions);
+    Ok(())
+  }
+
+  /// The names of the rules, the index corresponds
+  /// to the bit in the result of evaluate
+  pub fn get_rule_names(&self) -> &Vec<String> {
+    &self.names
+  }
+
+  pub fn get_n_rules(&self) -> usize {
+    self.rules.len()
+  }
+
+  /// Evaluate all rules for an event, bit k in
+  /// the result is set if the event passed rule k
+  pub fn evaluate(&self, event : &TofEventSummary) -> u8 {
+    let mut mask = 0u8;
+    for (k, conditions) in self.rules.iter().enumerate() {
+      if conditions.iter().all(|c| c.evaluate(event)) {
+        mask |= 1 << k;
+      }
+    }
+    mask
+  }
+
+  /// Evaluate all rules and store the result in
+  /// the event
+  pub fn tag(&self, event : &mut TofEventSummary) -> u8 {
+    let mask          = self.evaluate(event);
+    event.interesting = mask;
+    mask
+  }
+}
+
+impl Default for EventSelector {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for EventSelector {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<EventSelector:");
+    for (k, name) in self.names.iter().enumerate() {
+      let conds : Vec<String> = self.rules[k].iter().map(|c| c.to_string()).collect();
+      repr += &(format!("\n  [{}] {:<12} : {}", k, name, conds.join(" && ")));
+    }
+    repr += ">";
+    write!(f, "{}", repr)
+  }
+}
+
+#[test]
+fn parse_conditions() {
+  let cond = SelectionCondition::parse(" n_hits_umb>=2 ").unwrap();
+  assert_eq!(cond.variable, "n_hits_umb");
+  assert_eq!(cond.op, Comparison::GreaterEqual);
+  assert_eq!(cond.value, 2.0);
+  let cond = SelectionCondition::parse("tot_edep_cbe < 12.5").unwrap();
+  assert_eq!(cond.op, Comparison::Less);
+  assert_eq!(cond.value, 12.5);
+  assert_eq!(SelectionCondition::parse("foo > 1").unwrap_err(), SelectionError::UnknownVariable);
+  assert_eq!(SelectionCondition::parse("n_hits > x").unwrap_err(), SelectionError::InvalidValue);
+  assert_eq!(SelectionCondition::parse("n_hits 3").unwrap_err(), SelectionError::UnknownOperator);
+}
+
+#[test]
+fn select_events() {
+  use crate::events::TofHit;
+  let rules = vec![SelectionRule::new("umb_cube",  &["n_hits_umb >= 1", "n_hits_cbe >= 1"]),
+                   SelectionRule::new("many_hits", &["n_hits > 3"]),
+                   SelectionRule::new("gold",      &["quality >= 20"])];
+  let selector  = EventSelector::from_rules(&rules).unwrap();
+  let mut event = TofEventSummary::new();
+  for pid in [5u8, 70] {
+    let mut hit   = TofHit::new();
+    hit.paddle_id = pid;
+    event.hits.push(hit);
+  }
+  assert_eq!(selector.tag(&mut event), 0b001);
+  assert_eq!(event.interesting, 0b001);
+  event.quality = 30;
+  assert_eq!(selector.evaluate(&event), 0b101);
+  event.hits.truncate(1);
+  assert_eq!(selector.evaluate(&event), 0b100);
+
+  let too_many = vec![SelectionRule::new("any", &["n_hits >= 0"]); MAX_SELECTION_RULES + 1];
+  assert_eq!(EventSelector::from_rules(&too_many).unwrap_err(), SelectionError::TooManyRules);
+}
@@ -24,6 +24,8 @@ use crate::serialization::{
 
 use crate::packets::PacketType;
 use crate::errors::SerializationError;
+#[cfg(feature="database")]
+use crate::errors::EventError;
 
 use crate::events::{
   MasterTriggerEvent,
@@ -34,6 +36,7 @@ use crate::events::{
   TriggerType,
   EventStatus,
   transcode_trigger_sources,
+  MAX_SELECTION_RULES,
 };
 
 use crate::events::master_trigger::{
@@ -42,6 +45,11 @@ use crate::events::master_trigger::{
 };
 
 use crate::ProtocolVersion;
+use crate::constants::{
+  MAX_PID_CUBE,
+  MAX_PID_UMBRELLA,
+};
+use crate::reconstruction::TofTrack;
 
 cfg_if::cfg_if! {
   if #[cfg(feature = "database")]  {
@@ -170,14 +178,24 @@ impl TofEvent {
   /// we should have received HG hits (from waveforms)
   /// but we did not get them
   ///
+  /// Fails if a trigger hit is not in the mapping.
+  ///
   /// WARNING: The current implementation of this is 
   /// rather slow and not fit for production use
   /// FIXME - rewrite as a closure
   #[cfg(feature="database")]
-  pub fn get_missing_paddles_hg(&self, pid_map : &DsiJChPidMapping) -> Vec<u8> {
+  pub fn get_missing_paddles_hg(&self, pid_map : &DsiJChPidMapping) -> Result<Vec<u8>, EventError> {
     let mut missing = Vec::<u8>::new();
     for th in self.mt_event.get_trigger_hits() {
-      let pid = pid_map.get(&th.0).unwrap().get(&th.1).unwrap().get(&th.2.0).unwrap().0;
+      let pid = match pid_map.get(&th.0)
+                             .and_then(|j| j.get(&th.1))
+                             .and_then(|ch| ch.get(&th.2.0)) {
+        None => {
+          error!("Trigger hit on DSI {} J {} ch {} is not in the paddle mapping!", th.0, th.1, th.2.0);
+          return Err(EventError::UnknownTriggerHit);
+        }
+        Some(pids) => pids.0
+      };
       let mut found = false;
       for h in self.get_hits() {
         if h.paddle_id == pid {
@@ -189,7 +207,7 @@ impl TofEvent {
         missing.push(pid);
       }
     }
-    missing
+    Ok(missing)
   }
 
   /// Get the triggered paddle ids
@@ -325,11 +343,11 @@ impl TofEvent {
       for hit in &ev.hits {
         let h = hit.clone();
         if summary.version == ProtocolVersion::V1 {
-          if h.paddle_id <= 60 {
+          if h.paddle_id <= MAX_PID_CUBE {
             summary.n_hits_cbe += 1;
             summary.tot_edep_cbe += h.get_edep();
           }
-          else if h.paddle_id <= 108 && h.paddle_id > 60 {
+          else if h.paddle_id <= MAX_PID_UMBRELLA && h.paddle_id > MAX_PID_CUBE {
             summary.n_hits_umb += 1;
             summary.tot_edep_umb += h.get_edep();
           }
@@ -670,6 +688,12 @@ pub struct TofEventSummary {
   pub status            : EventStatus,
   pub version           : ProtocolVersion,
   pub quality           : u8,
+  /// Result of the interesting event selection 
+  /// (see EventSelector). Bit k is set if the 
+  /// event passed rule k.
+  /// Only serialized for ProtocolVersion::V2
+  /// and V3
+  pub interesting       : u8,
   pub trigger_sources   : u16,
 
   /// the number of triggered paddles coming
@@ -696,6 +720,10 @@ pub struct TofEventSummary {
   pub channel_mask       : Vec<u16>,
   pub mtb_link_mask      : u64,
   pub hits               : Vec<TofHit>,
+  /// The straight track fitted to the hits 
+  /// (see TofTrackFitter), with the reconstructed
+  /// beta. Only serialized for ProtocolVersion::V3
+  pub track              : Option<TofTrack>,
   // a bunch of calculated variablels, used 
   // for online interesting event search
   // these will be only available in ProtocolVersion 1
@@ -721,6 +749,7 @@ impl TofEventSummary {
       tot_edep_cbe       : 0.0,
       tot_edep_cor       : 0.0,
       quality            : 0,
+      interesting        : 0,
       trigger_sources    : 0,
       n_trigger_paddles  : 0,
       event_id           : 0,
@@ -734,6 +763,7 @@ impl TofEventSummary {
       channel_mask       : Vec::<u16>::new(),
       mtb_link_mask      : 0,
       hits               : Vec::<TofHit>::new(),
+      track              : None,
       paddles_set        : false,
     }
   }
@@ -780,14 +810,24 @@ impl TofEventSummary {
   /// we should have received HG hits (from waveforms)
   /// but we did not get them
   ///
+  /// Fails if a trigger hit is not in the mapping.
+  ///
   /// WARNING: The current implementation of this is 
   /// rather slow and not fit for production use
   /// FIXME - rewrite as a closure
   #[cfg(feature="database")]
-  pub fn get_missing_paddles_hg(&self, pid_map :   &DsiJChPidMapping) -> Vec<u8> {
+  pub fn get_missing_paddles_hg(&self, pid_map :   &DsiJChPidMapping) -> Result<Vec<u8>, EventError> {
     let mut missing = Vec::<u8>::new();
     for th in self.get_trigger_hits() {
-      let pid = pid_map.get(&th.0).unwrap().get(&th.1).unwrap().get(&th.2.0).unwrap().0;
+      let pid = match pid_map.get(&th.0)
+                             .and_then(|j| j.get(&th.1))
+                             .and_then(|ch| ch.get(&th.2.0)) {
+        None => {
+          error!("Trigger hit on DSI {} J {} ch {} is not in the paddle mapping!", th.0, th.1, th.2.0);
+          return Err(EventError::UnknownTriggerHit);
+        }
+        Some(pids) => pids.0
+      };
       let mut found = false;
       for h in &self.hits {
         if h.paddle_id == pid {
@@ -799,7 +839,7 @@ impl TofEventSummary {
         missing.push(pid);
       }
     }
-    missing
+    Ok(missing)
   }
   
   /// Get the triggered paddle ids
@@ -900,6 +940,32 @@ impl TofEventSummary {
   pub fn get_trigger_sources(&self) -> Vec<TriggerType> {
     transcode_trigger_sources(self.trigger_sources)
   }
+
+  /// The event passed any of the rules of the
+  /// interesting event selection
+  pub fn is_interesting(&self) -> bool {
+    self.interesting != 0
+  }
+
+  /// Attach the reconstructed track and switch
+  /// the summary to ProtocolVersion::V3, so that
+  /// it gets serialized
+  pub fn set_track(&mut self, track : TofTrack) {
+    self.track   = Some(track);
+    self.version = ProtocolVersion::V3;
+  }
+
+  /// The reconstructed beta, if the event has
+  /// a track
+  pub fn get_beta(&self) -> Option<f32> {
+    self.track.map(|t| t.beta)
+  }
+
+  /// The event passed the selection rule with
+  /// the given index
+  pub fn passed_rule(&self, rule : usize) -> bool {
+    rule < MAX_SELECTION_RULES && (self.interesting >> rule) & 0x1 == 1
+  }
   
   pub fn get_timestamp48(&self) -> u64 {
     ((self.timestamp16 as u64) << 32) | self.timestamp32 as u64
@@ -912,7 +978,7 @@ impl TofEventSummary {
   pub fn get_edep_umbrella(&self) -> f32 {
     let mut tot_edep = 0.0f32;
     for h in &self.hits {
-      if h.paddle_id < 61 || h.paddle_id > 108 {
+      if h.paddle_id <= MAX_PID_CUBE || h.paddle_id > MAX_PID_UMBRELLA {
         continue;
       }
       tot_edep += h.get_edep();
@@ -927,7 +993,7 @@ impl TofEventSummary {
   pub fn get_edep_cube(&self) -> f32 {
     let mut tot_edep = 0.0f32;
     for h in &self.hits {
-      if h.paddle_id > 60 {
+      if h.paddle_id > MAX_PID_CUBE {
         continue;
       }
       tot_edep += h.get_edep();
@@ -942,7 +1008,7 @@ impl TofEventSummary {
   pub fn get_edep_cortina(&self) -> f32 {
     let mut tot_edep = 0.0f32;
     for h in &self.hits {
-      if h.paddle_id < 109 {
+      if h.paddle_id <= MAX_PID_UMBRELLA {
         continue;
       }
       tot_edep += h.get_edep();
@@ -962,6 +1028,34 @@ impl TofEventSummary {
     tot_edep
   }
 
+  /// Number of hits with more than one pulse 
+  /// at any of the paddle ends
+  pub fn get_n_multi_pulse_hits(&self) -> usize {
+    self.hits.iter().filter(|h| h.has_secondary_pulses()).count()
+  }
+
+  /// Number of hits where the charge of the first
+  /// pulse is biased by pile-up
+  pub fn get_n_pileup_hits(&self) -> usize {
+    self.hits.iter().filter(|h| h.has_pileup()).count()
+  }
+
+  /// Total number of pulses at all paddle ends
+  pub fn get_n_pulses(&self) -> usize {
+    self.hits.iter().map(|h| h.get_n_pulses_a() + h.get_n_pulses_b()).sum()
+  }
+
+  /// Paddle ids of the hits with a secondary pulse
+  /// which arrived at least min_delay [ns] after 
+  /// the first one, e.g. from late annihilation
+  /// products
+  pub fn get_delayed_pulse_paddles(&self, min_delay : f32) -> Vec<u8> {
+    self.hits.iter()
+             .filter(|h| h.has_secondary_pulses() && h.get_max_pulse_delay() >= min_delay)
+             .map(|h| h.paddle_id)
+             .collect()
+  }
+
   //pub fn set_beta(&mut self, beta : f32) {
   //  // expecting beta in range of 0-1. If larger
   //  // than 1, we will save 1
@@ -996,7 +1090,9 @@ impl Serialization for TofEventSummary {
     stream.extend_from_slice(&self.n_trigger_paddles.to_le_bytes());
     stream.extend_from_slice(&self.event_id.to_le_bytes());
     // depending on the version, we send the fc event packet
-    if self.version == ProtocolVersion::V1 {
+    if self.version == ProtocolVersion::V1 
+    || self.version == ProtocolVersion::V2 
+    || self.version == ProtocolVersion::V3 {
       stream.extend_from_slice(&self.n_hits_umb  .to_le_bytes()); 
       stream.extend_from_slice(&self.n_hits_cbe  .to_le_bytes()); 
       stream.extend_from_slice(&self.n_hits_cor  .to_le_bytes()); 
@@ -1005,6 +1101,10 @@ impl Serialization for TofEventSummary {
       stream.extend_from_slice(&self.tot_edep_cor.to_le_bytes()); 
     }
     stream.extend_from_slice(&self.quality.to_le_bytes());
+    if self.version == ProtocolVersion::V2 
+    || self.version == ProtocolVersion::V3 {
+      stream.push(self.interesting);
+    }
     stream.extend_from_slice(&self.timestamp32.to_le_bytes());
     stream.extend_from_slice(&self.timestamp16.to_le_bytes());
     //stream.extend_from_slice(&self.primary_beta.to_le_bytes());
@@ -1023,6 +1123,9 @@ impl Serialization for TofEventSummary {
     for k in 0..self.hits.len() {
       stream.extend_from_slice(&self.hits[k].to_bytestream());
     }
+    if self.version == ProtocolVersion::V3 {
+      stream.extend_from_slice(&self.track.unwrap_or_default().to_bytestream());
+    }
     stream.extend_from_slice(&Self::TAIL.to_le_bytes());
     stream
   }
@@ -1044,7 +1147,9 @@ impl Serialization for TofEventSummary {
     summary.trigger_sources   = parse_u16(stream, pos);
     summary.n_trigger_paddles = parse_u8(stream, pos);
     summary.event_id          = parse_u32(stream, pos);
-    if summary.version == ProtocolVersion::V1 {
+    if summary.version == ProtocolVersion::V1 
+    || summary.version == ProtocolVersion::V2 
+    || summary.version == ProtocolVersion::V3 {
       summary.n_hits_umb      = parse_u8(stream, pos); 
       summary.n_hits_cbe      = parse_u8(stream, pos); 
       summary.n_hits_cor      = parse_u8(stream, pos); 
@@ -1053,6 +1158,10 @@ impl Serialization for TofEventSummary {
       summary.tot_edep_cor    = parse_f32(stream, pos); 
     }
     summary.quality            = parse_u8(stream, pos);
+    if summary.version == ProtocolVersion::V2 
+    || summary.version == ProtocolVersion::V3 {
+      summary.interesting      = parse_u8(stream, pos);
+    }
     summary.timestamp32        = parse_u32(stream, pos);
     summary.timestamp16        = parse_u16(stream, pos);
     summary.run_id             = parse_u16(stream, pos);
@@ -1067,6 +1176,9 @@ impl Serialization for TofEventSummary {
     for _ in 0..nhits {
       summary.hits.push(TofHit::from_bytestream(stream, pos)?);
     }
+    if summary.version == ProtocolVersion::V3 {
+      summary.track           = Some(TofTrack::from_bytestream(stream, pos)?);
+    }
     let tail = parse_u16(stream, pos);
     if tail != Self::TAIL {
       error!("Decoding of TAIL failed for version {}! Got {} instead!", version, tail);
@@ -1088,6 +1200,11 @@ impl fmt::Display for TofEventSummary {
     repr += &(format!("\n  EventID          : {}", self.event_id));
     repr += &(format!("\n  RunID            : {}", self.run_id));
     repr += &(format!("\n  EventStatus      : {}", self.status));
+    repr += &(format!("\n  Quality          : {}", EventQuality::from(self.quality)));
+    if self.version == ProtocolVersion::V2 
+    || self.version == ProtocolVersion::V3 {
+      repr += &(format!("\n  Interesting      : {:08b}", self.interesting));
+    }
     repr += &(format!("\n  TriggerSources   : {:?}", self.get_trigger_sources()));
     repr += &(format!("\n  NTrigPaddles     : {}", self.n_trigger_paddles));
     repr += &(format!("\n  DRS dead hits    : {}", self.drs_dead_lost_hits));
@@ -1113,6 +1230,9 @@ impl fmt::Display for TofEventSummary {
     for h in &self.hits {
       repr += &(format!("\n  {}", h));
     }
+    if let Some(track) = &self.track {
+      repr += &(format!("\n  {}", track));
+    }
     write!(f, "{}", repr)
   }
 }
@@ -1125,7 +1245,9 @@ impl FromRandom for TofEventSummary {
     let mut rng               = rand::thread_rng();
     let status                = EventStatus::from_random();
     let version               = ProtocolVersion::from_random();
-    if version == ProtocolVersion::V1 {
+    if version == ProtocolVersion::V1 
+    || version == ProtocolVersion::V2 
+    || version == ProtocolVersion::V3 {
       summary.n_hits_umb        = rng.gen::<u8>();
       summary.n_hits_cbe        = rng.gen::<u8>();
       summary.n_hits_cor        = rng.gen::<u8>();
@@ -1134,6 +1256,13 @@ impl FromRandom for TofEventSummary {
       summary.tot_edep_cor      = rng.gen::<f32>();
       summary.quality           = rng.gen::<u8>();
     }
+    if version == ProtocolVersion::V2 
+    || version == ProtocolVersion::V3 {
+      summary.interesting       = rng.gen::<u8>();
+    }
+    if version == ProtocolVersion::V3 {
+      summary.track             = Some(TofTrack::from_random());
+    }
     summary.status             = status;
     summary.version            = version;
     // variable packet for the FC
@@ -1171,6 +1300,27 @@ fn packable_tofeventsummary() {
   }
 }  
 
+#[test]
+fn tofeventsummary_track_v3() {
+  let mut summary   = TofEventSummary::new();
+  summary.event_id  = 42;
+  summary.quality   = EventQuality::Gold as u8;
+  let mut track     = TofTrack::new();
+  track.beta        = 0.8;
+  track.beta_err    = 0.05;
+  track.chi2_pos    = 1.2;
+  track.chi2_time   = 0.7;
+  track.ndof        = 3;
+  track.n_hits      = 3;
+  summary.set_track(track);
+  assert_eq!(summary.version, ProtocolVersion::V3);
+  let test = TofEventSummary::from_bytestream(&summary.to_bytestream(), &mut 0).unwrap();
+  assert_eq!(test.track, Some(track));
+  assert_eq!(test.get_beta(), Some(0.8));
+  assert_eq!(test.quality, summary.quality);
+  assert_eq!(test.event_id, 42);
+}
+
 #[test]
 fn emit_tofeventsummary() {
   for _ in 0..100 {
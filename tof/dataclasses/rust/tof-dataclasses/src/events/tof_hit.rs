@@ -7,6 +7,7 @@ use crate::serialization::{
   parse_u8,
   parse_u16,
   parse_f16,
+  search_for_u16,
   Serialization
 };
 use crate::ProtocolVersion;
@@ -86,6 +87,100 @@ impl fmt::Display for Peak {
   }
 }
 
+/// Maximum number of additional pulses per paddle end
+/// a TofHit can hold besides the first one
+pub const TOFHIT_MAX_SECONDARY_PULSES : usize = 4;
+
+/// Pile-up flags (TofHit::pileup)
+///
+/// A secondary pulse on side A/B starts within the 
+/// charge integration window of the first pulse,
+/// so the charge of the first pulse is biased
+pub const TOFHIT_PILEUP_A           : u8 = 0x1;
+pub const TOFHIT_PILEUP_B           : u8 = 0x2;
+/// There were more pulses on side A/B than 
+/// TOFHIT_MAX_SECONDARY_PULSES + 1, the latest
+/// pulses got dropped
+pub const TOFHIT_PULSES_DROPPED_A   : u8 = 0x4;
+pub const TOFHIT_PULSES_DROPPED_B   : u8 = 0x8;
+
+/// Flags (serialized for V3)
+///
+/// The hit carries a calibrated energy deposition
+/// (see TofHit::edep)
+pub const TOFHIT_ENERGY_CALIBRATED  : u8 = 0x1;
+/// The pulse on side A/B reached the saturation level
+pub const TOFHIT_SATURATED_A        : u8 = 0x2;
+pub const TOFHIT_SATURATED_B        : u8 = 0x4;
+
+/// A secondary pulse at one of the paddle ends,
+/// e.g. from a late annihilation product
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub struct TofPulse {
+  pub time   : f16,
+  pub peak   : f16,
+  pub charge : f16,
+}
+
+impl TofPulse {
+  pub fn new() -> Self {
+    Self {
+      time   : f16::from_f32(0.0),
+      peak   : f16::from_f32(0.0),
+      charge : f16::from_f32(0.0),
+    }
+  }
+
+  pub fn from_peak(peak : &Peak) -> Self {
+    Self {
+      time   : f16::from_f32(peak.time),
+      peak   : f16::from_f32(peak.height),
+      charge : f16::from_f32(peak.charge),
+    }
+  }
+
+  pub fn get_time(&self) -> f32 {
+    self.time.to_f32()
+  }
+
+  pub fn get_peak(&self) -> f32 {
+    self.peak.to_f32()
+  }
+
+  pub fn get_charge(&self) -> f32 {
+    self.charge.to_f32()
+  }
+  
+  fn write_to(&self, stream : &mut Vec<u8>) {
+    stream.extend_from_slice(&self.time  .to_le_bytes());
+    stream.extend_from_slice(&self.peak  .to_le_bytes());
+    stream.extend_from_slice(&self.charge.to_le_bytes());
+  }
+
+  fn read_from(stream : &Vec<u8>, pos : &mut usize) -> Self {
+    Self {
+      time   : parse_f16(stream, pos),
+      peak   : parse_f16(stream, pos),
+      charge : parse_f16(stream, pos),
+    }
+  }
+}
+
+impl Default for TofPulse {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for TofPulse {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "<TofPulse: time {:.2} peak {:.2} charge {:.2}>",
+           self.get_time(),
+           self.get_peak(),
+           self.get_charge())
+  }
+}
+
 /// Comprehensive paddle information
 ///
 /// Results of the (online) waveform analysis
@@ -104,6 +199,13 @@ pub struct TofHit {
   pub peak_b         : f16,
   pub charge_a       : f16,
   pub charge_b       : f16,
+  /// Calibrated energy deposition [MeV], see 
+  /// energy_calibration. For ProtocolVersion::V3
+  /// this is serialized in place of charge_min_i.
+  pub edep           : f16,
+  /// The edep field holds a calibrated energy 
+  /// deposition
+  pub energy_calibrated : bool,
   
   /// The paddle length will not get serialized
   /// and has to be set after the hit has been 
@@ -113,6 +215,11 @@ pub struct TofHit {
   /// serialized and has to be set after the hit has been 
   /// created
   pub cable_len      : f32,
+  /// Effective speed of light in the paddle [cm/ns].
+  /// Will not get serialized, defaults to 
+  /// C_LIGHT_PADDLE and can be set per paddle from
+  /// a TimingCalibration
+  pub c_paddle       : f32,
   /// The coordinates will not get serialized
   /// and has to be set after the hit has been 
   /// created
@@ -124,6 +231,7 @@ pub struct TofHit {
   pub timestamp32    : u32,
   pub timestamp16    : u16,
   pub ctr_etx        : u8,
+  /// Serialized for versions prior to V3 
   pub charge_min_i   : u16,
   /// Reconstructed particle interaction position
   /// across the paddle
@@ -151,6 +259,27 @@ pub struct TofHit {
   pub ftime_b        : f32,
   pub fpeak_a        : f32,
   pub fpeak_b        : f32,
+  /// The pulse reached the saturation level
+  /// (see AnalysisEngineSettings::saturation_level).
+  /// Serialized for V3, see set_pulse_quality_a
+  pub saturated_a    : bool,
+  pub saturated_b    : bool,
+  /// χ²/ndof of the pulse template fit, 0 if the
+  /// times were obtained with the CFD. Serialized
+  /// (as f16) for V3
+  pub fit_chi2_a     : f32,
+  pub fit_chi2_b     : f32,
+  // V2 variables (serialized only if
+  // the version is V2 or V3)
+  /// Pile-up flags, see TOFHIT_PILEUP_A etc.
+  pub pileup         : u8,
+  /// Number of valid entries in pulses_a/b
+  pub n_pulses_a     : u8,
+  pub n_pulses_b     : u8,
+  /// Additional pulses after the first one,
+  /// ordered in time
+  pub pulses_a       : [TofPulse;TOFHIT_MAX_SECONDARY_PULSES],
+  pub pulses_b       : [TofPulse;TOFHIT_MAX_SECONDARY_PULSES],
 }
 
 impl Default for TofHit {
@@ -174,6 +303,7 @@ impl fmt::Display for TofHit {
   ** paddle {} ** 
     Length        {:.2}
     Harting cable length {:.2}
+    c eff. [cm/ns] {:.2}
   ** reconstructed interaction
     energy_dep    {:.2}   
     pos_across    {:.2}   
@@ -182,7 +312,10 @@ impl fmt::Display for TofHit {
   ** V1 variables
     phase (ch9)   {:.4}
     baseline A/B  {:.2} {:.2}
-    bl. RMS  A/B  {:.2} {:.2}>",
+    bl. RMS  A/B  {:.2} {:.2}
+  ** V2 variables
+    n pulses A/B  {} {}
+    pile-up flags {:#x}>",
             self.version,
             self.paddle_id,
             self.get_time_a(),
@@ -194,6 +327,7 @@ impl fmt::Display for TofHit {
             paddle_info,
             self.paddle_len,
             self.cable_len,
+            self.c_paddle,
             self.get_edep(),
             self.get_pos(),
             self.get_t0(),
@@ -205,6 +339,9 @@ impl fmt::Display for TofHit {
             self.baseline_b,
             self.baseline_a_rms,
             self.baseline_b_rms,
+            self.get_n_pulses_a(),
+            self.get_n_pulses_b(),
+            self.pileup,
             )
   }
 }
@@ -213,7 +350,12 @@ impl Serialization for TofHit {
   
   const HEAD          : u16   = 61680; //0xF0F0)
   const TAIL          : u16   = 3855;
-  const SIZE          : usize = 30; // size in bytes with HEAD and TAIL
+  /// size in bytes with HEAD and TAIL. For V2, this 
+  /// is the minimum size, 3 + 6 bytes per secondary
+  /// pulse are added. V3 adds another byte of 
+  /// flags and the template fit χ² of both sides
+  /// (2 x f16) on top of V2.
+  const SIZE          : usize = 30;
 
   /// Serialize the packet
   ///
@@ -236,7 +378,11 @@ impl Serialization for TofHit {
     bytestream.extend_from_slice(&self.peak_b      .to_le_bytes()); 
     bytestream.extend_from_slice(&self.charge_a    .to_le_bytes()); 
     bytestream.extend_from_slice(&self.charge_b    .to_le_bytes()); 
-    bytestream.extend_from_slice(&self.charge_min_i.to_le_bytes()); 
+    if self.version == ProtocolVersion::V3 {
+      bytestream.extend_from_slice(&self.edep        .to_le_bytes()); 
+    } else {
+      bytestream.extend_from_slice(&self.charge_min_i.to_le_bytes()); 
+    }
     //bytestream.extend_from_slice(&self.pos_across  .to_le_bytes()); 
     //bytestream.extend_from_slice(&self.t0          .to_le_bytes()); 
     bytestream.extend_from_slice(&self.baseline_a   .to_le_bytes());
@@ -250,6 +396,33 @@ impl Serialization for TofHit {
     bytestream.push(self.version.to_u8());
     bytestream.extend_from_slice(&self.baseline_b.to_le_bytes());
     bytestream.extend_from_slice(&self.baseline_b_rms.to_le_bytes());
+    if self.version == ProtocolVersion::V2 
+    || self.version == ProtocolVersion::V3 {
+      bytestream.push(self.pileup);
+      bytestream.push(self.n_pulses_a);
+      bytestream.push(self.n_pulses_b);
+      for pulse in self.get_secondary_pulses_a() {
+        pulse.write_to(&mut bytestream);
+      }
+      for pulse in self.get_secondary_pulses_b() {
+        pulse.write_to(&mut bytestream);
+      }
+    }
+    if self.version == ProtocolVersion::V3 {
+      let mut flags = 0u8;
+      if self.energy_calibrated {
+        flags |= TOFHIT_ENERGY_CALIBRATED;
+      }
+      if self.saturated_a {
+        flags |= TOFHIT_SATURATED_A;
+      }
+      if self.saturated_b {
+        flags |= TOFHIT_SATURATED_B;
+      }
+      bytestream.push(flags);
+      bytestream.extend_from_slice(&f16::from_f32(self.fit_chi2_a).to_le_bytes());
+      bytestream.extend_from_slice(&f16::from_f32(self.fit_chi2_b).to_le_bytes());
+    }
     bytestream.extend_from_slice(&Self::TAIL       .to_le_bytes()); 
     bytestream
   }
@@ -264,7 +437,19 @@ impl Serialization for TofHit {
   fn from_bytestream(stream : &Vec<u8>, pos : &mut usize) 
     -> Result<Self, SerializationError> {
     let mut pp  = Self::new();
-    Self::verify_fixed(stream, pos)?;
+    // V2/V3 hits have a variable size, so peek at 
+    // the version byte first
+    let head_pos = search_for_u16(Self::HEAD, stream, *pos)?;
+    if stream.len() < head_pos + Self::SIZE {
+      return Err(SerializationError::StreamTooShort);
+    }
+    let version = ProtocolVersion::from(stream[head_pos + 23]);
+    if version == ProtocolVersion::V2 
+    || version == ProtocolVersion::V3 {
+      *pos = head_pos + 2;
+    } else {
+      Self::verify_fixed(stream, pos)?;
+    }
     // since we passed the above test, the packet
     // is valid
     pp.valid          = true;
@@ -275,7 +460,8 @@ impl Serialization for TofHit {
     pp.peak_b         = parse_f16(stream, pos);
     pp.charge_a       = parse_f16(stream, pos);
     pp.charge_b       = parse_f16(stream, pos);
-    pp.charge_min_i   = parse_u16(stream, pos);
+    // edep for V3, charge_min_i otherwise
+    let edep_or_min_i = parse_u16(stream, pos);
     pp.baseline_a     = parse_f16(stream, pos);
     pp.baseline_a_rms = parse_f16(stream, pos);
     //pp.time_a        = parse_u16(stream, pos);
@@ -293,8 +479,12 @@ impl Serialization for TofHit {
     pp.phase    = parse_f16(&phase_vec, &mut 0);
     //pp.ctr_etx       = parse_u8(stream, pos);
     //pp.reserved      = parse_u8(stream, pos);
-    let version      = ProtocolVersion::from(parse_u8(stream, pos));
-    pp.version       = version;
+    pp.version       = ProtocolVersion::from(parse_u8(stream, pos));
+    if pp.version == ProtocolVersion::V3 {
+      pp.edep         = f16::from_bits(edep_or_min_i);
+    } else {
+      pp.charge_min_i = edep_or_min_i;
+    }
     match pp.version {
       ProtocolVersion::V1 => {
         // in this version we do have phase instead of
@@ -308,6 +498,43 @@ impl Serialization for TofHit {
     }
     pp.baseline_b      = parse_f16(stream, pos);
     pp.baseline_b_rms  = parse_f16(stream, pos);
+    if pp.version == ProtocolVersion::V2 
+    || pp.version == ProtocolVersion::V3 {
+      pp.pileup        = parse_u8(stream, pos);
+      pp.n_pulses_a    = parse_u8(stream, pos);
+      pp.n_pulses_b    = parse_u8(stream, pos);
+      let n_pulses     = pp.n_pulses_a as usize + pp.n_pulses_b as usize;
+      if pp.n_pulses_a as usize > TOFHIT_MAX_SECONDARY_PULSES
+      || pp.n_pulses_b as usize > TOFHIT_MAX_SECONDARY_PULSES {
+        error!("TofHit claims to have {}/{} secondary pulses, but we can hold only {}!", pp.n_pulses_a, pp.n_pulses_b, TOFHIT_MAX_SECONDARY_PULSES);
+        return Err(SerializationError::WrongByteSize);
+      }
+      // flags and fit χ² for V3
+      let n_v3         = 5*(pp.version == ProtocolVersion::V3) as usize;
+      if stream.len() < *pos + 6*n_pulses + n_v3 + 2 {
+        return Err(SerializationError::StreamTooShort);
+      }
+      for k in 0..pp.n_pulses_a as usize {
+        pp.pulses_a[k] = TofPulse::read_from(stream, pos);
+      }
+      for k in 0..pp.n_pulses_b as usize {
+        pp.pulses_b[k] = TofPulse::read_from(stream, pos);
+      }
+      if pp.version == ProtocolVersion::V3 {
+        let flags            = parse_u8(stream, pos);
+        pp.energy_calibrated = flags & TOFHIT_ENERGY_CALIBRATED != 0;
+        pp.saturated_a       = flags & TOFHIT_SATURATED_A != 0;
+        pp.saturated_b       = flags & TOFHIT_SATURATED_B != 0;
+        pp.fit_chi2_a        = parse_f16(stream, pos).to_f32();
+        pp.fit_chi2_b        = parse_f16(stream, pos).to_f32();
+      }
+      let tail = parse_u16(stream, pos);
+      if tail != Self::TAIL {
+        error!("Decoding of TAIL failed for TofHit {}! Got {} instead!", pp.version, tail);
+        return Err(SerializationError::TailInvalid);
+      }
+      return Ok(pp);
+    }
 
     //pp.timestamp32   = parse_u32(stream, pos);
     //pp.timestamp16   = parse_u16(stream, pos);
@@ -327,14 +554,17 @@ impl TofHit {
       peak_b         : f16::from_f32(0.0),
       charge_a       : f16::from_f32(0.0),
       charge_b       : f16::from_f32(0.0),
+      edep           : f16::from_f32(0.0),
+      energy_calibrated : false,
       paddle_len     : f32::NAN,
       cable_len      : f32::NAN,
+      c_paddle       : C_LIGHT_PADDLE,
       x              : f32::NAN,
       y              : f32::NAN,
       z              : f32::NAN,
       
-      charge_min_i   : 0,
       // deprecated  
+      charge_min_i   : 0,
       pos_across     : 0,
       t0             : 0,
       ctr_etx        : 0,
@@ -354,6 +584,16 @@ impl TofHit {
       ftime_b        : 0.0,
       fpeak_a        : 0.0,
       fpeak_b        : 0.0,
+      saturated_a    : false,
+      saturated_b    : false,
+      fit_chi2_a     : 0.0,
+      fit_chi2_b     : 0.0,
+      // v2 variables
+      pileup         : 0,
+      n_pulses_a     : 0,
+      n_pulses_b     : 0,
+      pulses_a       : [TofPulse::new();TOFHIT_MAX_SECONDARY_PULSES],
+      pulses_b       : [TofPulse::new();TOFHIT_MAX_SECONDARY_PULSES],
     }
   }
   
@@ -396,6 +636,11 @@ impl TofHit {
 
   
 
+  /// Add a peak to the hit
+  ///
+  /// The first peak of each side sets time, peak
+  /// and charge of the hit, all further peaks are
+  /// added as secondary pulses.
   pub fn add_peak(&mut self, peak : &Peak)  {
     if self.paddle_id != TofHit::get_pid(peak.paddle_end_id) {
       //error!("Can't add peak to 
@@ -404,16 +649,149 @@ impl TofHit {
       error!("Invalide paddle end id {}", peak.paddle_end_id);
     }
     if peak.paddle_end_id > 2000 {
+      if self.has_pulse_b() {
+        self.add_secondary_pulse_b(TofPulse::from_peak(peak));
+        return;
+      }
       self.set_time_b  (peak.time);
       self.set_peak_b  (peak.height);
       self.set_charge_b(peak.charge);
     } else if peak.paddle_end_id < 2000 {
+      if self.has_pulse_a() {
+        self.add_secondary_pulse_a(TofPulse::from_peak(peak));
+        return;
+      }
       self.set_time_a  (peak.time);
       self.set_peak_a  (peak.height);
       self.set_charge_a(peak.charge);
     }
   }
 
+  /// The first pulse on side A has been set
+  pub fn has_pulse_a(&self) -> bool {
+    self.get_peak_a() != 0.0 || self.get_time_a() != 0.0
+  }
+
+  /// The first pulse on side B has been set
+  pub fn has_pulse_b(&self) -> bool {
+    self.get_peak_b() != 0.0 || self.get_time_b() != 0.0
+  }
+
+  /// Add a further pulse to side A. This will 
+  /// switch the hit to (at least) ProtocolVersion::V2.
+  /// If there is no space left, the pulse gets 
+  /// dropped and TOFHIT_PULSES_DROPPED_A is set.
+  pub fn add_secondary_pulse_a(&mut self, pulse : TofPulse) {
+    if self.version != ProtocolVersion::V3 {
+      self.version = ProtocolVersion::V2;
+    }
+    if self.n_pulses_a as usize >= TOFHIT_MAX_SECONDARY_PULSES {
+      self.pileup |= TOFHIT_PULSES_DROPPED_A;
+      return;
+    }
+    self.pulses_a[self.n_pulses_a as usize] = pulse;
+    self.n_pulses_a += 1;
+  }
+  
+  /// Add a further pulse to side B. This will 
+  /// switch the hit to (at least) ProtocolVersion::V2.
+  /// If there is no space left, the pulse gets 
+  /// dropped and TOFHIT_PULSES_DROPPED_B is set.
+  pub fn add_secondary_pulse_b(&mut self, pulse : TofPulse) {
+    if self.version != ProtocolVersion::V3 {
+      self.version = ProtocolVersion::V2;
+    }
+    if self.n_pulses_b as usize >= TOFHIT_MAX_SECONDARY_PULSES {
+      self.pileup |= TOFHIT_PULSES_DROPPED_B;
+      return;
+    }
+    self.pulses_b[self.n_pulses_b as usize] = pulse;
+    self.n_pulses_b += 1;
+  }
+
+  pub fn get_secondary_pulses_a(&self) -> &[TofPulse] {
+    &self.pulses_a[0..self.n_pulses_a as usize]
+  }
+  
+  pub fn get_secondary_pulses_b(&self) -> &[TofPulse] {
+    &self.pulses_b[0..self.n_pulses_b as usize]
+  }
+
+  /// All pulses on side A, including the first one
+  pub fn get_pulses_a(&self) -> Vec<TofPulse> {
+    let mut pulses = Vec::<TofPulse>::new();
+    if self.has_pulse_a() {
+      pulses.push(TofPulse {
+        time   : self.time_a,
+        peak   : self.peak_a,
+        charge : self.charge_a
+      });
+    }
+    pulses.extend_from_slice(self.get_secondary_pulses_a());
+    pulses
+  }
+  
+  /// All pulses on side B, including the first one
+  pub fn get_pulses_b(&self) -> Vec<TofPulse> {
+    let mut pulses = Vec::<TofPulse>::new();
+    if self.has_pulse_b() {
+      pulses.push(TofPulse {
+        time   : self.time_b,
+        peak   : self.peak_b,
+        charge : self.charge_b
+      });
+    }
+    pulses.extend_from_slice(self.get_secondary_pulses_b());
+    pulses
+  }
+
+  /// Number of pulses on side A, including the first one
+  pub fn get_n_pulses_a(&self) -> usize {
+    self.has_pulse_a() as usize + self.n_pulses_a as usize
+  }
+  
+  /// Number of pulses on side B, including the first one
+  pub fn get_n_pulses_b(&self) -> usize {
+    self.has_pulse_b() as usize + self.n_pulses_b as usize
+  }
+
+  /// Any of the paddle ends saw more than one pulse
+  pub fn has_secondary_pulses(&self) -> bool {
+    self.n_pulses_a > 0 || self.n_pulses_b > 0
+  }
+
+  /// The charge of the first pulse is biased by
+  /// a secondary pulse at any of the paddle ends
+  pub fn has_pileup(&self) -> bool {
+    self.pileup & (TOFHIT_PILEUP_A | TOFHIT_PILEUP_B) != 0
+  }
+
+  /// Time of the latest pulse with respect to 
+  /// the first pulse of the same side, considering
+  /// both sides. 0 if there are no secondary pulses.
+  pub fn get_max_pulse_delay(&self) -> f32 {
+    let mut delay = 0.0f32;
+    for pulse in self.get_secondary_pulses_a() {
+      delay = f32::max(delay, pulse.get_time() - self.get_time_a());
+    }
+    for pulse in self.get_secondary_pulses_b() {
+      delay = f32::max(delay, pulse.get_time() - self.get_time_b());
+    }
+    delay
+  }
+
+  /// Summed charge of all pulses on both sides
+  pub fn get_total_charge(&self) -> f32 {
+    let mut charge = self.get_charge_a() + self.get_charge_b();
+    for pulse in self.get_secondary_pulses_a() {
+      charge += pulse.get_charge();
+    }
+    for pulse in self.get_secondary_pulses_b() {
+      charge += pulse.get_charge();
+    }
+    charge
+  }
+
 
   // rework the whole getter/setter cluster, since 
   // we switched to f16 instead of our custom 
@@ -426,19 +804,19 @@ impl TofHit {
   pub fn get_pos(&self) -> f32 {
     let t0 = self.get_t0_nocable();
     let clean_tA = self.time_a.to_f32() - t0;
-    return clean_tA*C_LIGHT_PADDLE*10.0; 
+    return clean_tA*self.c_paddle*10.0; 
   }
 
   /// If the two reconstructed pulse times are not related to each other by the paddle length,
   /// meaning that they can't be caused by the same event, we dub this hit as "not following
   /// causality"
   pub fn obeys_causality(&self) -> bool {
-    (self.paddle_len/(10.0*C_LIGHT_PADDLE)) - f32::abs((self.time_a.to_f32() - self.time_b.to_f32())) > 0.0
+    (self.paddle_len/(10.0*self.c_paddle)) - f32::abs((self.time_a.to_f32() - self.time_b.to_f32())) > 0.0
     && self.get_t0_nocable() > 0.0
   }
 
   pub fn get_t0_nocable(&self) -> f32 {
-    0.5*(self.time_a.to_f32() + self.time_b.to_f32() - (self.paddle_len/(10.0*C_LIGHT_PADDLE)))
+    0.5*(self.time_a.to_f32() + self.time_b.to_f32() - (self.paddle_len/(10.0*self.c_paddle)))
   }
 
   /// Calculate the interaction time based on the peak timings measured 
@@ -449,14 +827,64 @@ impl TofHit {
   /// This assumes that the cable on both sides of the paddle are 
   /// the same length
   pub fn get_t0(&self) -> f32 {
-    0.5*(self.time_a.to_f32() + self.time_b.to_f32() - (self.paddle_len/(10.0*C_LIGHT_PADDLE)) - ((self.cable_len*2.0)/(10.0*C_LIGHT_CABLE)))
+    0.5*(self.time_a.to_f32() + self.time_b.to_f32() - (self.paddle_len/(10.0*self.c_paddle)) - ((self.cable_len*2.0)/(10.0*C_LIGHT_CABLE)))
   }
 
+  /// Energy deposition [MeV]
+  ///
+  /// If the hit has been energy calibrated, this is
+  /// the calibrated energy deposition, otherwise 
   /// Philip's energy deposition based on peak height
   pub fn get_edep(&self) -> f32 {
+    if self.is_energy_calibrated() {
+      return self.edep.to_f32();
+    }
     (1.29/34.3)*(self.peak_a.to_f32() + self.peak_b.to_f32()) / 2.0
   }
 
+  /// Set the calibrated energy deposition [MeV]
+  ///
+  /// This will switch the hit to ProtocolVersion::V3,
+  /// since only V3 serializes edep
+  pub fn set_edep(&mut self, edep : f32) {
+    self.edep              = f16::from_f32(edep);
+    self.energy_calibrated = true;
+    self.version           = ProtocolVersion::V3;
+  }
+
+  /// The hit has a calibrated energy deposition
+  pub fn is_energy_calibrated(&self) -> bool {
+    self.energy_calibrated
+  }
+
+  /// Set saturation and template fit χ² of the 
+  /// pulse on side A.
+  ///
+  /// This will switch the hit to ProtocolVersion::V3
+  /// if there is anything to report, since only V3 
+  /// serializes them
+  pub fn set_pulse_quality_a(&mut self, saturated : bool, fit_chi2 : f32) {
+    self.saturated_a = saturated;
+    self.fit_chi2_a  = fit_chi2;
+    if saturated || fit_chi2 != 0.0 {
+      self.version   = ProtocolVersion::V3;
+    }
+  }
+  
+  /// Set saturation and template fit χ² of the 
+  /// pulse on side B.
+  ///
+  /// This will switch the hit to ProtocolVersion::V3
+  /// if there is anything to report, since only V3 
+  /// serializes them
+  pub fn set_pulse_quality_b(&mut self, saturated : bool, fit_chi2 : f32) {
+    self.saturated_b = saturated;
+    self.fit_chi2_b  = fit_chi2;
+    if saturated || fit_chi2 != 0.0 {
+      self.version   = ProtocolVersion::V3;
+    }
+  }
+
   pub fn get_time_a(&self) -> f32 {
     self.time_a.to_f32()
   }
@@ -664,7 +1092,6 @@ impl TofHit {
     pp.peak_b         = f16::from_f32(rng.gen::<f32>());
     pp.charge_a       = f16::from_f32(rng.gen::<f32>());
     pp.charge_b       = f16::from_f32(rng.gen::<f32>());
-    //pp.charge_min_i = rng.gen::<>();
     //pp.pos_across   = rng.gen::<>();
     //pp.t0           = rng.gen::<>();
     //pp.ctr_etx      = rng.gen::<u8>();
@@ -674,6 +1101,35 @@ impl TofHit {
     pp.baseline_b     = f16::from_f32(rng.gen::<f32>());
     pp.baseline_b_rms = f16::from_f32(rng.gen::<f32>());
     pp.phase          = f16::from_f32(rng.gen::<f32>());
+    if pp.version == ProtocolVersion::V3 {
+      pp.edep              = f16::from_f32(rng.gen::<f32>());
+      pp.energy_calibrated = rng.gen::<bool>();
+      pp.saturated_a       = rng.gen::<bool>();
+      pp.saturated_b       = rng.gen::<bool>();
+      // only f16 precision survives the serialization
+      pp.fit_chi2_a        = f16::from_f32(rng.gen::<f32>()).to_f32();
+      pp.fit_chi2_b        = f16::from_f32(rng.gen::<f32>()).to_f32();
+    } else {
+      pp.charge_min_i      = rng.gen::<u16>();
+    }
+    if pp.version == ProtocolVersion::V2 
+    || pp.version == ProtocolVersion::V3 {
+      pp.pileup       = rng.gen::<u8>() & 0xf;
+      for _ in 0..rng.gen_range(0..TOFHIT_MAX_SECONDARY_PULSES + 1) {
+        pp.add_secondary_pulse_a(TofPulse {
+          time   : f16::from_f32(rng.gen::<f32>()),
+          peak   : f16::from_f32(rng.gen::<f32>()),
+          charge : f16::from_f32(rng.gen::<f32>()),
+        });
+      }
+      for _ in 0..rng.gen_range(0..TOFHIT_MAX_SECONDARY_PULSES + 1) {
+        pp.add_secondary_pulse_b(TofPulse {
+          time   : f16::from_f32(rng.gen::<f32>()),
+          peak   : f16::from_f32(rng.gen::<f32>()),
+          charge : f16::from_f32(rng.gen::<f32>()),
+        });
+      }
+    }
     pp
   }
 }
@@ -685,7 +1141,97 @@ fn serialization_tofhit() {
     let mut pos = 0;
     let data = TofHit::from_random();
     let test = TofHit::from_bytestream(&data.to_bytestream(),&mut pos).unwrap();
-    assert_eq!(pos, TofHit::SIZE);
+    if data.version == ProtocolVersion::V2 {
+      assert_eq!(pos, TofHit::SIZE + 3 + 6*(data.n_pulses_a + data.n_pulses_b) as usize);
+    } else if data.version == ProtocolVersion::V3 {
+      assert_eq!(pos, TofHit::SIZE + 8 + 6*(data.n_pulses_a + data.n_pulses_b) as usize);
+    } else {
+      assert_eq!(pos, TofHit::SIZE);
+    }
     assert_eq!(data, test);
   }
 }
+
+#[test]
+fn tofhit_secondary_pulses() {
+  let mut hit = TofHit::new();
+  hit.paddle_id = 12;
+  for k in 0..TOFHIT_MAX_SECONDARY_PULSES + 2 {
+    let mut peak = Peak::new();
+    peak.paddle_end_id = 1012;
+    peak.time          = 50.0 + 20.0*k as f32;
+    peak.height        = 10.0;
+    peak.charge        = 2.0;
+    hit.add_peak(&peak);
+  }
+  let mut peak = Peak::new();
+  peak.paddle_end_id = 2012;
+  peak.time          = 52.0;
+  peak.height        = 10.0;
+  peak.charge        = 2.0;
+  hit.add_peak(&peak);
+  assert_eq!(hit.version, ProtocolVersion::V2);
+  assert_eq!(hit.get_n_pulses_a(), TOFHIT_MAX_SECONDARY_PULSES + 1);
+  assert_eq!(hit.get_n_pulses_b(), 1);
+  assert_eq!(hit.pileup, TOFHIT_PULSES_DROPPED_A);
+  assert!(hit.has_secondary_pulses());
+  assert!(!hit.has_pileup());
+  assert_eq!(hit.get_max_pulse_delay(), 80.0);
+  assert_eq!(hit.get_total_charge(), 2.0*(TOFHIT_MAX_SECONDARY_PULSES + 2) as f32);
+  // hits in a stream
+  let mut stream = hit.to_bytestream();
+  stream.extend_from_slice(&TofHit::new().to_bytestream());
+  let mut pos  = 0;
+  let test     = TofHit::from_bytestream(&stream, &mut pos).unwrap();
+  let test_v1  = TofHit::from_bytestream(&stream, &mut pos).unwrap();
+  assert_eq!(pos, stream.len());
+  assert_eq!(test.get_pulses_a(), hit.get_pulses_a());
+  assert_eq!(test.get_pulses_b(), hit.get_pulses_b());
+  assert_eq!(test_v1.version, ProtocolVersion::V1);
+  assert!(!test_v1.has_secondary_pulses());
+}
+
+#[test]
+fn tofhit_energy_calibrated() {
+  let mut hit = TofHit::new();
+  hit.paddle_id    = 5;
+  hit.charge_min_i = 42;
+  let test = TofHit::from_bytestream(&hit.to_bytestream(), &mut 0).unwrap();
+  assert_eq!(test.version, ProtocolVersion::V1);
+  assert_eq!(test.charge_min_i, 42);
+  assert!(!test.is_energy_calibrated());
+  // calibrating switches to V3, where the
+  // slot of charge_min_i holds edep
+  hit.set_edep(2.5);
+  assert_eq!(hit.version, ProtocolVersion::V3);
+  let mut pos = 0;
+  let test    = TofHit::from_bytestream(&hit.to_bytestream(), &mut pos).unwrap();
+  assert_eq!(pos, TofHit::SIZE + 8);
+  assert_eq!(test.version, ProtocolVersion::V3);
+  assert!(test.is_energy_calibrated());
+  assert_eq!(test.get_edep(), 2.5);
+  assert_eq!(test.charge_min_i, 0);
+  // secondary pulses don't downgrade the version
+  hit.add_secondary_pulse_a(TofPulse::new());
+  assert_eq!(hit.version, ProtocolVersion::V3);
+  let test = TofHit::from_bytestream(&hit.to_bytestream(), &mut 0).unwrap();
+  assert_eq!(test.n_pulses_a, 1);
+  assert!(test.is_energy_calibrated());
+}
+
+#[test]
+fn tofhit_pulse_quality() {
+  let mut hit = TofHit::new();
+  // nothing to report, the version stays
+  hit.set_pulse_quality_a(false, 0.0);
+  assert_eq!(hit.version, ProtocolVersion::V1);
+  hit.set_pulse_quality_a(true, 0.0);
+  hit.set_pulse_quality_b(false, 1.5);
+  assert_eq!(hit.version, ProtocolVersion::V3);
+  let test = TofHit::from_bytestream(&hit.to_bytestream(), &mut 0).unwrap();
+  assert!(test.saturated_a);
+  assert!(!test.saturated_b);
+  assert_eq!(test.fit_chi2_a, 0.0);
+  assert_eq!(test.fit_chi2_b, 1.5);
+  assert!(!test.is_energy_calibrated());
+}
@@ -0,0 +1,576 @@
+//! Software emulation of the MTB trigger logic
+//!
+//! Re-evaluate the trigger decisions of the MTB
+//! offline from the LTB hit pattern of a
+//! MasterTriggerEvent. The hits are counted per
+//! TOF region (cube top/bottom/side/corner, umbrella,
+//! umbrella center, cortina) and compared to a set of
+//! thresholds per trigger type. The thresholds follow
+//! what liftof_lib::master_trigger::control writes to
+//! the MTB for the individual trigger types.
+//!
+//! Caveats:
+//! The hits are counted per paddle. The beta
+//! requirement of the GAPS triggers is approximated
+//! by requiring at least one hit above the Beta
+//! threshold of the LTB. Prescales and the debug
+//! triggers (Poisson, Forced, FixedRate) can not be
+//! emulated.
+
+use std::fmt;
+use std::collections::HashSet;
+
+use crate::DsiLtbRBMapping;
+use crate::constants::{
+  MAX_PID_CUBE_TOP,
+  MAX_PID_CUBE_BOT,
+  MAX_PID_CUBE_SIDE,
+  MAX_PID_CUBE,
+  MAX_PID_UMBRELLA_CENTER,
+  MAX_PID_UMBRELLA,
+  MAX_PID_CORTINA,
+};
+use crate::commands::config::TriggerConfig;
+use crate::events::{
+  MasterTriggerEvent,
+  TriggerType,
+};
+use crate::events::master_trigger::LTBThreshold;
+
+/// Number of triggered paddles in the different
+/// regions of the TOF
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct TriggerHitCounts {
+  pub cube_top        : u8,
+  pub cube_bot        : u8,
+  pub cube_side       : u8,
+  pub cube_corner     : u8,
+  /// All umbrella paddles, including the
+  /// center
+  pub umbrella        : u8,
+  pub umbrella_center : u8,
+  pub cortina         : u8,
+  /// Number of paddles with a hit above the
+  /// Beta threshold
+  pub n_beta          : u8,
+}
+
+impl TriggerHitCounts {
+
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Add a triggered paddle
+  pub fn add_paddle(&mut self, paddle_id : u8, threshold : LTBThreshold) {
+    if paddle_id == 0 || paddle_id > MAX_PID_CORTINA {
+      error!("Paddle id {} is not part of the TOF!", paddle_id);
+      return;
+    } else if paddle_id <= MAX_PID_CUBE_TOP {
+      self.cube_top    += 1;
+    } else if paddle_id <= MAX_PID_CUBE_BOT {
+      self.cube_bot    += 1;
+    } else if paddle_id <= MAX_PID_CUBE_SIDE {
+      self.cube_side   += 1;
+    } else if paddle_id <= MAX_PID_CUBE {
+      self.cube_corner += 1;
+    } else if paddle_id <= MAX_PID_UMBRELLA_CENTER {
+      self.umbrella        += 1;
+      self.umbrella_center += 1;
+    } else if paddle_id <= MAX_PID_UMBRELLA {
+      self.umbrella    += 1;
+    } else {
+      self.cortina     += 1;
+    }
+    if threshold == LTBThreshold::Beta || threshold == LTBThreshold::Veto {
+      self.n_beta += 1;
+    }
+  }
+
+  /// Triggered paddles in the cube
+  pub fn get_inner(&self) -> u8 {
+    self.cube_top + self.cube_bot + self.cube_side + self.cube_corner
+  }
+
+  /// Triggered paddles in umbrella and cortina
+  pub fn get_outer(&self) -> u8 {
+    self.umbrella + self.cortina
+  }
+
+  pub fn get_total(&self) -> u8 {
+    self.get_inner() + self.get_outer()
+  }
+}
+
+impl fmt::Display for TriggerHitCounts {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<TriggerHitCounts:");
+    repr += &(format!("\n  cube top/bot/side/corner : {}/{}/{}/{}", self.cube_top, self.cube_bot, self.cube_side, self.cube_corner));
+    repr += &(format!("\n  umbrella (center)        : {} ({})", self.umbrella, self.umbrella_center));
+    repr += &(format!("\n  cortina                  : {}", self.cortina));
+    repr += &(format!("\n  inner/outer/total        : {}/{}/{}", self.get_inner(), self.get_outer(), self.get_total()));
+    repr += &(format!("\n  above beta threshold     : {}>", self.n_beta));
+    write!(f, "{}", repr)
+  }
+}
+
+/// The hit thresholds for a trigger. A trigger fires
+/// if all the counts are >= the thresholds, so a
+/// threshold of 0 disables the condition.
+#[derive(Debug, Copy, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TriggerThresholds {
+  pub inner_tof       : u8,
+  pub outer_tof       : u8,
+  pub total_tof       : u8,
+  pub cube_side       : u8,
+  pub cube_top        : u8,
+  pub cube_bot        : u8,
+  pub cube_corner     : u8,
+  pub umbrella        : u8,
+  pub umbrella_center : u8,
+  pub cortina         : u8,
+  /// Require at least one hit above the
+  /// Beta threshold
+  pub require_beta    : bool,
+}
+
+impl TriggerThresholds {
+
+  /// Thresholds which let every event with at
+  /// least a hit pass
+  pub fn new() -> Self {
+    Self {
+      total_tof : 1,
+      ..Self::default()
+    }
+  }
+
+  /// The thresholds as they are set by liftof
+  /// for the given trigger type. Returns None for
+  /// trigger types which do not depend on the hits.
+  pub fn for_trigger(trigger_type : TriggerType, use_beta : bool) -> Option<Self> {
+    let mut thr = Self::default();
+    match trigger_type {
+      TriggerType::Any             => {
+        thr.total_tof       = 1;
+      }
+      TriggerType::Track           => {
+        thr.inner_tof       = 1;
+        thr.outer_tof       = 1;
+      }
+      TriggerType::TrackCentral    => {
+        thr.umbrella        = 1;
+        thr.cube_top        = 1;
+      }
+      TriggerType::TrackUmbCentral => {
+        thr.umbrella_center = 1;
+        thr.cube_top        = 1;
+      }
+      TriggerType::Gaps            => {
+        thr.inner_tof       = 3;
+        thr.outer_tof       = 3;
+        thr.total_tof       = 8;
+        thr.require_beta    = use_beta;
+      }
+      TriggerType::Gaps633         => {
+        thr.inner_tof       = 3;
+        thr.outer_tof       = 3;
+        thr.total_tof       = 6;
+        thr.require_beta    = use_beta;
+      }
+      TriggerType::Gaps422         => {
+        thr.inner_tof       = 2;
+        thr.outer_tof       = 2;
+        thr.total_tof       = 4;
+        thr.require_beta    = use_beta;
+      }
+      TriggerType::Gaps211         => {
+        thr.inner_tof       = 1;
+        thr.outer_tof       = 1;
+        thr.total_tof       = 2;
+        thr.require_beta    = use_beta;
+      }
+      TriggerType::UmbCube         => {
+        thr.umbrella        = 1;
+        thr.inner_tof       = 1;
+      }
+      TriggerType::UmbCubeZ        => {
+        thr.umbrella        = 1;
+        thr.cube_top        = 1;
+      }
+      TriggerType::UmbCorCube      => {
+        thr.umbrella        = 1;
+        thr.cortina         = 1;
+        thr.inner_tof       = 1;
+      }
+      TriggerType::CorCubeSide     => {
+        thr.cortina         = 1;
+        thr.cube_side       = 1;
+      }
+      TriggerType::Umb3Cube        => {
+        thr.umbrella        = 1;
+        thr.inner_tof       = 3;
+      }
+      _ => {
+        return None;
+      }
+    }
+    Some(thr)
+  }
+
+  /// Check if the hit counts pass all thresholds
+  pub fn passes(&self, counts : &TriggerHitCounts) -> bool {
+    counts.get_inner()        >= self.inner_tof
+    && counts.get_outer()     >= self.outer_tof
+    && counts.get_total()     >= self.total_tof
+    && counts.cube_side       >= self.cube_side
+    && counts.cube_top        >= self.cube_top
+    && counts.cube_bot        >= self.cube_bot
+    && counts.cube_corner     >= self.cube_corner
+    && counts.umbrella        >= self.umbrella
+    && counts.umbrella_center >= self.umbrella_center
+    && counts.cortina         >= self.cortina
+    && (!self.require_beta || counts.n_beta > 0)
+  }
+}
+
+impl fmt::Display for TriggerThresholds {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let disp = toml::to_string(self).unwrap_or(
+      String::from("-- DESERIALIZATION ERROR! --"));
+    write!(f, "<TriggerThresholds :\n{}>", disp)
+  }
+}
+
+/// The trigger type as it shows up in the trigger
+/// sources of the MasterTriggerEvent. Returns None
+/// for trigger types which are not reported.
+pub fn get_reported_trigger_type(trigger_type : TriggerType) -> Option<TriggerType> {
+  match trigger_type {
+    TriggerType::Gaps
+    | TriggerType::Gaps633
+    | TriggerType::Gaps422
+    | TriggerType::Gaps211      => Some(TriggerType::Gaps),
+    TriggerType::Any            => Some(TriggerType::Any),
+    TriggerType::Track          => Some(TriggerType::Track),
+    TriggerType::TrackCentral   => Some(TriggerType::TrackCentral),
+    _                           => None
+  }
+}
+
+/// Evaluate a set of trigger definitions for
+/// MasterTriggerEvents
+#[derive(Debug, Clone)]
+pub struct TriggerEmulator {
+  /// The trigger definitions which will be
+  /// evaluated
+  pub triggers : Vec<(TriggerType, TriggerThresholds)>,
+  /// DSI -> J -> LTB ch -> (paddle id, panel id),
+  /// see database::get_dsi_j_ch_pid_map
+  pid_map      : DsiLtbRBMapping,
+}
+
+impl TriggerEmulator {
+
+  /// An emulator which evaluates all trigger types
+  /// which depend on the hits
+  pub fn new(pid_map : DsiLtbRBMapping, use_beta : bool) -> Self {
+    let all_types = [TriggerType::Any,
+                     TriggerType::Track,
+                     TriggerType::TrackCentral,
+                     TriggerType::TrackUmbCentral,
+                     TriggerType::Gaps,
+                     TriggerType::Gaps633,
+                     TriggerType::Gaps422,
+                     TriggerType::Gaps211,
+                     TriggerType::UmbCube,
+                     TriggerType::UmbCubeZ,
+                     TriggerType::UmbCorCube,
+                     TriggerType::CorCubeSide,
+                     TriggerType::Umb3Cube];
+    let mut triggers = Vec::<(TriggerType, TriggerThresholds)>::new();
+    for tt in all_types {
+      if let Some(thr) = TriggerThresholds::for_trigger(tt, use_beta) {
+        triggers.push((tt, thr));
+      }
+    }
+    Self {
+      triggers,
+      pid_map,
+    }
+  }
+
+  /// An emulator for the trigger (and combo trigger)
+  /// of a TriggerConfig
+  pub fn from_trigger_config(cfg : &TriggerConfig, pid_map : DsiLtbRBMapping) -> Self {
+    let use_beta     = cfg.gaps_trigger_use_beta.unwrap_or(true);
+    let mut emulator = Self {
+      triggers : Vec::<(TriggerType, TriggerThresholds)>::new(),
+      pid_map,
+    };
+    let mut types = Vec::<TriggerType>::new();
+    if let Some(tt) = cfg.trigger_type {
+      types.push(tt);
+    }
+    if cfg.use_combo_trigger.unwrap_or(false) {
+      if let Some(tt) = cfg.combo_trigger_type {
+        types.push(tt);
+      }
+    }
+    for tt in types {
+      match TriggerThresholds::for_trigger(tt, use_beta) {
+        None => {
+          warn!("Trigger {} can not be emulated!", tt);
+        }
+        Some(thr) => {
+          emulator.set_thresholds(tt, thr);
+        }
+      }
+    }
+    emulator
+  }
+
+  /// Change the thresholds for a trigger type or
+  /// add it to the triggers which get evaluated
+  pub fn set_thresholds(&mut self, trigger_type : TriggerType, thresholds : TriggerThresholds) {
+    for trig in self.triggers.iter_mut() {
+      if trig.0 == trigger_type {
+        trig.1 = thresholds;
+        return;
+      }
+    }
+    self.triggers.push((trigger_type, thresholds));
+  }
+
+  /// The trigger types which are evaluated
+  pub fn get_trigger_types(&self) -> Vec<TriggerType> {
+    self.triggers.iter().map(|t| t.0).collect()
+  }
+
+  /// Count the triggered paddles per region
+  pub fn get_hit_counts(&self, mte : &MasterTriggerEvent) -> TriggerHitCounts {
+    let mut counts  = TriggerHitCounts::new();
+    let mut paddles = HashSet::<u8>::new();
+    for (dsi, j, (ch, _), thresh) in mte.get_trigger_hits() {
+      let pid = self.pid_map.get(&dsi)
+        .and_then(|jmap| jmap.get(&j))
+        .and_then(|chmap| chmap.get(&ch))
+        .map(|pid_panel| pid_panel.0)
+        .unwrap_or(0);
+      if pid == 0 {
+        debug!("No paddle mapped for DSI {} J {} CH {}!", dsi, j, ch);
+        continue;
+      }
+      if paddles.insert(pid) {
+        counts.add_paddle(pid, thresh);
+      }
+    }
+    counts
+  }
+
+  /// Get all the emulated triggers which fired
+  /// for this event
+  pub fn evaluate(&self, mte : &MasterTriggerEvent) -> Vec<TriggerType> {
+    let counts = self.get_hit_counts(mte);
+    self.triggers.iter()
+      .filter(|t| t.1.passes(&counts))
+      .map(|t| t.0)
+      .collect()
+  }
+}
+
+impl fmt::Display for TriggerEmulator {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<TriggerEmulator:");
+    for (tt, thr) in &self.triggers {
+      repr += &(format!("\n  {:<16} : inner {} outer {} total {} | cube side/top/bot/corner {}/{}/{}/{} | umb {} umb center {} cor {} | beta {}",
+                        format!("{:?}", tt), thr.inner_tof, thr.outer_tof, thr.total_tof,
+                        thr.cube_side, thr.cube_top, thr.cube_bot, thr.cube_corner,
+                        thr.umbrella, thr.umbrella_center, thr.cortina, thr.require_beta));
+    }
+    repr += ">";
+    write!(f, "{}", repr)
+  }
+}
+
+/// Counters for one emulated trigger
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TriggerCounter {
+  pub trigger_type : TriggerType,
+  /// Events where the emulated trigger fired
+  pub n_fired      : usize,
+  /// Events where the MTB reported the trigger
+  pub n_reported   : usize,
+  /// Events where both, the emulation and
+  /// the MTB, fired
+  pub n_both       : usize,
+}
+
+/// Measure trigger efficiencies by comparing the
+/// emulated triggers with the ones reported by
+/// the MTB
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriggerEfficiency {
+  pub n_events : usize,
+  pub counters : Vec<TriggerCounter>,
+}
+
+impl TriggerEfficiency {
+
+  pub fn new(emulator : &TriggerEmulator) -> Self {
+    let counters = emulator.get_trigger_types().iter().map(|tt| TriggerCounter {
+      trigger_type : *tt,
+      n_fired      : 0,
+      n_reported   : 0,
+      n_both       : 0,
+    }).collect();
+    Self {
+      n_events : 0,
+      counters,
+    }
+  }
+
+  /// Emulate the triggers for an event and
+  /// update the counters
+  pub fn add_event(&mut self, emulator : &TriggerEmulator, mte : &MasterTriggerEvent) {
+    self.n_events += 1;
+    let fired    = emulator.evaluate(mte);
+    let reported = mte.get_trigger_sources();
+    for cnt in self.counters.iter_mut() {
+      let has_fired   = fired.contains(&cnt.trigger_type);
+      let is_reported = match get_reported_trigger_type(cnt.trigger_type) {
+        None     => false,
+        Some(tt) => reported.contains(&tt)
+      };
+      if has_fired {
+        cnt.n_fired += 1;
+      }
+      if is_reported {
+        cnt.n_reported += 1;
+      }
+      if has_fired && is_reported {
+        cnt.n_both += 1;
+      }
+    }
+  }
+
+  /// The fraction of events for which the emulated
+  /// trigger fired
+  pub fn get_fire_fraction(&self, trigger_type : TriggerType) -> Option<f32> {
+    if self.n_events == 0 {
+      return None;
+    }
+    self.counters.iter()
+      .find(|c| c.trigger_type == trigger_type)
+      .map(|c| c.n_fired as f32 / self.n_events as f32)
+  }
+
+  /// The fraction of events reported by the MTB
+  /// for which the emulated trigger fired as well
+  pub fn get_efficiency(&self, trigger_type : TriggerType) -> Option<f32> {
+    self.counters.iter()
+      .find(|c| c.trigger_type == trigger_type && c.n_reported > 0)
+      .map(|c| c.n_both as f32 / c.n_reported as f32)
+  }
+}
+
+impl fmt::Display for TriggerEfficiency {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = format!("<TriggerEfficiency: {} events", self.n_events);
+    for cnt in &self.counters {
+      repr += &(format!("\n  {:<16} : fired {} reported {} both {}",
+                        format!("{:?}", cnt.trigger_type), cnt.n_fired, cnt.n_reported, cnt.n_both));
+    }
+    repr += ">";
+    write!(f, "{}", repr)
+  }
+}
+
+#[cfg(test)]
+fn make_pid_map() -> DsiLtbRBMapping {
+  use std::collections::HashMap;
+  // DSI 1, J 1 : paddles 1 (cube top), 25 (cube side),
+  //              61 (umbrella center), 80 (umbrella)
+  // DSI 1, J 2 : paddles 110, 120 (cortina), 14 (cube bot),
+  //              58 (cube corner)
+  let mut mapping = DsiLtbRBMapping::new();
+  let mut jmap    = HashMap::<u8, HashMap<u8, (u8, u8)>>::new();
+  for (j, pids) in [(1u8, [1u8, 25, 61, 80]), (2u8, [110u8, 120, 14, 58])] {
+    let mut chmap = HashMap::<u8, (u8, u8)>::new();
+    for (k, pid) in pids.iter().enumerate() {
+      chmap.insert(2*k as u8 + 1, (*pid, 0));
+      chmap.insert(2*k as u8 + 2, (*pid, 0));
+    }
+    jmap.insert(j, chmap);
+  }
+  mapping.insert(1, jmap);
+  mapping
+}
+
+#[test]
+fn emulate_triggers() {
+  let emulator = TriggerEmulator::new(make_pid_map(), false);
+  let mut mte  = MasterTriggerEvent::new();
+  // LTB 0 (DSI 1, J 1): cube top + umbrella center
+  // (Hit threshold)
+  mte.dsi_j_mask   = 0b1;
+  mte.channel_mask = vec![0b0001_0001];
+  let counts = emulator.get_hit_counts(&mte);
+  assert_eq!(counts.cube_top, 1);
+  assert_eq!(counts.umbrella, 1);
+  assert_eq!(counts.umbrella_center, 1);
+  assert_eq!(counts.get_total(), 2);
+  let fired = emulator.evaluate(&mte);
+  for tt in [TriggerType::Any, TriggerType::Track, TriggerType::TrackCentral,
+             TriggerType::TrackUmbCentral, TriggerType::Gaps211, TriggerType::UmbCube,
+             TriggerType::UmbCubeZ] {
+    assert!(fired.contains(&tt), "{:?} should have fired", tt);
+  }
+  for tt in [TriggerType::Gaps, TriggerType::Gaps633, TriggerType::Gaps422,
+             TriggerType::UmbCorCube, TriggerType::CorCubeSide, TriggerType::Umb3Cube] {
+    assert!(!fired.contains(&tt), "{:?} should not have fired", tt);
+  }
+
+  // all 8 paddles, the cube side one above the
+  // beta threshold
+  mte.dsi_j_mask   = 0b11;
+  mte.channel_mask = vec![0b0101_1001, 0b0101_0101];
+  let counts = emulator.get_hit_counts(&mte);
+  assert_eq!(counts.get_inner(), 4);
+  assert_eq!(counts.get_outer(), 4);
+  assert_eq!(counts.n_beta, 1);
+  let fired = emulator.evaluate(&mte);
+  for tt in [TriggerType::Gaps, TriggerType::Gaps633, TriggerType::Gaps422,
+             TriggerType::CorCubeSide, TriggerType::Umb3Cube] {
+    assert!(fired.contains(&tt), "{:?} should have fired", tt);
+  }
+
+  // require beta
+  let mut cfg = TriggerConfig::new();
+  cfg.set_gaps_trigger_use_beta(true);
+  cfg.trigger_type = Some(TriggerType::Gaps422);
+  let emulator = TriggerEmulator::from_trigger_config(&cfg, make_pid_map());
+  assert_eq!(emulator.get_trigger_types(), vec![TriggerType::Gaps422]);
+  assert_eq!(emulator.evaluate(&mte), vec![TriggerType::Gaps422]);
+  mte.channel_mask[0] = 0b0101_0101;
+  assert!(emulator.evaluate(&mte).is_empty());
+}
+
+#[test]
+fn trigger_efficiency() {
+  let emulator = TriggerEmulator::new(make_pid_map(), false);
+  let mut eff  = TriggerEfficiency::new(&emulator);
+  let mut mte  = MasterTriggerEvent::new();
+  mte.dsi_j_mask     = 0b1;
+  // cube top + umbrella, reported as track trigger
+  mte.channel_mask   = vec![0b0100_0001];
+  mte.trigger_source = 1 << 8;
+  eff.add_event(&emulator, &mte);
+  // only the cube top
+  mte.channel_mask   = vec![0b1];
+  eff.add_event(&emulator, &mte);
+  assert_eq!(eff.n_events, 2);
+  assert_eq!(eff.get_efficiency(TriggerType::Track), Some(0.5));
+  assert_eq!(eff.get_fire_fraction(TriggerType::Any), Some(1.0));
+  assert_eq!(eff.get_efficiency(TriggerType::UmbCube), None);
+}
@@ -19,6 +19,12 @@ use crate::serialization::{
 
 use crate::packets::PacketType;
 use crate::version::ProtocolVersion;
+use crate::events::MAX_SELECTION_RULES;
+use crate::events::{
+  EventIdStats,
+  EventStreamChecker,
+};
+use crate::clock_alignment::ClockAlignmentResult;
 // use std::collections::HashMap;
 
 #[cfg(feature="random")]
@@ -114,10 +120,9 @@ pub struct HeartBeatDataSink {
   pub n_packets_incoming : u64,
   /// bytes written to disk
   pub n_bytes_written    : u64,
-  /// event id check - missing event ids
-  pub n_evid_missing     : u64,
-  /// event id check - chunksize
-  pub n_evid_chunksize   : u64,
+  // 16 reserved bytes follow in the bytestream,
+  // formerly the event id check of the data sink. 
+  // See EventIdHeartbeat instead.
   /// length of incoming buffer for 
   /// the thread
   /// check for missing event ids
@@ -139,8 +144,6 @@ impl HeartBeatDataSink {
       n_packets_sent     : 0,
       n_packets_incoming : 0,
       n_bytes_written    : 0,
-      n_evid_missing     : 0,
-      n_evid_chunksize   : 0,
       evid_missing       : 0,
       evid_check_len     : 0,
       n_pack_write_disk  : 0,
@@ -193,8 +196,8 @@ impl Serialization for HeartBeatDataSink {
     hb.n_packets_sent     = parse_u64(stream, pos);
     hb.n_packets_incoming = parse_u64(stream, pos);
     hb.n_bytes_written    = parse_u64(stream, pos);
-    hb.n_evid_missing     = parse_u64(stream, pos);
-    hb.n_evid_chunksize   = parse_u64(stream, pos);
+    // reserved
+    *pos += 16;
     hb.evid_missing       = parse_u64(stream, pos);
     hb.evid_check_len     = parse_u64(stream, pos);
     hb.n_pack_write_disk  = parse_u64(stream, pos);
@@ -210,8 +213,8 @@ impl Serialization for HeartBeatDataSink {
     bs.extend_from_slice(&self.n_packets_sent.to_le_bytes());
     bs.extend_from_slice(&self.n_packets_incoming.to_le_bytes());
     bs.extend_from_slice(&self.n_bytes_written.to_le_bytes());
-    bs.extend_from_slice(&self.n_evid_missing.to_le_bytes());
-    bs.extend_from_slice(&self.n_evid_chunksize.to_le_bytes());
+    // reserved
+    bs.extend_from_slice(&[0u8;16]);
     bs.extend_from_slice(&self.evid_missing     .to_le_bytes() );
     bs.extend_from_slice(&self.evid_check_len   .to_le_bytes() );
     bs.extend_from_slice(&self.n_pack_write_disk.to_le_bytes() );
@@ -229,8 +232,6 @@ impl FromRandom for HeartBeatDataSink {
     let n_packets_sent     = rng.gen::<u64>();
     let n_packets_incoming = rng.gen::<u64>();
     let n_bytes_written    = rng.gen::<u64>();
-    let n_evid_missing     = rng.gen::<u64>();
-    let n_evid_chunksize   = rng.gen::<u64>();
     let evid_missing       = rng.gen::<u64>();
     let evid_check_len     = rng.gen::<u64>();
     let n_pack_write_disk  = rng.gen::<u64>();
@@ -240,8 +241,6 @@ impl FromRandom for HeartBeatDataSink {
       n_packets_sent,
       n_packets_incoming,
       n_bytes_written,
-      n_evid_missing,
-      n_evid_chunksize,
       evid_missing,
       evid_check_len,
       n_pack_write_disk,
@@ -485,6 +484,11 @@ fn pack_mtbheartbeat() {
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct EVTBLDRHeartbeat {
+  /// The protocol version is serialized in the 
+  /// most significant byte of met_seconds. 
+  /// Unknown/V1 is the original layout, V2 and
+  /// larger add the interesting event counters
+  pub version               : ProtocolVersion,
   /// Mission elapsed time in seconds
   pub met_seconds           : usize,
   /// Total number of received MasterTriggerEvents (from MTB)
@@ -534,6 +538,14 @@ pub struct EVTBLDRHeartbeat {
   pub n_rbe_per_loop          : usize,
   /// The totabl number of events with the "AnyDataMangling" flag set
   pub data_mangled_ev       : usize,
+  /// The total number of events which passed any of
+  /// the interesting event selection rules 
+  /// (available for ProtocolVersion::V2 and larger)
+  pub n_interesting         : usize,
+  /// The number of events which passed the individual
+  /// interesting event selection rules
+  /// (available for ProtocolVersion::V2 and larger)
+  pub n_rule_pass           : [usize;MAX_SELECTION_RULES],
   // pub seen_rbevents         : HashMap<u8, usize>,
 }
 
@@ -549,6 +561,7 @@ impl EVTBLDRHeartbeat {
   //   }
   pub fn new() -> Self {
     Self {
+      version              : ProtocolVersion::Unknown,
       met_seconds          : 0,
       n_mte_received_tot   : 0,
       n_rbe_received_tot   : 0,
@@ -569,6 +582,9 @@ impl EVTBLDRHeartbeat {
       n_rbe_orphan         : 0,
       n_rbe_from_past      : 0,
       data_mangled_ev      : 0,
+      // available for protocol version V2 and larger
+      n_interesting        : 0,
+      n_rule_pass          : [0;MAX_SELECTION_RULES],
       // seen_rbevents        : seen_rbevents, 
     }
   }
@@ -612,6 +628,26 @@ impl EVTBLDRHeartbeat {
    0.0
   }
 
+  /// Count the result of the interesting event
+  /// selection for an event (see EventSelector)
+  pub fn add_interesting(&mut self, mask : u8) {
+    if mask != 0 {
+      self.n_interesting += 1;
+    }
+    for k in 0..MAX_SELECTION_RULES {
+      if (mask >> k) & 0x1 == 1 {
+        self.n_rule_pass[k] += 1;
+      }
+    }
+  }
+
+  pub fn get_interesting_frac(&self) -> f64 {
+    if self.n_sent > 0 {
+      return self.n_interesting as f64 / self.n_sent as f64;
+    }
+    0.0
+  }
+
   pub fn get_drs_lost_frac(&self) -> f64 {
     if self.n_rbe_received_tot > 0 {
       return self.drs_bsy_lost_hg_hits as f64 / self.n_rbe_received_tot as f64;
@@ -620,7 +656,7 @@ impl EVTBLDRHeartbeat {
   }
 
   pub fn to_string(&self) -> String {
-    let mut repr = String::from("");
+    let mut repr = format!("(version : {})", self.version);
     repr += &(format!("\n \u{2B50} \u{2B50} \u{2B50} \u{2B50} \u{2B50} EVENTBUILDER HEARTBTEAT \u{2B50} \u{2B50} \u{2B50} \u{2B50} \u{2B50} "));
     repr += &(format!("\n Mission elapsed time (MET) [s]      : {}", self.met_seconds).bright_purple());
     repr += &(format!("\n Num. events sent                    : {}", self.n_sent).bright_purple());
@@ -640,6 +676,13 @@ impl EVTBLDRHeartbeat {
     }
     else {repr += &(format!("\n Percent events with data mangling: unable to calculate"));}
     repr += &(format!("\n \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504}"));
+    if self.version == ProtocolVersion::V2 
+    || self.version == ProtocolVersion::V3 {
+      repr += &(format!("\n Num. interesting events             : {}", self.n_interesting).bright_purple());
+      repr += &(format!("\n Percent interesting events          : {:.2}%", self.get_interesting_frac()*(100 as f64)).bright_purple());
+      repr += &(format!("\n Num. events passing rule [0..7]     : {:?}", self.n_rule_pass).bright_purple());
+      repr += &(format!("\n \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504} \u{2504}"));
+    }
     repr += &(format!("\n Received MTEvents                   : {}", self.n_mte_received_tot).bright_purple());
     repr += &(format!("\n Skipped MTEvents                    : {}", self.n_mte_skipped).bright_purple());
     repr += &(format!("\n Incoming/outgoing MTEvents fraction : {:.2}", self.get_incoming_vs_outgoing_mte()).bright_purple());
@@ -679,14 +722,24 @@ impl Packable for EVTBLDRHeartbeat {
 impl Serialization for EVTBLDRHeartbeat {
   const HEAD : u16 = 0xAAAA;
   const TAIL : u16 = 0x5555;
-  const SIZE : usize = 156; //
+  // the size depends on the protocol version,
+  // 156 bytes for Unknown/V1 and 228 bytes for
+  // V2 and larger
 
   fn from_bytestream(stream : &Vec<u8>, 
                      pos        : &mut usize)
     -> Result<Self, SerializationError>{
-    Self::verify_fixed(stream,pos)?;
     let mut hb = EVTBLDRHeartbeat::new();
-    hb.met_seconds          = parse_usize(stream,pos);
+    let head = parse_u16(stream, pos);
+    if head != Self::HEAD {
+      error!("Decoding of HEAD failed! Got {} instead!", head);
+      return Err(SerializationError::HeadInvalid);
+    }
+    // the version is "hijacking" the most 
+    // significant byte of the MET
+    let met_version         = parse_usize(stream,pos);
+    hb.version              = ProtocolVersion::from((met_version >> (usize::BITS - 8)) as u8 & 0xc0);
+    hb.met_seconds          = met_version & (usize::MAX >> 8);
     hb.n_mte_received_tot   = parse_usize(stream,pos);
     hb.n_rbe_received_tot   = parse_usize(stream,pos);
     hb.n_rbe_per_te         = parse_usize(stream,pos);
@@ -706,15 +759,28 @@ impl Serialization for EVTBLDRHeartbeat {
     hb.n_rbe_from_past      = parse_usize(stream,pos);
     hb.n_rbe_orphan         = parse_usize(stream,pos);
     hb.data_mangled_ev      = parse_usize(stream,pos);
+    if hb.version == ProtocolVersion::V2 
+    || hb.version == ProtocolVersion::V3 {
+      hb.n_interesting      = parse_usize(stream,pos);
+      for k in 0..MAX_SELECTION_RULES {
+        hb.n_rule_pass[k]   = parse_usize(stream,pos);
+      }
+    }
     // hb.seen_rbevents        = HashMap::from(parse_u8(stream, pos));
-    *pos += 2;
+    let tail = parse_u16(stream, pos);
+    if tail != Self::TAIL {
+      error!("Decoding of TAIL failed for version {}! Got {} instead!", hb.version, tail);
+      return Err(SerializationError::TailInvalid);
+    }
     Ok(hb)
   }
     
   fn to_bytestream(&self) -> Vec<u8> {
-    let mut bs = Vec::<u8>::with_capacity(Self::SIZE);
+    let mut bs = Vec::<u8>::new();
     bs.extend_from_slice(&Self::HEAD.to_le_bytes());
-    bs.extend_from_slice(&self.met_seconds.to_le_bytes());
+    let met_version = (self.met_seconds & (usize::MAX >> 8))
+                    | ((self.version.to_u8() as usize) << (usize::BITS - 8));
+    bs.extend_from_slice(&met_version.to_le_bytes());
     bs.extend_from_slice(&self.n_mte_received_tot.to_le_bytes());
     bs.extend_from_slice(&self.n_rbe_received_tot.to_le_bytes());
     bs.extend_from_slice(&self.n_rbe_per_te.to_le_bytes());
@@ -734,6 +800,13 @@ impl Serialization for EVTBLDRHeartbeat {
     bs.extend_from_slice(&self.n_rbe_from_past.to_le_bytes());
     bs.extend_from_slice(&self.n_rbe_orphan.to_le_bytes());
     bs.extend_from_slice(&self.data_mangled_ev.to_le_bytes());
+    if self.version == ProtocolVersion::V2 
+    || self.version == ProtocolVersion::V3 {
+      bs.extend_from_slice(&self.n_interesting.to_le_bytes());
+      for k in 0..MAX_SELECTION_RULES {
+        bs.extend_from_slice(&self.n_rule_pass[k].to_le_bytes());
+      }
+    }
     // bs.push(self.seen_rbevents.to_u8());
     bs.extend_from_slice(&Self::TAIL.to_le_bytes());
     bs
@@ -744,7 +817,9 @@ impl Serialization for EVTBLDRHeartbeat {
 impl FromRandom for EVTBLDRHeartbeat {
   fn from_random() -> Self {
     let mut rng       = rand::thread_rng();
-    let met_seconds   = rng.gen::<usize>();
+    let version       = ProtocolVersion::from_random();
+    // the most significant byte is taken by the version
+    let met_seconds   = rng.gen::<usize>() & (usize::MAX >> 8);
     let n_mte_received_tot = rng.gen::<usize>();
     let n_rbe_received_tot = rng.gen::<usize>();
     let n_rbe_per_te = rng.gen::<usize>();
@@ -764,7 +839,17 @@ impl FromRandom for EVTBLDRHeartbeat {
     let n_rbe_from_past = rng.gen::<usize>();
     let n_rbe_orphan = rng.gen::<usize>();
     let data_mangled_ev = rng.gen::<usize>();
+    let mut n_interesting = 0usize;
+    let mut n_rule_pass   = [0usize;MAX_SELECTION_RULES];
+    if version == ProtocolVersion::V2 
+    || version == ProtocolVersion::V3 {
+      n_interesting   = rng.gen::<usize>();
+      for k in 0..MAX_SELECTION_RULES {
+        n_rule_pass[k]  = rng.gen::<usize>();
+      }
+    }
     Self {
+      version,
       met_seconds,
       n_rbe_received_tot,
       n_rbe_per_te,
@@ -786,7 +871,9 @@ impl FromRandom for EVTBLDRHeartbeat {
       n_rbe_per_loop,
       n_rbe_from_past,
       n_rbe_orphan,
-      data_mangled_ev
+      data_mangled_ev,
+      n_interesting,
+      n_rule_pass,
     }
   }
 } 
@@ -808,3 +895,266 @@ fn pack_evtbldrheartbeat() {
     assert_eq!(hb, test);
   }
 }
+
+#[test]
+fn evtbldrheartbeat_versioned_layout() {
+  let mut hb  = EVTBLDRHeartbeat::new();
+  hb.met_seconds = 42;
+  hb.add_interesting(0b101);
+  // the original layout does not carry the counters
+  let test = EVTBLDRHeartbeat::from_bytestream(&hb.to_bytestream(), &mut 0).unwrap();
+  assert_eq!(hb.to_bytestream().len(), 156);
+  assert_eq!(test.version, ProtocolVersion::Unknown);
+  assert_eq!(test.met_seconds, 42);
+  assert_eq!(test.n_interesting, 0);
+  hb.version = ProtocolVersion::V2;
+  let test = EVTBLDRHeartbeat::from_bytestream(&hb.to_bytestream(), &mut 0).unwrap();
+  assert_eq!(hb.to_bytestream().len(), 228);
+  assert_eq!(test, hb);
+  assert_eq!(test.n_rule_pass[0], 1);
+  assert_eq!(test.n_rule_pass[1], 0);
+  assert_eq!(test.n_rule_pass[2], 1);
+}
+
+/// Event id continuity for the MasterTriggerEvent,
+/// TofEventSummary and TofEvent streams
+/// (see EventStreamChecker)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EventIdHeartbeat {
+  /// Mission elapsed time in seconds
+  pub met        : u64,
+  pub mte        : EventIdStats,
+  pub summary    : EventIdStats,
+  pub tof_event  : EventIdStats,
+}
+
+impl EventIdHeartbeat {
+
+  pub fn new() -> Self {
+    Self {
+      met        : 0,
+      mte        : EventIdStats::new(),
+      summary    : EventIdStats::new(),
+      tof_event  : EventIdStats::new(),
+    }
+  }
+
+  pub fn from_checker(met : u64, checker : &EventStreamChecker) -> Self {
+    Self {
+      met,
+      mte        : checker.mte.stats,
+      summary    : checker.summary.stats,
+      tof_event  : checker.tof_event.stats,
+    }
+  }
+
+  /// No problems have been found in any of the
+  /// streams
+  pub fn is_clean(&self) -> bool {
+    self.mte.is_clean() && self.summary.is_clean() && self.tof_event.is_clean()
+  }
+}
+
+impl Default for EventIdHeartbeat {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Packable for EventIdHeartbeat {
+  const PACKET_TYPE : PacketType = PacketType::EventIdHeartbeat;
+}
+
+impl Serialization for EventIdHeartbeat {
+  const HEAD : u16 = 0xAAAA;
+  const TAIL : u16 = 0x5555;
+  const SIZE : usize = 12 + 3*EventIdStats::SIZE;
+
+  fn from_bytestream(stream : &Vec<u8>,
+                     pos    : &mut usize)
+    -> Result<Self, SerializationError>{
+    Self::verify_fixed(stream, pos)?;
+    let mut hb   = EventIdHeartbeat::new();
+    hb.met       = parse_u64(stream, pos);
+    hb.mte       = EventIdStats::from_bytestream(stream, pos)?;
+    hb.summary   = EventIdStats::from_bytestream(stream, pos)?;
+    hb.tof_event = EventIdStats::from_bytestream(stream, pos)?;
+    *pos += 2;
+    Ok(hb)
+  }
+
+  fn to_bytestream(&self) -> Vec<u8> {
+    let mut bs = Vec::<u8>::with_capacity(Self::SIZE);
+    bs.extend_from_slice(&Self::HEAD.to_le_bytes());
+    bs.extend_from_slice(&self.met.to_le_bytes());
+    bs.extend_from_slice(&self.mte.to_bytestream());
+    bs.extend_from_slice(&self.summary.to_bytestream());
+    bs.extend_from_slice(&self.tof_event.to_bytestream());
+    bs.extend_from_slice(&Self::TAIL.to_le_bytes());
+    bs
+  }
+}
+
+#[cfg(feature="random")]
+impl FromRandom for EventIdHeartbeat {
+  fn from_random() -> Self {
+    let mut rng = rand::thread_rng();
+    Self {
+      met        : rng.gen::<u64>(),
+      mte        : EventIdStats::from_random(),
+      summary    : EventIdStats::from_random(),
+      tof_event  : EventIdStats::from_random(),
+    }
+  }
+}
+
+impl fmt::Display for EventIdHeartbeat {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<EventIdHeartbeat:");
+    repr += &(format!("\n  MET [s] : {}", self.met));
+    repr += &(format!("\n ** MasterTriggerEvent **\n  {}", self.mte));
+    repr += &(format!("\n ** TofEventSummary **\n  {}", self.summary));
+    repr += &(format!("\n ** TofEvent **\n  {}>", self.tof_event));
+    write!(f, "{}", repr)
+  }
+}
+
+#[cfg(feature="random")]
+#[test]
+fn pack_eventidheartbeat() {
+  for _ in 0..100 {
+    let hb = EventIdHeartbeat::from_random();
+    let test : EventIdHeartbeat = hb.pack().unpack().unwrap();
+    assert_eq!(hb, test);
+  }
+}
+
+/// Statistics of the alignment of the RB clocks
+/// in the event builder (see ClockAligner)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AlignmentHeartbeat {
+  /// Mission elapsed time in seconds
+  pub met            : u64,
+  /// The total number of events which went
+  /// through the alignment
+  pub n_events       : u64,
+  /// Events without any RB with a good sine
+  /// fit, so none of the hits got aligned
+  pub n_no_reference : u64,
+  /// RBs with hits which got aligned
+  pub n_rb_aligned   : u64,
+  /// RBs with hits which could not be aligned
+  pub n_rb_failed    : u64,
+}
+
+impl AlignmentHeartbeat {
+
+  pub fn new() -> Self {
+    Self {
+      met            : 0,
+      n_events       : 0,
+      n_no_reference : 0,
+      n_rb_aligned   : 0,
+      n_rb_failed    : 0,
+    }
+  }
+
+  /// Count the outcome of the alignment of an event
+  pub fn add(&mut self, result : &ClockAlignmentResult) {
+    self.n_events     += 1;
+    if result.reference.is_none() {
+      self.n_no_reference += 1;
+    }
+    self.n_rb_aligned += result.n_aligned as u64;
+    self.n_rb_failed  += result.n_failed as u64;
+  }
+
+  /// Fraction of the RBs with hits which 
+  /// could not be aligned
+  pub fn get_failed_frac(&self) -> f64 {
+    let n_rb = self.n_rb_aligned + self.n_rb_failed;
+    if n_rb > 0 {
+      return self.n_rb_failed as f64 / n_rb as f64;
+    }
+    0.0
+  }
+}
+
+impl Default for AlignmentHeartbeat {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Packable for AlignmentHeartbeat {
+  const PACKET_TYPE : PacketType = PacketType::AlignmentHeartbeat;
+}
+
+impl Serialization for AlignmentHeartbeat {
+  const HEAD : u16 = 0xAAAA;
+  const TAIL : u16 = 0x5555;
+  const SIZE : usize = 44;
+
+  fn from_bytestream(stream : &Vec<u8>,
+                     pos    : &mut usize)
+    -> Result<Self, SerializationError>{
+    Self::verify_fixed(stream, pos)?;
+    let mut hb        = AlignmentHeartbeat::new();
+    hb.met            = parse_u64(stream, pos);
+    hb.n_events       = parse_u64(stream, pos);
+    hb.n_no_reference = parse_u64(stream, pos);
+    hb.n_rb_aligned   = parse_u64(stream, pos);
+    hb.n_rb_failed    = parse_u64(stream, pos);
+    *pos += 2;
+    Ok(hb)
+  }
+
+  fn to_bytestream(&self) -> Vec<u8> {
+    let mut bs = Vec::<u8>::with_capacity(Self::SIZE);
+    bs.extend_from_slice(&Self::HEAD.to_le_bytes());
+    bs.extend_from_slice(&self.met.to_le_bytes());
+    bs.extend_from_slice(&self.n_events.to_le_bytes());
+    bs.extend_from_slice(&self.n_no_reference.to_le_bytes());
+    bs.extend_from_slice(&self.n_rb_aligned.to_le_bytes());
+    bs.extend_from_slice(&self.n_rb_failed.to_le_bytes());
+    bs.extend_from_slice(&Self::TAIL.to_le_bytes());
+    bs
+  }
+}
+
+#[cfg(feature="random")]
+impl FromRandom for AlignmentHeartbeat {
+  fn from_random() -> Self {
+    let mut rng = rand::thread_rng();
+    Self {
+      met            : rng.gen::<u64>(),
+      n_events       : rng.gen::<u64>(),
+      n_no_reference : rng.gen::<u64>(),
+      n_rb_aligned   : rng.gen::<u64>(),
+      n_rb_failed    : rng.gen::<u64>(),
+    }
+  }
+}
+
+impl fmt::Display for AlignmentHeartbeat {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<AlignmentHeartbeat:");
+    repr += &(format!("\n  MET [s]              : {}", self.met));
+    repr += &(format!("\n  N events             : {}", self.n_events));
+    repr += &(format!("\n  N w/o reference RB   : {}", self.n_no_reference));
+    repr += &(format!("\n  N RBs aligned        : {}", self.n_rb_aligned));
+    repr += &(format!("\n  N RBs failed         : {}", self.n_rb_failed));
+    repr += &(format!("\n  RBs failed [%]       : {:.2}>", self.get_failed_frac()*100.0));
+    write!(f, "{}", repr)
+  }
+}
+
+#[cfg(feature="random")]
+#[test]
+fn pack_alignmentheartbeat() {
+  for _ in 0..100 {
+    let hb = AlignmentHeartbeat::from_random();
+    let test : AlignmentHeartbeat = hb.pack().unpack().unwrap();
+    assert_eq!(hb, test);
+  }
+}
@@ -34,11 +34,20 @@ pub mod errors;
 pub mod serialization;
 pub mod constants;
 pub mod calibrations;
+pub mod calibration_quality;
+pub mod calibration_store;
+pub mod calibration_file;
 pub mod threading;
 pub mod commands;
 pub mod monitoring;
 pub mod io;
 pub mod analysis;
+pub mod pulse_template;
+pub mod reconstruction;
+pub mod timing_calibration;
+pub mod energy_calibration;
+pub mod clock_alignment;
+pub mod event_display;
 pub mod ipbus;
 pub mod series;
 pub mod heartbeats;
@@ -9,6 +9,7 @@
 //! [GAPS wiki](https://gaps1.astro.ucla.edu/wiki/gaps/index.php?title=TOF_environmental_sensors)
 
 use std::fmt;
+use std::time::Instant;
 //use std::collections::HashMap;
 
 //use half::f16;
@@ -74,7 +75,8 @@ pub use crate::series::{
   PAMoniDataSeries,
   PBMoniDataSeries,
   LTBMoniDataSeries,
-  RBMoniDataSeries
+  RBMoniDataSeries,
+  RBChannelNoiseMoniDataSeries,
 };
 
 /// Monitoring data shall share the same kind 
@@ -1632,6 +1634,298 @@ impl FromRandom for MtbMoniData {
   }
 }
 
+///////////////////////////////////////////////////////
+
+/// Number of RB channels which are connected to 
+/// paddle ends (channel 9 is the clock)
+pub const RB_NPADDLE_CHANNELS : usize = 8;
+
+/// Baseline and noise of the RB channels, averaged 
+/// over a monitoring period
+///
+/// The values are aggregated from the pedestals which 
+/// are calculated by the analysis engine for each
+/// event (TofHit::baseline_a/b and baseline_a/b_rms).
+/// Index 0 is RB channel 1.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RBChannelNoiseMoniData {
+  pub board_id        : u8,
+  /// Length of the monitoring period [s]
+  pub period          : u16,
+  /// Number of pedestal measurements
+  pub n_entries       : [u32;RB_NPADDLE_CHANNELS],
+  /// Mean baseline [mV]
+  pub baseline        : [f32;RB_NPADDLE_CHANNELS],
+  /// Standard deviation of the baseline
+  /// during the period [mV]
+  pub baseline_spread : [f32;RB_NPADDLE_CHANNELS],
+  /// Mean baseline RMS, that is the noise 
+  /// of the channel [mV]
+  pub noise           : [f32;RB_NPADDLE_CHANNELS],
+}
+
+impl RBChannelNoiseMoniData {
+  pub fn new() -> Self {
+    Self {
+      board_id        : 0,
+      period          : 0,
+      n_entries       : [0;RB_NPADDLE_CHANNELS],
+      baseline        : [0.0;RB_NPADDLE_CHANNELS],
+      baseline_spread : [0.0;RB_NPADDLE_CHANNELS],
+      noise           : [0.0;RB_NPADDLE_CHANNELS],
+    }
+  }
+
+  /// Channels (1-8) with a noise above max_noise [mV]
+  pub fn get_noisy_channels(&self, max_noise : f32) -> Vec<u8> {
+    let mut channels = Vec::<u8>::new();
+    for k in 0..RB_NPADDLE_CHANNELS {
+      if self.n_entries[k] > 0 && self.noise[k] > max_noise {
+        channels.push(k as u8 + 1);
+      }
+    }
+    channels
+  }
+
+  /// Channels (1-8) which seem dead. This is either
+  /// a flat line (noise below min_noise [mV]) or no 
+  /// pedestal measurement at all, while other 
+  /// channels of the board got some.
+  pub fn get_dead_channels(&self, min_noise : f32) -> Vec<u8> {
+    let mut channels = Vec::<u8>::new();
+    let board_active = self.n_entries.iter().any(|n| *n > 0);
+    for k in 0..RB_NPADDLE_CHANNELS {
+      if (self.n_entries[k] > 0 && self.noise[k] < min_noise)
+      || (self.n_entries[k] == 0 && board_active) {
+        channels.push(k as u8 + 1);
+      }
+    }
+    channels
+  }
+
+  /// Largest noise of all channels with entries
+  pub fn get_max_noise(&self) -> Option<f32> {
+    (0..RB_NPADDLE_CHANNELS).filter(|k| self.n_entries[*k] > 0)
+                            .map(|k| self.noise[k])
+                            .reduce(f32::max)
+  }
+  
+  /// Smallest noise of all channels with entries
+  pub fn get_min_noise(&self) -> Option<f32> {
+    (0..RB_NPADDLE_CHANNELS).filter(|k| self.n_entries[*k] > 0)
+                            .map(|k| self.noise[k])
+                            .reduce(f32::min)
+  }
+}
+
+impl Default for RBChannelNoiseMoniData {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for RBChannelNoiseMoniData {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<RBChannelNoiseMoniData:");
+    repr += &(format!("\n  BOARD ID  : {}", self.board_id));
+    repr += &(format!("\n  PERIOD    : {} [s]", self.period));
+    repr += "\n  CH | N ENTRIES | BASELINE [mV] | SPREAD [mV] | NOISE [mV]";
+    for k in 0..RB_NPADDLE_CHANNELS {
+      repr += &(format!("\n  {:2} | {:9} | {:13.3} | {:11.3} | {:10.3}",
+                        k + 1,
+                        self.n_entries[k],
+                        self.baseline[k],
+                        self.baseline_spread[k],
+                        self.noise[k]));
+    }
+    repr += ">";
+    write!(f, "{}", repr)
+  }
+}
+
+impl Packable for RBChannelNoiseMoniData {
+  const PACKET_TYPE : PacketType = PacketType::RBChannelNoiseMoniData;
+}
+
+impl Serialization for RBChannelNoiseMoniData {
+  
+  const HEAD : u16 = 0xAAAA;
+  const TAIL : u16 = 0x5555;
+  /// HEAD + TAIL + sum(sizeof(m) for m in _all_members_))
+  const SIZE : usize  = 4 + 1 + 2 + (RB_NPADDLE_CHANNELS*4*4);
+  
+  fn to_bytestream(&self) -> Vec<u8> {
+    let mut stream = Vec::<u8>::with_capacity(Self::SIZE);
+    stream.extend_from_slice(&Self::HEAD.to_le_bytes());
+    stream.push(self.board_id);
+    stream.extend_from_slice(&self.period.to_le_bytes());
+    for k in 0..RB_NPADDLE_CHANNELS {
+      stream.extend_from_slice(&self.n_entries[k]      .to_le_bytes());
+      stream.extend_from_slice(&self.baseline[k]       .to_le_bytes());
+      stream.extend_from_slice(&self.baseline_spread[k].to_le_bytes());
+      stream.extend_from_slice(&self.noise[k]          .to_le_bytes());
+    }
+    stream.extend_from_slice(&Self::TAIL.to_le_bytes());
+    stream
+  }
+  
+  fn from_bytestream(stream    : &Vec<u8>, 
+                     pos       : &mut usize) 
+    -> Result<Self, SerializationError>{
+    let mut moni  = Self::new();
+    Self::verify_fixed(stream, pos)?;
+    moni.board_id = parse_u8(stream, pos);
+    moni.period   = parse_u16(stream, pos);
+    for k in 0..RB_NPADDLE_CHANNELS {
+      moni.n_entries[k]       = parse_u32(stream, pos);
+      moni.baseline[k]        = parse_f32(stream, pos);
+      moni.baseline_spread[k] = parse_f32(stream, pos);
+      moni.noise[k]           = parse_f32(stream, pos);
+    }
+    *pos += 2;
+    Ok(moni)
+  }
+}
+
+#[cfg(feature = "random")]
+impl FromRandom for RBChannelNoiseMoniData {
+  fn from_random() -> Self {
+    let mut moni  = Self::new();
+    let mut rng   = rand::thread_rng();
+    moni.board_id = rng.gen::<u8>();
+    moni.period   = rng.gen::<u16>();
+    for k in 0..RB_NPADDLE_CHANNELS {
+      moni.n_entries[k]       = rng.gen::<u32>();
+      moni.baseline[k]        = rng.gen::<f32>();
+      moni.baseline_spread[k] = rng.gen::<f32>();
+      moni.noise[k]           = rng.gen::<f32>();
+    }
+    moni
+  }
+}
+
+impl MoniData for RBChannelNoiseMoniData {
+  fn get_board_id(&self) -> u8 {
+    self.board_id
+  }
+  
+  /// Access the (data) members by name, the 
+  /// channel variables as e.g. "noise_ch1" 
+  fn get(&self, varname : &str) -> Option<f32> {
+    match varname {
+      "board_id" => return Some(self.board_id as f32),
+      "period"   => return Some(self.period as f32),
+      _          => ()
+    }
+    let (var, ch) = varname.rsplit_once("_ch")?;
+    let ch        = ch.parse::<usize>().ok()?;
+    if ch == 0 || ch > RB_NPADDLE_CHANNELS {
+      return None;
+    }
+    match var {
+      "n_entries"       => Some(self.n_entries[ch - 1] as f32),
+      "baseline"        => Some(self.baseline[ch - 1]),
+      "baseline_spread" => Some(self.baseline_spread[ch - 1]),
+      "noise"           => Some(self.noise[ch - 1]),
+      _                 => None
+    }
+  }
+
+  /// A list of the variables in this MoniData
+  fn keys() -> Vec<&'static str> {
+    vec!["board_id", "period",
+         "n_entries_ch1", "n_entries_ch2", "n_entries_ch3", "n_entries_ch4",
+         "n_entries_ch5", "n_entries_ch6", "n_entries_ch7", "n_entries_ch8",
+         "baseline_ch1", "baseline_ch2", "baseline_ch3", "baseline_ch4",
+         "baseline_ch5", "baseline_ch6", "baseline_ch7", "baseline_ch8",
+         "baseline_spread_ch1", "baseline_spread_ch2", "baseline_spread_ch3", "baseline_spread_ch4",
+         "baseline_spread_ch5", "baseline_spread_ch6", "baseline_spread_ch7", "baseline_spread_ch8",
+         "noise_ch1", "noise_ch2", "noise_ch3", "noise_ch4",
+         "noise_ch5", "noise_ch6", "noise_ch7", "noise_ch8"]
+  }
+}
+
+/// Running baseline and noise statistics for the
+/// channels of a single RB
+///
+/// Add the pedestals of each analyzed event, and 
+/// get the RBChannelNoiseMoniData for the period
+/// with finish()
+#[derive(Debug, Clone)]
+pub struct RBChannelNoiseMonitor {
+  pub board_id : u8,
+  n_entries    : [u32;RB_NPADDLE_CHANNELS],
+  sum_bl       : [f64;RB_NPADDLE_CHANNELS],
+  sum_bl2      : [f64;RB_NPADDLE_CHANNELS],
+  sum_rms      : [f64;RB_NPADDLE_CHANNELS],
+  start        : Instant,
+}
+
+impl RBChannelNoiseMonitor {
+  pub fn new(board_id : u8) -> Self {
+    Self {
+      board_id,
+      n_entries : [0;RB_NPADDLE_CHANNELS],
+      sum_bl    : [0.0;RB_NPADDLE_CHANNELS],
+      sum_bl2   : [0.0;RB_NPADDLE_CHANNELS],
+      sum_rms   : [0.0;RB_NPADDLE_CHANNELS],
+      start     : Instant::now(),
+    }
+  }
+
+  /// Add a pedestal measurement
+  ///
+  /// # Arguments:
+  ///   * channel  : RB channel (1-8)
+  ///   * baseline : pedestal [mV]
+  ///   * rms      : pedestal RMS [mV]
+  pub fn add(&mut self, channel : usize, baseline : f32, rms : f32) {
+    if channel == 0 || channel > RB_NPADDLE_CHANNELS {
+      error!("Can not monitor noise for channel {}!", channel);
+      return;
+    }
+    if !baseline.is_finite() || !rms.is_finite() {
+      return;
+    }
+    let k = channel - 1;
+    self.n_entries[k] += 1;
+    self.sum_bl[k]    += baseline as f64;
+    self.sum_bl2[k]   += (baseline as f64).powi(2);
+    self.sum_rms[k]   += rms as f64;
+  }
+
+  /// Seconds since the start of the current period
+  pub fn get_elapsed(&self) -> f32 {
+    self.start.elapsed().as_secs_f32()
+  }
+
+  /// Total number of measurements in the current period
+  pub fn get_n_entries(&self) -> u32 {
+    self.n_entries.iter().sum()
+  }
+
+  /// Get the statistics for the current period
+  /// and start a new one
+  pub fn finish(&mut self) -> RBChannelNoiseMoniData {
+    let mut moni  = RBChannelNoiseMoniData::new();
+    moni.board_id = self.board_id;
+    moni.period   = self.get_elapsed().round().min(u16::MAX as f32) as u16;
+    for k in 0..RB_NPADDLE_CHANNELS {
+      moni.n_entries[k] = self.n_entries[k];
+      if self.n_entries[k] == 0 {
+        continue;
+      }
+      let n    = self.n_entries[k] as f64;
+      let mean = self.sum_bl[k]/n;
+      moni.baseline[k]        = mean as f32;
+      moni.baseline_spread[k] = (self.sum_bl2[k]/n - mean*mean).max(0.0).sqrt() as f32;
+      moni.noise[k]           = (self.sum_rms[k]/n) as f32;
+    }
+    *self = Self::new(self.board_id);
+    moni
+  }
+}
+
   
 #[test]
 #[cfg(feature = "random")]
@@ -1754,3 +2048,47 @@ fn pack_cpumonidata() {
   }
 }
 
+
+#[test]
+#[cfg(feature = "random")]
+fn pack_rbchannelnoisemonidata() {
+  for _ in 0..100 {
+    let data = RBChannelNoiseMoniData::from_random();
+    let test : RBChannelNoiseMoniData = data.pack().unpack().unwrap();
+    assert_eq!(data, test);
+  }
+}
+
+#[test]
+#[cfg(feature = "random")]
+fn monidata_rbchannelnoisemonidata() {
+  let data = RBChannelNoiseMoniData::from_random();
+  for k in RBChannelNoiseMoniData::keys() {
+    assert!(data.get(k).is_some());
+  }
+  assert!(data.get("noise_ch9").is_none());
+  assert_eq!(data.get("noise_ch3"), Some(data.noise[2]));
+  assert_eq!(data.get_board_id(), data.board_id);
+}
+
+#[test]
+fn rbchannelnoisemonitor() {
+  let mut monitor = RBChannelNoiseMonitor::new(12);
+  for k in 0..100 {
+    let bl = if k % 2 == 0 { 1.0 } else { 3.0 };
+    monitor.add(1, bl, 2.0);
+    monitor.add(2, 0.0, 12.0);
+    monitor.add(3, 0.0, 0.01);
+  }
+  assert_eq!(monitor.get_n_entries(), 300);
+  let moni = monitor.finish();
+  assert_eq!(monitor.get_n_entries(), 0);
+  assert_eq!(moni.board_id, 12);
+  assert_eq!(moni.n_entries[0], 100);
+  assert!((moni.baseline[0] - 2.0).abs() < 1e-4);
+  assert!((moni.baseline_spread[0] - 1.0).abs() < 1e-4);
+  assert!((moni.noise[0] - 2.0).abs() < 1e-4);
+  assert_eq!(moni.get_noisy_channels(5.0), vec![2]);
+  assert_eq!(moni.get_dead_channels(0.1), vec![3,4,5,6,7,8]);
+  assert_eq!(moni.get_max_noise(), Some(12.0));
+}
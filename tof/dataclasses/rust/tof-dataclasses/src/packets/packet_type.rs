@@ -33,6 +33,8 @@ pub enum PacketType {
   MTBHeartbeat          = 62u8, 
   EVTBLDRHeartbeat      = 63u8,
   RBChannelMaskConfig   = 64u8,
+  EventIdHeartbeat      = 65u8,
+  AlignmentHeartbeat    = 67u8,
   TofRBConfig           = 68u8,
   AnalysisEngineConfig  = 69u8,
   RBEventHeader         = 70u8,    // needs to go away
@@ -45,9 +47,11 @@ pub enum PacketType {
   PBMoniData            = 101u8,
   LTBMoniData           = 102u8,
   PAMoniData            = 103u8,
+  RBChannelNoiseMoniData = 104u8,
   RBEventMemoryView     = 120u8, // We'll keep it for now - indicates that the event
                                  // still needs to be processed.
   RBCalibration         = 130u8,
+  RBCalibrationQuality  = 131u8,
   TofCommand            = 140u8,
   TofCommandV2          = 141u8,
   TofResponse           = 142u8,
@@ -99,6 +103,8 @@ impl From<u8> for PacketType {
       62  => PacketType::MTBHeartbeat,
       63  => PacketType::EVTBLDRHeartbeat,
       64  => PacketType::RBChannelMaskConfig,
+      65  => PacketType::EventIdHeartbeat,
+      67  => PacketType::AlignmentHeartbeat,
       68  => PacketType::TofRBConfig,
       69  => PacketType::AnalysisEngineConfig,
       70  => PacketType::RBEventHeader,
@@ -110,8 +116,10 @@ impl From<u8> for PacketType {
       101 => PacketType::PBMoniData   ,
       102 => PacketType::LTBMoniData  ,
       103 => PacketType::PAMoniData   ,
+      104 => PacketType::RBChannelNoiseMoniData,
       120 => PacketType::RBEventMemoryView,
       130 => PacketType::RBCalibration,
+      131 => PacketType::RBCalibrationQuality,
       140 => PacketType::TofCommand,
       141 => PacketType::TofCommandV2,
       142 => PacketType::TofResponse,
@@ -158,6 +166,8 @@ impl PacketType {
     //  PacketType::MTBHeartbeat          => 62, 
     //  PacketType::EVTBLDRHeartbeat      => 63,
     //  PacketType::RBChannelMaskConfig   => 64,
+    //  PacketType::EventIdHeartbeat      => 65,
+    //  PacketType::AlignmentHeartbeat    => 67,
     //  PacketType::TofRBConfig           => 68,
     //  PacketType::AnalysisEngineConfig  => 69,
     //  PacketType::RBEventHeader         => 70,    // needs to go away
@@ -170,8 +180,10 @@ impl PacketType {
     //  PacketType::PBMoniData            => 101,
     //  PacketType::LTBMoniData           => 102,
     //  PacketType::PAMoniData            => 103,
+    //  PacketType::RBChannelNoiseMoniData => 104,
     //  PacketType::RBEventMemoryView     => 120, // We'll keep it for now - indicates that the event
     //  PacketType::RBCalibration         => 130,
+    //  PacketType::RBCalibrationQuality  => 131,
     //  PacketType::TofCommand            => 140,
     //  PacketType::TofCommandV2          => 141,
     //  PacketType::TofResponse           => 142,
@@ -208,6 +220,8 @@ impl FromRandom for PacketType {
       PacketType::HeartBeatDataSink,
       PacketType::MTBHeartbeat,
       PacketType::EVTBLDRHeartbeat,
+      PacketType::EventIdHeartbeat,
+      PacketType::AlignmentHeartbeat,
       PacketType::RBEventHeader,
       PacketType::RBEvent,
       PacketType::RBEventMemoryView,
@@ -228,9 +242,11 @@ impl FromRandom for PacketType {
       PacketType::PBMoniData,
       PacketType::LTBMoniData,
       PacketType::PAMoniData,
+      PacketType::RBChannelNoiseMoniData,
       PacketType::CPUMoniData,
       PacketType::MonitorMtb,
       PacketType::RBCalibration,
+      PacketType::RBCalibrationQuality,
       PacketType::TofDetectorStatus,
       PacketType::ConfigBinary,
       PacketType::LiftofRBBinary,
@@ -258,6 +274,8 @@ fn test_packet_types() {
   type_codes.push(PacketType::HeartBeatDataSink as u8);
   type_codes.push(PacketType::MTBHeartbeat as u8);
   type_codes.push(PacketType::EVTBLDRHeartbeat as u8);
+  type_codes.push(PacketType::EventIdHeartbeat as u8);
+  type_codes.push(PacketType::AlignmentHeartbeat as u8);
   type_codes.push(PacketType::RBEventHeader as u8);
   type_codes.push(PacketType::RBEvent as u8);
   type_codes.push(PacketType::TofRBConfig as u8);
@@ -270,6 +288,7 @@ fn test_packet_types() {
   type_codes.push(PacketType::PBMoniData as u8);
   type_codes.push(PacketType::LTBMoniData as u8);
   type_codes.push(PacketType::PAMoniData as u8);
+  type_codes.push(PacketType::RBChannelNoiseMoniData as u8);
   type_codes.push(PacketType::CPUMoniData as u8);
   type_codes.push(PacketType::RBPing as u8);
   type_codes.push(PacketType::PreampBiasConfig as u8);
@@ -281,6 +300,7 @@ fn test_packet_types() {
   type_codes.push(PacketType::RBChannelMaskConfig as u8);
   type_codes.push(PacketType::MonitorMtb as u8);
   type_codes.push(PacketType::RBCalibration as u8);
+  type_codes.push(PacketType::RBCalibrationQuality as u8);
   type_codes.push(PacketType::TofDetectorStatus as u8);
   type_codes.push(PacketType::ConfigBinary as u8);
   type_codes.push(PacketType::LiftofCCBinary as u8);
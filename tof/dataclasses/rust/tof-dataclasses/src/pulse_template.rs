@@ -0,0 +1,498 @@
+//! Pulse template fitting
+//!
+//! An alternative to the simple CFD (analysis::cfd_simple)
+//! for the extraction of hit time and amplitude. For each
+//! paddle end, an average pulse shape (template) is built
+//! from calibrated and pedestal subtracted waveforms. The
+//! time axis of the template is relative to the CFD time
+//! of the pulses it was built from.
+//!
+//! Each pulse is then fit with the template, where the
+//! amplitude and the time are the free parameters. Bins
+//! above the saturation level are excluded from the fit,
+//! so that the amplitude of saturated pulses can still
+//! be estimated from the rising edge and the tail.
+//!
+//! All times are in ns, all voltages in mV.
+
+use std::fmt;
+use std::collections::HashMap;
+
+use serde::{
+  Serialize,
+  Deserialize,
+};
+
+use crate::calibration_file::JsonFile;
+use crate::errors::WaveformError;
+
+/// Calibrated voltages at or above this are considered
+/// to be saturated (the DRS4 has a 1V input range)
+pub const PULSE_SATURATION_MV : f32 = 950.0;
+
+/// An average pulse shape, normalized to a peak
+/// height of 1
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PulseTemplate {
+  /// Spacing of the template samples
+  pub dt       : f32,
+  /// Time of the first sample with respect to
+  /// the reference (CFD) time
+  pub t_start  : f32,
+  pub values   : Vec<f32>,
+  /// Number of pulses the template was built from
+  pub n_pulses : u32,
+}
+
+impl PulseTemplate {
+
+  pub fn new() -> Self {
+    Self {
+      dt       : 0.25,
+      t_start  : -10.0,
+      values   : Vec::<f32>::new(),
+      n_pulses : 0,
+    }
+  }
+
+  /// Time of the last sample with respect to the
+  /// reference time
+  pub fn get_t_end(&self) -> f32 {
+    self.t_start + self.dt*(self.values.len().saturating_sub(1) as f32)
+  }
+
+  /// Time of the maximum with respect to the
+  /// reference time
+  pub fn get_t_peak(&self) -> f32 {
+    let mut idx = 0;
+    for k in 0..self.values.len() {
+      if self.values[k] > self.values[idx] {
+        idx = k;
+      }
+    }
+    self.t_start + self.dt*idx as f32
+  }
+
+  /// Integral of the normalized template (ns)
+  pub fn get_integral(&self) -> f32 {
+    self.values.iter().sum::<f32>()*self.dt
+  }
+
+  /// Template value at time tau with respect to the
+  /// reference time (linear interpolation, 0 outside
+  /// of the template)
+  pub fn eval(&self, tau : f32) -> f32 {
+    if self.values.is_empty() || self.dt <= 0.0 {
+      return 0.0;
+    }
+    let x = (tau - self.t_start)/self.dt;
+    if x < 0.0 || x > (self.values.len() - 1) as f32 {
+      return 0.0;
+    }
+    let k = x.floor() as usize;
+    if k + 1 >= self.values.len() {
+      return self.values[k];
+    }
+    let frac = x - k as f32;
+    self.values[k]*(1.0 - frac) + self.values[k+1]*frac
+  }
+}
+
+impl Default for PulseTemplate {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for PulseTemplate {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<PulseTemplate:");
+    repr += &(format!("\n  range    : {:.2} .. {:.2} [ns] (dt {} ns)", self.t_start, self.get_t_end(), self.dt));
+    repr += &(format!("\n  peak at  : {:.2} [ns]", self.get_t_peak()));
+    repr += &(format!("\n  integral : {:.2} [ns]", self.get_integral()));
+    repr += &(format!("\n  n pulses : {}>", self.n_pulses));
+    write!(f, "{}", repr)
+  }
+}
+
+/// Average pulses to get a PulseTemplate
+#[derive(Debug, Clone)]
+pub struct PulseTemplateBuilder {
+  pub dt       : f32,
+  pub t_start  : f32,
+  sums         : Vec<f64>,
+  n_pulses     : u32,
+}
+
+impl PulseTemplateBuilder {
+
+  /// # Arguments:
+  ///   * dt        : template sample spacing
+  ///   * t_start   : template begin with respect to the
+  ///     CFD time
+  ///   * n_samples : template length is n_samples*dt
+  pub fn new(dt : f32, t_start : f32, n_samples : usize) -> Self {
+    Self {
+      dt,
+      t_start,
+      sums     : vec![0.0;n_samples],
+      n_pulses : 0,
+    }
+  }
+
+  pub fn get_n_pulses(&self) -> u32 {
+    self.n_pulses
+  }
+
+  /// Add a pedestal subtracted pulse
+  ///
+  /// Pulses which are saturated, or which are not
+  /// fully contained in the waveform are rejected.
+  ///
+  /// # Arguments:
+  ///   * t_ref     : CFD time of the pulse
+  ///   * amplitude : peak height of the pulse
+  ///
+  /// # Returns:
+  ///   true if the pulse was added
+  pub fn add_pulse(&mut self,
+                   voltages  : &[f32],
+                   times     : &[f32],
+                   t_ref     : f32,
+                   amplitude : f32) -> bool {
+    if amplitude <= 0.0 || amplitude >= PULSE_SATURATION_MV || times.len() < 2 {
+      return false;
+    }
+    let t_first = t_ref + self.t_start;
+    let t_last  = t_ref + self.t_start + self.dt*(self.sums.len() as f32);
+    if t_first < times[0] || t_last > times[times.len() - 1] {
+      return false;
+    }
+    for k in 0..self.sums.len() {
+      let t = t_ref + self.t_start + self.dt*k as f32;
+      self.sums[k] += (interpolate_at(voltages, times, t)/amplitude) as f64;
+    }
+    self.n_pulses += 1;
+    true
+  }
+
+  /// The average of all added pulses, normalized
+  /// to a peak height of 1
+  pub fn finish(&self) -> Option<PulseTemplate> {
+    if self.n_pulses == 0 {
+      return None;
+    }
+    let max = self.sums.iter().cloned().fold(f64::MIN, f64::max);
+    if max <= 0.0 {
+      return None;
+    }
+    Some(PulseTemplate {
+      dt       : self.dt,
+      t_start  : self.t_start,
+      values   : self.sums.iter().map(|s| (s/max) as f32).collect(),
+      n_pulses : self.n_pulses,
+    })
+  }
+}
+
+impl Default for PulseTemplateBuilder {
+  fn default() -> Self {
+    Self::new(0.25, -10.0, 240)
+  }
+}
+
+/// Templates for all paddle ends
+///
+/// The key is the paddle end id (see TofHit::get_pid),
+/// that is paddle id + 1000 for the A-side and paddle
+/// id + 2000 for the B-side.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PulseTemplateLibrary {
+  pub templates : HashMap<u16, PulseTemplate>,
+}
+
+impl PulseTemplateLibrary {
+
+  pub fn new() -> Self {
+    Self {
+      templates : HashMap::<u16, PulseTemplate>::new(),
+    }
+  }
+
+  pub fn get(&self, paddle_end_id : u16) -> Option<&PulseTemplate> {
+    self.templates.get(&paddle_end_id)
+  }
+
+  pub fn insert(&mut self, paddle_end_id : u16, template : PulseTemplate) {
+    self.templates.insert(paddle_end_id, template);
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.templates.is_empty()
+  }
+
+  pub fn len(&self) -> usize {
+    self.templates.len()
+  }
+
+  /// Build the library from a set of builders (e.g.
+  /// after a run). Builders with less than min_pulses
+  /// pulses are ignored.
+  pub fn from_builders(builders   : &HashMap<u16, PulseTemplateBuilder>,
+                       min_pulses : u32) -> Self {
+    let mut lib = Self::new();
+    for (pend, builder) in builders {
+      if builder.get_n_pulses() < min_pulses {
+        debug!("Only {} pulses for paddle end {}, not building a template!", builder.get_n_pulses(), pend);
+        continue;
+      }
+      if let Some(template) = builder.finish() {
+        lib.insert(*pend, template);
+      }
+    }
+    lib
+  }
+}
+
+impl JsonFile for PulseTemplateLibrary {
+  // the templates are long lists of numbers
+  const PRETTY : bool = false;
+}
+
+impl fmt::Display for PulseTemplateLibrary {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut pends : Vec<&u16> = self.templates.keys().collect();
+    pends.sort();
+    let mut repr = String::from("<PulseTemplateLibrary:");
+    repr += &(format!("\n  n templates : {}", self.templates.len()));
+    repr += &(format!("\n  paddle ends : {:?}>", pends));
+    write!(f, "{}", repr)
+  }
+}
+
+/// The result of a template fit to a single pulse
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TemplateFitResult {
+  /// Peak height of the fitted template (mV)
+  pub amplitude : f32,
+  /// Time of the pulse, comparable to the CFD time
+  pub time      : f32,
+  pub chi2      : f32,
+  pub ndof      : u16,
+  /// Bins above the saturation level were found
+  /// (and excluded from the fit)
+  pub saturated : bool,
+}
+
+impl TemplateFitResult {
+
+  pub fn new() -> Self {
+    Self {
+      amplitude : 0.0,
+      time      : 0.0,
+      chi2      : f32::NAN,
+      ndof      : 0,
+      saturated : false,
+    }
+  }
+
+  pub fn get_reduced_chi2(&self) -> f32 {
+    if self.ndof == 0 {
+      return f32::NAN;
+    }
+    self.chi2/self.ndof as f32
+  }
+
+  /// Charge of the fitted pulse (integral of the
+  /// template scaled by the amplitude, divided by
+  /// the impedance)
+  pub fn get_charge(&self, template : &PulseTemplate, impedance : f32) -> f32 {
+    self.amplitude*template.get_integral()/impedance
+  }
+}
+
+impl Default for TemplateFitResult {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for TemplateFitResult {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<TemplateFitResult:");
+    repr += &(format!("\n  amplitude : {:.2} [mV]", self.amplitude));
+    repr += &(format!("\n  time      : {:.3} [ns]", self.time));
+    repr += &(format!("\n  chi2/ndof : {:.2}/{}", self.chi2, self.ndof));
+    repr += &(format!("\n  saturated : {}>", self.saturated));
+    write!(f, "{}", repr)
+  }
+}
+
+/// Linear interpolation of the waveform at time t
+fn interpolate_at(voltages : &[f32], times : &[f32], t : f32) -> f32 {
+  let k = times.partition_point(|x| *x <= t);
+  if k == 0 {
+    return voltages[0];
+  }
+  if k >= times.len() {
+    return voltages[times.len() - 1];
+  }
+  let dt = times[k] - times[k-1];
+  if dt <= 0.0 {
+    return voltages[k-1];
+  }
+  voltages[k-1] + (voltages[k] - voltages[k-1])*(t - times[k-1])/dt
+}
+
+/// Best amplitude and χ² for a fixed time
+fn template_chi2(voltages   : &[f32],
+                 times      : &[f32],
+                 bins       : &[usize],
+                 template   : &PulseTemplate,
+                 t0         : f32,
+                 noise      : f32) -> (f32, f32) {
+  let mut svt = 0.0f32;
+  let mut stt = 0.0f32;
+  for k in bins {
+    let tv = template.eval(times[*k] - t0);
+    svt += voltages[*k]*tv;
+    stt += tv*tv;
+  }
+  if stt <= 0.0 {
+    return (0.0, f32::MAX);
+  }
+  let amplitude = svt/stt;
+  let mut chi2  = 0.0f32;
+  for k in bins {
+    let res = voltages[*k] - amplitude*template.eval(times[*k] - t0);
+    chi2 += res*res;
+  }
+  (amplitude, chi2/(noise*noise))
+}
+
+/// Fit amplitude and time of a pedestal subtracted
+/// pulse with a template
+///
+/// The time is scanned in steps of the template
+/// sample spacing around the start value and refined
+/// with a parabola through the χ² minimum. For each
+/// time, the amplitude is given analytically.
+///
+/// # Arguments:
+///   * t_guess      : start value for the time, e.g.
+///     from cfd_simple
+///   * search_range : scan t_guess +- search_range
+///   * noise        : voltage uncertainty of a bin
+///     (e.g. the pedestal RMS)
+///   * saturation   : bins at or above this voltage
+///     are excluded from the fit
+pub fn fit_template(voltages     : &[f32],
+                    times        : &[f32],
+                    template     : &PulseTemplate,
+                    t_guess      : f32,
+                    search_range : f32,
+                    noise        : f32,
+                    saturation   : f32) -> Result<TemplateFitResult, WaveformError> {
+  if template.values.len() < 2 || template.dt <= 0.0 {
+    return Err(WaveformError::FitFailed);
+  }
+  let noise = if noise > 0.0 { noise } else { 1.0 };
+  let t_lo  = t_guess - search_range + template.t_start;
+  let t_hi  = t_guess + search_range + template.get_t_end();
+  let mut result = TemplateFitResult::new();
+  let mut bins   = Vec::<usize>::new();
+  for k in 0..times.len().min(voltages.len()) {
+    if times[k] < t_lo || times[k] > t_hi {
+      continue;
+    }
+    if voltages[k] >= saturation {
+      result.saturated = true;
+      continue;
+    }
+    bins.push(k);
+  }
+  if bins.len() < 3 {
+    return Err(WaveformError::FitFailed);
+  }
+  let n_steps = (search_range/template.dt).ceil() as i32;
+  let mut best = (0.0f32, f32::MAX, t_guess);
+  let mut chi2_at = Vec::<(f32,f32)>::new();
+  for step in -n_steps..=n_steps {
+    let t0 = t_guess + step as f32*template.dt;
+    let (amp, chi2) = template_chi2(voltages, times, &bins, template, t0, noise);
+    chi2_at.push((t0, chi2));
+    if chi2 < best.1 {
+      best = (amp, chi2, t0);
+    }
+  }
+  if best.1 == f32::MAX {
+    return Err(WaveformError::FitFailed);
+  }
+  // parabola through the minimum and its neighbours
+  let idx = chi2_at.iter().position(|x| x.0 == best.2).unwrap_or(0);
+  let mut t_best = best.2;
+  if idx > 0 && idx + 1 < chi2_at.len() {
+    let (c_l, c_m, c_r) = (chi2_at[idx-1].1, chi2_at[idx].1, chi2_at[idx+1].1);
+    let denom = c_l - 2.0*c_m + c_r;
+    if denom > 0.0 {
+      t_best += 0.5*template.dt*(c_l - c_r)/denom;
+    }
+  }
+  let (amp, chi2) = template_chi2(voltages, times, &bins, template, t_best, noise);
+  if chi2 <= best.1 {
+    result.amplitude = amp;
+    result.chi2      = chi2;
+    result.time      = t_best;
+  } else {
+    result.amplitude = best.0;
+    result.chi2      = best.1;
+    result.time      = best.2;
+  }
+  result.ndof = (bins.len() - 2) as u16;
+  Ok(result)
+}
+
+#[cfg(test)]
+fn test_pulse(t : f32, t0 : f32, amplitude : f32) -> f32 {
+  // simple pulse shape with a fast rise and
+  // a slower exponential decay
+  let tau = t - t0;
+  if tau < 0.0 {
+    return 0.0;
+  }
+  amplitude*(1.0 - (-tau/1.5).exp())*(-tau/8.0).exp()/0.66
+}
+
+#[test]
+fn build_and_fit_template() {
+  let times : Vec<f32> = (0..1024).map(|k| k as f32*0.5).collect();
+  // the templates are aligned at the reference time,
+  // for a fixed shape the onset is a fine reference
+  let mut builder = PulseTemplateBuilder::new(0.25, -5.0, 200);
+  for k in 0..10 {
+    let t0 = 200.0 + 0.1*k as f32;
+    let volts : Vec<f32> = times.iter().map(|t| test_pulse(*t, t0, 100.0)).collect();
+    let amp = volts.iter().cloned().fold(f32::MIN, f32::max);
+    assert!(builder.add_pulse(&volts, &times, t0, amp));
+  }
+  let template = builder.finish().unwrap();
+  assert_eq!(template.n_pulses, 10);
+  assert!((template.values.iter().cloned().fold(f32::MIN, f32::max) - 1.0).abs() < 1e-6);
+
+  // fit a pulse with a different amplitude and time
+  let volts : Vec<f32> = times.iter().map(|t| test_pulse(*t, 250.3, 300.0)).collect();
+  let peak   = volts.iter().cloned().fold(f32::MIN, f32::max);
+  let result = fit_template(&volts, &times, &template, 249.0, 3.0, 1.0, PULSE_SATURATION_MV).unwrap();
+  assert!((result.time - 250.3).abs() < 0.1);
+  assert!((result.amplitude/peak - 1.0).abs() < 0.05);
+  assert!(!result.saturated);
+
+  // saturated pulse, the amplitude is still recovered
+  let volts : Vec<f32> = times.iter().map(|t| test_pulse(*t, 250.3, 1500.0)).collect();
+  let peak   = volts.iter().cloned().fold(f32::MIN, f32::max);
+  assert!(peak > PULSE_SATURATION_MV);
+  let volts : Vec<f32> = volts.iter().map(|v| v.min(PULSE_SATURATION_MV + 10.0)).collect();
+  let result = fit_template(&volts, &times, &template, 249.0, 3.0, 1.0, PULSE_SATURATION_MV).unwrap();
+  assert!(result.saturated);
+  assert!((result.amplitude/peak - 1.0).abs() < 0.1);
+}
@@ -0,0 +1,754 @@
+//! TOF only event reconstruction
+//!
+//! Fit a straight track to the hits of a TofEventSummary
+//! and reconstruct the velocity (β) of the particle from
+//! the time of flight between the outer TOF (umbrella
+//! and cortina) and the inner TOF (cube).
+//!
+//! The paddle information needs to be set for the
+//! event (TofEventSummary::set_paddles), since the
+//! hit positions are derived from the paddle geometry.
+//! All positions are in mm, all times in ns.
+//!
+//! With the track direction, the energy deposition
+//! of the hits can be corrected for the path length
+//! through the paddles to estimate the charge (Z)
+//! of the particle (ChargeEstimator).
+
+use std::fmt;
+use std::collections::HashMap;
+
+use crate::constants::{
+  C_LIGHT_VACUUM,
+  MAX_PID_CUBE,
+};
+use crate::errors::ReconstructionError;
+use crate::serialization::{
+  Serialization,
+  SerializationError,
+  parse_u8,
+  parse_u16,
+  parse_f32,
+};
+use crate::events::{
+  TofEventSummary,
+  TofHit,
+};
+#[cfg(feature="database")]
+use crate::database::Paddle;
+#[cfg(feature="random")]
+use crate::FromRandom;
+#[cfg(feature="random")]
+use rand::Rng;
+
+/// The result of the straight line fit
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TofTrack {
+  /// A point on the track (the centroid of the
+  /// hits)
+  pub origin     : [f32;3],
+  /// Unit vector along the track, pointing in
+  /// the direction of flight
+  pub direction  : [f32;3],
+  /// Velocity in units of c, from the time of
+  /// flight between outer and inner TOF
+  pub beta       : f32,
+  pub beta_err   : f32,
+  /// Spatial χ² (distance of the hits from the
+  /// track)
+  pub chi2_pos   : f32,
+  /// Timing χ² (hit times with respect to the
+  /// times expected from beta)
+  pub chi2_time  : f32,
+  /// Degrees of freedom of chi2_pos + chi2_time
+  pub ndof       : u16,
+  pub n_hits     : u8,
+  pub n_outer    : u8,
+  pub n_inner    : u8,
+}
+
+impl TofTrack {
+
+  pub fn new() -> Self {
+    Self {
+      origin     : [0.0;3],
+      direction  : [0.0, 0.0, -1.0],
+      beta       : f32::NAN,
+      beta_err   : f32::NAN,
+      chi2_pos   : f32::NAN,
+      chi2_time  : f32::NAN,
+      ndof       : 0,
+      n_hits     : 0,
+      n_outer    : 0,
+      n_inner    : 0,
+    }
+  }
+
+  /// χ²/ndof of the combined spatial and timing χ²
+  pub fn get_reduced_chi2(&self) -> f32 {
+    if self.ndof == 0 {
+      return f32::NAN;
+    }
+    (self.chi2_pos + self.chi2_time)/(self.ndof as f32)
+  }
+
+  /// The particle is going downwards
+  pub fn is_downgoing(&self) -> bool {
+    self.direction[2] < 0.0
+  }
+
+  /// Zenith angle (deg) of the incoming particle,
+  /// 0 for a vertically downgoing particle
+  pub fn get_zenith(&self) -> f32 {
+    (-self.direction[2]).clamp(-1.0, 1.0).acos().to_degrees()
+  }
+
+  /// Azimuth angle (deg) of the direction of flight
+  /// in the x-y plane, measured from the x-axis
+  pub fn get_azimuth(&self) -> f32 {
+    self.direction[1].atan2(self.direction[0]).to_degrees().rem_euclid(360.0)
+  }
+
+  /// Position of the track at a given z
+  ///
+  /// Returns None for horizontal tracks
+  pub fn get_pos_at_z(&self, z : f32) -> Option<[f32;3]> {
+    if self.direction[2] == 0.0 {
+      return None;
+    }
+    let s = (z - self.origin[2])/self.direction[2];
+    Some([self.origin[0] + s*self.direction[0],
+          self.origin[1] + s*self.direction[1],
+          z])
+  }
+}
+
+impl Default for TofTrack {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Serialization for TofTrack {
+  const HEAD : u16 = 0xAAAA;
+  const TAIL : u16 = 0x5555;
+  const SIZE : usize = 49;
+
+  fn from_bytestream(stream : &Vec<u8>,
+                     pos    : &mut usize)
+    -> Result<Self, SerializationError> {
+    Self::verify_fixed(stream, pos)?;
+    let mut track = TofTrack::new();
+    for k in 0..3 {
+      track.origin[k]    = parse_f32(stream, pos);
+    }
+    for k in 0..3 {
+      track.direction[k] = parse_f32(stream, pos);
+    }
+    track.beta           = parse_f32(stream, pos);
+    track.beta_err       = parse_f32(stream, pos);
+    track.chi2_pos       = parse_f32(stream, pos);
+    track.chi2_time      = parse_f32(stream, pos);
+    track.ndof           = parse_u16(stream, pos);
+    track.n_hits         = parse_u8(stream, pos);
+    track.n_outer        = parse_u8(stream, pos);
+    track.n_inner        = parse_u8(stream, pos);
+    *pos += 2;
+    Ok(track)
+  }
+
+  fn to_bytestream(&self) -> Vec<u8> {
+    let mut bs = Vec::<u8>::with_capacity(Self::SIZE);
+    bs.extend_from_slice(&Self::HEAD.to_le_bytes());
+    for k in 0..3 {
+      bs.extend_from_slice(&self.origin[k].to_le_bytes());
+    }
+    for k in 0..3 {
+      bs.extend_from_slice(&self.direction[k].to_le_bytes());
+    }
+    bs.extend_from_slice(&self.beta.to_le_bytes());
+    bs.extend_from_slice(&self.beta_err.to_le_bytes());
+    bs.extend_from_slice(&self.chi2_pos.to_le_bytes());
+    bs.extend_from_slice(&self.chi2_time.to_le_bytes());
+    bs.extend_from_slice(&self.ndof.to_le_bytes());
+    bs.push(self.n_hits);
+    bs.push(self.n_outer);
+    bs.push(self.n_inner);
+    bs.extend_from_slice(&Self::TAIL.to_le_bytes());
+    bs
+  }
+}
+
+#[cfg(feature="random")]
+impl FromRandom for TofTrack {
+  fn from_random() -> Self {
+    let mut rng = rand::thread_rng();
+    Self {
+      origin     : [rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>()],
+      direction  : [rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>()],
+      beta       : rng.gen::<f32>(),
+      beta_err   : rng.gen::<f32>(),
+      chi2_pos   : rng.gen::<f32>(),
+      chi2_time  : rng.gen::<f32>(),
+      ndof       : rng.gen::<u16>(),
+      n_hits     : rng.gen::<u8>(),
+      n_outer    : rng.gen::<u8>(),
+      n_inner    : rng.gen::<u8>(),
+    }
+  }
+}
+
+impl fmt::Display for TofTrack {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<TofTrack:");
+    repr += &(format!("\n  origin      : {:.1} {:.1} {:.1} [mm]", self.origin[0], self.origin[1], self.origin[2]));
+    repr += &(format!("\n  direction   : {:.3} {:.3} {:.3}", self.direction[0], self.direction[1], self.direction[2]));
+    repr += &(format!("\n  zenith      : {:.2} [deg]", self.get_zenith()));
+    repr += &(format!("\n  beta        : {:.3} +- {:.3}", self.beta, self.beta_err));
+    repr += &(format!("\n  chi2 (pos)  : {:.2}", self.chi2_pos));
+    repr += &(format!("\n  chi2 (time) : {:.2}", self.chi2_time));
+    repr += &(format!("\n  ndof        : {}", self.ndof));
+    repr += &(format!("\n  n hits      : {} (outer {}, inner {})>", self.n_hits, self.n_outer, self.n_inner));
+    write!(f, "{}", repr)
+  }
+}
+
+/// Straight line fit of TOF hits
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TofTrackFitter {
+  /// Position resolution of a hit (mm)
+  pub sigma_pos    : f32,
+  /// Time resolution of a hit (ns)
+  pub sigma_time   : f32,
+  /// Ignore hits which don't obey causality
+  /// (see TofHit::obeys_causality)
+  pub only_causal  : bool,
+}
+
+impl TofTrackFitter {
+
+  pub fn new() -> Self {
+    Self {
+      sigma_pos   : 50.0,
+      sigma_time  : 0.5,
+      only_causal : true,
+    }
+  }
+
+  fn is_outer(hit : &TofHit) -> bool {
+    hit.paddle_id > MAX_PID_CUBE
+  }
+
+  /// Fit a straight track to the hits of an event
+  /// and reconstruct beta
+  pub fn fit(&self, event : &TofEventSummary) -> Result<TofTrack, ReconstructionError> {
+    if !event.paddles_set {
+      return Err(ReconstructionError::PaddlesNotSet);
+    }
+    let hits : Vec<&TofHit> = event.hits.iter()
+      .filter(|h| !self.only_causal || h.obeys_causality())
+      .filter(|h| h.get_t0().is_finite() && h.x.is_finite() && h.y.is_finite() && h.z.is_finite())
+      .collect();
+    if hits.len() < 2 {
+      return Err(ReconstructionError::NotEnoughHits);
+    }
+    let n_outer = hits.iter().filter(|h| Self::is_outer(h)).count();
+    let n_inner = hits.len() - n_outer;
+    if n_outer == 0 {
+      return Err(ReconstructionError::NoOuterHits);
+    }
+    if n_inner == 0 {
+      return Err(ReconstructionError::NoInnerHits);
+    }
+    let pos   : Vec<[f32;3]> = hits.iter().map(|h| [h.x, h.y, h.z]).collect();
+    let times : Vec<f32>     = hits.iter().map(|h| h.get_t0()).collect();
+    let n     = hits.len() as f32;
+
+    // the centroid is on the track
+    let mut origin = [0.0f32;3];
+    for p in &pos {
+      for k in 0..3 {
+        origin[k] += p[k]/n;
+      }
+    }
+    let mut cov = [[0.0f32;3];3];
+    for p in &pos {
+      for i in 0..3 {
+        for j in 0..3 {
+          cov[i][j] += (p[i] - origin[i])*(p[j] - origin[j]);
+        }
+      }
+    }
+    // the direction is the principal axis of the
+    // hit distribution, which we get by power
+    // iteration, starting from the line connecting
+    // the earliest and the latest hit
+    let first = (0..hits.len()).min_by(|a,b| times[*a].total_cmp(&times[*b])).unwrap();
+    let last  = (0..hits.len()).max_by(|a,b| times[*a].total_cmp(&times[*b])).unwrap();
+    let mut dir = [pos[last][0] - pos[first][0],
+                   pos[last][1] - pos[first][1],
+                   pos[last][2] - pos[first][2]];
+    if norm(&dir) == 0.0 {
+      return Err(ReconstructionError::DegenerateTrack);
+    }
+    for _ in 0..50 {
+      let mut next = [0.0f32;3];
+      for i in 0..3 {
+        for j in 0..3 {
+          next[i] += cov[i][j]*dir[j];
+        }
+      }
+      let len = norm(&next);
+      if len == 0.0 {
+        break;
+      }
+      dir = [next[0]/len, next[1]/len, next[2]/len];
+    }
+    let len = norm(&dir);
+    dir     = [dir[0]/len, dir[1]/len, dir[2]/len];
+
+    // path length along the track
+    let mut path : Vec<f32> = pos.iter().map(|p| dot(&sub(p, &origin), &dir)).collect();
+    // orient the track along the time flow
+    let mut cov_st = 0.0f32;
+    let t_mean     = times.iter().sum::<f32>()/n;
+    for k in 0..hits.len() {
+      cov_st += path[k]*(times[k] - t_mean);
+    }
+    if cov_st < 0.0 {
+      dir  = [-dir[0], -dir[1], -dir[2]];
+      path = path.iter().map(|s| -s).collect();
+    }
+
+    let mut track    = TofTrack::new();
+    track.origin     = origin;
+    track.direction  = dir;
+    track.n_hits     = hits.len() as u8;
+    track.n_outer    = n_outer as u8;
+    track.n_inner    = n_inner as u8;
+
+    // spatial chi2 - each hit constrains the
+    // track in 2 dimensions, the track has 4
+    // parameters
+    let mut chi2_pos = 0.0f32;
+    for k in 0..hits.len() {
+      let d    = sub(&pos[k], &origin);
+      let perp = sub(&d, &[path[k]*dir[0], path[k]*dir[1], path[k]*dir[2]]);
+      chi2_pos += dot(&perp, &perp)/(self.sigma_pos*self.sigma_pos);
+    }
+    track.chi2_pos = chi2_pos;
+
+    // beta from the mean position and time of
+    // the hits in the outer and inner TOF
+    let mut s_outer = 0.0f32;
+    let mut t_outer = 0.0f32;
+    let mut s_inner = 0.0f32;
+    let mut t_inner = 0.0f32;
+    for k in 0..hits.len() {
+      if Self::is_outer(hits[k]) {
+        s_outer += path[k]/(n_outer as f32);
+        t_outer += times[k]/(n_outer as f32);
+      } else {
+        s_inner += path[k]/(n_inner as f32);
+        t_inner += times[k]/(n_inner as f32);
+      }
+    }
+    let delta_s = s_inner - s_outer;
+    let delta_t = t_inner - t_outer;
+    if delta_t == 0.0 {
+      return Err(ReconstructionError::DegenerateTrack);
+    }
+    track.beta     = delta_s/(C_LIGHT_VACUUM*delta_t);
+    let delta_t_err = self.sigma_time*f32::sqrt(1.0/(n_outer as f32) + 1.0/(n_inner as f32));
+    track.beta_err = f32::abs(track.beta*delta_t_err/delta_t);
+
+    // timing chi2 - the only free parameter is
+    // the time at the origin
+    let inv_v     = 1.0/(track.beta*C_LIGHT_VACUUM);
+    let t_origin  = times.iter().zip(&path).map(|(t,s)| t - s*inv_v).sum::<f32>()/n;
+    let mut chi2_time = 0.0f32;
+    for k in 0..hits.len() {
+      let res    = times[k] - t_origin - path[k]*inv_v;
+      chi2_time += res*res/(self.sigma_time*self.sigma_time);
+    }
+    track.chi2_time = chi2_time;
+    // 2n - 4 (spatial) + n - 2 (time, beta and t_origin)
+    track.ndof      = (3*hits.len()).saturating_sub(6) as u16;
+    Ok(track)
+  }
+}
+
+impl Default for TofTrackFitter {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for TofTrackFitter {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<TofTrackFitter:");
+    repr += &(format!("\n  sigma pos   : {} [mm]", self.sigma_pos));
+    repr += &(format!("\n  sigma time  : {} [ns]", self.sigma_time));
+    repr += &(format!("\n  only causal : {}>", self.only_causal));
+    write!(f, "{}", repr)
+  }
+}
+
+/// Most probable energy loss of a minimum ionizing
+/// particle in the TOF scintillator (MeV/mm)
+pub const MIP_DEDX : f32 = 0.2;
+
+/// Geometry of a paddle as needed for the path
+/// length correction
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PaddleGeometry {
+  /// Thickness of the paddle (mm)
+  pub thickness : f32,
+  /// Unit vector normal to the paddle face
+  pub normal    : [f32;3],
+}
+
+impl PaddleGeometry {
+
+  #[cfg(feature="database")]
+  pub fn from_paddle(paddle : &Paddle) -> Self {
+    let normal = [paddle.normal_x, paddle.normal_y, paddle.normal_z];
+    let len    = norm(&normal);
+    Self {
+      // the database has the dimensions in cm
+      thickness : paddle.height*10.0,
+      normal    : [normal[0]/len, normal[1]/len, normal[2]/len],
+    }
+  }
+
+  /// The path length (mm) of a track with the
+  /// given direction through the paddle
+  ///
+  /// The cosine of the incident angle is limited
+  /// to min_cos, to avoid diverging path lengths
+  /// for grazing tracks.
+  pub fn get_path_length(&self, direction : &[f32;3], min_cos : f32) -> f32 {
+    let cos = f32::abs(dot(direction, &self.normal)).max(min_cos);
+    self.thickness/cos
+  }
+}
+
+/// The result of the charge estimation
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ChargeEstimate {
+  /// Estimated charge
+  pub z         : f32,
+  pub z_err     : f32,
+  /// Truncated mean of the path length corrected
+  /// energy deposition (MeV/mm)
+  pub dedx_mean : f32,
+  /// Number of hits used for the truncated mean
+  pub n_used    : u8,
+  /// Number of hits with a valid dE/dx
+  pub n_hits    : u8,
+}
+
+impl ChargeEstimate {
+
+  pub fn new() -> Self {
+    Self {
+      z         : f32::NAN,
+      z_err     : f32::NAN,
+      dedx_mean : f32::NAN,
+      n_used    : 0,
+      n_hits    : 0,
+    }
+  }
+}
+
+impl Default for ChargeEstimate {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for ChargeEstimate {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<ChargeEstimate:");
+    repr += &(format!("\n  Z          : {:.2} +- {:.2}", self.z, self.z_err));
+    repr += &(format!("\n  dE/dx      : {:.4} [MeV/mm]", self.dedx_mean));
+    repr += &(format!("\n  hits used  : {}/{}>", self.n_used, self.n_hits));
+    write!(f, "{}", repr)
+  }
+}
+
+/// Estimate the charge of a particle from the
+/// energy deposition of its TOF hits
+///
+/// The energy deposition of each hit is corrected
+/// for the path length through the paddle, using
+/// the direction of a reconstructed TofTrack, and
+/// for the gain of the individual paddle. The hits
+/// are combined into a truncated mean of dE/dx,
+/// which is robust against the Landau tail and
+/// δ-rays. Z is then estimated by assuming
+/// dE/dx ~ Z²/β² (below beta_mip) relative to a
+/// minimum ionizing particle.
+#[derive(Debug, Clone)]
+pub struct ChargeEstimator {
+  /// Paddle geometries, by paddle id
+  pub paddles       : HashMap<u8, PaddleGeometry>,
+  /// Relative gain of each paddle. The energy
+  /// deposition is divided by the gain. Paddles
+  /// without gain constant have a gain of 1.
+  pub gains         : HashMap<u8, f32>,
+  /// dE/dx of a minimum ionizing particle (MeV/mm)
+  pub mip_dedx      : f32,
+  /// Velocity at which the energy loss is minimal.
+  /// Tracks with larger beta are not corrected.
+  pub beta_mip      : f32,
+  /// Fraction of the hits with the largest dE/dx
+  /// which get discarded for the truncated mean
+  pub truncation    : f32,
+  /// Lower limit for the cosine of the incident
+  /// angle (see PaddleGeometry::get_path_length)
+  pub min_cos       : f32,
+}
+
+impl ChargeEstimator {
+
+  pub fn new() -> Self {
+    Self {
+      paddles    : HashMap::<u8, PaddleGeometry>::new(),
+      gains      : HashMap::<u8, f32>::new(),
+      mip_dedx   : MIP_DEDX,
+      beta_mip   : 0.95,
+      truncation : 0.3,
+      min_cos    : 0.2,
+    }
+  }
+
+  /// Get the paddle geometries from the database
+  #[cfg(feature="database")]
+  pub fn set_paddles(&mut self, paddles : &HashMap<u8, Paddle>) {
+    for (pid, pdl) in paddles {
+      self.paddles.insert(*pid, PaddleGeometry::from_paddle(pdl));
+    }
+  }
+
+  pub fn set_gain(&mut self, paddle_id : u8, gain : f32) {
+    self.gains.insert(paddle_id, gain);
+  }
+
+  pub fn get_gain(&self, paddle_id : u8) -> f32 {
+    *self.gains.get(&paddle_id).unwrap_or(&1.0)
+  }
+
+  /// Path length and gain corrected energy 
+  /// deposition (MeV/mm) of a single hit
+  ///
+  /// Returns None if there is no geometry 
+  /// information for the paddle or the energy
+  /// deposition is not positive
+  pub fn get_dedx(&self, hit : &TofHit, track : &TofTrack) -> Option<f32> {
+    let geo  = self.paddles.get(&hit.paddle_id)?;
+    let edep = hit.get_edep()/self.get_gain(hit.paddle_id);
+    if !edep.is_finite() || edep <= 0.0 {
+      return None;
+    }
+    Some(edep/geo.get_path_length(&track.direction, self.min_cos))
+  }
+
+  /// Estimate the charge for an event with a
+  /// reconstructed track
+  pub fn estimate(&self, event : &TofEventSummary, track : &TofTrack) -> Result<ChargeEstimate, ReconstructionError> {
+    let mut dedx : Vec<f32> = event.hits.iter()
+      .filter_map(|h| self.get_dedx(h, track))
+      .collect();
+    if dedx.is_empty() {
+      return Err(ReconstructionError::NotEnoughHits);
+    }
+    dedx.sort_by(|a, b| a.total_cmp(b));
+    let n_hits = dedx.len();
+    let n_drop = (self.truncation.clamp(0.0, 1.0)*n_hits as f32).floor() as usize;
+    let n_used = (n_hits - n_drop).max(1);
+    let used   = &dedx[0..n_used];
+    let mean   = used.iter().sum::<f32>()/(n_used as f32);
+    let mut var = 0.0f32;
+    for d in used {
+      var += (d - mean)*(d - mean);
+    }
+    // for a single hit, assume a Landau like
+    // relative width
+    let mean_err = if n_used > 1 {
+      f32::sqrt(var/((n_used - 1) as f32)/(n_used as f32))
+    } else {
+      0.3*mean
+    };
+    let mut beta_corr = 1.0;
+    if track.beta.is_finite() && track.beta > 0.0 && track.beta < self.beta_mip {
+      beta_corr = track.beta/self.beta_mip;
+    }
+    let mut estimate    = ChargeEstimate::new();
+    estimate.dedx_mean  = mean;
+    estimate.n_hits     = n_hits as u8;
+    estimate.n_used     = n_used as u8;
+    estimate.z          = beta_corr*f32::sqrt(mean/self.mip_dedx);
+    estimate.z_err      = 0.5*estimate.z*mean_err/mean;
+    Ok(estimate)
+  }
+}
+
+impl Default for ChargeEstimator {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for ChargeEstimator {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<ChargeEstimator:");
+    repr += &(format!("\n  paddles    : {}", self.paddles.len()));
+    repr += &(format!("\n  gains      : {}", self.gains.len()));
+    repr += &(format!("\n  MIP dE/dx  : {} [MeV/mm]", self.mip_dedx));
+    repr += &(format!("\n  beta MIP   : {}", self.beta_mip));
+    repr += &(format!("\n  truncation : {}", self.truncation));
+    repr += &(format!("\n  min cos    : {}>", self.min_cos));
+    write!(f, "{}", repr)
+  }
+}
+
+pub(crate) fn sub(a : &[f32;3], b : &[f32;3]) -> [f32;3] {
+  [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub(crate) fn dot(a : &[f32;3], b : &[f32;3]) -> f32 {
+  a[0]*b[0] + a[1]*b[1] + a[2]*b[2]
+}
+
+pub(crate) fn norm(a : &[f32;3]) -> f32 {
+  dot(a, a).sqrt()
+}
+
+#[cfg(feature = "random")]
+#[test]
+fn serialization_toftrack() {
+  for _ in 0..100 {
+    let track = TofTrack::from_random();
+    let test  = TofTrack::from_bytestream(&track.to_bytestream(), &mut 0).unwrap();
+    assert_eq!(track, test);
+  }
+}
+
+#[cfg(test)]
+fn make_hit(pid : u8, pos : [f32;3], t0 : f32) -> TofHit {
+  // hit in the center of a 1 m long paddle
+  let mut hit    = TofHit::new();
+  hit.paddle_id  = pid;
+  hit.paddle_len = 1000.0;
+  hit.cable_len  = 0.0;
+  hit.x          = pos[0];
+  hit.y          = pos[1];
+  hit.z          = pos[2];
+  let t_end      = t0 + 0.5*hit.paddle_len/(10.0*crate::constants::C_LIGHT_PADDLE);
+  hit.set_time_a(t_end);
+  hit.set_time_b(t_end);
+  hit
+}
+
+#[test]
+fn fit_vertical_track() {
+  let mut event = TofEventSummary::new();
+  // umbrella at 1500 mm, cube top at 900 mm and
+  // cube bottom at 0. beta = 0.5
+  let v = 0.5*C_LIGHT_VACUUM;
+  for (pid, z) in [(70u8, 1500.0f32), (10, 900.0), (1, 0.0)] {
+    event.hits.push(make_hit(pid, [100.0, -50.0, z], 10.0 + (1500.0 - z)/v));
+  }
+  event.paddles_set = true;
+  let fitter = TofTrackFitter::new();
+  let track  = fitter.fit(&event).unwrap();
+  assert!(track.is_downgoing());
+  assert!(track.get_zenith() < 0.1);
+  assert!((track.beta - 0.5).abs() < 0.01);
+  assert!(track.chi2_pos < 1e-3);
+  assert_eq!(track.n_outer, 1);
+  assert_eq!(track.n_inner, 2);
+  assert_eq!(track.ndof, 3);
+  let pos = track.get_pos_at_z(450.0).unwrap();
+  assert!((pos[0] - 100.0).abs() < 0.1);
+  assert!((pos[1] + 50.0).abs() < 0.1);
+}
+
+#[test]
+fn fit_inclined_upgoing_track() {
+  let mut event = TofEventSummary::new();
+  // particle coming from below, 45 deg in x-z
+  let v   = 0.9*C_LIGHT_VACUUM;
+  let dir = [f32::sqrt(0.5), 0.0, f32::sqrt(0.5)];
+  for (pid, s) in [(5u8, 0.0f32), (20, 600.0), (80, 2000.0)] {
+    event.hits.push(make_hit(pid, [s*dir[0], 0.0, s*dir[2]], 5.0 + s/v));
+  }
+  event.paddles_set = true;
+  let track = TofTrackFitter::new().fit(&event).unwrap();
+  assert!(!track.is_downgoing());
+  assert!((track.direction[0] - dir[0]).abs() < 1e-3);
+  assert!((track.beta - 0.9).abs() < 0.05);
+}
+
+#[test]
+fn fit_errors() {
+  let fitter    = TofTrackFitter::new();
+  let mut event = TofEventSummary::new();
+  assert_eq!(fitter.fit(&event).unwrap_err(), ReconstructionError::PaddlesNotSet);
+  event.paddles_set = true;
+  event.hits.push(make_hit(1, [0.0, 0.0, 0.0], 10.0));
+  assert_eq!(fitter.fit(&event).unwrap_err(), ReconstructionError::NotEnoughHits);
+  event.hits.push(make_hit(2, [0.0, 0.0, 100.0], 11.0));
+  assert_eq!(fitter.fit(&event).unwrap_err(), ReconstructionError::NoOuterHits);
+}
+
+#[test]
+fn charge_estimate() {
+  let mut estimator = ChargeEstimator::new();
+  let geo = PaddleGeometry {
+    thickness : 6.35,
+    normal    : [0.0, 0.0, 1.0],
+  };
+  for pid in [1u8, 10, 70, 80] {
+    estimator.paddles.insert(pid, geo);
+  }
+  let mut track = TofTrack::new();
+  // 60 deg zenith, path length is twice the thickness
+  track.direction = [f32::sqrt(0.75), 0.0, -0.5];
+  track.beta      = 0.99;
+  assert!((geo.get_path_length(&track.direction, estimator.min_cos) - 12.7).abs() < 1e-3);
+
+  // a Z=2 particle deposits 4 times the MIP energy
+  let edep_he   = 4.0*MIP_DEDX*12.7;
+  let mut event = TofEventSummary::new();
+  for pid in [1u8, 10, 70] {
+    let mut hit = make_hit(pid, [0.0, 0.0, 0.0], 10.0);
+    // get_edep is linear in the peak heights
+    let peak = edep_he*34.3/1.29;
+    hit.set_peak_a(peak);
+    hit.set_peak_b(peak);
+    event.hits.push(hit);
+  }
+  // a δ-ray, which gets truncated
+  let mut hit = make_hit(80, [0.0, 0.0, 0.0], 10.0);
+  hit.set_peak_a(20.0*edep_he*34.3/1.29);
+  hit.set_peak_b(20.0*edep_he*34.3/1.29);
+  event.hits.push(hit);
+  let charge = estimator.estimate(&event, &track).unwrap();
+  assert_eq!(charge.n_hits, 4);
+  assert_eq!(charge.n_used, 3);
+  assert!((charge.z - 2.0).abs() < 0.05);
+
+  // a gain of 4 in all paddles looks like Z=1
+  for pid in [1u8, 10, 70, 80] {
+    estimator.set_gain(pid, 4.0);
+  }
+  let charge = estimator.estimate(&event, &track).unwrap();
+  assert!((charge.z - 1.0).abs() < 0.05);
+
+  // slow particles deposit more energy
+  track.beta = 0.475;
+  let charge = estimator.estimate(&event, &track).unwrap();
+  assert!((charge.z - 0.5).abs() < 0.05);
+}
@@ -19,6 +19,7 @@ use crate::monitoring::{
     PBMoniData,
     MtbMoniData, 
     CPUMoniData,
+    RBChannelNoiseMoniData,
 };
 
 #[cfg(feature = "polars")]
@@ -426,3 +427,47 @@ impl MoniSeries<CPUMoniData> for CPUMoniDataSeries {
 }
 
 
+
+////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RBChannelNoiseMoniDataSeries {
+  data        : HashMap<u8, VecDeque<RBChannelNoiseMoniData>>,
+  max_size    : usize,
+}
+
+impl RBChannelNoiseMoniDataSeries {
+  pub fn new() -> Self {
+    Self {
+      data     : HashMap::<u8, VecDeque<RBChannelNoiseMoniData>>::new(),
+      max_size : 10000,
+    }
+  }
+} 
+
+impl Default for RBChannelNoiseMoniDataSeries {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for RBChannelNoiseMoniDataSeries {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "<RBChannelNoiseMoniDataSeries : {} boards>", self.data.len())
+  }
+}
+
+impl MoniSeries<RBChannelNoiseMoniData> for RBChannelNoiseMoniDataSeries {
+
+  fn get_data(&self) -> &HashMap<u8,VecDeque<RBChannelNoiseMoniData>> {
+    return &self.data;
+  }
+
+  fn get_data_mut(&mut self) -> &mut HashMap<u8,VecDeque<RBChannelNoiseMoniData>> {
+    return &mut self.data;
+  }
+ 
+  fn get_max_size(&self) -> usize {
+    return self.max_size;
+  }
+}
@@ -0,0 +1,762 @@
+//! Paddle timing calibration with cosmic muons
+//!
+//! The hit times at the paddle ends contain a delay
+//! due to the Harting cables and the electronics, which
+//! is different for each paddle end. Together with the
+//! effective speed of light in the paddle, these delays
+//! determine the interaction time (TofHit::get_t0) and
+//! the position along the paddle (TofHit::get_pos).
+//!
+//! The TimingCalibrator fits these constants to a run
+//! of cosmic muons. For each event, a straight track is
+//! fit to the positions of the hits across the paddles,
+//! and the crossing point of the track with each hit 
+//! paddle is calculated. Then
+//!
+//! * the time difference of the two paddle ends is
+//!   linear in the crossing position along the paddle,
+//!   with a slope of 2/c_eff and an intercept of
+//!   offset_a - offset_b
+//! * the mean time of the two paddle ends, corrected for
+//!   the flight path of the muon (β = 1), differs from
+//!   the event average by (offset_a + offset_b)/2
+//!
+//! Since the event times depend on the offsets of all
+//! hit paddles, this is iterated. Only relative offsets
+//! can be determined, the mean offset of all calibrated
+//! paddles is fixed to 0.
+//!
+//! The constants are written to a (versioned) JSON file.
+//! The analysis engine subtracts the offsets from the
+//! hit times (TimingCalibration::apply_offsets), the
+//! effective speed of light has to be set for the hits
+//! before the paddle information (TofHit::set_paddle),
+//! see TimingCalibration::set_light_speeds.
+//!
+//! All times are in ns, all positions in mm and the
+//! effective speed of light in cm/ns (like C_LIGHT_PADDLE).
+
+use std::fmt;
+use std::collections::HashMap;
+
+use serde::{
+  Serialize,
+  Deserialize,
+};
+
+use crate::constants::{
+  C_LIGHT_PADDLE,
+  C_LIGHT_VACUUM,
+};
+use crate::calibration_file::{
+  CalibrationHeader,
+  JsonFile,
+};
+use crate::errors::ReconstructionError;
+use crate::events::{
+  TofEventSummary,
+  TofHit,
+};
+use crate::reconstruction::{
+  sub,
+  dot,
+  norm,
+};
+#[cfg(feature="database")]
+use crate::database::Paddle;
+
+/// Timing constants for a single paddle
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaddleTimingConstants {
+  /// Delay of the A side, gets subtracted from
+  /// the A side hit time
+  pub offset_a     : f32,
+  /// Delay of the B side, gets subtracted from
+  /// the B side hit time
+  pub offset_b     : f32,
+  /// Effective speed of light in the paddle [cm/ns]
+  pub c_eff        : f32,
+  /// Number of hits the constants were derived from
+  pub n_entries    : u32,
+  /// RMS of the time residuals of the last iteration
+  pub residual_rms : f32,
+}
+
+impl PaddleTimingConstants {
+
+  pub fn new() -> Self {
+    Self {
+      offset_a     : 0.0,
+      offset_b     : 0.0,
+      c_eff        : C_LIGHT_PADDLE,
+      n_entries    : 0,
+      residual_rms : 0.0,
+    }
+  }
+}
+
+impl Default for PaddleTimingConstants {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for PaddleTimingConstants {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "<PaddleTimingConstants: offset A/B {:.3}/{:.3} [ns], c_eff {:.2} [cm/ns], {} entries, residual RMS {:.3} [ns]>",
+           self.offset_a,
+           self.offset_b,
+           self.c_eff,
+           self.n_entries,
+           self.residual_rms)
+  }
+}
+
+/// Timing constants for all paddles
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TimingCalibration {
+  /// Version, time of creation and description
+  #[serde(flatten)]
+  pub header      : CalibrationHeader,
+  /// Constants by paddle id
+  pub constants   : HashMap<u8, PaddleTimingConstants>,
+}
+
+impl TimingCalibration {
+
+  pub fn new() -> Self {
+    Self {
+      header      : CalibrationHeader::new(),
+      constants   : HashMap::<u8, PaddleTimingConstants>::new(),
+    }
+  }
+
+  pub fn get(&self, paddle_id : u8) -> Option<&PaddleTimingConstants> {
+    self.constants.get(&paddle_id)
+  }
+
+  pub fn insert(&mut self, paddle_id : u8, constants : PaddleTimingConstants) {
+    self.constants.insert(paddle_id, constants);
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.constants.is_empty()
+  }
+
+  pub fn len(&self) -> usize {
+    self.constants.len()
+  }
+
+  /// Subtract the paddle end offsets from the hit
+  /// times. Hits of paddles without constants are
+  /// not changed.
+  pub fn apply_offsets(&self, hit : &mut TofHit) {
+    if let Some(c) = self.constants.get(&hit.paddle_id) {
+      hit.set_time_a(hit.get_time_a() - c.offset_a);
+      hit.set_time_b(hit.get_time_b() - c.offset_b);
+    }
+  }
+
+  /// Set the effective speed of light in the paddle
+  /// for the hit
+  pub fn set_light_speed(&self, hit : &mut TofHit) {
+    if let Some(c) = self.constants.get(&hit.paddle_id) {
+      hit.c_paddle = c.c_eff;
+    }
+  }
+
+  /// Set the effective speed of light for all hits
+  /// of the event
+  ///
+  /// This has to be done before the paddle information
+  /// gets set (TofEventSummary::set_paddles), since the
+  /// hit positions depend on it.
+  pub fn set_light_speeds(&self, event : &mut TofEventSummary) {
+    for h in event.hits.iter_mut() {
+      self.set_light_speed(h);
+    }
+  }
+
+  /// Combine with a correction, which was derived from
+  /// data to which this calibration had already been
+  /// applied. The offsets add up, the effective speeds
+  /// of light are taken from the correction.
+  pub fn combine(&self, correction : &TimingCalibration) -> TimingCalibration {
+    let mut combined         = self.clone();
+    combined.header.update(&correction.header);
+    for (pid, corr) in &correction.constants {
+      let c = combined.constants.entry(*pid).or_default();
+      c.offset_a     += corr.offset_a;
+      c.offset_b     += corr.offset_b;
+      c.c_eff         = corr.c_eff;
+      c.n_entries     = corr.n_entries;
+      c.residual_rms  = corr.residual_rms;
+    }
+    combined
+  }
+}
+
+impl JsonFile for TimingCalibration {}
+
+impl fmt::Display for TimingCalibration {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut pids : Vec<&u8> = self.constants.keys().collect();
+    pids.sort();
+    let mut repr = String::from("<TimingCalibration:");
+    repr += &(format!("\n  version     : {}", self.header.version));
+    repr += &(format!("\n  created     : {}", self.header.created));
+    repr += &(format!("\n  description : {}", self.header.description));
+    for pid in pids {
+      repr += &(format!("\n  {:3} : {}", pid, self.constants[pid]));
+    }
+    repr += ">";
+    write!(f, "{}", repr)
+  }
+}
+
+/// Geometry of a paddle as needed for the timing
+/// calibration
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PaddleAxis {
+  /// Center of the paddle (mm)
+  pub center : [f32;3],
+  /// Unit vector along the paddle, pointing
+  /// from the A to the B side
+  pub axis   : [f32;3],
+  /// Unit vector normal to the paddle face
+  pub normal : [f32;3],
+  /// Length of the paddle (mm)
+  pub length : f32,
+}
+
+impl PaddleAxis {
+
+  #[cfg(feature="database")]
+  pub fn from_paddle(paddle : &Paddle) -> Self {
+    let pr     = paddle.principal();
+    let normal = [paddle.normal_x, paddle.normal_y, paddle.normal_z];
+    let len    = norm(&normal);
+    // the database has the dimensions in cm
+    Self {
+      center : [paddle.global_pos_x_l0*10.0,
+                paddle.global_pos_y_l0*10.0,
+                paddle.global_pos_z_l0*10.0],
+      axis   : [pr.0, pr.1, pr.2],
+      normal : [normal[0]/len, normal[1]/len, normal[2]/len],
+      length : paddle.length*10.0,
+    }
+  }
+}
+
+/// Paddle end times of a hit, as they are
+/// needed for the calibration
+#[derive(Debug, Copy, Clone)]
+struct CalibrationHit {
+  paddle_id : u8,
+  time_a    : f32,
+  time_b    : f32,
+}
+
+/// Linear regression of the position along the 
+/// paddle (from the track) vs the time difference 
+/// of the paddle ends. 
+///
+/// The position from the track has the larger 
+/// uncertainty, so it is the dependent variable 
+/// (otherwise the slope would be biased).
+#[derive(Debug, Copy, Clone, Default)]
+struct DeltaTFit {
+  n    : u32,
+  s_x  : f64,
+  s_y  : f64,
+  s_xx : f64,
+  s_xy : f64,
+}
+
+impl DeltaTFit {
+  fn add(&mut self, x : f32, y : f32) {
+    self.n    += 1;
+    self.s_x  += x as f64;
+    self.s_y  += y as f64;
+    self.s_xx += (x as f64)*(x as f64);
+    self.s_xy += (x as f64)*(y as f64);
+  }
+
+  /// Variance of the time differences
+  fn var_x(&self) -> f64 {
+    let n = self.n as f64;
+    self.s_xx/n - (self.s_x/n).powi(2)
+  }
+
+  fn slope(&self) -> Option<f32> {
+    let n     = self.n as f64;
+    let denom = n*self.s_xx - self.s_x*self.s_x;
+    if self.n < 2 || denom <= 0.0 {
+      return None;
+    }
+    Some(((n*self.s_xy - self.s_x*self.s_y)/denom) as f32)
+  }
+}
+
+/// Derive the paddle timing constants from
+/// cosmic muon events
+///
+/// Add the events with add_event, then get the
+/// calibration with fit. All events are kept in
+/// memory (up to max_events), since the fit is
+/// iterative.
+///
+/// The tracks are straight lines parametrized in z, 
+/// (x,y) = (x0 + ax*z, y0 + ay*z), and the particles
+/// are assumed to be downgoing. The track fit only 
+/// uses the position of the hits across the paddles,
+/// since the position along the paddles depends on the 
+/// constants which get fit.
+#[derive(Debug, Clone)]
+pub struct TimingCalibrator {
+  /// Paddle geometries, by paddle id
+  pub paddles       : HashMap<u8, PaddleAxis>,
+  /// Assumed velocity of the particles (in
+  /// units of c)
+  pub beta          : f32,
+  pub n_iterations  : usize,
+  /// Minimum number of hits for a paddle to
+  /// get calibrated
+  pub min_entries   : u32,
+  /// Minimum number of hits for an event to
+  /// be used
+  pub min_hits      : usize,
+  /// Position resolution across the paddles (mm)
+  pub sigma_across  : f32,
+  /// Maximum χ² per hit of the track fit
+  pub max_chi2_pos  : f32,
+  /// Hits with a larger time residual [ns] are
+  /// ignored (after the first iteration)
+  pub max_residual  : f32,
+  /// Maximum number of events to keep
+  pub max_events    : usize,
+  events            : Vec<Vec<CalibrationHit>>,
+}
+
+impl TimingCalibrator {
+
+  pub fn new() -> Self {
+    Self {
+      paddles      : HashMap::<u8, PaddleAxis>::new(),
+      beta         : 1.0,
+      n_iterations : 20,
+      min_entries  : 50,
+      min_hits     : 4,
+      sigma_across : 50.0,
+      max_chi2_pos : 10.0,
+      max_residual : 3.0,
+      max_events   : 1000000,
+      events       : Vec::<Vec<CalibrationHit>>::new(),
+    }
+  }
+
+  /// Get the paddle geometries from the database
+  #[cfg(feature="database")]
+  pub fn set_paddles(&mut self, paddles : &HashMap<u8, Paddle>) {
+    for (pid, pdl) in paddles {
+      self.paddles.insert(*pid, PaddleAxis::from_paddle(pdl));
+    }
+  }
+
+  pub fn get_n_events(&self) -> usize {
+    self.events.len()
+  }
+
+  /// Add an event to the calibration sample
+  ///
+  /// Returns false if the event can't be used,
+  /// that is if it does not have enough hits 
+  /// in paddles with known geometry, or if the
+  /// sample is full.
+  pub fn add_event(&mut self, event : &TofEventSummary) -> bool {
+    if self.events.len() >= self.max_events {
+      return false;
+    }
+    let hits : Vec<CalibrationHit> = event.hits.iter()
+      .filter(|h| self.paddles.contains_key(&h.paddle_id))
+      .filter(|h| h.get_time_a().is_finite() && h.get_time_b().is_finite())
+      .map(|h| CalibrationHit {
+        paddle_id : h.paddle_id,
+        time_a    : h.get_time_a(),
+        time_b    : h.get_time_b()})
+      .collect();
+    if hits.len() < self.min_hits {
+      return false;
+    }
+    self.events.push(hits);
+    true
+  }
+
+  /// Fit a track to the hits of an event
+  ///
+  /// Returns the track parameters (x0, ax, y0, ay) 
+  /// and the z of the crossing points of the track 
+  /// with the paddles (NaN if the track is parallel
+  /// to the paddle).
+  fn fit_track(&self, hits : &[CalibrationHit]) -> Option<([f32;4], Vec<f32>)> {
+    let geos : Vec<&PaddleAxis> = hits.iter().map(|h| &self.paddles[&h.paddle_id]).collect();
+    let mut z_cross : Vec<f32> = geos.iter().map(|g| g.center[2]).collect();
+    let mut par = [0.0f32;4];
+    // the crossing points of paddles which are not
+    // horizontal depend on the track, so iterate
+    for _ in 0..3 {
+      let mut ata = [[0.0f64;4];4];
+      let mut atb = [0.0f64;4];
+      let mut n_rows = 0usize;
+      for (g, z) in geos.iter().zip(&z_cross) {
+        if !z.is_finite() {
+          continue;
+        }
+        // position across the paddle
+        let w   = cross(&g.normal, &g.axis);
+        let z   = *z as f64;
+        let row = [w[0] as f64, w[0] as f64*z, w[1] as f64, w[1] as f64*z];
+        let rhs = dot(&g.center, &w) as f64 - w[2] as f64*z;
+        for i in 0..4 {
+          for j in 0..4 {
+            ata[i][j] += row[i]*row[j];
+          }
+          atb[i] += row[i]*rhs;
+        }
+        n_rows += 1;
+      }
+      if n_rows < 4 {
+        return None;
+      }
+      let sol = solve4(ata, atb)?;
+      par     = [sol[0] as f32, sol[1] as f32, sol[2] as f32, sol[3] as f32];
+      let dir = [par[1], par[3], 1.0];
+      let len = norm(&dir);
+      for (k, g) in geos.iter().enumerate() {
+        let den = dot(&g.normal, &dir);
+        if (den/len).abs() < 0.1 {
+          z_cross[k] = f32::NAN;
+          continue;
+        }
+        z_cross[k] = (g.normal[0]*(g.center[0] - par[0])
+                    + g.normal[1]*(g.center[1] - par[2])
+                    + g.normal[2]*g.center[2])/den;
+      }
+    }
+    // the spatial χ²
+    let mut chi2 = 0.0f32;
+    for (g, z) in geos.iter().zip(&z_cross) {
+      if !z.is_finite() {
+        continue;
+      }
+      let pos = [par[0] + par[1]*z, par[2] + par[3]*z, *z];
+      let d   = dot(&sub(&pos, &g.center), &cross(&g.normal, &g.axis));
+      chi2   += d*d/(self.sigma_across*self.sigma_across);
+    }
+    if chi2/(hits.len() as f32) > self.max_chi2_pos {
+      return None;
+    }
+    Some((par, z_cross))
+  }
+
+  /// Fit the timing constants
+  ///
+  /// # Arguments:
+  ///   * initial : starting values for the offsets and
+  ///     the effective speed of light. Paddles which
+  ///     don't have enough entries keep these values.
+  ///
+  /// The result keeps the version of the initial 
+  /// constants, it only gets incremented when the 
+  /// result is combined with the applied calibration
+  /// (TimingCalibration::combine).
+  pub fn fit(&self, initial : &TimingCalibration) -> Result<TimingCalibration, ReconstructionError> {
+    let mut constants = HashMap::<u8, PaddleTimingConstants>::new();
+    for pid in self.paddles.keys() {
+      constants.insert(*pid, *initial.get(*pid).unwrap_or(&PaddleTimingConstants::new()));
+    }
+    // the tracks don't depend on the constants
+    let tracks : Vec<Option<([f32;4], Vec<f32>)>> = self.events.iter()
+      .map(|ev| self.fit_track(ev))
+      .collect();
+    let n_tracks = tracks.iter().filter(|t| t.is_some()).count();
+    if n_tracks == 0 {
+      return Err(ReconstructionError::NotEnoughHits);
+    }
+    let v = self.beta*C_LIGHT_VACUUM;
+    for iteration in 0..self.n_iterations {
+      let mut dt_fits   = HashMap::<u8, DeltaTFit>::new();
+      let mut residuals = HashMap::<u8, Vec<f32>>::new();
+      for (ev, track) in self.events.iter().zip(&tracks) {
+        let (par, z_cross) = match track {
+          None        => continue,
+          Some(track) => track
+        };
+        let path_scale = f32::sqrt(1.0 + par[1]*par[1] + par[3]*par[3]);
+        // crossing point along the paddle (u), path 
+        // length along the track (s) and t0 with the 
+        // current constants
+        let mut samples = Vec::<(&CalibrationHit, f32, f32, f32)>::with_capacity(ev.len());
+        for (h, z) in ev.iter().zip(z_cross) {
+          if !z.is_finite() {
+            continue;
+          }
+          let geo = &self.paddles[&h.paddle_id];
+          let c   = &constants[&h.paddle_id];
+          let pos = [par[0] + par[1]*z, par[2] + par[3]*z, *z];
+          let u   = dot(&sub(&pos, &geo.center), &geo.axis);
+          // allow for the resolution of the track, a 
+          // hard cut at the paddle ends would bias the 
+          // slope
+          if u.abs() > 0.5*geo.length + 3.0*self.sigma_across {
+            continue;
+          }
+          // downgoing
+          let s   = -z*path_scale;
+          let t0  = 0.5*(h.time_a - c.offset_a + h.time_b - c.offset_b) - geo.length/(20.0*c.c_eff);
+          samples.push((h, u, s, t0));
+        }
+        if samples.len() < 2 {
+          continue;
+        }
+        let t_origin = samples.iter().map(|(_,_,s,t0)| t0 - s/v).sum::<f32>()/(samples.len() as f32);
+        for (h, u, s, t0) in samples {
+          let res = t0 - s/v - t_origin;
+          if iteration > 0 && res.abs() > self.max_residual {
+            continue;
+          }
+          dt_fits.entry(h.paddle_id).or_default().add(h.time_a - h.time_b, u);
+          residuals.entry(h.paddle_id).or_default().push(res);
+        }
+      }
+      // update the constants
+      let mut calibrated = Vec::<u8>::new();
+      for (pid, c) in constants.iter_mut() {
+        let fit = match dt_fits.get(pid) {
+          None      => continue,
+          Some(fit) => fit
+        };
+        if fit.n < self.min_entries {
+          continue;
+        }
+        let length    = self.paddles[pid].length;
+        let n         = fit.n as f32;
+        let mut c_eff = c.c_eff;
+        // we need the particles spread out over the
+        // paddle to get the slope
+        // (the spread of the time differences is
+        // 2*spread(u)/(10*c_eff))
+        if fit.var_x().sqrt()*(5.0*c_eff) as f64 > 0.1*length as f64 {
+          if let Some(slope) = fit.slope() {
+            let c_fit = slope/5.0;
+            if c_fit.is_finite() && c_fit > 0.3*C_LIGHT_PADDLE && c_fit < 3.0*C_LIGHT_PADDLE {
+              c_eff = c_fit;
+            }
+          }
+        }
+        // offset_a - offset_b with the chosen slope
+        let delta = (fit.s_x as f32 - 2.0*(fit.s_y as f32)/(10.0*c_eff))/n;
+        let res   = &residuals[pid];
+        let mean  = res.iter().sum::<f32>()/n;
+        let rms   = (res.iter().map(|r| (r - mean)*(r - mean)).sum::<f32>()/n).sqrt();
+        // t0 changes with c_eff, which needs to be 
+        // compensated by the offsets
+        let m     = 0.5*(c.offset_a + c.offset_b) + mean
+                  - 0.5*length/(10.0*c_eff) + 0.5*length/(10.0*c.c_eff);
+        c.offset_a     = m + 0.5*delta;
+        c.offset_b     = m - 0.5*delta;
+        c.c_eff        = c_eff;
+        c.n_entries    = fit.n;
+        c.residual_rms = rms;
+        calibrated.push(*pid);
+      }
+      if calibrated.is_empty() {
+        return Err(ReconstructionError::NotEnoughHits);
+      }
+      // fix the global offset
+      let m_global = calibrated.iter()
+        .map(|pid| 0.5*(constants[pid].offset_a + constants[pid].offset_b))
+        .sum::<f32>()/(calibrated.len() as f32);
+      for pid in &calibrated {
+        let c = constants.get_mut(pid).unwrap();
+        c.offset_a -= m_global;
+        c.offset_b -= m_global;
+      }
+      debug!("Timing calibration iteration {} - {} paddles", iteration, calibrated.len());
+    }
+    let mut cali = TimingCalibration::new();
+    cali.header.version     = initial.header.version;
+    cali.header.description = format!("Muon timing calibration from {} tracks", n_tracks);
+    for (pid, c) in constants {
+      if c.n_entries >= self.min_entries || initial.get(pid).is_some() {
+        cali.insert(pid, c);
+      }
+    }
+    Ok(cali)
+  }
+}
+
+impl Default for TimingCalibrator {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for TimingCalibrator {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<TimingCalibrator:");
+    repr += &(format!("\n  paddles      : {}", self.paddles.len()));
+    repr += &(format!("\n  events       : {}", self.events.len()));
+    repr += &(format!("\n  beta         : {}", self.beta));
+    repr += &(format!("\n  iterations   : {}", self.n_iterations));
+    repr += &(format!("\n  min entries  : {}", self.min_entries));
+    repr += &(format!("\n  max residual : {} [ns]>", self.max_residual));
+    write!(f, "{}", repr)
+  }
+}
+
+fn cross(a : &[f32;3], b : &[f32;3]) -> [f32;3] {
+  [a[1]*b[2] - a[2]*b[1],
+   a[2]*b[0] - a[0]*b[2],
+   a[0]*b[1] - a[1]*b[0]]
+}
+
+/// Solve the linear system m*x = b (Gaussian 
+/// elimination with partial pivoting)
+fn solve4(mut m : [[f64;4];4], mut b : [f64;4]) -> Option<[f64;4]> {
+  let scale = m.iter().flatten().fold(0.0f64, |acc, x| acc.max(x.abs()));
+  if scale == 0.0 {
+    return None;
+  }
+  for col in 0..4 {
+    let pivot = (col..4).max_by(|i, j| m[*i][col].abs().total_cmp(&m[*j][col].abs())).unwrap();
+    if m[pivot][col].abs() < 1e-9*scale {
+      return None;
+    }
+    m.swap(col, pivot);
+    b.swap(col, pivot);
+    let pivot_row = m[col];
+    for row in col + 1..4 {
+      let f = m[row][col]/pivot_row[col];
+      for (x, p) in m[row].iter_mut().zip(pivot_row).skip(col) {
+        *x -= f*p;
+      }
+      b[row] -= f*b[col];
+    }
+  }
+  let mut x = [0.0f64;4];
+  for row in (0..4).rev() {
+    let mut sum = b[row];
+    for k in row + 1..4 {
+      sum -= m[row][k]*x[k];
+    }
+    x[row] = sum/m[row][row];
+  }
+  Some(x)
+}
+
+#[test]
+fn timing_calibration_apply() {
+  let mut cali = TimingCalibration::new();
+  let mut c    = PaddleTimingConstants::new();
+  c.offset_a   = 2.0;
+  c.offset_b   = -1.0;
+  c.c_eff      = 16.0;
+  cali.insert(5, c);
+  let mut hit = TofHit::new();
+  hit.paddle_id = 5;
+  hit.set_time_a(20.0);
+  hit.set_time_b(10.0);
+  cali.apply_offsets(&mut hit);
+  cali.set_light_speed(&mut hit);
+  assert_eq!(hit.get_time_a(), 18.0);
+  assert_eq!(hit.get_time_b(), 11.0);
+  assert_eq!(hit.c_paddle, 16.0);
+  // no constants for this paddle
+  hit.paddle_id = 6;
+  cali.apply_offsets(&mut hit);
+  assert_eq!(hit.get_time_a(), 18.0);
+
+  let mut corr = TimingCalibration::new();
+  c.offset_a   = 0.5;
+  c.offset_b   = 0.5;
+  c.c_eff      = 15.0;
+  corr.insert(5, c);
+  let combined = cali.combine(&corr);
+  assert_eq!(combined.header.version, 1);
+  assert_eq!(combined.get(5).unwrap().offset_a, 2.5);
+  assert_eq!(combined.get(5).unwrap().offset_b, -0.5);
+  assert_eq!(combined.get(5).unwrap().c_eff, 15.0);
+}
+
+#[test]
+#[cfg(feature = "random")]
+fn timing_calibration_muons() {
+  use rand::Rng;
+  let mut rng        = rand::thread_rng();
+  let mut calibrator = TimingCalibrator::new();
+  let mut truth      = HashMap::<u8, PaddleTimingConstants>::new();
+  // 4 layers of 6 paddles (1.2 m long, 20 cm wide),
+  // alternating along x and y. The upper two layers
+  // are outer TOF.
+  let layers = [(61u8, 1500.0f32, [1.0f32, 0.0, 0.0]),
+                (67,   1000.0,    [0.0, 1.0, 0.0]),
+                (1,     500.0,    [1.0, 0.0, 0.0]),
+                (7,       0.0,    [0.0, 1.0, 0.0])];
+  for (pid0, z, axis) in layers {
+    for k in 0..6u8 {
+      let across = -500.0 + 200.0*k as f32;
+      let center = [across*axis[1], across*axis[0], z];
+      calibrator.paddles.insert(pid0 + k, PaddleAxis {
+        center,
+        axis,
+        normal : [0.0, 0.0, 1.0],
+        length : 1200.0,
+      });
+      let mut c = PaddleTimingConstants::new();
+      c.offset_a = rng.gen_range(-2.0..2.0);
+      c.offset_b = rng.gen_range(-2.0..2.0);
+      c.c_eff    = rng.gen_range(14.0..17.0);
+      truth.insert(pid0 + k, c);
+    }
+  }
+  let mut n_events = 0;
+  while n_events < 10000 {
+    let start = [rng.gen_range(-600.0..600.0f32), rng.gen_range(-600.0..600.0f32), 1500.0f32];
+    let dir   = [rng.gen_range(-0.5..0.5f32), rng.gen_range(-0.5..0.5f32), -1.0f32];
+    let len   = norm(&dir);
+    let dir   = [dir[0]/len, dir[1]/len, dir[2]/len];
+    let mut event = TofEventSummary::new();
+    for (pid0, z, axis) in layers {
+      let s     = (z - start[2])/dir[2];
+      let cross = [start[0] + s*dir[0], start[1] + s*dir[1], z];
+      let along = dot(&cross, &axis);
+      let perp  = cross[0]*axis[1] + cross[1]*axis[0];
+      if along.abs() > 600.0 || perp.abs() >= 600.0 {
+        continue;
+      }
+      let pid = pid0 + ((perp + 600.0)/200.0).floor() as u8;
+      let c   = &truth[&pid];
+      let t   = 10.0 + s/C_LIGHT_VACUUM;
+      let mut hit   = TofHit::new();
+      hit.paddle_id = pid;
+      hit.set_time_a(t + (600.0 + along)/(10.0*c.c_eff) + c.offset_a);
+      hit.set_time_b(t + (600.0 - along)/(10.0*c.c_eff) + c.offset_b);
+      event.hits.push(hit);
+    }
+    if calibrator.add_event(&event) {
+      n_events += 1;
+    }
+  }
+  let cali = calibrator.fit(&TimingCalibration::new()).unwrap();
+  assert_eq!(cali.len(), 24);
+  assert_eq!(cali.header.version, 0);
+  assert_eq!(TimingCalibration::new().combine(&cali).header.version, 1);
+  let m_true = truth.values().map(|c| 0.5*(c.offset_a + c.offset_b)).sum::<f32>()/24.0;
+  for (pid, c) in &truth {
+    let fit = cali.get(*pid).unwrap();
+    assert!((fit.offset_a - (c.offset_a - m_true)).abs() < 0.2, "paddle {}: {} vs {}", pid, fit, c);
+    assert!((fit.offset_b - (c.offset_b - m_true)).abs() < 0.2, "paddle {}: {} vs {}", pid, fit, c);
+    assert!((fit.c_eff - c.c_eff).abs() < 0.5, "paddle {}: {} vs {}", pid, fit, c);
+  }
+}
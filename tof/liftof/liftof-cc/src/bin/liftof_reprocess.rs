@@ -0,0 +1,387 @@
+//! Liftof reprocess - redo the waveform analysis for
+//! already written TofEvent run files
+//!
+//! Features
+//!
+//! * re-run the waveform analysis with a chosen set
+//!   of calibrations and AnalysisEngineSettings
+//! * rebuild the TofEventSummary (event quality,
+//!   interesting event tags)
+//! * process several files in parallel
+//! * resume an interrupted reprocessing by just
+//!   running the same command again
+//! * build the pulse templates for the template fit
+//!   of the waveform analysis (--build-templates)
+//! * fit the paddle timing constants to the cosmic
+//!   muons in the run files (--calibrate-timing)
+//! * fit the paddle gains to the minimum ionizing
+//!   particles in the run files (--calibrate-energy)
+//! * create the calibration store manifest for the
+//!   RB calibrations taken before there was a store
+//!   (--scan-calibrations)
+//!
+//! The output is written to <output_dir>/<tag>, where
+//! also a copy of the configuration is kept.
+
+#[macro_use] extern crate log;
+
+use std::process::exit;
+use std::time::Instant;
+
+use clap::{
+  arg,
+  command,
+  Parser
+};
+
+use liftof_lib::{
+  init_env_logger,
+  LIFTOF_LOGO_SHOW,
+  LiftofSettings,
+};
+use liftof_lib::settings::ReprocessingSettings;
+use liftof_lib::reprocessing::{
+  load_paddles,
+  load_readoutboards,
+  build_pulse_templates,
+  calibrate_timing,
+  calibrate_energy,
+  reprocess_files,
+  EventReprocessor,
+};
+
+use tof_dataclasses::io::TofPacketReader;
+use tof_dataclasses::calibration_file::JsonFile;
+use tof_dataclasses::calibration_store::CalibrationStore;
+use tof_dataclasses::timing_calibration::TimingCalibration;
+use tof_dataclasses::energy_calibration::EnergyCalibration;
+
+#[derive(Parser, Debug)]
+#[command(author = "J.A.Stoessl", version, about, long_about = None)]
+#[command(propagate_version = true)]
+struct LiftofReprocessArgs {
+  /// A TofEvent run file or a directory with
+  /// run files
+  #[arg(required_unless_present = "scan_calibrations")]
+  input          : Option<String>,
+  /// Reprocessing configuration (.toml)
+  #[arg(short, long)]
+  config         : Option<String>,
+  /// Take calibration directory, db path and the
+  /// analysis/quality/tagging settings from the
+  /// liftof-cc configuration of the run. Ignored
+  /// if --config is given.
+  #[arg(long)]
+  liftof_config  : Option<String>,
+  /// Overwrite the output directory of the
+  /// configuration
+  #[arg(short, long)]
+  output_dir     : Option<String>,
+  /// Overwrite the tag of the configuration
+  #[arg(short, long)]
+  tag            : Option<String>,
+  /// Overwrite the number of files processed
+  /// in parallel
+  #[arg(short = 'j', long)]
+  threads        : Option<usize>,
+  /// Write the configuration to this file
+  /// and exit
+  #[arg(long)]
+  write_config   : Option<String>,
+  /// Don't reprocess, but build pulse templates
+  /// from the input files, write them to this
+  /// file and exit
+  #[arg(long)]
+  build_templates : Option<String>,
+  /// Minimum number of pulses per paddle end 
+  /// for --build-templates
+  #[arg(long, default_value_t = 100)]
+  min_pulses     : u32,
+  /// Don't reprocess, but fit the paddle timing
+  /// constants to the muons in the input files,
+  /// write them to this file and exit
+  #[arg(long)]
+  calibrate_timing : Option<String>,
+  /// Timing calibration which was applied to the
+  /// hit times in the input files (for
+  /// --calibrate-timing)
+  #[arg(long)]
+  applied_timing : Option<String>,
+  /// Maximum number of events for --calibrate-timing
+  #[arg(long, default_value_t = 1000000)]
+  max_timing_events : usize,
+  /// Don't reprocess, but fit the paddle gains to
+  /// the minimum ionizing particles in the input 
+  /// files and exit. If the file already exists,
+  /// the new constants are added to it.
+  #[arg(long)]
+  calibrate_energy : Option<String>,
+  /// First run the new gain constants are valid for
+  /// (default: first run in the input files)
+  #[arg(long)]
+  first_run      : Option<u32>,
+  /// Last run the new gain constants are valid for
+  /// (default: all later runs)
+  #[arg(long)]
+  last_run       : Option<u32>,
+  /// Maximum number of events for --calibrate-energy
+  #[arg(long, default_value_t = 1000000)]
+  max_energy_events : usize,
+  /// Don't reprocess, but add the calibration files
+  /// in the calibration store directory (or, if not
+  /// set, the calibration directory) which are not 
+  /// yet in its manifest and exit
+  #[arg(long)]
+  scan_calibrations : bool,
+}
+
+fn main() {
+  init_env_logger();
+  println!("{}", LIFTOF_LOGO_SHOW);
+  println!("-----------------------------------------------");
+  println!(" >> Offline reprocessing of TofEvent run files");
+  println!("-----------------------------------------------");
+
+  let args = LiftofReprocessArgs::parse();
+  let mut settings = match (&args.config, &args.liftof_config) {
+    (Some(cfg), _) => {
+      match ReprocessingSettings::from_toml(cfg) {
+        Err(err) => {
+          error!("Unable to read reprocessing config {}! {err}", cfg);
+          exit(1);
+        }
+        Ok(s) => s
+      }
+    }
+    (None, Some(cfg)) => {
+      match LiftofSettings::from_toml(cfg) {
+        Err(err) => {
+          error!("Unable to read liftof config {}! {err}", cfg);
+          exit(1);
+        }
+        Ok(s) => ReprocessingSettings::from_liftof_settings(&s)
+      }
+    }
+    (None, None) => {
+      warn!("No configuration given, using defaults!");
+      ReprocessingSettings::new()
+    }
+  };
+  if let Some(output_dir) = args.output_dir {
+    settings.output_dir = output_dir;
+  }
+  if let Some(tag) = args.tag {
+    settings.tag = tag;
+  }
+  if let Some(n_threads) = args.threads {
+    settings.n_threads = n_threads;
+  }
+  if let Some(fname) = args.write_config {
+    settings.to_toml(fname);
+    exit(0);
+  }
+  println!("=> Using settings {}", settings);
+
+  if args.scan_calibrations {
+    let directory = if settings.calibration_store.is_empty() {
+      settings.calibration_dir.clone()
+    } else {
+      settings.calibration_store.clone()
+    };
+    let mut store = match CalibrationStore::open(&directory) {
+      Err(err) => {
+        error!("Unable to open calibration store {}! {err}", directory);
+        exit(1);
+      }
+      Ok(store) => store
+    };
+    let scanned = match CalibrationStore::scan(&directory) {
+      Err(err) => {
+        error!("Unable to scan {} for calibrations! {err}", directory);
+        exit(1);
+      }
+      Ok(scanned) => scanned
+    };
+    // don't lose the quality and run information
+    // of the calibrations which are already known
+    let mut n_new = 0usize;
+    for record in scanned.records {
+      if !store.records.iter().any(|r| r.rb_id == record.rb_id && r.timestamp == record.timestamp) {
+        store.add(record);
+        n_new += 1;
+      }
+    }
+    match store.save() {
+      Err(err) => {
+        error!("Unable to write the calibration store manifest to {}! {err}", directory);
+        exit(1);
+      }
+      Ok(_) => {
+        println!("=> Added {} calibrations, {} in the store {}", n_new, store.len(), directory);
+        exit(0);
+      }
+    }
+  }
+
+  let input = args.input.clone().unwrap_or_default();
+  let files = TofPacketReader::new(input.clone()).filenames;
+  if files.is_empty() {
+    error!("No run files found in {}!", input);
+    exit(1);
+  }
+  println!("=> Will reprocess {} files", files.len());
+
+  if let Some(fname) = args.calibrate_timing {
+    let paddles = match load_paddles(&settings) {
+      Err(err) => {
+        error!("Unable to load paddles! {err}");
+        exit(1);
+      }
+      Ok(pdls) => pdls
+    };
+    let applied = match args.applied_timing {
+      None          => TimingCalibration::new(),
+      Some(applied) => {
+        match TimingCalibration::from_file(&applied) {
+          Err(err) => {
+            error!("Unable to read timing calibration {}! {err}", applied);
+            exit(1);
+          }
+          Ok(cali) => cali
+        }
+      }
+    };
+    let cali = match calibrate_timing(&files, &paddles, &applied, args.max_timing_events) {
+      Err(err) => {
+        error!("Timing calibration failed! {err}");
+        exit(1);
+      }
+      Ok(cali) => cali
+    };
+    println!("{}", cali);
+    match cali.to_file(&fname) {
+      Err(err) => {
+        error!("Unable to write timing calibration to {}! {err}", fname);
+        exit(1);
+      }
+      Ok(_) => {
+        println!("=> Wrote timing constants for {} paddles to {}", cali.len(), fname);
+        exit(0);
+      }
+    }
+  }
+
+  if let Some(fname) = args.calibrate_energy {
+    let paddles = match load_paddles(&settings) {
+      Err(err) => {
+        error!("Unable to load paddles! {err}");
+        exit(1);
+      }
+      Ok(pdls) => pdls
+    };
+    let timing_file = &settings.analysis_engine_settings.timing_calibration_file;
+    let timing = if timing_file.is_empty() {
+      TimingCalibration::new()
+    } else {
+      match TimingCalibration::from_file(timing_file) {
+        Err(err) => {
+          error!("Unable to read timing calibration {}! {err}", timing_file);
+          exit(1);
+        }
+        Ok(cali) => cali
+      }
+    };
+    let new_cali = match calibrate_energy(&files,
+                                          &paddles,
+                                          &timing,
+                                          args.first_run,
+                                          args.last_run,
+                                          args.max_energy_events) {
+      Err(err) => {
+        error!("Energy calibration failed! {err}");
+        exit(1);
+      }
+      Ok(cali) => cali
+    };
+    println!("{}", new_cali);
+    let cali = if std::path::Path::new(&fname).exists() {
+      match EnergyCalibration::from_file(&fname) {
+        Err(err) => {
+          error!("Unable to read existing energy calibration {}! {err}", fname);
+          exit(1);
+        }
+        Ok(mut cali) => {
+          cali.merge(&new_cali);
+          cali
+        }
+      }
+    } else {
+      new_cali
+    };
+    match cali.to_file(&fname) {
+      Err(err) => {
+        error!("Unable to write energy calibration to {}! {err}", fname);
+        exit(1);
+      }
+      Ok(_) => {
+        println!("=> Wrote gain constants (version {}) for {} paddles to {}", cali.header.version, cali.len(), fname);
+        exit(0);
+      }
+    }
+  }
+
+  let rbs = match load_readoutboards(&settings) {
+    Err(err) => {
+      error!("Unable to load readoutboards! {err}");
+      exit(1);
+    }
+    Ok(rbs) => rbs
+  };
+  println!("=> Loaded calibrations for {} readoutboards", rbs.len());
+  if let Some(fname) = args.build_templates {
+    let templates = build_pulse_templates(&files,
+                                          &rbs,
+                                          &settings.analysis_engine_settings,
+                                          args.min_pulses);
+    println!("{}", templates);
+    match templates.to_file(&fname) {
+      Err(err) => {
+        error!("Unable to write pulse templates to {}! {err}", fname);
+        exit(1);
+      }
+      Ok(_) => {
+        println!("=> Wrote {} pulse templates to {}", templates.len(), fname);
+        exit(0);
+      }
+    }
+  }
+  let paddles = match load_paddles(&settings) {
+    Err(err) => {
+      error!("Unable to load paddles! {err}");
+      exit(1);
+    }
+    Ok(pdls) => pdls
+  };
+  let reprocessor = match EventReprocessor::new(settings, rbs, paddles) {
+    Err(err) => {
+      error!("Unable to set up reprocessing! {err}");
+      exit(1);
+    }
+    Ok(rp) => rp
+  };
+
+  let start = Instant::now();
+  match reprocess_files(files, reprocessor) {
+    Err(err) => {
+      error!("Reprocessing failed! {err}");
+      exit(1);
+    }
+    Ok(stats) => {
+      println!("{}", stats);
+      println!("=> Reprocessing took {:.1} seconds", start.elapsed().as_secs_f64());
+      if stats.n_files_failed > 0 {
+        exit(2);
+      }
+    }
+  }
+}
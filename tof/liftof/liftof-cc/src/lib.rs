@@ -71,6 +71,7 @@ use tof_dataclasses::commands::{
 use tof_dataclasses::status::TofDetectorStatus;
 use tof_dataclasses::packets::TofPacket;
 use tof_dataclasses::database::ReadoutBoard;
+use tof_dataclasses::calibration_store::CalibrationStore;
 
 use tof_dataclasses::io::{
     TofPacketWriter,
@@ -735,6 +736,18 @@ pub fn verification_run(timeout        : u32,
 /// timeout is expired. The timeout can be set in the configuration
 /// file
 ///
+/// Each calibration is written together with its quality 
+/// report. Calibrations which have been rejected by the 
+/// readoutboard communicators (see CalibrationQualitySettings)
+/// go to the "rejected" subdirectory instead, and the 
+/// previous calibration of the board is copied over.
+///
+/// All written calibrations are recorded in the calibration
+/// store (manifest in the calibration directory), with the 
+/// current run as the first run they are valid for. This 
+/// allows to find the right calibration when data is 
+/// analyzed later on.
+///
 /// # Argumeents:
 ///
 ///   * thread_control : general shared memory to hold configuration
@@ -753,7 +766,9 @@ pub fn calibrate_tof(thread_control : Arc<Mutex<ThreadControl>>,
  
   let mut cali_dir_created = false;
   let mut cali_output_dir  = String::from("");
+  let mut rejected_rbs     = Vec::<u8>::new();
   let mut cali_base_dir        = String::from("");
+  let mut run_id               = 0u32;
 
   match thread_control.lock() {
     Ok(mut tc) => {
@@ -761,6 +776,7 @@ pub fn calibrate_tof(thread_control : Arc<Mutex<ThreadControl>>,
         tc.finished_calibrations.insert(rb.rb_id,false); 
       }
       cali_base_dir = tc.liftof_settings.calibration_dir.clone();
+      run_id        = tc.run_id;
       cc_pub_addr   = tc.liftof_settings.cmd_dispatcher_settings.cc_server_address.clone();
       tc.write_data_to_disk = true;
     },
@@ -769,6 +785,18 @@ pub fn calibrate_tof(thread_control : Arc<Mutex<ThreadControl>>,
     },
   }
 
+  // calibrations taken outside of a run 
+  // (run id 0) don't get a first run
+  let first_run   = if run_id > 0 {Some(run_id)} else {None};
+  let sw_version  = env!("CARGO_PKG_VERSION");
+  let mut cali_store = match CalibrationStore::open(&cali_base_dir) {
+    Err(err) => {
+      error!("Unable to open calibration store in {}! The new calibrations won't be recorded! {err}", cali_base_dir);
+      None
+    }
+    Ok(store) => Some(store)
+  };
+
   // deprecated commanding
   let voltage_level = DEFAULT_CALIB_VOLTAGE;
   let rb_id         = DEFAULT_RB_ID;
@@ -865,8 +893,6 @@ pub fn calibrate_tof(thread_control : Arc<Mutex<ThreadControl>>,
           }
           if tc.finished_calibrations[&rbid.rb_id] {
             cali_received += 1;
-            let rbcali = tc.calibrations.get(&rbid.rb_id).expect("We got the signal tat this calibration is ready but it is not!");
-            let pack   = rbcali.pack();
             // See RBCalibration reference
             let file_type  = FileType::CalibrationFile(rbid.rb_id);
             //println!("==> Writing stream to file with prefix {}", streamfile_name);
@@ -880,9 +906,59 @@ pub fn calibrate_tof(thread_control : Arc<Mutex<ThreadControl>>,
               }
               cali_dir_created = true;
             }
-            let mut cali_writer = TofPacketWriter::new(cali_output_dir.clone(), file_type);
-            cali_writer.add_tof_packet(&pack);
-            drop(cali_writer);
+            // the quality report is stored in the same 
+            // file, right after the calibration
+            let quality = tc.calibration_qualities.get(&rbid.rb_id).cloned();
+            match tc.rejected_calibrations.remove(&rbid.rb_id) {
+              Some(bad_cali) => {
+                // keep it for inspection, but out of the 
+                // way of ReadoutBoard::load_latest_calibration
+                let rejected_dir = format!("{}/rejected", cali_output_dir);
+                match create_dir_all(rejected_dir.clone()) {
+                  Ok(_)    => info!("Created {} for rejected calibrations!", rejected_dir),
+                  Err(err) => error!("Unable to create {} for rejected calibrations! {}", rejected_dir, err)
+                }
+                let mut cali_writer = TofPacketWriter::new(rejected_dir, file_type);
+                cali_writer.add_tof_packet(&bad_cali.pack());
+                if let Some(q) = &quality {
+                  cali_writer.add_tof_packet(&q.pack());
+                }
+                if let Some(store) = cali_store.as_mut() {
+                  store.add_calibration(&cali_writer.file_name, &bad_cali, quality.as_ref(), sw_version, false, first_run);
+                }
+                drop(cali_writer);
+                rejected_rbs.push(rbid.rb_id);
+                // The board keeps using its previous calibration,
+                // which also has to go to the new directory, since 
+                // "latest" will point there.
+                let mut previous = rbid.clone();
+                previous.calib_file_path = cali_base_dir.clone() + "latest";
+                match previous.load_latest_calibration() {
+                  Err(err) => error!("Unable to load the previous calibration for RB {}! {err}", rbid.rb_id),
+                  Ok(_)    => {
+                    if previous.calibration.rb_id != rbid.rb_id {
+                      error!("There is no previous calibration for RB {}!", rbid.rb_id);
+                    } else {
+                      let mut cali_writer = TofPacketWriter::new(cali_output_dir.clone(), FileType::CalibrationFile(rbid.rb_id));
+                      cali_writer.add_tof_packet(&previous.calibration.pack());
+                      drop(cali_writer);
+                    }
+                  }
+                }
+              }
+              None => {
+                let rbcali = tc.calibrations.get(&rbid.rb_id).expect("We got the signal tat this calibration is ready but it is not!");
+                let mut cali_writer = TofPacketWriter::new(cali_output_dir.clone(), file_type);
+                cali_writer.add_tof_packet(&rbcali.pack());
+                if let Some(q) = &quality {
+                  cali_writer.add_tof_packet(&q.pack());
+                }
+                if let Some(store) = cali_store.as_mut() {
+                  store.add_calibration(&cali_writer.file_name, rbcali, quality.as_ref(), sw_version, true, first_run);
+                }
+                drop(cali_writer);
+              }
+            }
 
             bar.set_position(cali_received);
             finished_keys.push(rbid.rb_id);
@@ -913,6 +989,16 @@ pub fn calibrate_tof(thread_control : Arc<Mutex<ThreadControl>>,
       }
     }
   } // end loop
+  if !rejected_rbs.is_empty() {
+    error!("Calibrations of RBs {:?} failed the quality checks and have not been installed! See {}/rejected", rejected_rbs, cali_output_dir);
+    println!("=> Rejected calibrations for RBs {:?}, these boards keep their previous calibration!", rejected_rbs);
+  }
+  if let Some(store) = cali_store {
+    match store.save() {
+      Err(err) => error!("Unable to update the calibration store in {}! {err}", cali_base_dir),
+      Ok(_)    => println!("=> Recorded the calibrations in {}", CalibrationStore::get_manifest_path(&cali_base_dir)),
+    }
+  }
   // The last step is to create te symlink
   let cali_link_dir = cali_base_dir.clone() + "latest";
   match fs::remove_file(cali_link_dir.clone()) {
@@ -16,7 +16,7 @@ use std::time::{
   Instant,
   Duration,
 };
-//use std::collections::HashMap;
+use std::collections::HashMap;
 //use std::io::Write;
 use std::process::{
   Command,
@@ -61,6 +61,7 @@ use tof_dataclasses::database::{
   connect_to_db,
   get_linkid_rbid_map,
   ReadoutBoard,
+  Paddle,
 };
 
 //use tof_dataclasses::constants::PAD_CMD_32BIT;
@@ -524,6 +525,22 @@ fn main() {
     rb_id_list.push(k.rb_id);
   }
   let mtb_link_id_map = get_linkid_rbid_map(&rb_list);
+  // paddle information for the (optional) reconstruction
+  // and event classification in the event builder
+  let mut paddle_map  = HashMap::<u8, Paddle>::new();
+  if config.event_builder_settings.reconstruct_beta 
+  || config.event_quality_settings.classify_events {
+    match Paddle::all(&mut conn) {
+      Err(err) => {
+        error!("Unable to retrieve paddle information! {err}. Check db_path in the liftof settings (.toml) file and DB integrity!");
+      }
+      Ok(paddles) => {
+        for pdl in paddles {
+          paddle_map.insert(pdl.paddle_id as u8, pdl);
+        }
+      }
+    }
+  }
 
   // Prepare outputfiles
   let mut new_run_id        = 0u32;
@@ -640,6 +657,7 @@ fn main() {
                                   &ev_from_rb,
                                   &tp_to_sink_c,
                                   mtb_link_id_map,
+                                  paddle_map,
                                   thread_control_eb);
      })
     .expect("Failed to spawn event-builder thread!");
@@ -25,15 +25,33 @@ use tof_dataclasses::events::{
   TofEvent,
   RBEvent,
   EventStatus,
+  EventQuality,
 };
 
 use tof_dataclasses::serialization::Packable;
 use tof_dataclasses::packets::TofPacket;
 use tof_dataclasses::commands::config::BuildStrategy;
-use tof_dataclasses::heartbeats::EVTBLDRHeartbeat;
+use tof_dataclasses::heartbeats::{
+  EVTBLDRHeartbeat,
+  EventIdHeartbeat,
+  AlignmentHeartbeat,
+};
+use tof_dataclasses::database::{
+  Paddle,
+  get_dsi_j_ch_pid_map,
+};
+use tof_dataclasses::reconstruction::TofTrackFitter;
+use tof_dataclasses::calibration_file::JsonFile;
+use tof_dataclasses::timing_calibration::TimingCalibration;
+use tof_dataclasses::clock_alignment::ClockAligner;
+use tof_dataclasses::events::EventSelector;
+use tof_dataclasses::events::EventStreamChecker;
+use tof_dataclasses::version::ProtocolVersion;
 
 use liftof_lib::settings::{
   TofEventBuilderSettings,
+  EventQualitySettings,
+  InterestingEventSettings,
 };
 use liftof_lib::thread_control::ThreadControl;
 
@@ -70,11 +88,17 @@ use crate::constants::EVENT_BUILDER_EVID_CACHE_SIZE;
 ///                    RBs will know their link id themselves?
 ///                    This is currently only needed for the build strategy
 ///                    "AdaptiveThorough"
+/// * paddles        : Paddle information, needed for the
+///                    (optional) track fit and beta
+///                    reconstruction and the event 
+///                    classification. If empty, both
+///                    will be disabled
 /// * settings       : Configure the event builder
 pub fn event_builder (m_trig_ev      : &Receiver<MasterTriggerEvent>,
                       ev_from_rb     : &Receiver<RBEvent>,
                       data_sink      : &Sender<TofPacket>,
                       mtb_link_map   : HashMap<u8,u8>,
+                      paddles        : HashMap<u8,Paddle>,
                       thread_control : Arc<Mutex<ThreadControl>>) { 
   // deleteme
   //let file_type = FileType::RunFile(12345);
@@ -89,6 +113,11 @@ pub fn event_builder (m_trig_ev      : &Receiver<MasterTriggerEvent>,
   let mut send_rbwf_freq  : u32;
   let mut rbwf_ctr        = 0u64;
   let mut settings        : TofEventBuilderSettings;
+  let mut quality_settings : EventQualitySettings;
+  let mut interesting_settings : InterestingEventSettings;
+  let mut timing_file     : String;
+  let mut align_clocks    : bool;
+  let mut aligner         : ClockAligner;
   let mut run_id          : u32;
   // this can block it is fine bc it is only 
   // happening once at init
@@ -100,6 +129,11 @@ pub fn event_builder (m_trig_ev      : &Receiver<MasterTriggerEvent>,
         send_tev_sum      = tc.liftof_settings.data_publisher_settings.send_tof_summary_packets;
         send_rbwf_freq    = tc.liftof_settings.data_publisher_settings.send_rbwf_every_x_event;
         settings          = tc.liftof_settings.event_builder_settings.clone();
+        quality_settings  = tc.liftof_settings.event_quality_settings.clone();
+        interesting_settings = tc.liftof_settings.interesting_event_settings.clone();
+        timing_file       = tc.liftof_settings.analysis_engine_settings.timing_calibration_file.clone();
+        align_clocks      = tc.liftof_settings.analysis_engine_settings.align_clocks;
+        aligner           = tc.liftof_settings.analysis_engine_settings.get_clock_aligner();
         run_id            = tc.run_id;
         cali_active       = tc.calibration_active;
       }
@@ -116,6 +150,12 @@ pub fn event_builder (m_trig_ev      : &Receiver<MasterTriggerEvent>,
     }
   }
   info!("Will assign run id {} to events!", run_id);
+  if paddles.is_empty() 
+  && (settings.reconstruct_beta || quality_settings.classify_events) {
+    error!("No paddle information available! Won't reconstruct beta or classify events!");
+    settings.reconstruct_beta        = false;
+    quality_settings.classify_events = false;
+  }
 
   // event caches for assembled events
   let mut heartbeat            = EVTBLDRHeartbeat::new();
@@ -123,6 +163,10 @@ pub fn event_builder (m_trig_ev      : &Receiver<MasterTriggerEvent>,
   let mut event_id_cache       = VecDeque::<u32>::with_capacity(EVENT_BUILDER_EVID_CACHE_SIZE);
   let mut n_received           : usize;
   let mut last_evid            = 0;
+  // event id continuity of the MTB, TofEventSummary 
+  // and TofEvent streams
+  let mut evid_checker         = EventStreamChecker::new();
+  let mut alignment_hb         = AlignmentHeartbeat::new();
   let mut n_sent               = 0usize;
   // debug
   let mut last_rb_evid         : u32;
@@ -134,6 +178,39 @@ pub fn event_builder (m_trig_ev      : &Receiver<MasterTriggerEvent>,
   let mut retire               = false;
   let mut hb_timer             = Instant::now(); 
   let hb_interval              = Duration::from_secs(settings.hb_send_interval as u64);
+  let track_fitter             = TofTrackFitter::new();
+  let classifier               = quality_settings.get_classifier();
+  let pid_map                  = get_dsi_j_ch_pid_map(&paddles.values().cloned().collect());
+  let mut tag_events           = interesting_settings.tag_events;
+  let selector                 = match interesting_settings.get_selector() {
+    Ok(sel) => {
+      info!("Will tag interesting events with {}", sel);
+      sel
+    }
+    Err(err) => {
+      error!("Unable to parse interesting event rules! {err}. Won't tag events!");
+      tag_events = false;
+      EventSelector::new()
+    }
+  };
+  // the interesting event counters are only
+  // serialized for V2
+  if tag_events {
+    heartbeat.version = ProtocolVersion::V2;
+  }
+  // the effective speed of light in the paddles
+  // (the offsets are already applied by the
+  // analysis engine)
+  let mut timing               = TimingCalibration::new();
+  if !timing_file.is_empty() {
+    match TimingCalibration::from_file(&timing_file) {
+      Ok(cali) => {
+        info!("Loaded timing calibration version {} for {} paddles!", cali.header.version, cali.len());
+        timing = cali;
+      }
+      Err(err) => error!("Unable to load timing calibration from {}! {err}", timing_file),
+    }
+  }
   
   loop {
     if check_tc_update.elapsed().as_secs() > 2 {
@@ -188,6 +265,7 @@ pub fn event_builder (m_trig_ev      : &Receiver<MasterTriggerEvent>,
         }   
         Ok(mt) => {
           debug!("Received MasterTriggerEvent {}!", mt);
+          evid_checker.add_mte(&mt);
           let mut event       = TofEvent::from(mt);
           event.header.run_id = run_id;
           if last_evid != 0 {
@@ -414,6 +492,12 @@ pub fn event_builder (m_trig_ev      : &Receiver<MasterTriggerEvent>,
             if ev_timed_out {
               ev_to_send.mt_event.event_status = EventStatus::EventTimeOut;
             }
+            // shift the hit times of all boards to the
+            // clock of the reference board
+            if align_clocks {
+              let alignment = aligner.align(&mut ev_to_send.rb_events);
+              alignment_hb.add(&alignment);
+            }
             // update event status, so that we will also see in an 
             // (optionally) produced tof event summary if the 
             // event has isuses
@@ -427,26 +511,74 @@ pub fn event_builder (m_trig_ev      : &Receiver<MasterTriggerEvent>,
             let mut save_to_disk = true;
             n_sent += 1;
             heartbeat.n_sent += 1;
-            if send_tev_sum {
-              let tes  = ev_to_send.get_summary();
-              if settings.only_save_interesting {
+            if send_tev_sum || quality_settings.classify_events || tag_events {
+              let mut tes  = ev_to_send.get_summary();
+              if settings.reconstruct_beta || quality_settings.classify_events {
+                timing.set_light_speeds(&mut tes);
+                tes.set_paddles(&paddles);
+              }
+              if quality_settings.classify_events {
+                let quality = if quality_settings.use_missing_hg {
+                  match classifier.classify_with_mapping(&tes, &pid_map) {
+                    Ok(quality) => quality,
+                    Err(err)    => {
+                      error!("Unable to classify event {}! {err}", evid);
+                      EventQuality::Unknown
+                    }
+                  }
+                } else {
+                  classifier.classify(&tes)
+                };
+                tes.quality        = quality as u8;
+                ev_to_send.quality = quality;
+              }
+              if tag_events {
+                let mask = selector.tag(&mut tes);
+                // the interesting flag is only serialized
+                // for V2 (and V3), untagged events keep 
+                // their version
+                if mask != 0 && tes.version != ProtocolVersion::V3 {
+                  tes.version = ProtocolVersion::V2;
+                }
+                heartbeat.add_interesting(mask);
+              }
+              let mut beta_ok = true;
+              if settings.reconstruct_beta {
+                beta_ok = false;
+                match track_fitter.fit(&tes) {
+                  Err(err) => {
+                    debug!("Track fit failed for event {}! {}", evid, err);
+                  }
+                  Ok(track) => {
+                    trace!("{}", track);
+                    beta_ok = track.beta >= settings.thr_beta_min
+                           && track.beta <= settings.thr_beta_max;
+                    tes.set_track(track);
+                  }
+                }
+              }
+              if send_tev_sum && settings.only_save_interesting {
                 save_to_disk = false;
                 if tes.n_hits_umb   >= settings.thr_n_hits_umb 
                 && tes.n_hits_cbe   >= settings.thr_n_hits_cbe
                 && tes.n_hits_cor   >= settings.thr_n_hits_cor
                 && tes.tot_edep_umb >= settings.thr_tot_edep_umb
                 && tes.tot_edep_cbe >= settings.thr_tot_edep_cbe
-                && tes.tot_edep_cor >= settings.thr_tot_edep_cor {
+                && tes.tot_edep_cor >= settings.thr_tot_edep_cor 
+                && beta_ok {
                   save_to_disk = true;
                 }
               }
-              let pack = tes.pack();
-              match data_sink.send(pack) {
-                Err(err) => {
-                  error!("Packet sending failed! Err {}", err);
-                }
-                Ok(_)    => {
-                  debug!("Event with id {} sent!", evid);
+              if send_tev_sum {
+                evid_checker.add_summary(&tes);
+                let pack = tes.pack();
+                match data_sink.send(pack) {
+                  Err(err) => {
+                    error!("Packet sending failed! Err {}", err);
+                  }
+                  Ok(_)    => {
+                    debug!("Event with id {} sent!", evid);
+                  }
                 }
               }
             }
@@ -475,6 +607,11 @@ pub fn event_builder (m_trig_ev      : &Receiver<MasterTriggerEvent>,
             // "interesting" event cuts in place, then this can 
             // be restricted.
             if save_to_disk {
+              // with only_save_interesting, the gaps in this 
+              // stream are intended
+              if !settings.only_save_interesting {
+                evid_checker.add_tof_event(&ev_to_send);
+              }
               let pack = ev_to_send.pack();
               match data_sink.send(pack) {
                 Err(err) => {
@@ -510,6 +647,27 @@ pub fn event_builder (m_trig_ev      : &Receiver<MasterTriggerEvent>,
         Ok(_)    => {
         }
       }
+      let evid_hb = EventIdHeartbeat::from_checker(heartbeat.met_seconds as u64, &evid_checker);
+      if !evid_hb.is_clean() {
+        debug!("{}", evid_hb);
+      }
+      match data_sink.send(evid_hb.pack()) {
+        Err(err) => {
+          error!("Packet sending failed! Err {}", err);
+        }
+        Ok(_)    => {
+        }
+      }
+      if align_clocks {
+        alignment_hb.met = heartbeat.met_seconds as u64;
+        match data_sink.send(alignment_hb.pack()) {
+          Err(err) => {
+            error!("Packet sending failed! Err {}", err);
+          }
+          Ok(_)    => {
+          }
+        }
+      }
       hb_timer = Instant::now();
     } 
   } // end loop
@@ -89,8 +89,6 @@ pub fn global_data_sink(incoming       : &Receiver<TofPacket>,
     send_rbwaveform_packets = false;
   }
 
-  let mut evid_check        = Vec::<u32>::new();
-
   let ctx = zmq::Context::new();
   // FIXME - should we just move to another socket if that one is not working?
   let data_socket = ctx.socket(zmq::PUB).expect("Can not create socket!");
@@ -109,8 +107,6 @@ pub fn global_data_sink(incoming       : &Receiver<TofPacket>,
 
   //let mut event_cache = Vec::<TofPacket>::with_capacity(100); 
 
-  // for debugging/profiling
-  let mut timer                = Instant::now();
   let mut check_settings_timer = Instant::now();
 
   // run settings 
@@ -212,21 +208,8 @@ pub fn global_data_sink(incoming       : &Receiver<TofPacket>,
       //
       //
 
-    let evid_check_len = evid_check.len();
-    if timer.elapsed().as_secs() > 10 {
-      // FIXME - might be too slow?
-      if evid_check_len > 0 {
-        let mut evid = evid_check[0];
-        for _ in 0..evid_check_len {
-          if !evid_check.contains(&evid) {
-            heartbeat.n_evid_missing += 1;
-            heartbeat.n_evid_chunksize = evid_check_len as u64;
-          }
-          evid += 1;
-        }
-      }
-      timer = Instant::now();
-    }
+    // the event id continuity is checked by the event 
+    // builder (see EventIdHeartbeat)
     if hb_timer.elapsed() >= hb_interval {
       heartbeat.met += hb_timer.elapsed().as_secs();
       
@@ -236,7 +219,6 @@ pub fn global_data_sink(incoming       : &Receiver<TofPacket>,
           trace!("Heartbeat sent");
         }
       } 
-      evid_check.clear();
       hb_timer = Instant::now();
     }
   } //end loop
@@ -17,6 +17,7 @@ use crossbeam_channel::Sender;
 
 use tof_dataclasses::database::ReadoutBoard;
 use tof_dataclasses::events::RBEvent;
+use tof_dataclasses::monitoring::RBChannelNoiseMonitor;
 use tof_dataclasses::packets::{
   TofPacket,
   PacketType,
@@ -26,18 +27,19 @@ use tof_dataclasses::serialization::{
   Serialization,
   Packable
 };
-use tof_dataclasses::calibrations::{
-  RBCalibrations,
-  RBCalibrationsFlightT,
-  RBCalibrationsFlightV,
-  };
+use tof_dataclasses::calibrations::RBCalibrations;
+use tof_dataclasses::calibration_quality::RBCalibrationQuality;
 
-use liftof_lib::{
-  waveform_analysis,
+use liftof_lib::waveform_analyzer::{
+  AnalysisEngine,
+  WaveformBuffers,
 };
 
 use liftof_lib::thread_control::ThreadControl;
-use liftof_lib::settings::AnalysisEngineSettings;
+use liftof_lib::settings::{
+  AnalysisEngineSettings,
+  CalibrationQualitySettings,
+};
 
 /*************************************/
 
@@ -58,8 +60,8 @@ use liftof_lib::settings::AnalysisEngineSettings;
 ///                         will be forwarded to the sink.
 /// * rb                  : ReadoutBoard instance, as loaded from the database. This will be used
 ///                         for readoutboard id as well as paddle assignment.
-/// * ae_settings         : Settings to configure peakfinding algorithms etc. 
-///                         These can be configured with an external .toml file
+/// * thread_control      : Shared state. The settings for the analysis engine (waveform
+///                         analyzer, peakfinding algorithms etc.) are taken from here.
 pub fn readoutboard_communicator(ev_to_builder       : Sender<RBEvent>,
                                  tp_to_sink          : Sender<TofPacket>,
                                  mut rb              : ReadoutBoard,
@@ -101,13 +103,21 @@ pub fn readoutboard_communicator(ev_to_builder       : Sender<RBEvent>,
   }
   let mut tc_timer = Instant::now();
   let mut verification_active = false;
+  let mut noise_monitor = RBChannelNoiseMonitor::new(board_id as u8);
+  // the RB sends the quality report right 
+  // before the calibration
+  let mut last_quality : Option<RBCalibrationQuality> = None;
   
   let ae_settings         : AnalysisEngineSettings; 
+  let cq_settings         : CalibrationQualitySettings;
   let run_analysis_engine : bool;
+  let run_id              : u32;
   match thread_control.lock() {
     Ok(tc) => {
       ae_settings         = tc.liftof_settings.analysis_engine_settings.clone();
+      cq_settings         = tc.liftof_settings.calibration_quality_settings.clone();
       run_analysis_engine = tc.liftof_settings.run_analysis_engine;
+      run_id              = tc.run_id;
     }
     Err(err) => {
       error!("Can't acquire lock for ThreadControl! Unable to set calibration mode! {err}");
@@ -115,12 +125,44 @@ pub fn readoutboard_communicator(ev_to_builder       : Sender<RBEvent>,
       return;
     }
   }
+  if run_analysis_engine && ae_settings.needs_templates() {
+    match rb.load_templates(&ae_settings.template_file) {
+      Err(err) => error!("Unable to load pulse templates for RB {} from {}! Will use cfd! {}", rb.rb_id, ae_settings.template_file, err),
+      Ok(n)    => info!("Loaded {} pulse templates for RB {}!", n, rb.rb_id),
+    }
+  }
+  if run_analysis_engine && !ae_settings.timing_calibration_file.is_empty() {
+    match rb.load_timing_calibration(&ae_settings.timing_calibration_file) {
+      Err(err) => error!("Unable to load timing calibration for RB {} from {}! Hit times will not be corrected! {}", rb.rb_id, ae_settings.timing_calibration_file, err),
+      Ok(n)    => info!("Loaded timing constants for {} paddles for RB {}!", n, rb.rb_id),
+    }
+  }
+  if run_analysis_engine && !ae_settings.energy_calibration_file.is_empty() {
+    match rb.load_energy_calibration(&ae_settings.energy_calibration_file, run_id) {
+      Err(err) => error!("Unable to load energy calibration for RB {} from {}! Hits won't have a calibrated energy deposition! {}", rb.rb_id, ae_settings.energy_calibration_file, err),
+      Ok(n)    => info!("Loaded gain constants for {} paddles for RB {} (run {})!", n, rb.rb_id, run_id),
+    }
+  }
+  let engine = match AnalysisEngine::new(&ae_settings) {
+    Err(err) => {
+      error!("Unable to set up the waveform analyzer(s) for RB {}! Falling back to the simple analyzer! {err}", rb.rb_id);
+      let mut fallback_settings              = ae_settings.clone();
+      fallback_settings.analyzer             = String::from("simple");
+      fallback_settings.compare_analyzer     = String::from("");
+      fallback_settings.use_zscore           = false;
+      fallback_settings.use_template_fit     = false;
+      AnalysisEngine::new(&fallback_settings).expect("The simple analyzer is always available!")
+    }
+    Ok(engine) => engine
+  };
   if run_analysis_engine {
-    info!("Will run analysis engine!");
+    info!("Will run analysis engine with analyzer {}!", engine.get_analyzer_name());
     //println!("Will use the following settings! {}", ae_settings);
   } else {
     warn!("Will not run analysis engine!");
   }
+  // the calibrated waveforms of each event go here
+  let mut wf_buffers = WaveformBuffers::new();
 
   // start continuous thread activity, read data from RB sockets,
   // do analysis and pass them on.
@@ -180,15 +222,38 @@ pub fn readoutboard_communicator(ev_to_builder       : Sender<RBEvent>,
                 if event.hits.len() == 0 
                 && !event.header.drs_lost_trigger() 
                 && run_analysis_engine {
-                  match waveform_analysis(&mut event, 
-                                          &rb,
-                                          ae_settings) {
+                  match engine.analyze(&mut event, &rb, &mut wf_buffers) {
                     Ok(_) => (),
                     Err(err) => {
                       warn!("Unable to analyze waveforms for this event! {err}");
                     }
                   }
                 }
+                // the hits are either from the analysis above
+                // or have already been analyzed on the RB
+                if ae_settings.noise_moni_interval > 0 {
+                  for h in &event.hits {
+                    // ends without a waveform (e.g. masked
+                    // channels) never got a baseline
+                    if h.get_bl_a_rms() > 0.0 {
+                      if let Some(ch) = rb.get_pid_rbchA(h.paddle_id) {
+                        noise_monitor.add(ch as usize, h.get_bl_a(), h.get_bl_a_rms());
+                      }
+                    }
+                    if h.get_bl_b_rms() > 0.0 {
+                      if let Some(ch) = rb.get_pid_rbchB(h.paddle_id) {
+                        noise_monitor.add(ch as usize, h.get_bl_b(), h.get_bl_b_rms());
+                      }
+                    }
+                  }
+                  if noise_monitor.get_elapsed() > ae_settings.noise_moni_interval as f32 {
+                    let noise_moni = noise_monitor.finish();
+                    match tp_to_sink.send(noise_moni.pack()) {
+                      Err(err) => error!("Can not send RBChannelNoiseMoniData to data sink! Err {err}"),
+                      Ok(_)    => debug!("RBChannelNoiseMoniData sent"),
+                    }
+                  }
+                }
                 if verification_active {
                   for h in &event.hits {
                     // average charge/peak hit
@@ -220,44 +285,81 @@ pub fn readoutboard_communicator(ev_to_builder       : Sender<RBEvent>,
                 //n_events += 1;
                 n_chunk += 1;
               } 
+              PacketType::RBCalibrationQuality => {
+                // will be published together with 
+                // the calibration
+                match tp.unpack::<RBCalibrationQuality>() {
+                  Ok(quality) => {
+                    last_quality = Some(quality);
+                  }
+                  Err(err) => {
+                    error!("Received calibration quality report, but got error when unpacking! {err}");
+                  }
+                }
+              }
               PacketType::RBCalibration => {
-                let mut flight_v = RBCalibrationsFlightV::new();
-                let mut flight_t = RBCalibrationsFlightT::new(); 
-
                 match tp.unpack::<RBCalibrations>() {
                   Ok(cali) => {
-                    flight_v = cali.emit_flightvcal();
-                    flight_t = cali.emit_flighttcal(); 
-                    //println!("= => [rb_comm] Received RBCalibration!");
+                    let quality = match last_quality.take() {
+                      Some(q) if q.rb_id == cali.rb_id && q.timestamp == cali.timestamp => q,
+                      _ => {
+                        // older liftof-rb
+                        warn!("No quality report for the calibration of RB {}! Will evaluate it here, which is incomplete if the calibration data was not sent along!", board_id);
+                        RBCalibrationQuality::from_calibration(&cali)
+                      }
+                    };
+                    let failures = cq_settings.cuts.get_failures(&quality);
+                    for failure in &failures {
+                      error!("Calibration of RB {} failed quality check! {}", board_id, failure);
+                    }
+                    let reject = !failures.is_empty() && cq_settings.reject_bad_calibrations;
+                    match tp_to_sink.send(quality.pack()) {
+                      Err(err) => error!("Can not send tof packet to data sink! Err {err}"),
+                      Ok(_)    => debug!("Packet sent"),
+                    }
                     match thread_control.lock() {
                       Ok(mut tc) => {
-                        tc.calibrations.insert(board_id, cali.clone()); 
+                        tc.calibration_qualities.insert(board_id, quality);
+                        if reject {
+                          tc.rejected_calibrations.insert(board_id, cali.clone());
+                        } else {
+                          tc.rejected_calibrations.remove(&board_id);
+                          tc.calibrations.insert(board_id, cali.clone()); 
+                        }
                         *tc.finished_calibrations.get_mut(&board_id).unwrap() = true; 
-                        rb.calibration = cali;
                       }
                       Err(err) => {
                         error!("Can't acquire lock for ThreadControl!! {err}");
                       },
                     }
+                    if reject {
+                      error!("Refusing to install the calibration for RB {}! Will keep the previous one!", board_id);
+                    } else {
+                      let flight_v_tp = cali.emit_flightvcal().pack();
+                      let flight_t_tp = cali.emit_flighttcal().pack();
+                      rb.calibration  = cali;
+                      match tp_to_sink.send(flight_v_tp) {
+                        Err(err) => error!("Can not send tof packet to data sink! Err {err}"),
+                        Ok(_)    => debug!("Packet sent"),
+                      }
+                      match tp_to_sink.send(flight_t_tp) {
+                        Err(err) => error!("Can not send tof packet to data sink! Err {err}"),
+                        Ok(_)    => debug!("Packet sent"),
+                      }
+                      match tp_to_sink.send(tp) {
+                        Err(err) => error!("Can not send tof packet to data sink! Err {err}"),
+                        Ok(_)    => debug!("Packet sent"),
+                      }
+                    }
                   }
                   Err(err) => {
                     error!("Received calibration package, but got error when unpacking! {err}");
+                    match tp_to_sink.send(tp) {
+                      Err(err) => error!("Can not send tof packet to data sink! Err {err}"),
+                      Ok(_)    => debug!("Packet sent"),
+                    }
                   }
                 }
-                let flight_v_tp = flight_v.pack();
-                let flight_t_tp = flight_t.pack();
-                match tp_to_sink.send(flight_v_tp) {
-                  Err(err) => error!("Can not send tof packet to data sink! Err {err}"),
-                  Ok(_)    => debug!("Packet sent"),
-                }
-                match tp_to_sink.send(flight_t_tp) {
-                  Err(err) => error!("Can not send tof packet to data sink! Err {err}"),
-                  Ok(_)    => debug!("Packet sent"),
-                }
-                match tp_to_sink.send(tp) {
-                  Err(err) => error!("Can not send tof packet to data sink! Err {err}"),
-                  Ok(_)    => debug!("Packet sent"),
-                }
               }
               _ => {
                 // Currently, we will just forward all other packets
@@ -275,6 +377,9 @@ pub fn readoutboard_communicator(ev_to_builder       : Sender<RBEvent>,
     debug!("Digested {n_chunk} chunks!");
     debug!("Noticed {n_errors} errors!");
   } // end loop
+  if let Some(comparison) = engine.get_comparison() {
+    info!("RB {} : {}", board_id, comparison);
+  }
   println!("= => [rbcomm] thread for RB {} finished! (not recoverable)", board_id);
 } // end fun
 
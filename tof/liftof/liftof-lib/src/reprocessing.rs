@@ -0,0 +1,942 @@
+//! Offline reprocessing of TofEvent run files
+//!
+//! Re-run the waveform analysis for all RBEvents in
+//! already written run files with a (new) set of
+//! calibrations and AnalysisEngineSettings, rebuild
+//! the TofEventSummary (quality, interesting event
+//! tags) and write everything to new run files.
+//!
+//! The output goes to output_dir/tag, together with
+//! a copy of the ReprocessingSettings. Each input file
+//! is first written to a ".part" file, which gets
+//! renamed once the file is complete. Files for which
+//! the output already exists are skipped, so an
+//! interrupted reprocessing can simply be restarted
+//! with the same settings.
+//!
+//! With a calibration store, each file is reprocessed
+//! with the RB calibrations which are valid for the run
+//! of its events, instead of the newest ones.
+
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{
+  BufWriter,
+  Write,
+};
+use std::path::Path;
+use std::thread;
+use std::error::Error;
+use std::collections::{
+  HashMap,
+  VecDeque,
+};
+use std::sync::{
+  Arc,
+  Mutex,
+};
+
+use crossbeam_channel::unbounded;
+
+use tof_dataclasses::database::{
+  connect_to_db,
+  get_dsi_j_ch_pid_map,
+  DsiJChPidMapping,
+  Paddle,
+  ReadoutBoard,
+};
+use tof_dataclasses::events::{
+  EventClassifier,
+  EventQuality,
+  EventSelector,
+  TofEvent,
+  TofEventSummary,
+};
+use tof_dataclasses::analysis::{
+  calculate_pedestal,
+  cfd_simple,
+  find_peaks,
+};
+use tof_dataclasses::calibration_store::CalibrationStore;
+use tof_dataclasses::calibration_file::JsonFile;
+use tof_dataclasses::io::TofPacketReader;
+use tof_dataclasses::packets::PacketType;
+use tof_dataclasses::energy_calibration::{
+  EnergyCalibration,
+  MipCalibrator,
+};
+use tof_dataclasses::pulse_template::{
+  PulseTemplateBuilder,
+  PulseTemplateLibrary,
+};
+use tof_dataclasses::serialization::{
+  Packable,
+  Serialization,
+};
+use tof_dataclasses::timing_calibration::{
+  TimingCalibration,
+  TimingCalibrator,
+};
+use tof_dataclasses::clock_alignment::ClockAligner;
+use tof_dataclasses::version::ProtocolVersion;
+
+use crate::settings::{
+  AnalysisEngineSettings,
+  ReprocessingSettings,
+};
+use crate::waveform_analyzer::{
+  AnalysisEngine,
+  WaveformBuffers,
+};
+
+/// A copy of the settings will be stored with this
+/// name in the output directory
+pub const REPROCESSING_MANIFEST : &str = "reprocessing.toml";
+
+/// Counters for the reprocessing
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct ReprocessingStats {
+  /// Number of files which were reprocessed
+  pub n_files          : usize,
+  /// Number of files which were already done
+  pub n_files_skipped  : usize,
+  /// Number of files which could not be processed
+  pub n_files_failed   : usize,
+  pub n_events         : usize,
+  pub n_rbevents       : usize,
+  /// RBEvents with successful waveform analysis
+  pub n_analyzed       : usize,
+  /// RBEvents for which the analysis failed
+  pub n_failed         : usize,
+  /// RBEvents without waveforms (e.g. lost
+  /// trigger). The original hits are kept.
+  pub n_no_waveforms   : usize,
+  /// RBEvents from boards without calibration.
+  /// The original hits are kept.
+  pub n_no_calibration : usize,
+  /// Events which passed any of the interesting
+  /// event rules
+  pub n_interesting    : usize,
+  /// Events without any board with a good ch9 sine
+  /// fit to align the clocks to
+  pub n_no_clock_ref   : usize,
+  /// RBEvents with hits which could not be aligned
+  /// to the reference clock
+  pub n_unaligned      : usize,
+  /// TofEvents which could not be unpacked. They
+  /// are copied unchanged to the output.
+  pub n_unpack_failed  : usize,
+  /// Packets which are not TofEvents
+  pub n_other_packets  : usize,
+}
+
+impl ReprocessingStats {
+
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn add(&mut self, other : &ReprocessingStats) {
+    self.n_files          += other.n_files;
+    self.n_files_skipped  += other.n_files_skipped;
+    self.n_files_failed   += other.n_files_failed;
+    self.n_events         += other.n_events;
+    self.n_rbevents       += other.n_rbevents;
+    self.n_analyzed       += other.n_analyzed;
+    self.n_failed         += other.n_failed;
+    self.n_no_waveforms   += other.n_no_waveforms;
+    self.n_no_calibration += other.n_no_calibration;
+    self.n_interesting    += other.n_interesting;
+    self.n_no_clock_ref   += other.n_no_clock_ref;
+    self.n_unaligned      += other.n_unaligned;
+    self.n_unpack_failed  += other.n_unpack_failed;
+    self.n_other_packets  += other.n_other_packets;
+  }
+}
+
+impl fmt::Display for ReprocessingStats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<ReprocessingStats:");
+    repr += &(format!("\n  files (done/skipped/failed) : {}/{}/{}", self.n_files, self.n_files_skipped, self.n_files_failed));
+    repr += &(format!("\n  TofEvents                   : {}", self.n_events));
+    repr += &(format!("\n  RBEvents                    : {}", self.n_rbevents));
+    repr += &(format!("\n  -- analyzed                 : {}", self.n_analyzed));
+    repr += &(format!("\n  -- analysis failed          : {}", self.n_failed));
+    repr += &(format!("\n  -- no waveforms             : {}", self.n_no_waveforms));
+    repr += &(format!("\n  -- no calibration           : {}", self.n_no_calibration));
+    repr += &(format!("\n  interesting events          : {}", self.n_interesting));
+    repr += &(format!("\n  no clock reference          : {}", self.n_no_clock_ref));
+    repr += &(format!("\n  RBEvents not clock aligned  : {}", self.n_unaligned));
+    repr += &(format!("\n  TofEvents copied unchanged  : {}", self.n_unpack_failed));
+    repr += &(format!("\n  other packets               : {}>", self.n_other_packets));
+    write!(f, "{}", repr)
+  }
+}
+
+/// Everything which is needed to reprocess a single
+/// event. This is shared between the worker threads.
+pub struct EventReprocessor {
+  pub settings   : ReprocessingSettings,
+  pub rbs        : HashMap<u8, ReadoutBoard>,
+  pub paddles    : HashMap<u8, Paddle>,
+  pub classifier : EventClassifier,
+  pub selector   : EventSelector,
+  /// Effective speed of light in the paddles
+  pub timing     : TimingCalibration,
+  /// Gain constants for all runs
+  pub energy     : EnergyCalibration,
+  /// Waveform analyzer(s) as configured in the 
+  /// analysis engine settings
+  pub engine     : AnalysisEngine,
+  /// Alignment of the hit times of the different
+  /// boards (if align_clocks is set)
+  pub aligner    : ClockAligner,
+  /// To pick the RB calibrations for each run
+  /// (if calibration_store is set)
+  pub cali_store : Option<CalibrationStore>,
+  pid_map        : DsiJChPidMapping,
+  /// ReadoutBoards with the calibrations attached
+  /// for the last few runs (with a calibration store)
+  run_cache      : Mutex<VecDeque<(u32, Arc<HashMap<u8, ReadoutBoard>>)>>,
+}
+
+impl EventReprocessor {
+
+  /// Create a new reprocessor. Fails if the
+  /// interesting event rules are invalid, the
+  /// timing or energy calibration or the
+  /// calibration store can't be read or an 
+  /// analyzer is unknown.
+  ///
+  /// # Arguments
+  ///
+  /// * settings : the reprocessing configuration
+  /// * rbs      : ReadoutBoards with the calibrations
+  ///              to use attached. Boards without
+  ///              calibration should not be in here.
+  ///              With a calibration store, these are
+  ///              only used for runs for which the store
+  ///              has no calibration.
+  /// * paddles  : Paddle information from the DB
+  pub fn new(settings : ReprocessingSettings,
+             rbs      : HashMap<u8, ReadoutBoard>,
+             paddles  : HashMap<u8, Paddle>) -> Result<Self, Box<dyn Error>> {
+    let classifier = settings.event_quality_settings.get_classifier();
+    let selector   = settings.interesting_event_settings.get_selector()?;
+    let pid_map    = get_dsi_j_ch_pid_map(&paddles.values().cloned().collect());
+    let timing_file = &settings.analysis_engine_settings.timing_calibration_file;
+    let timing     = if timing_file.is_empty() {
+      TimingCalibration::new()
+    } else {
+      TimingCalibration::from_file(timing_file)?
+    };
+    let energy_file = &settings.analysis_engine_settings.energy_calibration_file;
+    let energy     = if energy_file.is_empty() {
+      EnergyCalibration::new()
+    } else {
+      EnergyCalibration::from_file(energy_file)?
+    };
+    let engine     = AnalysisEngine::new(&settings.analysis_engine_settings)?;
+    info!("Using waveform analyzer {}", engine.get_analyzer_name());
+    let aligner    = settings.analysis_engine_settings.get_clock_aligner();
+    let cali_store = if settings.calibration_store.is_empty() {
+      None
+    } else {
+      let store = CalibrationStore::open(&settings.calibration_store)?;
+      info!("Using calibrations for each run from {}", store);
+      Some(store)
+    };
+    Ok(Self {
+      settings,
+      rbs,
+      paddles,
+      classifier,
+      selector,
+      timing,
+      energy,
+      engine,
+      aligner,
+      cali_store,
+      pid_map,
+      run_cache  : Mutex::new(VecDeque::new()),
+    })
+  }
+
+  /// The ReadoutBoards with the calibrations which 
+  /// are valid for a run attached
+  ///
+  /// Boards without a calibration for the run in the
+  /// calibration store keep their calibration. Without
+  /// a store, these are just the ReadoutBoards of the
+  /// reprocessor.
+  pub fn get_readoutboards_for_run(&self, run_id : u32) -> HashMap<u8, ReadoutBoard> {
+    let mut rbs = self.rbs.clone();
+    if let Some(store) = &self.cali_store {
+      for rb in rbs.values_mut() {
+        if let Err(err) = rb.load_calibration_for_run(store, run_id) {
+          warn!("{err} Using the calibration from {}!", self.settings.calibration_dir);
+        }
+      }
+    }
+    rbs
+  }
+
+  /// Like get_readoutboards_for_run, but the boards 
+  /// are cached for the last few runs (one more than 
+  /// there are threads), so the calibrations for a run 
+  /// are only loaded once, even if the run is spread 
+  /// over many files.
+  pub fn get_cached_readoutboards_for_run(&self, run_id : u32) -> Arc<HashMap<u8, ReadoutBoard>> {
+    // keep the lock while loading, so that other 
+    // threads don't load the same run in parallel
+    let mut cache = match self.run_cache.lock() {
+      Err(err) => {
+        error!("Unable to lock the run cache! {err}");
+        return Arc::new(self.get_readoutboards_for_run(run_id));
+      }
+      Ok(cache) => cache
+    };
+    if let Some((_, rbs)) = cache.iter().find(|(run, _)| *run == run_id) {
+      return Arc::clone(rbs);
+    }
+    let rbs = Arc::new(self.get_readoutboards_for_run(run_id));
+    cache.push_back((run_id, Arc::clone(&rbs)));
+    while cache.len() > self.settings.n_threads.max(1) + 1 {
+      cache.pop_front();
+    }
+    rbs
+  }
+
+  /// Re-run the waveform analysis for all RBEvents
+  /// of the event and rebuild the TofEventSummary
+  pub fn reprocess_event(&self, event : &mut TofEvent, stats : &mut ReprocessingStats) -> TofEventSummary {
+    self.reprocess_event_with(event, &self.rbs, &mut WaveformBuffers::new(), stats)
+  }
+
+  /// Same as reprocess_event, but with the given
+  /// ReadoutBoards (and their calibrations), e.g.
+  /// from get_readoutboards_for_run, and memory
+  /// for the calibrated waveforms which is reused
+  /// for all events
+  pub fn reprocess_event_with(&self,
+                              event   : &mut TofEvent,
+                              rbs     : &HashMap<u8, ReadoutBoard>,
+                              buffers : &mut WaveformBuffers,
+                              stats   : &mut ReprocessingStats) -> TofEventSummary {
+    stats.n_events += 1;
+    for rbev in event.rb_events.iter_mut() {
+      stats.n_rbevents += 1;
+      let rb = match rbs.get(&rbev.header.rb_id) {
+        None => {
+          stats.n_no_calibration += 1;
+          continue;
+        }
+        Some(rb) => rb
+      };
+      if rbev.header.drs_lost_trigger() || rbev.adc.iter().all(|ch| ch.is_empty()) {
+        stats.n_no_waveforms += 1;
+        continue;
+      }
+      let old_hits = std::mem::take(&mut rbev.hits);
+      match self.engine.analyze(rbev, rb, buffers) {
+        Err(err) => {
+          debug!("Unable to analyze waveforms for RB {} event {}! {err}", rb.rb_id, rbev.header.event_id);
+          stats.n_failed += 1;
+          rbev.hits = old_hits;
+        }
+        Ok(_) => {
+          stats.n_analyzed += 1;
+          // the gains depend on the run, so they
+          // are not attached to the boards
+          for hit in rbev.hits.iter_mut() {
+            self.energy.apply(hit, event.header.run_id);
+          }
+        }
+      }
+    }
+    if self.settings.analysis_engine_settings.align_clocks {
+      let alignment = self.aligner.align(&mut event.rb_events);
+      if alignment.reference.is_none() {
+        stats.n_no_clock_ref += 1;
+      }
+      stats.n_unaligned += alignment.n_failed;
+    }
+    let quality_settings = &self.settings.event_quality_settings;
+    let mut tes = event.get_summary();
+    self.timing.set_light_speeds(&mut tes);
+    tes.set_paddles(&self.paddles);
+    if quality_settings.classify_events {
+      let quality = if quality_settings.use_missing_hg {
+        match self.classifier.classify_with_mapping(&tes, &self.pid_map) {
+          Ok(quality) => quality,
+          Err(err)    => {
+            error!("Unable to classify event {}! {err}", tes.event_id);
+            EventQuality::Unknown
+          }
+        }
+      } else {
+        self.classifier.classify(&tes)
+      };
+      tes.quality   = quality as u8;
+      event.quality = quality;
+    }
+    if self.settings.interesting_event_settings.tag_events {
+      // the interesting flag is only serialized
+      // for V2, untagged events keep their version
+      if self.selector.tag(&mut tes) > 0 {
+        tes.version = ProtocolVersion::V2;
+        stats.n_interesting += 1;
+      }
+    }
+    tes
+  }
+
+  /// Reprocess a single run file
+  ///
+  /// # Arguments
+  ///
+  /// * infile  : TofEvent run file to reprocess
+  /// * out_dir : write the reprocessed file(s) to
+  ///             this directory
+  pub fn reprocess_file(&self, infile : &str, out_dir : &str) -> io::Result<ReprocessingStats> {
+    let mut stats  = ReprocessingStats::new();
+    let outfile    = get_output_filename(infile, out_dir, false);
+    let sumfile    = get_output_filename(infile, out_dir, true);
+    let outfile_p  = format!("{}.part", outfile);
+    let sumfile_p  = format!("{}.part", sumfile);
+    let mut writer = BufWriter::new(File::create(&outfile_p)?);
+    let mut sum_writer = if self.settings.write_summary {
+      Some(BufWriter::new(File::create(&sumfile_p)?))
+    } else {
+      None
+    };
+    // the calibrations for the current run
+    // (only with a calibration store)
+    let mut run_rbs     = Arc::new(HashMap::<u8, ReadoutBoard>::new());
+    let mut current_run = None;
+    let mut buffers     = WaveformBuffers::new();
+    let reader = TofPacketReader::new(String::from(infile));
+    for tp in reader {
+      match tp.packet_type {
+        PacketType::TofEvent => {
+          match tp.unpack::<TofEvent>() {
+            Err(err) => {
+              // don't lose the event, maybe a later
+              // version of the reprocessing can read it
+              error!("Unable to unpack TofEvent from {}! {err} Copying the packet unchanged.", infile);
+              stats.n_unpack_failed += 1;
+              writer.write_all(&tp.to_bytestream())?;
+            }
+            Ok(mut event) => {
+              let tes = if self.cali_store.is_some() {
+                if current_run != Some(event.header.run_id) {
+                  current_run = Some(event.header.run_id);
+                  run_rbs     = self.get_cached_readoutboards_for_run(event.header.run_id);
+                }
+                self.reprocess_event_with(&mut event, &run_rbs, &mut buffers, &mut stats)
+              } else {
+                self.reprocess_event_with(&mut event, &self.rbs, &mut buffers, &mut stats)
+              };
+              writer.write_all(&event.pack().to_bytestream())?;
+              if let Some(sw) = sum_writer.as_mut() {
+                sw.write_all(&tes.pack().to_bytestream())?;
+              }
+            }
+          }
+        }
+        _ => {
+          stats.n_other_packets += 1;
+          if self.settings.copy_other_packets {
+            writer.write_all(&tp.to_bytestream())?;
+          }
+        }
+      }
+    }
+    // finish the summary first, the existence of
+    // the main file marks the input file as done
+    if let Some(mut sw) = sum_writer {
+      sw.flush()?;
+      fs::rename(&sumfile_p, &sumfile)?;
+    }
+    writer.flush()?;
+    fs::rename(&outfile_p, &outfile)?;
+    stats.n_files += 1;
+    Ok(stats)
+  }
+}
+
+/// The name of the reprocessed file for a given input
+/// file. The summary files follow the naming of
+/// FileType::SummaryFile.
+pub fn get_output_filename(infile : &str, out_dir : &str, summary : bool) -> String {
+  let basename = Path::new(infile)
+    .file_name()
+    .map(|f| f.to_string_lossy().to_string())
+    .unwrap_or(String::from(infile));
+  let basename = if summary {
+    basename.replace(".tof.", ".tofsum.")
+  } else {
+    basename
+  };
+  format!("{}/{}", out_dir.trim_end_matches('/'), basename)
+}
+
+/// Check if the output for a file already exists
+pub fn is_reprocessed(infile : &str, out_dir : &str) -> bool {
+  Path::new(&get_output_filename(infile, out_dir, false)).exists()
+}
+
+/// The parts of the settings which change the
+/// result of the reprocessing
+fn get_configuration_string(settings : &ReprocessingSettings) -> String {
+  let mut config = settings.clone();
+  config.output_dir = String::from("");
+  config.n_threads  = 0;
+  toml::to_string(&config).unwrap_or(String::from(""))
+}
+
+/// Create the output directory (output_dir/tag) and
+/// store the settings in it.
+///
+/// If the directory already contains a manifest with
+/// a different configuration this will fail, so that
+/// files from different configurations don't get
+/// mixed when resuming.
+pub fn prepare_output_dir(settings : &ReprocessingSettings) -> io::Result<String> {
+  if settings.tag.is_empty() {
+    return Err(io::Error::new(io::ErrorKind::InvalidInput, "Reprocessing tag must not be empty!"));
+  }
+  if settings.output_dir.is_empty() {
+    // would end up as /tag
+    return Err(io::Error::new(io::ErrorKind::InvalidInput, "Reprocessing output_dir must not be empty!"));
+  }
+  let out_dir  = format!("{}/{}", settings.output_dir.trim_end_matches('/'), settings.tag);
+  fs::create_dir_all(&out_dir)?;
+  let manifest = format!("{}/{}", out_dir, REPROCESSING_MANIFEST);
+  if Path::new(&manifest).exists() {
+    match ReprocessingSettings::from_toml(&manifest) {
+      Err(err) => {
+        error!("Unable to read {}! {err}", manifest);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Can not read existing reprocessing manifest!"));
+      }
+      Ok(existing) => {
+        if get_configuration_string(&existing) != get_configuration_string(settings) {
+          error!("{} contains output of a different configuration! Choose a different tag!", out_dir);
+          return Err(io::Error::new(io::ErrorKind::AlreadyExists, "Reprocessing configuration mismatch!"));
+        }
+        info!("Resuming reprocessing in {}", out_dir);
+      }
+    }
+  } else {
+    settings.to_toml(manifest);
+  }
+  Ok(out_dir)
+}
+
+/// Get the ReadoutBoards from the DB and attach the
+/// newest calibration from settings.calibration_dir
+/// (or, if there is none, the newest one in the
+/// calibration store). Boards without calibration
+/// are dropped.
+pub fn load_readoutboards(settings : &ReprocessingSettings) -> Result<HashMap<u8, ReadoutBoard>, Box<dyn Error>> {
+  let mut conn   = connect_to_db(settings.db_path.clone())?;
+  let rb_list    = ReadoutBoard::all(&mut conn).ok_or("Unable to retrieve RB information from the DB!")?;
+  let mut rbs    = HashMap::<u8, ReadoutBoard>::new();
+  let store      = if settings.calibration_store.is_empty() {
+    None
+  } else {
+    Some(CalibrationStore::open(&settings.calibration_store)?)
+  };
+  for mut rb in rb_list {
+    rb.calib_file_path = settings.calibration_dir.clone();
+    if let Err(err) = rb.load_latest_calibration() {
+      error!("Unable to load calibration for RB {}! {err}", rb.rb_id);
+      continue;
+    }
+    // load_latest_calibration will not fail
+    // if there is no file at all
+    if rb.calibration.rb_id != rb.rb_id {
+      if let Some(store) = &store {
+        if let Some(record) = store.get_latest(rb.rb_id) {
+          match store.load(record) {
+            Err(err) => error!("Unable to load calibration for RB {} from {}! {err}", rb.rb_id, record.file),
+            Ok(cali) => rb.calibration = cali,
+          }
+        }
+      }
+    }
+    if rb.calibration.rb_id != rb.rb_id {
+      warn!("No calibration for RB {} in {}! Hits for this board won't be reprocessed!", rb.rb_id, settings.calibration_dir);
+      continue;
+    }
+    if settings.analysis_engine_settings.needs_templates() {
+      let template_file = &settings.analysis_engine_settings.template_file;
+      match rb.load_templates(template_file) {
+        Err(err) => {
+          error!("Unable to load pulse templates for RB {} from {}! Will use cfd! {err}", rb.rb_id, template_file);
+        }
+        Ok(n) => {
+          debug!("Loaded {} pulse templates for RB {}", n, rb.rb_id);
+        }
+      }
+    }
+    let timing_file = &settings.analysis_engine_settings.timing_calibration_file;
+    if !timing_file.is_empty() {
+      match rb.load_timing_calibration(timing_file) {
+        Err(err) => {
+          error!("Unable to load timing calibration for RB {} from {}! {err}", rb.rb_id, timing_file);
+        }
+        Ok(n) => {
+          debug!("Loaded timing constants for {} paddles for RB {}", n, rb.rb_id);
+        }
+      }
+    }
+    rbs.insert(rb.rb_id, rb);
+  }
+  Ok(rbs)
+}
+
+/// Build pulse templates for the template fit of the
+/// waveform analysis from the waveforms in run files
+///
+/// For each paddle end, the first peak of each waveform
+/// is aligned at its CFD time and added to the average.
+/// Saturated pulses are not used.
+///
+/// # Arguments
+///
+/// * files      : TofEvent run files
+/// * rbs        : ReadoutBoards with calibrations
+/// * settings   : pedestal, peakfinding and cfd settings
+/// * min_pulses : minimum number of pulses for a 
+///                paddle end to get a template
+pub fn build_pulse_templates(files      : &[String],
+                             rbs        : &HashMap<u8, ReadoutBoard>,
+                             settings   : &AnalysisEngineSettings,
+                             min_pulses : u32) -> PulseTemplateLibrary {
+  let mut builders = HashMap::<u16, PulseTemplateBuilder>::new();
+  let mut buffers  = WaveformBuffers::new();
+  for fname in files {
+    let reader = TofPacketReader::new(fname.clone());
+    for tp in reader {
+      if tp.packet_type != PacketType::TofEvent {
+        continue;
+      }
+      let event = match tp.unpack::<TofEvent>() {
+        Err(err) => {
+          error!("Unable to unpack TofEvent from {}! {err}", fname);
+          continue;
+        }
+        Ok(ev) => ev
+      };
+      for rbev in event.rb_events.iter() {
+        let rb = match rbs.get(&rbev.header.rb_id) {
+          None     => continue,
+          Some(rb) => rb
+        };
+        if rbev.header.drs_lost_trigger() {
+          continue;
+        }
+        let calibrated = rb.calibration.calibrate_event(rbev,
+                                                        &mut buffers.voltages,
+                                                        &mut buffers.times);
+        for pid in rb.get_paddle_ids() {
+          if pid == 0 {
+            continue;
+          }
+          // can't fail by construction of pid
+          let ch_a = rb.get_pid_rbchA(pid).unwrap() as usize;
+          let ch_b = rb.get_pid_rbchB(pid).unwrap() as usize;
+          for (pend, ch) in [(1000 + pid as u16, ch_a), (2000 + pid as u16, ch_b)] {
+            if ch == 0 || calibrated & (1 << (ch - 1)) == 0 {
+              continue;
+            }
+            let voltages = &mut buffers.voltages[ch - 1];
+            let times    = &buffers.times[ch - 1];
+            let (ped, _) = calculate_pedestal(voltages,
+                                              settings.pedestal_thresh,
+                                              settings.pedestal_begin_bin,
+                                              settings.pedestal_win_bins);
+            for v in voltages.iter_mut() {
+              *v -= ped;
+            }
+            let peaks = match find_peaks(voltages,
+                                         times,
+                                         settings.find_pks_t_start,
+                                         settings.find_pks_t_window,
+                                         settings.min_peak_size,
+                                         settings.find_pks_thresh,
+                                         settings.max_peaks) {
+              Err(_)  => continue,
+              Ok(pks) => pks
+            };
+            let pk = match peaks.first() {
+              None     => continue,
+              Some(pk) => *pk
+            };
+            let t_ref = match cfd_simple(voltages, times, settings.cfd_fraction, pk.0, pk.1) {
+              Err(_)  => continue,
+              Ok(cfd) => cfd
+            };
+            let amplitude = voltages[pk.0..pk.1].iter().cloned().fold(f32::MIN, f32::max);
+            if amplitude >= settings.saturation_level {
+              continue;
+            }
+            builders.entry(pend)
+                    .or_default()
+                    .add_pulse(voltages, times, t_ref, amplitude);
+          }
+        }
+      }
+    }
+  }
+  PulseTemplateLibrary::from_builders(&builders, min_pulses)
+}
+
+/// Fit the paddle timing constants to the cosmic
+/// muons in run files
+///
+/// The hit times in the files might already be
+/// corrected with a timing calibration, the fit result
+/// is combined with this one.
+///
+/// # Arguments
+///
+/// * files      : run files with TofEvents or
+///                TofEventSummaries
+/// * paddles    : Paddle information from the DB
+/// * applied    : timing calibration which was used
+///                for the hit times in the files
+/// * max_events : stop reading after this many
+///                usable events
+pub fn calibrate_timing(files      : &[String],
+                        paddles    : &HashMap<u8, Paddle>,
+                        applied    : &TimingCalibration,
+                        max_events : usize) -> Result<TimingCalibration, Box<dyn Error>> {
+  let mut calibrator = TimingCalibrator::new();
+  calibrator.set_paddles(paddles);
+  calibrator.max_events = max_events;
+  'files: for fname in files {
+    let reader = TofPacketReader::new(fname.clone());
+    for tp in reader {
+      let tes = match tp.packet_type {
+        PacketType::TofEventSummary => {
+          match tp.unpack::<TofEventSummary>() {
+            Err(err) => {
+              error!("Unable to unpack TofEventSummary from {}! {err}", fname);
+              continue;
+            }
+            Ok(tes) => tes
+          }
+        }
+        PacketType::TofEvent => {
+          match tp.unpack::<TofEvent>() {
+            Err(err) => {
+              error!("Unable to unpack TofEvent from {}! {err}", fname);
+              continue;
+            }
+            Ok(ev) => ev.get_summary()
+          }
+        }
+        _ => continue
+      };
+      calibrator.add_event(&tes);
+      if calibrator.get_n_events() >= max_events {
+        break 'files;
+      }
+    }
+    info!("{} events for the timing calibration after {}", calibrator.get_n_events(), fname);
+  }
+  // the times in the files have the offsets
+  // already subtracted, so only the speed of
+  // light is a starting value
+  let mut initial = TimingCalibration::new();
+  for (pid, c) in applied.constants.iter() {
+    let mut start = *c;
+    start.offset_a = 0.0;
+    start.offset_b = 0.0;
+    initial.insert(*pid, start);
+  }
+  let correction = calibrator.fit(&initial)?;
+  Ok(applied.combine(&correction))
+}
+
+/// Fit the paddle gains to the minimum ionizing
+/// particles in run files
+///
+/// # Arguments
+///
+/// * files      : run files with TofEvents or
+///                TofEventSummaries
+/// * paddles    : Paddle information from the DB
+/// * timing     : effective speed of light in the 
+///                paddles (for the track fit)
+/// * first_run  : first run the constants are valid
+///                for. If None, the first run in the
+///                files is used.
+/// * last_run   : last run the constants are valid
+///                for. If None, they are valid for all
+///                later runs.
+/// * max_events : stop reading after this many
+///                usable events
+pub fn calibrate_energy(files      : &[String],
+                        paddles    : &HashMap<u8, Paddle>,
+                        timing     : &TimingCalibration,
+                        first_run  : Option<u32>,
+                        last_run   : Option<u32>,
+                        max_events : usize) -> Result<EnergyCalibration, Box<dyn Error>> {
+  let mut calibrator = MipCalibrator::new();
+  calibrator.set_paddles(paddles);
+  let mut min_run = u32::MAX;
+  'files: for fname in files {
+    let reader = TofPacketReader::new(fname.clone());
+    for tp in reader {
+      let (mut tes, run_id) = match tp.packet_type {
+        PacketType::TofEventSummary => {
+          match tp.unpack::<TofEventSummary>() {
+            Err(err) => {
+              error!("Unable to unpack TofEventSummary from {}! {err}", fname);
+              continue;
+            }
+            // the summary only has the lower 16 bits
+            // of the run id
+            Ok(tes) => {
+              let run_id = tes.run_id as u32;
+              (tes, run_id)
+            }
+          }
+        }
+        PacketType::TofEvent => {
+          match tp.unpack::<TofEvent>() {
+            Err(err) => {
+              error!("Unable to unpack TofEvent from {}! {err}", fname);
+              continue;
+            }
+            Ok(ev) => (ev.get_summary(), ev.header.run_id)
+          }
+        }
+        _ => continue
+      };
+      timing.set_light_speeds(&mut tes);
+      tes.set_paddles(paddles);
+      if calibrator.add_event(&tes) {
+        min_run = min_run.min(run_id);
+      }
+      if calibrator.get_n_events() >= max_events {
+        break 'files;
+      }
+    }
+    info!("{} events for the energy calibration after {}", calibrator.get_n_events(), fname);
+  }
+  let first_run = first_run.unwrap_or(if min_run == u32::MAX {0} else {min_run});
+  let last_run  = last_run.unwrap_or(u32::MAX);
+  Ok(calibrator.fit(first_run, last_run)?)
+}
+
+/// Get the paddle information from the DB
+pub fn load_paddles(settings : &ReprocessingSettings) -> Result<HashMap<u8, Paddle>, Box<dyn Error>> {
+  let mut conn    = connect_to_db(settings.db_path.clone())?;
+  let mut paddles = HashMap::<u8, Paddle>::new();
+  for pdl in Paddle::all(&mut conn).ok_or("Unable to retrieve paddle information from the DB!")? {
+    paddles.insert(pdl.paddle_id as u8, pdl);
+  }
+  Ok(paddles)
+}
+
+/// Reprocess a number of run files in parallel
+///
+/// Files which have already been reprocessed with
+/// the same configuration are skipped.
+///
+/// # Arguments
+///
+/// * files       : TofEvent run files to reprocess
+/// * reprocessor : setup with the settings, calibrations
+///                 and paddles
+pub fn reprocess_files(files       : Vec<String>,
+                       reprocessor : EventReprocessor) -> Result<ReprocessingStats, Box<dyn Error>> {
+  let out_dir     = prepare_output_dir(&reprocessor.settings)?;
+  let n_threads   = reprocessor.settings.n_threads.max(1);
+  let mut stats   = ReprocessingStats::new();
+  let (file_sender, file_receiver)   = unbounded::<String>();
+  let (stats_sender, stats_receiver) = unbounded::<ReprocessingStats>();
+  for f in files {
+    if is_reprocessed(&f, &out_dir) {
+      info!("{} has already been reprocessed, skipping!", f);
+      stats.n_files_skipped += 1;
+      continue;
+    }
+    file_sender.send(f)?;
+  }
+  // workers will stop once the channel is empty
+  drop(file_sender);
+  let reprocessor = Arc::new(reprocessor);
+  let mut workers = Vec::new();
+  for k in 0..n_threads {
+    let files_c   = file_receiver.clone();
+    let stats_c   = stats_sender.clone();
+    let rp        = Arc::clone(&reprocessor);
+    let out_dir_c = out_dir.clone();
+    let worker    = thread::Builder::new()
+      .name(format!("reprocess-{}", k))
+      .spawn(move || {
+        for f in files_c.iter() {
+          info!("Reprocessing {}", f);
+          let mut fstats = ReprocessingStats::new();
+          match rp.reprocess_file(&f, &out_dir_c) {
+            Err(err) => {
+              error!("Reprocessing of {} failed! {err}", f);
+              fstats.n_files_failed += 1;
+            }
+            Ok(result) => {
+              info!("Finished {}", f);
+              fstats = result;
+            }
+          }
+          match stats_c.send(fstats) {
+            Err(err) => error!("Unable to send stats! {err}"),
+            Ok(_)    => ()
+          }
+        }
+      })?;
+    workers.push(worker);
+  }
+  drop(stats_sender);
+  for fstats in stats_receiver.iter() {
+    stats.add(&fstats);
+  }
+  for worker in workers {
+    if worker.join().is_err() {
+      error!("A reprocessing thread panicked!");
+    }
+  }
+  if let Some(comparison) = reprocessor.engine.get_comparison() {
+    println!("{}", comparison);
+  }
+  Ok(stats)
+}
+
+#[test]
+fn reprocessing_output_filenames() {
+  let infile = "/data/Run42_3.240101_120000UTC.tof.gaps";
+  assert_eq!(get_output_filename(infile, "/out/v2/", false), "/out/v2/Run42_3.240101_120000UTC.tof.gaps");
+  assert_eq!(get_output_filename(infile, "/out/v2", true), "/out/v2/Run42_3.240101_120000UTC.tofsum.gaps");
+}
+
+#[test]
+fn reprocessing_manifest() {
+  let mut settings    = ReprocessingSettings::new();
+  let base            = std::env::temp_dir().join(format!("liftof-reprocessing-{}", std::process::id()));
+  settings.output_dir = base.to_string_lossy().to_string();
+  settings.tag        = String::from("test");
+  let out_dir         = prepare_output_dir(&settings).unwrap();
+  assert!(Path::new(&format!("{}/{}", out_dir, REPROCESSING_MANIFEST)).exists());
+  // changing the number of threads is ok for resuming
+  settings.n_threads  = 12;
+  assert!(prepare_output_dir(&settings).is_ok());
+  settings.analysis_engine_settings.cfd_fraction = 0.5;
+  assert_eq!(prepare_output_dir(&settings).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+  fs::remove_dir_all(base).unwrap();
+}
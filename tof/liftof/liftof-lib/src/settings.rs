@@ -25,6 +25,16 @@ use tof_dataclasses::commands::config::{
   DataPublisherConfig,
 };
 use tof_dataclasses::events::master_trigger::TriggerType;
+use tof_dataclasses::pulse_template::PULSE_SATURATION_MV;
+use tof_dataclasses::clock_alignment::ClockAligner;
+use tof_dataclasses::calibration_quality::RBCalibrationQualityCuts;
+use tof_dataclasses::events::{
+  EventClassifier,
+  EventQualityRules,
+  EventSelector,
+  SelectionRule,
+};
+use tof_dataclasses::errors::SelectionError;
 
 //use tof_dataclasses::events::master_trigger::TriggerType;
 use tof_dataclasses::events::DataType;
@@ -608,7 +618,12 @@ impl Default for RBSettings {
 
 /// Settings to change the configuration of the analysis engine 
 /// (pulse extraction)
-#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
+///
+/// Fields missing in the configuration file take their
+/// default value (see new()), so that configuration files
+/// from before the analyzer registry keep working.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct AnalysisEngineSettings {
   /// pulse integration start
   pub integration_start      : f32,
@@ -620,9 +635,45 @@ pub struct AnalysisEngineSettings {
   pub pedestal_begin_bin     : usize,
   /// Pedestal width (bins)
   pub pedestal_win_bins      : usize,
-  /// Use a zscore algorithm to find the peaks instead
-  /// of Jeff's algorithm
+  /// Name of the waveform analyzer which extracts the
+  /// hits, e.g. "simple", "zscore" or "template" 
+  /// (see waveform_analyzer::AnalyzerRegistry)
+  pub analyzer               : String,
+  /// Name of a second waveform analyzer which runs on 
+  /// the same waveforms for comparison. Its results are 
+  /// only used for the comparison statistics, the hits 
+  /// are always from the analyzer above. Empty to disable
+  pub compare_analyzer       : String,
+  /// Fit the sine of channel 9 to get the phase of the
+  /// hits. Events without channel 9 data will fail the
+  /// analysis if this is set
+  pub fit_sine               : bool,
+  /// Align the hit times of all RBs of an event to the
+  /// clock of a reference RB with the channel 9 sine
+  /// fits (needs fit_sine)
+  pub align_clocks           : bool,
+  /// RBs to use as clock reference, in order of 
+  /// preference. If none of them has a good sine fit,
+  /// the RB with the lowest id and a good fit is used
+  pub clock_reference_rbs    : Vec<u8>,
+  /// Minimum amplitude [mV] of a good ch9 sine fit
+  pub sine_min_amplitude     : f32,
+  /// Maximum amplitude [mV] of a good ch9 sine fit
+  pub sine_max_amplitude     : f32,
+  /// Maximum rms of the ch9 sine fit residuals 
+  /// relative to the amplitude
+  pub sine_max_rel_rms       : f32,
+  /// Deprecated, same as analyzer = "zscore"
   pub use_zscore             : bool,
+  /// Lag (in bins) of the moving window of the zscore 
+  /// peak finder
+  pub zscore_lag             : usize,
+  /// Number of standard deviations over the moving mean 
+  /// at which the zscore peak finder signals
+  pub zscore_threshold       : f32,
+  /// Influence (0-1) of signals on the moving mean of 
+  /// the zscore peak finder
+  pub zscore_influence       : f32,
   /// Peakfinding start time
   pub find_pks_t_start       : f32,
   /// Peakfinding window
@@ -634,7 +685,39 @@ pub struct AnalysisEngineSettings {
   /// Max allowed peaks
   pub max_peaks              : usize,
   /// Timing CFG fraction
-  pub cfd_fraction           : f32
+  pub cfd_fraction           : f32,
+  /// Input impedance [Ω] of the RB channels, to 
+  /// get the pulse charge from the integrated voltage
+  pub impedance              : f32,
+  /// Deprecated, same as analyzer = "template". The
+  /// template analyzer uses a fit of the per-paddle-end
+  /// pulse templates for hit timing and charge instead 
+  /// of the CFD. Paddle ends without a template fall 
+  /// back to the CFD
+  pub use_template_fit       : bool,
+  /// JSON file with the pulse template library
+  /// (see tof_dataclasses::pulse_template)
+  pub template_file          : String,
+  /// Search range for the template time fit around
+  /// the CFD time [ns]
+  pub template_search_range  : f32,
+  /// Voltage [mV] above which a pulse is flagged as saturated
+  pub saturation_level       : f32,
+  /// Publish the baseline and noise of the RB channels 
+  /// (RBChannelNoiseMoniData) every noise_moni_interval
+  /// seconds. 0 disables the noise monitoring
+  pub noise_moni_interval    : u16,
+  /// JSON file with the paddle timing calibration 
+  /// (see tof_dataclasses::timing_calibration). The
+  /// offsets get subtracted from the hit times. 
+  /// Empty for no timing calibration
+  pub timing_calibration_file : String,
+  /// JSON file with the paddle gain constants 
+  /// (see tof_dataclasses::energy_calibration). The
+  /// constants valid for the run are used for the 
+  /// calibrated energy deposition of the hits.
+  /// Empty for no energy calibration
+  pub energy_calibration_file : String,
 }
 
 impl AnalysisEngineSettings {
@@ -645,15 +728,69 @@ impl AnalysisEngineSettings {
       pedestal_thresh           : 10.0,
       pedestal_begin_bin        : 10,
       pedestal_win_bins         : 50,
+      analyzer                  : String::from("simple"),
+      compare_analyzer          : String::from(""),
+      fit_sine                  : true,
+      align_clocks              : false,
+      clock_reference_rbs       : Vec::<u8>::new(),
+      sine_min_amplitude        : 10.0,
+      sine_max_amplitude        : 1000.0,
+      sine_max_rel_rms          : 0.2,
       use_zscore                : false,
+      zscore_lag                : 30,
+      zscore_threshold          : 5.0,
+      zscore_influence          : 0.0,
       find_pks_t_start          : 270.0,
       find_pks_t_window         : 70.0,
       min_peak_size             : 3,
       find_pks_thresh           : 10.0,
       max_peaks                 : 5,
-      cfd_fraction              : 0.2
+      cfd_fraction              : 0.2,
+      impedance                 : 50.0,
+      use_template_fit          : false,
+      template_file             : String::from(""),
+      template_search_range     : 2.0,
+      saturation_level          : PULSE_SATURATION_MV,
+      noise_moni_interval       : 60,
+      timing_calibration_file   : String::from(""),
+      energy_calibration_file   : String::from(""),
     }
   }
+
+  /// The name of the analyzer to use for the hits. 
+  ///
+  /// The deprecated use_template_fit and use_zscore 
+  /// flags take precedence over the default "simple" 
+  /// analyzer, so that older configuration files 
+  /// keep working.
+  pub fn get_analyzer_name(&self) -> &str {
+    if self.analyzer == "simple" {
+      if self.use_template_fit {
+        return "template";
+      }
+      if self.use_zscore {
+        return "zscore";
+      }
+    }
+    &self.analyzer
+  }
+
+  /// The clock alignment as configured
+  pub fn get_clock_aligner(&self) -> ClockAligner {
+    let mut aligner       = ClockAligner::new();
+    aligner.reference_rbs = self.clock_reference_rbs.clone();
+    aligner.min_amplitude = self.sine_min_amplitude;
+    aligner.max_amplitude = self.sine_max_amplitude;
+    aligner.max_rel_rms   = self.sine_max_rel_rms;
+    aligner
+  }
+
+  /// Check if any of the configured analyzers needs 
+  /// the pulse templates
+  pub fn needs_templates(&self) -> bool {
+    self.get_analyzer_name() == "template" 
+    || self.compare_analyzer == "template"
+  }
 }
 
 impl fmt::Display for AnalysisEngineSettings {
@@ -671,7 +808,12 @@ impl Default for AnalysisEngineSettings {
 }
 
 /// Settings to change the configuration of the TOF Eventbuilder
+///
+/// Fields missing in the configuration file take their
+/// default value (see new()), so that configuration files
+/// from before the beta reconstruction keep working.
 #[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct TofEventBuilderSettings {
   pub cachesize           : u32,
   pub n_mte_per_loop      : u32,
@@ -695,6 +837,13 @@ pub struct TofEventBuilderSettings {
   pub thr_tot_edep_umb      : f32,
   pub thr_tot_edep_cbe      : f32,
   pub thr_tot_edep_cor      : f32,
+  /// Fit a straight track to the hits and reconstruct
+  /// beta. If only_save_interesting is set, events
+  /// additionally need a successful fit with beta
+  /// within [thr_beta_min, thr_beta_max]
+  pub reconstruct_beta      : bool,
+  pub thr_beta_min          : f32,
+  pub thr_beta_max          : f32,
 }
 
 impl TofEventBuilderSettings {
@@ -716,6 +865,9 @@ impl TofEventBuilderSettings {
       thr_tot_edep_umb      : 0.0,
       thr_tot_edep_cbe      : 0.0,
       thr_tot_edep_cor      : 0.0,
+      reconstruct_beta      : false,
+      thr_beta_min          : 0.0,
+      thr_beta_max          : 2.0,
     }
   }
 
@@ -883,6 +1035,166 @@ impl Default for DataPublisherSettings {
 }
 
 
+/// Rules to assign an EventQuality to the events 
+/// (see tof_dataclasses::events::EventClassifier).
+/// The rules are checked from four_leaf_clover 
+/// downwards, the event gets the first grade for 
+/// which it passes all the rules.
+///
+/// Fields missing in the configuration file take their
+/// default value (see new()).
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct EventQualitySettings {
+  /// Assign a quality to each event in the 
+  /// event builder. This needs the paddle 
+  /// information and costs some time per event,
+  /// so it is off by default.
+  pub classify_events  : bool,
+  /// Also check for triggered paddles without HG 
+  /// hits. This is slow!
+  pub use_missing_hg   : bool,
+  pub silver           : EventQualityRules,
+  pub gold             : EventQualityRules,
+  pub diamond          : EventQualityRules,
+  pub four_leaf_clover : EventQualityRules,
+}
+
+impl EventQualitySettings {
+  pub fn new() -> Self {
+    let cls = EventClassifier::new();
+    Self {
+      classify_events  : false,
+      use_missing_hg   : false,
+      silver           : cls.silver,
+      gold             : cls.gold,
+      diamond          : cls.diamond,
+      four_leaf_clover : cls.four_leaf_clover,
+    }
+  }
+
+  pub fn get_classifier(&self) -> EventClassifier {
+    EventClassifier {
+      silver           : self.silver.clone(),
+      gold             : self.gold.clone(),
+      diamond          : self.diamond.clone(),
+      four_leaf_clover : self.four_leaf_clover.clone(),
+    }
+  }
+}
+
+impl fmt::Display for EventQualitySettings {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let disp = toml::to_string(self).unwrap_or(
+      String::from("-- DESERIALIZATION ERROR! --"));
+    write!(f, "<EventQualitySettings :\n{}>", disp)
+  }
+}
+
+impl Default for EventQualitySettings {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Tag interesting events for downlink prioritization.
+///
+/// Each rule is a list of conditions, e.g. 
+/// ["n_hits_umb >= 1", "tot_edep_cbe > 20"], which all
+/// have to be fulfilled. The result is stored as a 
+/// bitmask in TofEventSummary::interesting and 
+/// the number of events passing each rule is counted
+/// in the EVTBLDRHeartbeat.
+/// See tof_dataclasses::events::selection for the 
+/// available variables. At most 8 rules are allowed.
+///
+/// Fields missing in the configuration file take their
+/// default value (see new()).
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct InterestingEventSettings {
+  /// Evaluate the rules in the event builder. This 
+  /// switches the TofEventSummary to ProtocolVersion::V2
+  pub tag_events : bool,
+  pub rules      : Vec<SelectionRule>,
+}
+
+impl InterestingEventSettings {
+  pub fn new() -> Self {
+    let rules = vec![
+      SelectionRule::new("umb_cube",   &["n_hits_umb >= 1", "n_hits_cbe >= 1"]),
+      SelectionRule::new("high_mult",  &["n_hits >= 10"]),
+      SelectionRule::new("high_edep",  &["max_edep > 10"]),
+    ];
+    Self {
+      tag_events : false,
+      rules,
+    }
+  }
+
+  pub fn get_selector(&self) -> Result<EventSelector, SelectionError> {
+    EventSelector::from_rules(&self.rules)
+  }
+}
+
+impl fmt::Display for InterestingEventSettings {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let disp = toml::to_string(self).unwrap_or(
+      String::from("-- DESERIALIZATION ERROR! --"));
+    write!(f, "<InterestingEventSettings :\n{}>", disp)
+  }
+}
+
+impl Default for InterestingEventSettings {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Checks of the RB calibrations before liftof-cc 
+/// uses them.
+///
+/// The RBs send a quality report (see
+/// tof_dataclasses::calibration_quality) with each
+/// calibration. A calibration failing any of the
+/// cuts is not installed, the board keeps its 
+/// previous calibration.
+///
+/// Fields missing in the configuration file take their
+/// default value (see new()), so without the section
+/// failures are only reported.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct CalibrationQualitySettings {
+  /// Refuse calibrations which fail the cuts. If 
+  /// false (default), failures are only reported.
+  pub reject_bad_calibrations : bool,
+  pub cuts                    : RBCalibrationQualityCuts,
+}
+
+impl CalibrationQualitySettings {
+  pub fn new() -> Self {
+    Self {
+      reject_bad_calibrations : false,
+      cuts                    : RBCalibrationQualityCuts::new(),
+    }
+  }
+}
+
+impl fmt::Display for CalibrationQualitySettings {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let disp = toml::to_string(self).unwrap_or(
+      String::from("-- DESERIALIZATION ERROR! --"));
+    write!(f, "<CalibrationQualitySettings :\n{}>", disp)
+  }
+}
+
+impl Default for CalibrationQualitySettings {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct LiftofSettings {
   /// read run .toml files from this directory and 
@@ -923,6 +1235,14 @@ pub struct LiftofSettings {
   pub mtb_settings               : MTBSettings,
   /// Settings for the TOF event builder
   pub event_builder_settings     : TofEventBuilderSettings,
+  /// Rules for the event quality classification
+  /// (not classifying if the section is missing)
+  #[serde(default)]
+  pub event_quality_settings     : EventQualitySettings,
+  /// Rules for tagging interesting events
+  /// (not tagging if the section is missing)
+  #[serde(default)]
+  pub interesting_event_settings : InterestingEventSettings,
   /// Settings for the analysis engine
   pub analysis_engine_settings   : AnalysisEngineSettings,
   /// Configure data publshing and saving on local disc
@@ -931,6 +1251,10 @@ pub struct LiftofSettings {
   pub cmd_dispatcher_settings    : CommandDispatcherSettings,
   /// Settings for the individual RBs
   pub rb_settings                : RBSettings,
+  /// Quality checks of the RB calibrations
+  /// (report only if the section is missing)
+  #[serde(default)]
+  pub calibration_quality_settings : CalibrationQualitySettings,
   /// Mask individual channels (e.g. dead preamps) 
   /// for the readout boards
   pub rb_channel_mask            : ChannelMaskSettings,
@@ -957,10 +1281,13 @@ impl LiftofSettings {
       verification_runtime_sec  : 0, // no verification run per default
       mtb_settings              : MTBSettings::new(),
       event_builder_settings    : TofEventBuilderSettings::new(),
+      event_quality_settings    : EventQualitySettings::new(),
+      interesting_event_settings: InterestingEventSettings::new(),
       analysis_engine_settings  : AnalysisEngineSettings::new(),
       data_publisher_settings   : DataPublisherSettings::new(),
       cmd_dispatcher_settings   : CommandDispatcherSettings::new(),
       rb_settings               : RBSettings::new(),
+      calibration_quality_settings : CalibrationQualitySettings::new(),
       rb_channel_mask           : ChannelMaskSettings::new(),
       preamp_settings           : PreampSettings::new(),
       ltb_settings              : LTBThresholdSettings::new(),
@@ -1128,6 +1455,149 @@ impl Default for LiftofSettings {
   }
 }
 
+/// Configuration for the offline reprocessing of
+/// TofEvent run files (see liftof_lib::reprocessing)
+///
+/// The reprocessed files are written to
+/// output_dir/tag, together with a copy of this
+/// configuration.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ReprocessingSettings {
+  /// Name for this reprocessing configuration.
+  /// The output will be in a subdirectory with
+  /// this name.
+  pub tag                        : String,
+  /// Base directory for the reprocessed files
+  /// (default is the current directory)
+  pub output_dir                 : String,
+  /// Load the newest calibration for each RB
+  /// from this directory
+  pub calibration_dir            : String,
+  /// If not empty, take the RB calibrations which
+  /// are valid for the run of the events from the
+  /// calibration store in this directory instead.
+  /// Boards without a calibration for the run in
+  /// the store keep the one from calibration_dir.
+  pub calibration_store          : String,
+  /// Location of the database (RB and paddle
+  /// information)
+  pub db_path                    : String,
+  /// Number of files which are processed in
+  /// parallel
+  pub n_threads                  : usize,
+  /// Additionally write .tofsum. files with the
+  /// rebuilt TofEventSummaries
+  pub write_summary              : bool,
+  /// Copy all packets which are not TofEvents
+  /// (e.g. heartbeats, monitoring) to the output
+  pub copy_other_packets         : bool,
+  /// Rules for the event quality classification
+  pub event_quality_settings     : EventQualitySettings,
+  /// Rules for tagging interesting events
+  pub interesting_event_settings : InterestingEventSettings,
+  /// Settings for the waveform analysis
+  pub analysis_engine_settings   : AnalysisEngineSettings,
+}
+
+impl ReprocessingSettings {
+  pub fn new() -> Self {
+    Self {
+      tag                        : String::from("reprocessed"),
+      output_dir                 : String::from("."),
+      calibration_dir            : String::from(""),
+      calibration_store          : String::from(""),
+      db_path                    : String::from("/home/gaps/config/gaps_flight.db"),
+      n_threads                  : 4,
+      write_summary              : true,
+      copy_other_packets         : true,
+      event_quality_settings     : EventQualitySettings::new(),
+      interesting_event_settings : InterestingEventSettings::new(),
+      analysis_engine_settings   : AnalysisEngineSettings::new(),
+    }
+  }
+
+  /// Start from the settings which were used for
+  /// a run
+  pub fn from_liftof_settings(settings : &LiftofSettings) -> Self {
+    let mut rp_settings = Self::new();
+    rp_settings.calibration_dir            = settings.calibration_dir.clone();
+    // liftof-cc records its calibrations there
+    rp_settings.calibration_store          = settings.calibration_dir.clone();
+    rp_settings.db_path                    = settings.db_path.clone();
+    rp_settings.event_quality_settings     = settings.event_quality_settings.clone();
+    rp_settings.interesting_event_settings = settings.interesting_event_settings.clone();
+    rp_settings.analysis_engine_settings   = settings.analysis_engine_settings.clone();
+    rp_settings
+  }
+
+  /// Write the settings to a toml file
+  pub fn to_toml(&self, mut filename : String) {
+    if !filename.ends_with(".toml") {
+      filename += ".toml";
+    }
+    info!("Will write to file {}!", filename);
+    match File::create(&filename) {
+      Err(err) => {
+        error!("Unable to open file {}! {}", filename, err);
+      }
+      Ok(mut file) => {
+        match toml::to_string_pretty(&self) {
+          Err(err) => {
+            error!("Unable to serialize toml! {err}");
+          }
+          Ok(toml_string) => {
+            match file.write_all(toml_string.as_bytes()) {
+              Err(err) => error!("Unable to write to file {}! {}", filename, err),
+              Ok(_)    => debug!("Wrote settings to {}!", filename)
+            }
+          }
+        }
+      }
+    }
+  }
+
+  pub fn from_toml(filename : &str) -> Result<ReprocessingSettings, SerializationError> {
+    match File::open(filename) {
+      Err(err) => {
+        error!("Unable to open {}! {}", filename, err);
+        Err(SerializationError::TomlDecodingError)
+      }
+      Ok(mut file) => {
+        let mut toml_string = String::from("");
+        match file.read_to_string(&mut toml_string) {
+          Err(err) => {
+            error!("Unable to read {}! {}", filename, err);
+            Err(SerializationError::TomlDecodingError)
+          }
+          Ok(_) => {
+            match toml::from_str(&toml_string) {
+              Err(err) => {
+                error!("Can't interpret toml! {}", err);
+                Err(SerializationError::TomlDecodingError)
+              }
+              Ok(settings) => Ok(settings)
+            }
+          }
+        }
+      }
+    }
+  }
+}
+
+impl fmt::Display for ReprocessingSettings {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let disp = toml::to_string(self).unwrap_or(
+      String::from("-- DESERIALIZATION ERROR! --"));
+    write!(f, "<ReprocessingSettings :\n{}>", disp)
+  }
+}
+
+impl Default for ReprocessingSettings {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 /// Readoutboard configuration for a specific run
 #[derive(Debug, Copy, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct LiftofRBConfig {
@@ -1433,3 +1903,76 @@ fn read_config_file() {
 }
 
 
+
+#[test]
+fn analysis_engine_settings_defaults() {
+  // a section as written before the analyzer 
+  // registry, e.g. without analyzer and zscore_* 
+  let cfg = "integration_start = 270.0\n\
+             integration_window = 70.0\n\
+             use_template_fit = true\n";
+  let settings : AnalysisEngineSettings = toml::from_str(cfg).unwrap();
+  assert_eq!(settings.analyzer, "simple");
+  assert_eq!(settings.compare_analyzer, "");
+  assert!(settings.fit_sine);
+  assert_eq!(settings.zscore_lag, 30);
+  assert_eq!(settings.get_analyzer_name(), "template");
+}
+
+#[test]
+fn event_quality_settings_defaults() {
+  // a configuration file from before the event 
+  // quality classification
+  let mut cfg = toml::Value::try_from(LiftofSettings::new()).unwrap();
+  cfg.as_table_mut().unwrap().remove("event_quality_settings");
+  let settings : LiftofSettings = cfg.try_into().unwrap();
+  assert!(!settings.event_quality_settings.classify_events);
+  let quality : EventQualitySettings = toml::from_str("classify_events = true\n").unwrap();
+  assert!(quality.classify_events);
+  assert!(!quality.use_missing_hg);
+}
+
+#[test]
+fn interesting_event_settings_defaults() {
+  let mut cfg = toml::Value::try_from(LiftofSettings::new()).unwrap();
+  cfg.as_table_mut().unwrap().remove("interesting_event_settings");
+  let settings : LiftofSettings = cfg.try_into().unwrap();
+  assert!(!settings.interesting_event_settings.tag_events);
+  let tagging : InterestingEventSettings = toml::from_str("tag_events = true\n").unwrap();
+  assert!(tagging.tag_events);
+  assert_eq!(tagging.rules.len(), InterestingEventSettings::new().rules.len());
+}
+
+#[test]
+fn calibration_quality_settings_defaults() {
+  let mut cfg = toml::Value::try_from(LiftofSettings::new()).unwrap();
+  cfg.as_table_mut().unwrap().remove("calibration_quality_settings");
+  let settings : LiftofSettings = cfg.try_into().unwrap();
+  assert!(!settings.calibration_quality_settings.reject_bad_calibrations);
+  let cq : CalibrationQualitySettings = toml::from_str("reject_bad_calibrations = true\n\
+                                                        [cuts]\n\
+                                                        max_noise = 5.0\n").unwrap();
+  assert!(cq.reject_bad_calibrations);
+  assert_eq!(cq.cuts.max_noise, 5.0);
+  assert_eq!(cq.cuts.max_outlier_cells, RBCalibrationQualityCuts::new().max_outlier_cells);
+}
+
+#[test]
+fn event_builder_settings_defaults() {
+  // a section as written before the beta
+  // reconstruction
+  let cfg = "cachesize = 100000\n\
+             n_mte_per_loop = 1\n\
+             n_rbe_per_loop = 1\n\
+             te_timeout_sec = 15\n\
+             sort_events = false\n\
+             build_strategy = \"Adaptive\"\n\
+             greediness = 3\n\
+             wait_nrb = 40\n\
+             hb_send_interval = 10\n";
+  let settings : TofEventBuilderSettings = toml::from_str(cfg).unwrap();
+  assert_eq!(settings.te_timeout_sec, 15);
+  assert!(!settings.reconstruct_beta);
+  assert_eq!(settings.thr_beta_min, 0.0);
+  assert_eq!(settings.thr_beta_max, 2.0);
+}
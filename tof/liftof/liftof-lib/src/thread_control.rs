@@ -8,6 +8,7 @@ use tof_dataclasses::database::ReadoutBoard;
 
 use tof_dataclasses::status::TofDetectorStatus;
 use tof_dataclasses::calibrations::RBCalibrations;
+use tof_dataclasses::calibration_quality::RBCalibrationQuality;
 
 use crate::settings::LiftofSettings;
 
@@ -29,6 +30,12 @@ pub struct ThreadControl {
   pub finished_calibrations      : HashMap<u8,bool>,
   /// Hold the actual calibration data
   pub calibrations               : HashMap<u8, RBCalibrations>,
+  /// Calibrations which failed the quality 
+  /// checks and have not been installed
+  pub rejected_calibrations      : HashMap<u8, RBCalibrations>,
+  /// Quality reports of the last received 
+  /// calibrations
+  pub calibration_qualities      : HashMap<u8, RBCalibrationQuality>,
   /// Hold off the master trigger thread, until everything else
   /// is ready
   pub holdoff_mtb_thread         : bool,
@@ -77,6 +84,8 @@ impl ThreadControl {
       calibration_active         : false,
       finished_calibrations      : HashMap::<u8,bool>::new(),
       calibrations               : HashMap::<u8, RBCalibrations>::new(),
+      rejected_calibrations      : HashMap::<u8, RBCalibrations>::new(),
+      calibration_qualities      : HashMap::<u8, RBCalibrationQuality>::new(),
       sigint_recvd               : false,
       end_all_rb_threads         : false,
       holdoff_mtb_thread         : false,
@@ -114,6 +123,9 @@ impl fmt::Display for ThreadControl {
     for k in self.finished_calibrations.keys() {
       repr        += &(format!("\n  -- finished  {}  : {}", k, self.finished_calibrations.get(k).unwrap()));       
     }
+    for k in self.rejected_calibrations.keys() {
+      repr        += &(format!("\n  -- rejected  {}", k));
+    }
     repr        += &(format!("\n    -- verification run: {}", self.verification_active));
     repr        += "\n    -- program status:";
     repr        += &(format!("\n  stop flag        : {}", self.stop_flag));
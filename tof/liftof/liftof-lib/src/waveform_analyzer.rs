@@ -0,0 +1,856 @@
+//! Pluggable waveform analysis
+//!
+//! The pulse extraction for the waveform of a single
+//! paddle end is done by a `WaveformAnalyzer`. The
+//! analyzers are registered by name in the
+//! `AnalyzerRegistry` and selected with `analyzer` in
+//! the `AnalysisEngineSettings`.
+//!
+//! The `AnalysisEngine` does everything which is the
+//! same for all analyzers (voltage and time calibration,
+//! pedestal subtraction, sine fit, timing and energy
+//! calibration) and can run a second analyzer on the
+//! same waveforms to compare the two. The result of the
+//! sine fit is stored in the RBEventHeader, so that the
+//! hits of the different boards can be aligned later
+//! (see tof_dataclasses::clock_alignment).
+//!
+//! Available analyzers:
+//!
+//! * "simple"   : threshold peak finder, CFD timing and
+//!                charge integration
+//! * "zscore"   : like simple, but with the zscore
+//!                peak finder
+//! * "template" : like simple, but time and charge are
+//!                replaced by a fit of the pulse template
+//!                of the paddle end (if available)
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use half::f16;
+
+use tof_dataclasses::constants::{
+  NWORDS,
+  NCHN,
+};
+use tof_dataclasses::calibrations::RBWaveforms;
+use tof_dataclasses::database::ReadoutBoard;
+use tof_dataclasses::errors::AnalysisError;
+use tof_dataclasses::events::{
+  RBEvent,
+  TofHit,
+  TofPulse,
+};
+use tof_dataclasses::events::tof_hit::{
+  TOFHIT_PILEUP_A,
+  TOFHIT_PILEUP_B,
+};
+use tof_dataclasses::analysis::{
+  calculate_pedestal,
+  integrate,
+  cfd_simple,
+  find_peaks,
+  find_peaks_zscore,
+};
+use tof_dataclasses::pulse_template::fit_template;
+
+use crate::settings::AnalysisEngineSettings;
+use crate::{
+  fit_sine_sydney,
+  sine_fit_rms,
+};
+
+/// The hit information of a single paddle end as
+/// extracted by a WaveformAnalyzer
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PulseResult {
+  /// Time of the (first) pulse [ns], 0 if no
+  /// pulse was found
+  pub time      : f32,
+  /// Peak voltage [mV]
+  pub peak      : f32,
+  /// Charge [pC]
+  pub charge    : f32,
+  pub saturated : bool,
+  /// Reduced chi2 of a pulse fit, 0 if the pulse
+  /// was not fitted
+  pub fit_chi2  : f32,
+  /// Further pulses after the first one
+  pub secondary : Vec<TofPulse>,
+  /// A secondary pulse started before the first 
+  /// one had recovered (see is_pileup)
+  pub pileup    : bool,
+}
+
+impl PulseResult {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn has_pulse(&self) -> bool {
+    self.time > 0.0
+  }
+}
+
+impl fmt::Display for PulseResult {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = String::from("<PulseResult:");
+    repr += &(format!("\n  time      : {:.3}", self.time));
+    repr += &(format!("\n  peak      : {:.2}", self.peak));
+    repr += &(format!("\n  charge    : {:.2}", self.charge));
+    repr += &(format!("\n  saturated : {}", self.saturated));
+    repr += &(format!("\n  fit chi2  : {:.2}", self.fit_chi2));
+    repr += &(format!("\n  secondary : {}", self.secondary.len()));
+    repr += &(format!("\n  pileup    : {}>", self.pileup));
+    write!(f, "{}", repr)
+  }
+}
+
+/// Memory for the calibrated waveforms of all channels
+/// of a RBEvent, which is reused from event to event.
+///
+/// Each thread running the analysis needs its own.
+pub struct WaveformBuffers {
+  /// Calibrated voltages [mV] \[channel 0-8\]\[sample\]
+  pub voltages : Box<RBWaveforms>,
+  /// Calibrated times [ns] \[channel 0-8\]\[sample\]
+  pub times    : Box<RBWaveforms>,
+}
+
+impl WaveformBuffers {
+  pub fn new() -> Self {
+    Self {
+      voltages : Box::new([[0.0;NWORDS];NCHN]),
+      times    : Box::new([[0.0;NWORDS];NCHN]),
+    }
+  }
+}
+
+impl Default for WaveformBuffers {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Extract time, peak and charge of the pulse(s) in
+/// the waveform of a single paddle end.
+///
+/// Implementations have to be stateless, the same
+/// analyzer is used for all boards (and threads).
+pub trait WaveformAnalyzer : Send + Sync {
+  /// The name the analyzer is registered with
+  fn name(&self) -> &'static str;
+
+  /// Analyze the waveform of a single paddle end
+  ///
+  /// # Arguments
+  ///
+  /// * voltages      : calibrated and pedestal subtracted
+  ///                   waveform [mV]
+  /// * times         : calibrated waveform times [ns]
+  /// * ped_err       : rms of the pedestal [mV]
+  /// * paddle_end_id : 1000 + paddle id for the A side,
+  ///                   2000 + paddle id for the B side
+  /// * rb            : the board the waveform is from
+  ///                   (e.g. for the pulse templates)
+  /// * settings      : analysis engine settings
+  fn analyze(&self,
+             voltages      : &[f32],
+             times         : &[f32],
+             ped_err       : f32,
+             paddle_end_id : u16,
+             rb            : &ReadoutBoard,
+             settings      : &AnalysisEngineSettings) -> PulseResult;
+}
+
+/// The waveform has to drop below this fraction of 
+/// the peak of the first pulse, before a secondary 
+/// pulse does not count as pile-up anymore
+pub const PILEUP_RECOVERY_FRACTION : f32 = 0.1;
+
+/// A secondary pulse piles up on the first pulse if 
+/// it starts before the waveform has recovered from 
+/// the first one, that is, dropped below 
+/// PILEUP_RECOVERY_FRACTION of its peak.
+///
+/// # Arguments
+///
+/// * voltages      : calibrated waveform [mV]
+/// * first_max_bin : bin of the maximum of the first
+///                   pulse
+/// * start_bin     : first bin of the secondary pulse
+pub fn is_pileup(voltages      : &[f32],
+                 first_max_bin : usize,
+                 start_bin     : usize) -> bool {
+  if start_bin <= first_max_bin {
+    return true;
+  }
+  let threshold = PILEUP_RECOVERY_FRACTION*voltages[first_max_bin];
+  !voltages[first_max_bin..start_bin].iter().any(|v| *v < threshold)
+}
+
+/// Get time, peak and charge for the peaks found by
+/// any of the peak finders. The first peak gives
+/// time, peak and charge of the hit, all further
+/// peaks are stored as secondary pulses.
+fn pulses_from_peaks(voltages : &[f32],
+                     times    : &[f32],
+                     peaks    : &[(usize, usize)],
+                     settings : &AnalysisEngineSettings) -> PulseResult {
+  let mut result = PulseResult::new();
+  let mut first_max_bin = 0usize;
+  let mut cfd_times = Vec::<f32>::new();
+  for (n_pk, pk) in peaks.iter().enumerate() {
+    if n_pk > 0 {
+      // secondary pulses
+      let pk_time = match cfd_simple(voltages,
+                                     times,
+                                     settings.cfd_fraction,
+                                     pk.0, pk.1) {
+        Err(err) => {
+          debug!("Unable to calculate cfd for peak {} {}! {}", pk.0, pk.1, err);
+          continue;
+        }
+        Ok(cfd) => cfd
+      };
+      let mut pk_max = pk.0;
+      for n in pk.0..pk.1 {
+        if voltages[n] > voltages[pk_max] {
+          pk_max = n;
+        }
+      }
+      let start_q_int = pk_max.saturating_sub(40);
+      let stop_q_int  = usize::min(pk_max + 160, voltages.len() - 1);
+      let pk_charge   = match integrate(voltages,
+                                        times,
+                                        start_q_int,
+                                        stop_q_int,
+                                        settings.impedance) {
+        Err(err) => {
+          error!("Integration failed! Err {err}");
+          0.0
+        }
+        Ok(chrg) => chrg
+      };
+      if is_pileup(voltages, first_max_bin, pk.0) {
+        result.pileup = true;
+      }
+      result.secondary.push(TofPulse {
+        time   : f16::from_f32(pk_time),
+        peak   : f16::from_f32(voltages[pk_max]),
+        charge : f16::from_f32(pk_charge),
+      });
+      continue;
+    }
+    match cfd_simple(voltages,
+                     times,
+                     settings.cfd_fraction,
+                     pk.0, pk.1) {
+      Err(err) => {
+        debug!("Unable to calculate cfd for peak {} {}! {}", pk.0, pk.1, err);
+      }
+      Ok(cfd) => {
+        cfd_times.push(cfd);
+      }
+    }
+    let pk_height = voltages[pk.0..pk.1].iter().max_by(|a,b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Less)).unwrap();
+    result.peak = *pk_height;
+    let max_index = voltages.iter().position(|element| *element == result.peak).unwrap();
+
+    let (start_q_int, stop_q_int) = if max_index.saturating_sub(40) < 10 {
+      (10, 210)
+    } else {
+      (max_index - 40, max_index + 160)
+    };
+    first_max_bin = max_index;
+    match integrate(voltages,
+                    times,
+                    start_q_int,
+                    stop_q_int,
+                    settings.impedance) {
+      Err(err) => {
+        error!("Integration failed! Err {err}");
+      }
+      Ok(chrg)   => {
+        result.charge = chrg;
+      }
+    }
+  }
+  if !cfd_times.is_empty() {
+    result.time = cfd_times[0];
+  }
+  result.saturated = result.peak >= settings.saturation_level;
+  result
+}
+
+/// Jeff's threshold peak finder, CFD timing and
+/// charge integration
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SimpleAnalyzer;
+
+impl WaveformAnalyzer for SimpleAnalyzer {
+  fn name(&self) -> &'static str {
+    "simple"
+  }
+
+  fn analyze(&self,
+             voltages      : &[f32],
+             times         : &[f32],
+             _ped_err      : f32,
+             paddle_end_id : u16,
+             rb            : &ReadoutBoard,
+             settings      : &AnalysisEngineSettings) -> PulseResult {
+    match find_peaks(voltages ,
+                     times    ,
+                     settings.find_pks_t_start ,
+                     settings.find_pks_t_window,
+                     settings.min_peak_size    ,
+                     settings.find_pks_thresh  ,
+                     settings.max_peaks      ) {
+      Err(err) => {
+        // FIXME - if this happens, most likely the channel is dead.
+        debug!("Unable to find peaks for RB{:02} paddle end {}! Ignoring this channel!", rb.rb_id, paddle_end_id);
+        debug!("We won't be able to calculate timing information for this channel! Err {err}");
+        PulseResult::new()
+      }
+      Ok(peaks) => pulses_from_peaks(voltages, times, &peaks, settings)
+    }
+  }
+}
+
+/// Like the SimpleAnalyzer, but the peaks are found
+/// with the (smoothed) zscore algorithm
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ZScoreAnalyzer;
+
+impl WaveformAnalyzer for ZScoreAnalyzer {
+  fn name(&self) -> &'static str {
+    "zscore"
+  }
+
+  fn analyze(&self,
+             voltages      : &[f32],
+             times         : &[f32],
+             _ped_err      : f32,
+             paddle_end_id : u16,
+             rb            : &ReadoutBoard,
+             settings      : &AnalysisEngineSettings) -> PulseResult {
+    match find_peaks_zscore(times,
+                            voltages,
+                            settings.find_pks_t_start,
+                            settings.find_pks_t_window,
+                            settings.zscore_lag,
+                            settings.zscore_threshold as f64,
+                            settings.zscore_influence as f64) {
+      Err(err) => {
+        debug!("Unable to find peaks for RB{:02} paddle end {}! Ignoring this channel! Err {err}", rb.rb_id, paddle_end_id);
+        PulseResult::new()
+      }
+      Ok(peaks) => {
+        let peaks : Vec<(usize, usize)> = peaks.into_iter()
+          .filter(|pk| pk.1 + 1 - pk.0 >= settings.min_peak_size)
+          .take(settings.max_peaks)
+          .collect();
+        pulses_from_peaks(voltages, times, &peaks, settings)
+      }
+    }
+  }
+}
+
+/// Like the SimpleAnalyzer, but time, peak and charge
+/// of the first pulse are replaced by a fit of the
+/// pulse template of the paddle end. Paddle ends
+/// without template (or a failed fit) keep the CFD
+/// results.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct TemplateFitAnalyzer;
+
+impl WaveformAnalyzer for TemplateFitAnalyzer {
+  fn name(&self) -> &'static str {
+    "template"
+  }
+
+  fn analyze(&self,
+             voltages      : &[f32],
+             times         : &[f32],
+             ped_err       : f32,
+             paddle_end_id : u16,
+             rb            : &ReadoutBoard,
+             settings      : &AnalysisEngineSettings) -> PulseResult {
+    let mut result = SimpleAnalyzer.analyze(voltages, times, ped_err, paddle_end_id, rb, settings);
+    if !result.has_pulse() {
+      return result;
+    }
+    match rb.templates.get(paddle_end_id) {
+      None => {
+        trace!("No pulse template for paddle end {}, using cfd!", paddle_end_id);
+      }
+      Some(template) => {
+        match fit_template(voltages,
+                           times,
+                           template,
+                           result.time,
+                           settings.template_search_range,
+                           ped_err,
+                           settings.saturation_level) {
+          Err(err) => {
+            debug!("Template fit failed for paddle end {}, using cfd! {}", paddle_end_id, err);
+          }
+          Ok(fit) => {
+            result.time      = fit.time;
+            result.peak      = fit.amplitude;
+            result.charge    = fit.get_charge(template, settings.impedance);
+            result.fit_chi2  = fit.get_reduced_chi2();
+            result.saturated = result.saturated || fit.saturated;
+          }
+        }
+      }
+    }
+    result
+  }
+}
+
+/// Creates a new instance of an analyzer
+pub type AnalyzerConstructor = fn() -> Box<dyn WaveformAnalyzer>;
+
+/// Look up waveform analyzers by name
+pub struct AnalyzerRegistry {
+  constructors : HashMap<String, AnalyzerConstructor>,
+}
+
+impl AnalyzerRegistry {
+
+  /// A registry with all analyzers of this crate
+  pub fn new() -> Self {
+    let mut registry = Self {
+      constructors : HashMap::<String, AnalyzerConstructor>::new(),
+    };
+    registry.register("simple",   || Box::new(SimpleAnalyzer));
+    registry.register("zscore",   || Box::new(ZScoreAnalyzer));
+    registry.register("template", || Box::new(TemplateFitAnalyzer));
+    registry
+  }
+
+  /// Add an analyzer. An analyzer with the same name
+  /// will be replaced.
+  pub fn register(&mut self, name : &str, constructor : AnalyzerConstructor) {
+    self.constructors.insert(String::from(name), constructor);
+  }
+
+  /// The names of all registered analyzers (sorted)
+  pub fn get_names(&self) -> Vec<String> {
+    let mut names : Vec<String> = self.constructors.keys().cloned().collect();
+    names.sort();
+    names
+  }
+
+  /// Create the analyzer registered with this name
+  pub fn build(&self, name : &str) -> Result<Box<dyn WaveformAnalyzer>, AnalysisError> {
+    match self.constructors.get(name) {
+      None => {
+        error!("No waveform analyzer with name {}! Available are {:?}", name, self.get_names());
+        Err(AnalysisError::UnknownAnalyzer)
+      }
+      Some(constructor) => Ok(constructor())
+    }
+  }
+}
+
+impl Default for AnalyzerRegistry {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Statistics of the differences between the hits of
+/// two analyzers which ran on the same waveforms
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AnalyzerComparison {
+  pub analyzer         : String,
+  pub reference        : String,
+  /// Paddle ends where both analyzers found a pulse
+  pub n_both           : usize,
+  /// Paddle ends where only the analyzer found a pulse
+  pub n_only_analyzer  : usize,
+  /// Paddle ends where only the reference found a pulse
+  pub n_only_reference : usize,
+  sum_dt               : f64,
+  sum_dt2              : f64,
+  n_dq                 : usize,
+  sum_dq               : f64,
+  sum_dq2              : f64,
+}
+
+impl AnalyzerComparison {
+  pub fn new(analyzer : &str, reference : &str) -> Self {
+    Self {
+      analyzer  : String::from(analyzer),
+      reference : String::from(reference),
+      ..Default::default()
+    }
+  }
+
+  /// Add the results of both analyzers for the same
+  /// paddle end
+  pub fn add(&mut self, pulse : &PulseResult, reference : &PulseResult) {
+    match (pulse.has_pulse(), reference.has_pulse()) {
+      (true, true) => {
+        self.n_both  += 1;
+        let dt        = (pulse.time - reference.time) as f64;
+        self.sum_dt  += dt;
+        self.sum_dt2 += dt*dt;
+        if reference.charge != 0.0 {
+          let dq        = ((pulse.charge - reference.charge)/reference.charge) as f64;
+          self.n_dq    += 1;
+          self.sum_dq  += dq;
+          self.sum_dq2 += dq*dq;
+        }
+      }
+      (true, false) => self.n_only_analyzer  += 1,
+      (false, true) => self.n_only_reference += 1,
+      (false, false) => ()
+    }
+  }
+
+  /// Add the statistics of another comparison of
+  /// the same analyzers
+  pub fn merge(&mut self, other : &AnalyzerComparison) {
+    self.n_both           += other.n_both;
+    self.n_only_analyzer  += other.n_only_analyzer;
+    self.n_only_reference += other.n_only_reference;
+    self.sum_dt           += other.sum_dt;
+    self.sum_dt2          += other.sum_dt2;
+    self.n_dq             += other.n_dq;
+    self.sum_dq           += other.sum_dq;
+    self.sum_dq2          += other.sum_dq2;
+  }
+
+  /// Mean time difference analyzer - reference [ns]
+  pub fn get_mean_dt(&self) -> f64 {
+    if self.n_both == 0 {
+      return 0.0;
+    }
+    self.sum_dt/self.n_both as f64
+  }
+
+  /// Standard deviation of the time difference [ns]
+  pub fn get_std_dt(&self) -> f64 {
+    if self.n_both == 0 {
+      return 0.0;
+    }
+    let mean = self.get_mean_dt();
+    f64::max(self.sum_dt2/self.n_both as f64 - mean*mean, 0.0).sqrt()
+  }
+
+  /// Mean relative charge difference
+  /// (analyzer - reference)/reference
+  pub fn get_mean_dq(&self) -> f64 {
+    if self.n_dq == 0 {
+      return 0.0;
+    }
+    self.sum_dq/self.n_dq as f64
+  }
+
+  /// Standard deviation of the relative charge difference
+  pub fn get_std_dq(&self) -> f64 {
+    if self.n_dq == 0 {
+      return 0.0;
+    }
+    let mean = self.get_mean_dq();
+    f64::max(self.sum_dq2/self.n_dq as f64 - mean*mean, 0.0).sqrt()
+  }
+}
+
+impl fmt::Display for AnalyzerComparison {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut repr = format!("<AnalyzerComparison: {} vs {}", self.analyzer, self.reference);
+    repr += &(format!("\n  pulses found by both    : {}", self.n_both));
+    repr += &(format!("\n  -- only by {:<12} : {}", self.analyzer, self.n_only_analyzer));
+    repr += &(format!("\n  -- only by {:<12} : {}", self.reference, self.n_only_reference));
+    repr += &(format!("\n  time diff [ns]          : {:.3} +- {:.3}", self.get_mean_dt(), self.get_std_dt()));
+    repr += &(format!("\n  rel. charge diff        : {:.4} +- {:.4}>", self.get_mean_dq(), self.get_std_dq()));
+    write!(f, "{}", repr)
+  }
+}
+
+/// Runs the configured WaveformAnalyzer(s) over the
+/// waveforms of RBEvents to create the TofHits.
+///
+/// The engine is meant to be created once (e.g. per
+/// readoutboard thread) and can be shared between
+/// threads.
+pub struct AnalysisEngine {
+  pub settings : AnalysisEngineSettings,
+  analyzer     : Box<dyn WaveformAnalyzer>,
+  reference    : Option<Box<dyn WaveformAnalyzer>>,
+  comparison   : Mutex<AnalyzerComparison>,
+}
+
+impl AnalysisEngine {
+
+  /// Set up the analyzers from the settings. Fails if
+  /// any of the analyzer names is unknown.
+  pub fn new(settings : &AnalysisEngineSettings) -> Result<Self, AnalysisError> {
+    Self::from_registry(settings, &AnalyzerRegistry::new())
+  }
+
+  /// Same as new, but the analyzers are taken from the
+  /// given registry (e.g. with additional analyzers)
+  pub fn from_registry(settings : &AnalysisEngineSettings,
+                       registry : &AnalyzerRegistry) -> Result<Self, AnalysisError> {
+    let analyzer  = registry.build(settings.get_analyzer_name())?;
+    let reference = if settings.compare_analyzer.is_empty() {
+      None
+    } else {
+      Some(registry.build(&settings.compare_analyzer)?)
+    };
+    let comparison = match &reference {
+      None      => AnalyzerComparison::default(),
+      Some(ana) => AnalyzerComparison::new(analyzer.name(), ana.name())
+    };
+    Ok(Self {
+      settings   : settings.clone(),
+      analyzer,
+      reference,
+      comparison : Mutex::new(comparison),
+    })
+  }
+
+  /// The name of the analyzer which creates the hits
+  pub fn get_analyzer_name(&self) -> &'static str {
+    self.analyzer.name()
+  }
+
+  /// The statistics of the comparison with the second
+  /// analyzer so far (None if there is no second
+  /// analyzer configured)
+  pub fn get_comparison(&self) -> Option<AnalyzerComparison> {
+    if self.reference.is_none() {
+      return None;
+    }
+    match self.comparison.lock() {
+      Err(err) => {
+        error!("Unable to acquire lock for analyzer comparison! {err}");
+        None
+      }
+      Ok(cmp) => Some(cmp.clone())
+    }
+  }
+
+  /// Extract the TofHits from the waveforms of the
+  /// event. Existing hits of the event will be replaced.
+  ///
+  /// # Arguments
+  ///
+  /// * event       : current RBEvent with waveforms to
+  ///                 work on
+  /// * rb          : ReadoutBoard as loaded from the DB,
+  ///                 with latest calibration attached
+  ///                 (and pulse templates for the template
+  ///                 analyzer, timing and gain constants if
+  ///                 available)
+  /// * buffers     : memory for the calibrated waveforms
+  pub fn analyze(&self,
+                 event   : &mut RBEvent,
+                 rb      : &ReadoutBoard,
+                 buffers : &mut WaveformBuffers) -> Result<(), AnalysisError> {
+    // Don't do analysis for mangled events!
+    if event.has_any_mangling_flag() {
+      warn!("Event for RB {} has data mangling! Not doing analysis!", rb.rb_id);
+      return Err(AnalysisError::DataMangling);
+    }
+    match event.self_check() {
+      Err(_err) => {
+        // Phlip want to ahve all hits even if they are broken
+      },
+      Ok(_)    => ()
+    }
+    let settings        = &self.settings;
+    let active_channels = event.header.get_channels();
+    // Step 0 : Calibration of all channels at once
+    //FIXME - spike cleaning!
+    let calibrated      = rb.calibration.calibrate_event(event,
+                                                         &mut buffers.voltages,
+                                                         &mut buffers.times);
+    let voltages        = &mut buffers.voltages;
+    let times           = &buffers.times;
+
+    // Step 1 : If desired, fit sine
+    let mut fit_result = (0.0f32, 0.0f32, 0.0f32);
+    if settings.fit_sine {
+      if !active_channels.contains(&8) || calibrated & (1 << 8) == 0 {
+        warn!("RB {} does not have ch9 data!", rb.rb_id);
+        return Err(AnalysisError::NoChannel9);
+      }
+      fit_result = fit_sine_sydney(&voltages[8], &times[8]);
+      event.header.set_sine_fit(fit_result);
+      event.header.sine_fit_rms = sine_fit_rms(&voltages[8], &times[8], fit_result);
+    }
+
+    let mut comparison = AnalyzerComparison::default();
+    let mut paddles    = HashMap::<u8, TofHit>::new();
+    for pid in rb.get_paddle_ids() {
+      // cant' fail by constructon of pid
+      let ch_a = rb.get_pid_rbchA(pid).unwrap() as usize;
+      let ch_b = rb.get_pid_rbchB(pid).unwrap() as usize;
+      let mut hit = TofHit::new();
+      hit.paddle_id = pid;
+      for (k, ch) in [ch_a, ch_b].iter().enumerate() {
+        if !active_channels.contains(&(*ch as u8 -1)) {
+          trace!("Skipping channel {} because it is not marked to be readout in the event header channel mask!", ch);
+          continue;
+        }
+        if calibrated & (1 << (*ch - 1)) == 0 {
+          debug!("Skipping channel {} of RB {}, it has no (complete) waveform!", ch, rb.rb_id);
+          continue;
+        }
+        let ch_volts = &mut voltages[*ch - 1];
+        let ch_times = &times[*ch - 1];
+        // Step 2: Pedestal subtraction
+        let (ped, ped_err) = calculate_pedestal(ch_volts,
+                                                settings.pedestal_thresh,
+                                                settings.pedestal_begin_bin,
+                                                settings.pedestal_win_bins);
+        trace!("Calculated pedestal of {} +- {}", ped, ped_err);
+        for v in ch_volts.iter_mut() {
+          *v -= ped;
+        }
+        // Step 3 : Pulse extraction
+        let pend  = if k == 0 { 1000 + pid as u16 } else { 2000 + pid as u16 };
+        let pulse = self.analyzer.analyze(ch_volts, ch_times, ped_err, pend, rb, settings);
+        if let Some(reference) = &self.reference {
+          let ref_pulse = reference.analyze(ch_volts, ch_times, ped_err, pend, rb, settings);
+          comparison.add(&pulse, &ref_pulse);
+        }
+        if k == 0 {
+          hit.ftime_a        = pulse.time;
+          hit.fpeak_a        = pulse.peak;
+          hit.set_time_a(pulse.time);
+          hit.set_charge_a(pulse.charge);
+          hit.set_peak_a(pulse.peak);
+          hit.baseline_a     = f16::from_f32(ped);
+          hit.baseline_a_rms = f16::from_f32(ped_err);
+          hit.set_pulse_quality_a(pulse.saturated, pulse.fit_chi2);
+          for sec in pulse.secondary {
+            hit.add_secondary_pulse_a(sec);
+          }
+          if pulse.pileup {
+            hit.pileup |= TOFHIT_PILEUP_A;
+          }
+        } else {
+          hit.ftime_b        = pulse.time;
+          hit.fpeak_b        = pulse.peak;
+          hit.set_time_b(pulse.time);
+          hit.set_charge_b(pulse.charge);
+          hit.set_peak_b(pulse.peak);
+          hit.baseline_b     = f16::from_f32(ped);
+          hit.baseline_b_rms = f16::from_f32(ped_err);
+          hit.set_pulse_quality_b(pulse.saturated, pulse.fit_chi2);
+          for sec in pulse.secondary {
+            hit.add_secondary_pulse_b(sec);
+          }
+          if pulse.pileup {
+            hit.pileup |= TOFHIT_PILEUP_B;
+          }
+          // this is the seoond iteration,
+          // we are done!
+          hit.phase = f16::from_f32(fit_result.2);
+          paddles.insert(pid, hit);
+        }
+      }
+    }
+    if self.reference.is_some() {
+      match self.comparison.lock() {
+        Err(err) => error!("Unable to acquire lock for analyzer comparison! {err}"),
+        Ok(mut cmp) => cmp.merge(&comparison)
+      }
+    }
+    // Step 4 : Subtract the timing offsets of the paddle
+    //          ends (empty unless a timing calibration
+    //          was loaded for this board)
+    if !rb.timing.is_empty() {
+      for hit in paddles.values_mut() {
+        rb.timing.apply_offsets(hit);
+      }
+    }
+    // Step 5 : Calibrated energy deposition (if gains
+    //          were loaded for this board)
+    for hit in paddles.values_mut() {
+      if let Some(gain) = rb.gains.get(&hit.paddle_id) {
+        gain.apply(hit);
+      }
+    }
+    event.hits = paddles.into_values().collect();
+    Ok(())
+  }
+}
+
+#[test]
+fn analyzer_registry() {
+  let registry = AnalyzerRegistry::new();
+  assert_eq!(registry.get_names(), vec!["simple", "template", "zscore"]);
+  for name in registry.get_names() {
+    assert_eq!(registry.build(&name).unwrap().name(), name);
+  }
+  assert!(registry.build("foo").is_err());
+  let mut settings = AnalysisEngineSettings::new();
+  settings.use_template_fit = true;
+  assert_eq!(settings.get_analyzer_name(), "template");
+  settings.compare_analyzer = String::from("zscore");
+  let engine = AnalysisEngine::new(&settings).unwrap();
+  assert_eq!(engine.get_analyzer_name(), "template");
+  assert_eq!(engine.get_comparison().unwrap().reference, "zscore");
+  settings.compare_analyzer = String::from("foo");
+  assert!(AnalysisEngine::new(&settings).is_err());
+}
+
+#[test]
+fn pileup_recovery() {
+  // two pulses on a flat baseline, the second one
+  // either well separated (but still within the
+  // charge integration window of the first one)
+  // or sitting on the tail of the first one
+  let pulse = |voltages : &mut Vec<f32>, max_bin : usize, height : f32| {
+    for (k, v) in voltages.iter_mut().enumerate() {
+      let dt = k as f32 - max_bin as f32;
+      if dt < 0.0 {
+        *v += height*f32::exp(-dt*dt/8.0);
+      } else {
+        *v += height*f32::exp(-dt/10.0);
+      }
+    }
+  };
+  let mut separated = vec![0.0f32;1024];
+  pulse(&mut separated, 200, 100.0);
+  pulse(&mut separated, 300, 50.0);
+  assert!(!is_pileup(&separated, 200, 295));
+  let mut piled_up  = vec![0.0f32;1024];
+  pulse(&mut piled_up, 200, 100.0);
+  pulse(&mut piled_up, 215, 50.0);
+  assert!(is_pileup(&piled_up, 200, 210));
+  // overlapping peak ranges
+  assert!(is_pileup(&piled_up, 200, 190));
+}
+
+#[test]
+fn analyzer_comparison() {
+  let mut cmp = AnalyzerComparison::new("simple", "template");
+  let mut a   = PulseResult::new();
+  let mut b   = PulseResult::new();
+  a.time   = 101.0;
+  a.charge = 11.0;
+  b.time   = 100.0;
+  b.charge = 10.0;
+  cmp.add(&a, &b);
+  a.time   = 99.0;
+  a.charge = 9.0;
+  cmp.add(&a, &b);
+  cmp.add(&PulseResult::new(), &b);
+  assert_eq!(cmp.n_both, 2);
+  assert_eq!(cmp.n_only_reference, 1);
+  assert!(cmp.get_mean_dt().abs() < 1e-9);
+  assert!((cmp.get_std_dt() - 1.0).abs() < 1e-9);
+  assert!((cmp.get_std_dq() - 0.1).abs() < 1e-6);
+}
@@ -6,6 +6,7 @@ use std::fs::read_to_string;
 use tof_control::ltb_control::ltb_threshold;
 use tof_dataclasses::serialization::{
     Serialization,
+    Packable,
     //parse_u16,
 };
 use tof_dataclasses::io::RBEventMemoryStreamer;
@@ -34,6 +35,7 @@ use tof_dataclasses::commands::config::RunConfig;
 
 // Takeru's tof-control
 use tof_dataclasses::calibrations::RBCalibrations;
+use tof_dataclasses::calibration_quality::RBCalibrationQuality;
 use tof_dataclasses::errors::{CalibrationError,
                               RunError,
                               SetError};
@@ -360,6 +362,19 @@ pub fn rb_calibration(rc_to_runner    : &Sender<RunConfig>,
   //  println!("cali vdips {}", calibration.v_dips[0][k]);
   //  println!("cali tbins {}", calibration.tbin[0][k]);
   //}
+  // the quality report needs the calibration data,
+  // which might not be sent along, so do it here.
+  // It goes out first, so that liftof-cc has it 
+  // when the calibration arrives.
+  let quality = RBCalibrationQuality::from_calibration(&calibration);
+  println!("== ==> [rb_calibration] Quality : {}", quality);
+  match tp_to_publisher.send(quality.pack()) {
+    Err(err) => {
+      error!("Unable to send RBCalibrationQuality package! Error {err}");
+      return Err(CalibrationError::CanNotConnectToMyOwnZMQSocket);
+    },
+    Ok(_) => ()
+  }
   let calib_pack = TofPacket::from(&calibration);
   match tp_to_publisher.send(calib_pack) {
     Err(err) => {
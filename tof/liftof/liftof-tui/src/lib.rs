@@ -139,6 +139,8 @@ fn packet_sorter(packet_type : &PacketType,
         PacketType::MTBHeartbeat          => pack_key = "MTBHeartbeat", 
         PacketType::EVTBLDRHeartbeat      => pack_key = "EVTBLDRHeartbeat",
         PacketType::RBChannelMaskConfig   => pack_key = "RBChannelMaskConfig",
+        PacketType::EventIdHeartbeat      => pack_key = "EventIdHeartbeat",
+        PacketType::AlignmentHeartbeat    => pack_key = "AlignmentHeartbeat",
         PacketType::TofRBConfig           => pack_key = "TofRBConfig",
         PacketType::AnalysisEngineConfig  => pack_key = "AnalysisEngineConfig",
         PacketType::RBEventHeader         => pack_key = "RBEventHeader",    // needs to go away
@@ -151,8 +153,10 @@ fn packet_sorter(packet_type : &PacketType,
         PacketType::PBMoniData            => pack_key = "PBMoniData",
         PacketType::LTBMoniData           => pack_key = "LTBMoniData",
         PacketType::PAMoniData            => pack_key = "PAMoniData",
+        PacketType::RBChannelNoiseMoniData => pack_key = "RBChannelNoiseMoniData",
         PacketType::RBEventMemoryView     => pack_key = "RBEventMemoryView", // We'll keep it for now - indicates that the event
         PacketType::RBCalibration         => pack_key = "RBCalibration",
+        PacketType::RBCalibrationQuality  => pack_key = "RBCalibrationQuality",
         PacketType::TofCommand            => pack_key = "TofCommand",
         PacketType::TofCommandV2          => pack_key = "TofCommandV2",
         PacketType::TofResponse           => pack_key = "TofResponse",
@@ -309,6 +313,7 @@ pub fn packet_distributor(tp_from_sock : Receiver<TofPacket>,
           PacketType::LTBMoniData |
           PacketType::PAMoniData  |
           PacketType::PBMoniData  |
+          PacketType::RBChannelNoiseMoniData |
           PacketType::RBMoniData => {
             match tp_sender_rb.send(tp) {
               Err(err) => error!("Can't send TP! {err}"),
@@ -779,6 +779,9 @@ impl<'a> TabbedInterface<'a> {
               UIMenuItem::GlobalRates => {
                 self.wf_tab.view = RBTabView::GlobalRates;
               }
+              UIMenuItem::ChannelNoise => {
+                self.wf_tab.view = RBTabView::ChannelNoise;
+              }
               _ => ()
             }
           }
@@ -855,6 +858,9 @@ impl<'a> TabbedInterface<'a> {
               UIMenuItem::GlobalRates => {
                 self.wf_tab.view = RBTabView::GlobalRates;
               }
+              UIMenuItem::ChannelNoise => {
+                self.wf_tab.view = RBTabView::ChannelNoise;
+              }
               _ => ()
             }
           }
@@ -89,6 +89,7 @@ pub enum UIMenuItem {
   PAMoniData,
   PBMoniData,
   LTBMoniData,
+  ChannelNoise,
   SelectBoard,
   // event menu
   TofSummary,
@@ -138,6 +139,7 @@ impl UIMenuItem {
       UIMenuItem::PAMoniData     => String::from("PAMoniData"    ),
       UIMenuItem::PBMoniData     => String::from("PBMoniData"    ),
       UIMenuItem::LTBMoniData    => String::from("LTBMoniData"   ),
+      UIMenuItem::ChannelNoise   => String::from("ChannelNoise"  ),
       UIMenuItem::SelectBoard    => String::from("SelectBoard"   ),
       UIMenuItem::TofSummary     => String::from("TofSummary"    ),
       UIMenuItem::TofEvents      => String::from("TofEvents"     ),
@@ -306,7 +308,8 @@ impl UIMenu<'_> for RBMenu2<'_> {
                      UIMenuItem::RBMoniData,
                      UIMenuItem::PBMoniData,
                      UIMenuItem::PAMoniData,
-                     UIMenuItem::LTBMoniData];
+                     UIMenuItem::LTBMoniData,
+                     UIMenuItem::ChannelNoise];
                      //UIMenuItem::SelectBoard];
     items
   }
@@ -336,8 +339,9 @@ impl UIMenu<'_> for RBMenu2<'_> {
       4 => UIMenuItem::PAMoniData,
       5 => UIMenuItem::PBMoniData,
       6 => UIMenuItem::LTBMoniData,
-      7 => UIMenuItem::SelectBoard,
-      8 => UIMenuItem::Quit,
+      7 => UIMenuItem::ChannelNoise,
+      8 => UIMenuItem::SelectBoard,
+      9 => UIMenuItem::Quit,
       _ => UIMenuItem::Unknown
     }
   }
@@ -352,6 +356,7 @@ impl  RBMenu2<'_> {
                            "RBMoniData", 
                            "PBMoniData", "PAMoniData",
                            "LTBMoniData",
+                           "ChannelNoise",
                            "SelectBoards [LTB & RB]"];
 
     let titles : Vec<Line> = title_str
@@ -71,9 +71,11 @@ use tof_dataclasses::monitoring::{
   RBMoniData,
   LTBMoniData,
   PAMoniData,
+  RBChannelNoiseMoniData,
   RBMoniDataSeries,
   LTBMoniDataSeries,
   PAMoniDataSeries,
+  RBChannelNoiseMoniDataSeries,
 };
 
 use tof_dataclasses::series::MoniSeries;
@@ -105,6 +107,7 @@ pub enum RBTabView {
   PBMoniData,
   LTBMoniData,
   GlobalRates,
+  ChannelNoise,
   SelectRB,
 }
 
@@ -122,6 +125,8 @@ pub struct RBTab<'a>  {
   pub ltb_moni_queue     : LTBMoniDataSeries,  
   pub pa_show_biases     : bool,
   pub pa_moni_queue      : PAMoniDataSeries,
+  /// Baseline and noise of the individual channels
+  pub noise_moni_queue   : RBChannelNoiseMoniDataSeries,
   pub met_queue          : VecDeque<f64>,
   pub met_queue_moni     : HashMap<u8,VecDeque<f64>>,
   pub met_queue_ltb_moni : HashMap<u8,VecDeque<f64>>,
@@ -267,6 +272,7 @@ RBTab {
       pa_show_biases     : false,
       pa_moni_queue      : PAMoniDataSeries::new(),
       met_queue_pa_moni  : HashMap::<u8, VecDeque<f64>>::new(),
+      noise_moni_queue   : RBChannelNoiseMoniDataSeries::new(),
       fpgatmp_queue      : VecDeque::<(f64,f64)>::with_capacity(queue_size),
       fpgatmp_fr_moni    : true,
 
@@ -440,6 +446,29 @@ RBTab {
               }
               return Ok(());
             },
+            PacketType::RBChannelNoiseMoniData => {
+              trace!("Received new RBChannelNoiseMoniData!");
+              let moni : RBChannelNoiseMoniData = pack.unpack()?;
+              if self.alerts_active {
+                match self.alerts.lock() {
+                  Ok(mut al) => {
+                    if let Some(max_noise) = moni.get_max_noise() {
+                      if let Some(alert) = al.get_mut("rb_ch_noisy") {
+                        alert.trigger(max_noise);
+                      }
+                    }
+                    if let Some(min_noise) = moni.get_min_noise() {
+                      if let Some(alert) = al.get_mut("rb_ch_dead") {
+                        alert.trigger(min_noise);
+                      }
+                    }
+                  },
+                  Err(err)   =>  error!("Unable to lock global alerts! {err}"),
+                }
+              }
+              self.noise_moni_queue.add(moni);
+              return Ok(());
+            },
             PacketType::RBEvent => {
               ev = pack.unpack()?;
             },
@@ -1398,6 +1427,48 @@ RBTab {
           //.highlight_symbol(">>");
         frame.render_widget(table, *main_window); 
       }
+      RBTabView::ChannelNoise => {
+        let mut rows = Vec::<Row>::new();
+        let title : String;
+        match self.noise_moni_queue.get_last_moni(self.rb_selector) {
+          None => {
+            title = format!("No RBChannelNoiseMoniData for RB {:02} available", self.rb_selector);
+          }
+          Some(moni) => {
+            title = format!("RB {:02} channel baselines & noise (last {} s)", self.rb_selector, moni.period);
+            for k in 0..moni.noise.len() {
+              rows.push(Row::new(vec![format!("{}", k + 1),
+                                      format!("{}", moni.n_entries[k]),
+                                      format!("{:.2}", moni.baseline[k]),
+                                      format!("{:.2}", moni.baseline_spread[k]),
+                                      format!("{:.2}", moni.noise[k])]));
+            }
+          }
+        }
+        let widths = [
+          Constraint::Percentage(10),
+          Constraint::Percentage(15),
+          Constraint::Percentage(25),
+          Constraint::Percentage(25),
+          Constraint::Percentage(25),
+        ];
+        let table = Table::new(rows, widths)
+          .column_spacing(1)
+          .style(self.theme.style())
+          .header(
+            Row::new(vec!["Ch", "N", "Baseline [mV]", "Spread [mV]", "Noise [mV]"])
+              .style(self.theme.style())
+              .bottom_margin(1),
+          )
+          .block(
+            Block::default()
+              .borders(Borders::ALL)
+              .style(self.theme.style())
+              .title(title)
+              .border_type(BorderType::Rounded),
+            );
+        frame.render_widget(table, *main_window); 
+      }
       // This seems deprecated
       RBTabView::Info => {
         let main_view = Layout::default()
@@ -122,9 +122,13 @@ impl TofSummaryTab {
         // triggerd paddles histogram
         self.n_trg_pdl_histo.fill(&(ts.n_trigger_paddles as f32));
         // missing hg hits for paddles histogram
-        let missing_hg = ts.get_missing_paddles_hg(&self.pid_map);
-        for pid in &missing_hg {
-            self.miss_hg_hits.fill(&(*pid as f32));
+        match ts.get_missing_paddles_hg(&self.pid_map) {
+          Err(err) => error!("Unable to get paddles with missing HG hits! {err}"),
+          Ok(missing_hg) => {
+            for pid in &missing_hg {
+              self.miss_hg_hits.fill(&(*pid as f32));
+            }
+          }
         }
         if self.event_id_test.len() != self.evid_test_len {
           self.event_id_test.push(ts.event_id);